@@ -80,6 +80,8 @@ async fn runner_inner(inner: &Arc<PinheadInner>, tx_id: String) -> Result<(), an
             value: tx_guard.value,
             gas: Some(tx_guard.gas_limit.into()),
             data: tx_guard.data.clone(),
+            max_fee_per_gas: tx_guard.max_fee_per_gas,
+            max_priority_fee_per_gas: tx_guard.max_priority_fee_per_gas,
             ..Eip1559TransactionRequest::default()
         })
     };
@@ -93,6 +95,7 @@ async fn runner_inner(inner: &Arc<PinheadInner>, tx_id: String) -> Result<(), an
 
         tx_guard.status = Status::Pending;
         tx_guard.hash = Some(pending_tx.tx_hash());
+        tx_guard.sent_at = Some(Utc::now());
     }
 
     tracing::info!("Awaiting for receipt");
@@ -148,18 +151,21 @@ impl Pinhead {
 
         let tx = RelayerTransactionBase {
             transaction_id: tx_id.clone(),
-            to:             tx_request.to.context("Missing to")?,
-            value:          tx_request.value,
-            gas_limit:      tx_request
+            to: tx_request.to.context("Missing to")?,
+            value: tx_request.value,
+            gas_limit: tx_request
                 .gas_limit
                 .map(|gas_limit| gas_limit.as_u32())
                 .unwrap_or(DEFAULT_GAS_LIMIT),
-            data:           tx_request.data,
-            status:         Status::Pending,
-            hash:           None,
-            valid_until:    tx_request
+            data: tx_request.data,
+            status: Status::Pending,
+            hash: None,
+            valid_until: tx_request
                 .valid_until
                 .unwrap_or(Utc::now() + chrono::Duration::hours(24)),
+            max_fee_per_gas: tx_request.max_fee_per_gas,
+            max_priority_fee_per_gas: tx_request.max_priority_fee_per_gas,
+            sent_at: None,
         };
 
         txs.insert(tx_id.clone(), Arc::new(Mutex::new(tx.clone())));
@@ -169,6 +175,28 @@ impl Pinhead {
         Ok(tx)
     }
 
+    /// Updates the fees of a transaction. Unlike the real relayer, the
+    /// transaction is not resubmitted to the network.
+    pub async fn replace_transaction(
+        &self,
+        tx_id: &str,
+        tx_request: SendBaseTransactionRequestOwned,
+    ) -> anyhow::Result<RelayerTransactionBase> {
+        let txs = self.inner.txs.lock().await;
+
+        let tx = txs
+            .get(tx_id)
+            .context(format!("Transaction {} not found", tx_id))?;
+
+        let mut tx_guard = tx.lock().await;
+
+        tx_guard.max_fee_per_gas = tx_request.max_fee_per_gas;
+        tx_guard.max_priority_fee_per_gas = tx_request.max_priority_fee_per_gas;
+        tx_guard.sent_at = Some(Utc::now());
+
+        Ok(tx_guard.clone())
+    }
+
     pub async fn list_transactions(
         &self,
         status: Option<Status>,
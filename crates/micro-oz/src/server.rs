@@ -31,6 +31,23 @@ async fn send_transaction(
     }
 }
 
+async fn replace_transaction(
+    State(pinhead): State<Pinhead>,
+    Path(tx_id): Path<String>,
+    Json(request): Json<SendBaseTransactionRequestOwned>,
+) -> Result<Json<RelayerTransactionBase>, StatusCode> {
+    let result = pinhead.replace_transaction(&tx_id, request).await;
+
+    match result {
+        Ok(tx) => Ok(Json(tx)),
+        Err(err) => {
+            tracing::error!("Pinhead replace_transaction error: {:?}", err);
+
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ListTransactionsQuery {
     #[serde(default)]
@@ -105,7 +122,10 @@ pub async fn spawn(rpc_url: String, secret_key: SigningKey) -> anyhow::Result<Se
 
     let router = Router::new()
         .route("/txs", post(send_transaction).get(list_transactions))
-        .route("/txs/:tx_id", get(query_transaction))
+        .route(
+            "/txs/:tx_id",
+            get(query_transaction).put(replace_transaction),
+        )
         .with_state(pinhead.clone());
 
     let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
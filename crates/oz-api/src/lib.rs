@@ -81,6 +81,23 @@ impl OzApi {
         Self::json_or_error(res).await
     }
 
+    /// Replaces a pending transaction, e.g. to resubmit it with higher fees.
+    ///
+    /// https://docs.openzeppelin.com/defender/relay-api-reference#replace-transaction
+    pub async fn replace_transaction(
+        &self,
+        tx_id: &str,
+        tx: SendBaseTransactionRequest<'_>,
+    ) -> Result<RelayerTransactionBase> {
+        let url = self.txs_url()?.join("txs/")?.join(tx_id)?;
+
+        let headers = self.headers().await?;
+
+        let res = headers.apply(self.client.put(url)).json(&tx).send().await?;
+
+        Self::json_or_error(res).await
+    }
+
     pub async fn list_transactions(
         &self,
         status: Option<Status>,
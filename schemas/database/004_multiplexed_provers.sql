@@ -0,0 +1,4 @@
+-- Multiple provers can now serve the same batch size, so a prover is
+-- identified by its batch size and url instead.
+ALTER TABLE provers DROP CONSTRAINT provers_pkey;
+ALTER TABLE provers ADD PRIMARY KEY (batch_size, url);
@@ -0,0 +1,14 @@
+-- Batches rejected by the prover, along with the full response of the prover
+-- so that failures can be investigated after the fact.
+CREATE TABLE failed_batches (
+    id          BIGSERIAL     PRIMARY KEY,
+    post_root   BYTEA         NOT NULL,
+    pre_root    BYTEA         NOT NULL,
+    start_index BIGINT        NOT NULL,
+    prover_url  VARCHAR(1028) NOT NULL,
+    status_code INT           NOT NULL,
+    error_body  TEXT          NOT NULL,
+    failed_at   TIMESTAMPTZ   NOT NULL
+);
+
+CREATE INDEX failed_batches_post_root_idx ON failed_batches (post_root);
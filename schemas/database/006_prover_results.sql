@@ -0,0 +1,11 @@
+-- Proofs generated for batches, keyed by the input hash of the batch, so that
+-- a batch that is retried after a failed transaction doesn't need to be proven
+-- again. Entries are removed once the batch has been mined.
+CREATE TABLE prover_results (
+    input_hash BYTEA       NOT NULL PRIMARY KEY,
+    post_root  BYTEA       NOT NULL,
+    proof      BYTEA       NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX prover_results_post_root_idx ON prover_results (post_root);
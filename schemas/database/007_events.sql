@@ -0,0 +1,14 @@
+-- Events waiting to be delivered to the webhook. Delivered events are removed,
+-- events that could not be delivered after all attempts are kept with status
+-- 'dead' until they are redriven.
+CREATE TABLE events (
+    id              BIGSERIAL   PRIMARY KEY,
+    payload         TEXT        NOT NULL,
+    status          VARCHAR(50) NOT NULL,
+    attempts        INT         NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL,
+    last_error      TEXT,
+    created_at      TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX events_status_next_attempt_at_idx ON events (status, next_attempt_at);
@@ -0,0 +1,7 @@
+-- The transactions that published roots to the identity manager, as seen in
+-- its `TreeChanged` events.
+CREATE TABLE root_transactions (
+    root             BYTEA  NOT NULL PRIMARY KEY,
+    transaction_hash BYTEA  NOT NULL,
+    block_number     BIGINT NOT NULL
+);
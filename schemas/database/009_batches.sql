@@ -0,0 +1,19 @@
+-- Every batch constructed by the sequencer and where it is in its lifecycle:
+-- proving -> proven -> submitted -> mined -> finalized, or failed.
+CREATE TABLE batches (
+    post_root        BYTEA         NOT NULL PRIMARY KEY,
+    pre_root         BYTEA         NOT NULL,
+    -- The leaves of the identities in the batch, excluding padding.
+    start_index      BIGINT        NOT NULL,
+    end_index        BIGINT        NOT NULL,
+    batch_size       INT           NOT NULL,
+    prover_url       VARCHAR(1028),
+    proof            BYTEA,
+    transaction_id   VARCHAR(256),
+    transaction_hash BYTEA,
+    status           VARCHAR(50)   NOT NULL,
+    created_at       TIMESTAMPTZ   NOT NULL,
+    updated_at       TIMESTAMPTZ   NOT NULL
+);
+
+CREATE INDEX batches_status_idx ON batches (status);
@@ -0,0 +1,16 @@
+-- The propagation of finalized roots to secondary chains through their state
+-- bridges: pending -> submitted -> propagated. A root that's still pending or
+-- submitted when a later root is propagated is superseded, as bridges only
+-- relay the latest root.
+CREATE TABLE root_propagations (
+    id             BIGSERIAL    PRIMARY KEY,
+    root           BYTEA        NOT NULL,
+    chain_id       BIGINT       NOT NULL,
+    status         VARCHAR(50)  NOT NULL,
+    transaction_id VARCHAR(256),
+    created_at     TIMESTAMPTZ  NOT NULL,
+    updated_at     TIMESTAMPTZ  NOT NULL,
+    UNIQUE (root, chain_id)
+);
+
+CREATE INDEX root_propagations_chain_id_status_idx ON root_propagations (chain_id, status);
@@ -0,0 +1,13 @@
+ALTER TABLE unprocessed_identities
+    ADD COLUMN error_code    VARCHAR(50),
+    ADD COLUMN error_details JSONB;
+
+UPDATE unprocessed_identities
+SET    error_code = CASE
+           WHEN error_message = 'Duplicate commitment.' THEN 'duplicate_commitment'
+           ELSE 'unknown'
+       END,
+       error_details = jsonb_build_object('message', error_message)
+WHERE  error_message IS NOT NULL;
+
+ALTER TABLE unprocessed_identities DROP COLUMN error_message;
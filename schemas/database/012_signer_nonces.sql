@@ -0,0 +1,19 @@
+-- The next nonce to use for each locally held signing key.
+CREATE TABLE signer_nonces (
+    address    BYTEA       NOT NULL PRIMARY KEY,
+    nonce      BIGINT      NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Every signed transaction, including replacements, so that transactions can
+-- be rebroadcast and awaited after a restart.
+CREATE TABLE signer_transactions (
+    transaction_hash BYTEA       NOT NULL PRIMARY KEY,
+    address          BYTEA       NOT NULL,
+    nonce            BIGINT      NOT NULL,
+    data             BYTEA,
+    raw              BYTEA       NOT NULL,
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX signer_transactions_nonce ON signer_transactions (address, nonce);
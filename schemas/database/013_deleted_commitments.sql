@@ -0,0 +1,8 @@
+-- Commitments whose leaf has been deleted. Re-inserting one would let a
+-- deleted identity produce nullifiers again, so insertions are checked
+-- against this table.
+CREATE TABLE deleted_commitments (
+    commitment BYTEA       NOT NULL PRIMARY KEY,
+    leaf_index BIGINT      NOT NULL,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,7 @@
+-- The block at which each signed transaction was sent and the fees it pays,
+-- so that transactions that stay pending can be replaced with higher fees.
+-- Replacements share the address and nonce of the transaction they replace.
+ALTER TABLE signer_transactions
+    ADD COLUMN sent_at_block            BIGINT,
+    ADD COLUMN max_fee_per_gas          BYTEA,
+    ADD COLUMN max_priority_fee_per_gas BYTEA;
@@ -0,0 +1,7 @@
+-- The next block to scan for events of each contract, so that scanning
+-- resumes where it left off after a restart.
+CREATE TABLE scan_cursors (
+    address    BYTEA       NOT NULL PRIMARY KEY,
+    next_block BIGINT      NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,7 @@
+-- The lease held by the instance that batches and submits identities when
+-- several sequencer instances share the database. There is at most one row.
+CREATE TABLE leader_lease (
+    id         BOOLEAN     NOT NULL PRIMARY KEY DEFAULT TRUE CHECK (id),
+    holder     TEXT        NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
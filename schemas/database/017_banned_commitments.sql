@@ -0,0 +1,14 @@
+-- Commitments that may not be inserted, e.g. because they are known to be
+-- abusive. Banned commitments already in the tree can be queued for deletion.
+CREATE TABLE banned_commitments (
+    commitment BYTEA       NOT NULL PRIMARY KEY,
+    reason     TEXT,
+    banned_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Leaves queued for deletion from the tree.
+CREATE TABLE deletion_queue (
+    commitment BYTEA       NOT NULL PRIMARY KEY,
+    leaf_index BIGINT      NOT NULL,
+    queued_at  TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,25 @@
+-- Every mutating API call, who made it and how it ended. Entries can't be
+-- changed or removed once written.
+CREATE TABLE audit_log (
+    id           BIGSERIAL   NOT NULL PRIMARY KEY,
+    created_at   TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    method       TEXT        NOT NULL,
+    path         TEXT        NOT NULL,
+    -- A fingerprint of the credentials the call was made with.
+    api_key      TEXT,
+    source_ip    TEXT        NOT NULL,
+    -- The hex encoded SHA-256 of the request body.
+    payload_hash TEXT        NOT NULL,
+    -- The HTTP status code of the response.
+    outcome      INTEGER     NOT NULL
+);
+
+CREATE FUNCTION reject_audit_log_changes() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'the audit log is append only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_log_changes();
@@ -0,0 +1,3 @@
+-- The id of the API request that queued the identity, so that the logs of
+-- inserting it can be correlated with the request.
+ALTER TABLE unprocessed_identities ADD COLUMN request_id TEXT;
@@ -0,0 +1,8 @@
+-- When each stage of the lifecycle of an identity was reached, to measure the
+-- time to inclusion. Identities are batched when their batch is created and
+-- mined at `identities.mined_at`.
+ALTER TABLE identities ADD COLUMN received_at TIMESTAMPTZ;
+
+ALTER TABLE batches
+    ADD COLUMN proven_at    TIMESTAMPTZ,
+    ADD COLUMN submitted_at TIMESTAMPTZ;
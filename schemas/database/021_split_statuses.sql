@@ -0,0 +1,10 @@
+-- Identities in the tree and identities queued for insertion go through
+-- different statuses. Keep either table from holding the statuses of the
+-- other, e.g. an identity in the tree can't be marked as new or failed.
+ALTER TABLE identities
+    ADD CONSTRAINT identities_status_check
+    CHECK (status IN ('pending', 'processed', 'mined'));
+
+ALTER TABLE unprocessed_identities
+    ADD CONSTRAINT unprocessed_identities_status_check
+    CHECK (status IN ('new', 'failed'));
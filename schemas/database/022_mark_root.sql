@@ -0,0 +1,42 @@
+-- Moves the identities up to and including `marked_root` to `new_status`, and
+-- for `processed` the identities after it back to pending. Returns the leaf
+-- index of the root, or NULL if the root is unknown.
+--
+-- Transitions are serialized by an advisory lock held until the end of the
+-- calling transaction, so that concurrent markings of different roots can't
+-- interleave or deadlock on the rows they update.
+CREATE FUNCTION mark_root(marked_root BYTEA, new_status VARCHAR(50))
+RETURNS BIGINT
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    root_leaf_index BIGINT;
+BEGIN
+    PERFORM pg_advisory_xact_lock(hashtext('identities.mark_root'));
+
+    SELECT leaf_index INTO root_leaf_index
+    FROM   identities
+    WHERE  root = marked_root;
+
+    IF root_leaf_index IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    IF new_status = 'processed' THEN
+        UPDATE identities
+        SET    status = CASE WHEN leaf_index <= root_leaf_index THEN 'processed' ELSE 'pending' END,
+               mined_at = CASE WHEN leaf_index <= root_leaf_index THEN CURRENT_TIMESTAMP ELSE NULL END
+        WHERE  (leaf_index <= root_leaf_index AND status NOT IN ('processed', 'mined'))
+        OR     leaf_index > root_leaf_index;
+    ELSIF new_status = 'mined' THEN
+        UPDATE identities
+        SET    status = 'mined'
+        WHERE  leaf_index <= root_leaf_index
+        AND    status <> 'mined';
+    ELSE
+        RAISE EXCEPTION 'roots can''t be marked as %', new_status;
+    END IF;
+
+    RETURN root_leaf_index;
+END;
+$$;
@@ -0,0 +1,11 @@
+-- The next leaf to allocate to an identity. Allocations lock this row until
+-- their identities are inserted, so that concurrent batchers can't hand out
+-- the same leaves, and roll back with them, so that no leaves are skipped.
+-- There is exactly one row.
+CREATE TABLE leaf_allocation (
+    id        BOOLEAN NOT NULL PRIMARY KEY DEFAULT TRUE CHECK (id),
+    next_leaf BIGINT  NOT NULL
+);
+
+INSERT INTO leaf_allocation (next_leaf)
+SELECT COALESCE(MAX(leaf_index) + 1, 0) FROM identities;
@@ -0,0 +1,7 @@
+-- Marks a database that a dry run has written simulated batches to. Their
+-- roots never reached the chain, so the database can't be used for real
+-- submissions. There is at most one row.
+CREATE TABLE dry_run (
+    id         BOOLEAN     NOT NULL PRIMARY KEY DEFAULT TRUE CHECK (id),
+    started_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,7 @@
+-- Set while batching is paused by an operator, so that the pause outlasts
+-- restarts and carries over to a new leader. There is at most one row.
+CREATE TABLE batching_pause (
+    id        BOOLEAN     NOT NULL PRIMARY KEY DEFAULT TRUE CHECK (id),
+    reason    TEXT,
+    paused_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
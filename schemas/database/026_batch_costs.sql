@@ -0,0 +1,5 @@
+-- The gas used by the transaction of every mined batch and the price it paid
+-- per gas in wei, as reported by its receipt.
+ALTER TABLE batches
+    ADD COLUMN gas_used            BIGINT,
+    ADD COLUMN effective_gas_price BIGINT;
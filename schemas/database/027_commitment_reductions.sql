@@ -0,0 +1,8 @@
+-- Commitments that were submitted as integers outside of the scalar field and
+-- reduced modulo its order before they were queued, keyed by the reduced
+-- commitment.
+CREATE TABLE commitment_reductions (
+    commitment BYTEA       NOT NULL PRIMARY KEY,
+    original   BYTEA       NOT NULL,
+    reduced_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,13 @@
+-- Notifies the `unprocessed_identities` channel whenever identities are queued
+-- or requeued, so that the insertion task wakes up right away instead of on
+-- its next poll of the table.
+CREATE FUNCTION notify_unprocessed_identities() RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify('unprocessed_identities', '');
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER unprocessed_identities_notify
+    AFTER INSERT OR UPDATE OF status ON unprocessed_identities
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_unprocessed_identities();
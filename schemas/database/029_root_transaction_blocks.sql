@@ -0,0 +1,5 @@
+-- The hash of the block that mined the transaction publishing a root, so that
+-- the root can be found on chain without the block number being ambiguous
+-- across reorgs. Unknown for roots mined before it was recorded.
+ALTER TABLE root_transactions
+    ADD COLUMN block_hash BYTEA;
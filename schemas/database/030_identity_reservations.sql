@@ -0,0 +1,10 @@
+-- Commitments reserved for insertion, e.g. while the identity is being
+-- verified. Reservations count against the capacity of the tree until they are
+-- confirmed, which queues the commitment, or they expire.
+CREATE TABLE identity_reservations (
+    commitment  BYTEA       NOT NULL PRIMARY KEY,
+    reserved_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at  TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX identity_reservations_expires_at ON identity_reservations (expires_at);
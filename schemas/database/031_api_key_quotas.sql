@@ -0,0 +1,19 @@
+-- The insertion quotas of API keys, identified by the fingerprint of their
+-- credentials as in the audit log. Limits that aren't set fall back to the
+-- default quotas.
+CREATE TABLE api_key_quotas (
+    api_key       TEXT        NOT NULL PRIMARY KEY,
+    daily_limit   BIGINT,
+    monthly_limit BIGINT,
+    updated_at    TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- The number of identities inserted with each API key per day and month, in
+-- UTC, whether or not the key has a quota.
+CREATE TABLE api_key_usage (
+    api_key      TEXT        NOT NULL,
+    period       VARCHAR(10) NOT NULL,
+    period_start DATE        NOT NULL,
+    inserted     BIGINT      NOT NULL,
+    PRIMARY KEY (api_key, period, period_start)
+);
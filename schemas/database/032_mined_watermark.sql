@@ -0,0 +1,84 @@
+-- The highest leaf index that has ever been mined. Mined identities are final,
+-- so no transition may return a leaf at or below it to pending or processed.
+CREATE TABLE mined_watermark (
+    id         BOOLEAN NOT NULL PRIMARY KEY DEFAULT TRUE CHECK (id),
+    leaf_index BIGINT  NOT NULL
+);
+
+INSERT INTO mined_watermark (leaf_index)
+SELECT MAX(leaf_index)
+FROM   identities
+WHERE  status = 'mined'
+HAVING MAX(leaf_index) IS NOT NULL;
+
+CREATE FUNCTION raise_mined_watermark() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO mined_watermark (leaf_index)
+    SELECT MAX(leaf_index)
+    FROM   mined_rows
+    WHERE  status = 'mined'
+    HAVING MAX(leaf_index) IS NOT NULL
+    ON CONFLICT (id) DO UPDATE
+    SET    leaf_index = GREATEST(mined_watermark.leaf_index, EXCLUDED.leaf_index);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER identities_mined_watermark
+    AFTER UPDATE ON identities
+    REFERENCING NEW TABLE AS mined_rows
+    FOR EACH STATEMENT EXECUTE FUNCTION raise_mined_watermark();
+
+-- A backstop for bugs in the sequencer: moving a mined identity out of mined
+-- fails the whole statement, whichever query attempts it.
+CREATE FUNCTION refuse_mined_regression() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'identity at leaf % is mined and can''t become %', OLD.leaf_index, NEW.status
+        USING ERRCODE = 'SQ001';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER identities_refuse_mined_regression
+    BEFORE UPDATE OF status ON identities
+    FOR EACH ROW
+    WHEN (OLD.status = 'mined' AND NEW.status <> 'mined')
+    EXECUTE FUNCTION refuse_mined_regression();
+
+-- Marking a root as processed no longer returns the mined identities after it
+-- to pending. Such a marking is stale, e.g. from reconciling with a chain that
+-- was reorged, and is reported by the sequencer instead.
+CREATE OR REPLACE FUNCTION mark_root(marked_root BYTEA, new_status VARCHAR(50))
+RETURNS BIGINT
+LANGUAGE plpgsql
+AS $$
+DECLARE
+    root_leaf_index BIGINT;
+BEGIN
+    PERFORM pg_advisory_xact_lock(hashtext('identities.mark_root'));
+
+    SELECT leaf_index INTO root_leaf_index
+    FROM   identities
+    WHERE  root = marked_root;
+
+    IF root_leaf_index IS NULL THEN
+        RETURN NULL;
+    END IF;
+
+    IF new_status = 'processed' THEN
+        UPDATE identities
+        SET    status = CASE WHEN leaf_index <= root_leaf_index THEN 'processed' ELSE 'pending' END,
+               mined_at = CASE WHEN leaf_index <= root_leaf_index THEN CURRENT_TIMESTAMP ELSE NULL END
+        WHERE  status <> 'mined'
+        AND    (leaf_index > root_leaf_index OR status <> 'processed');
+    ELSIF new_status = 'mined' THEN
+        UPDATE identities
+        SET    status = 'mined'
+        WHERE  leaf_index <= root_leaf_index
+        AND    status <> 'mined';
+    ELSE
+        RAISE EXCEPTION 'roots can''t be marked as %', new_status;
+    END IF;
+
+    RETURN root_leaf_index;
+END;
+$$;
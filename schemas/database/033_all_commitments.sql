@@ -0,0 +1,65 @@
+-- Every commitment that is queued or in the tree, and which of the two tables
+-- it is in. Its primary key is what keeps a commitment from being queued
+-- twice, or queued while it is in the tree, even when the requests race.
+CREATE TABLE all_commitments (
+    commitment BYTEA       NOT NULL PRIMARY KEY,
+    source     VARCHAR(20) NOT NULL CHECK (source IN ('unprocessed', 'identities'))
+);
+
+INSERT INTO all_commitments (commitment, source)
+SELECT commitment, 'identities'
+FROM   identities;
+
+-- Failed entries may share their commitment with an identity in the tree.
+INSERT INTO all_commitments (commitment, source)
+SELECT commitment, 'unprocessed'
+FROM   unprocessed_identities
+ON CONFLICT (commitment) DO NOTHING;
+
+-- New identities claim their commitment before they are queued, so this only
+-- records commitments queued otherwise, e.g. when a batch is cancelled.
+CREATE FUNCTION record_unprocessed_commitment() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO all_commitments (commitment, source)
+    VALUES (NEW.commitment, 'unprocessed')
+    ON CONFLICT (commitment) DO NOTHING;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER unprocessed_identities_record_commitment
+    AFTER INSERT ON unprocessed_identities
+    FOR EACH ROW EXECUTE FUNCTION record_unprocessed_commitment();
+
+CREATE FUNCTION record_identity_commitment() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO all_commitments (commitment, source)
+    VALUES (NEW.commitment, 'identities')
+    ON CONFLICT (commitment) DO UPDATE
+    SET    source = EXCLUDED.source;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER identities_record_commitment
+    AFTER INSERT ON identities
+    FOR EACH ROW EXECUTE FUNCTION record_identity_commitment();
+
+-- A commitment is only forgotten by the table it is recorded for, so that
+-- dropping a failed entry doesn't forget the same commitment in the tree.
+CREATE FUNCTION forget_commitment() RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM all_commitments
+    WHERE  commitment = OLD.commitment
+    AND    source = TG_TABLE_NAME;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER unprocessed_identities_forget_commitment
+    AFTER DELETE ON unprocessed_identities
+    FOR EACH ROW EXECUTE FUNCTION forget_commitment();
+
+CREATE TRIGGER identities_forget_commitment
+    AFTER DELETE ON identities
+    FOR EACH ROW EXECUTE FUNCTION forget_commitment();
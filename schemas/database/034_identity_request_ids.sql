@@ -0,0 +1,3 @@
+-- The id of the request that queued the identity, kept once it is in the tree
+-- so that cancelling its batch can queue it again under the same request.
+ALTER TABLE identities ADD COLUMN request_id TEXT;
@@ -0,0 +1,3 @@
+-- A prover identified by its batch size and url can serve several batch sizes
+-- from the same url.
+ALTER TABLE provers DROP CONSTRAINT provers_url_key;
@@ -1,60 +1,210 @@
+#[cfg(feature = "submission")]
 use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Instant;
 
-use anyhow::Result as AnyhowResult;
-use chrono::Duration;
+use anyhow::{bail, ensure, Result as AnyhowResult};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 use clap::Parser;
+use ethers::core::k256::elliptic_curve::sec1::ToEncodedPoint;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, H256};
+use futures::future::try_join_all;
 use hyper::StatusCode;
-use semaphore::poseidon_tree::LazyPoseidonTree;
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_gauge, register_int_counter, register_int_gauge, register_int_gauge_vec, Gauge,
+    IntCounter, IntGauge, IntGaugeVec,
+};
+use semaphore::poseidon_tree::Proof;
 use semaphore::protocol::verify_proof;
 use serde::Serialize;
-use tracing::{info, instrument, warn};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{error, info, instrument, warn};
 
 use crate::contracts::{IdentityManager, SharedIdentityManager};
+#[cfg(feature = "submission")]
 use crate::database::prover::{ProverConfiguration as DbProverConf, Provers};
+use crate::database::types::{
+    AuditEntry, Batch, BatchCosts, BatchStatus, BatchingPause, FailedBatch, QuotaConsumption,
+    QuotaPeriod, RootPropagation, RootTransaction, StageLatency, StoredAuditEntry, StoredEvent,
+};
 use crate::database::{self, Database};
 use crate::ethereum::{self, Ethereum};
+use crate::follower::Follower;
 use crate::identity_tree::{
-    CanonicalTreeBuilder, Hash, InclusionProof, RootItem, Status, TreeState, TreeVersionReadOps,
+    dense_prefix_depth_for, is_reduced, reduce, CanonicalTreeBuilder, CommitmentError, Hash,
+    InclusionProof, ProcessedStatus, RootAccumulator, RootItem, Status, TreeItem, TreeState,
+    TreeVersionReadOps, UnprocessedStatus,
 };
+use crate::inclusion_estimate::InclusionEstimator;
+use crate::leader::LeaderLease;
+use crate::observer::Observer;
+#[cfg(feature = "submission")]
+use crate::prover::batch_insertion;
 use crate::prover::batch_insertion::ProverConfiguration;
+#[cfg(feature = "submission")]
 use crate::prover::map::make_insertion_map;
-use crate::prover::{self, batch_insertion};
+use crate::reconciliation::{self, ReconciliationPolicy};
+use crate::retention::Retention;
+use crate::secret::SecretString;
 use crate::server::error::Error as ServerError;
 use crate::server::{ToResponseCode, VerifySemaphoreProofQuery, VerifySemaphoreProofRequest};
+use crate::snapshot::{Snapshot, Snapshotter};
+use crate::standby::Standby;
+use crate::task_monitor::scheduler::JobStatus;
+use crate::task_monitor::supervisor::TaskStatus;
 use crate::task_monitor::TaskMonitor;
-use crate::{contracts, task_monitor};
+use crate::utils::proof_cache::ProofCache;
+use crate::utils::request_deadline;
+use crate::utils::worker_pool::WorkerPool;
+use crate::{config, contracts, events, leader, prover, quotas, retention, standby, task_monitor};
+
+/// The largest number of leaves returned by a single `/sync` request.
+const MAX_SYNC_PAGE_SIZE: usize = 10_000;
+
+/// The maximum number of audit entries returned at once.
+const MAX_AUDIT_PAGE_SIZE: usize = 1000;
+
+static REMAINING_TREE_CAPACITY: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "remaining_tree_capacity",
+        "Leaves left in the tree, counting queued identities"
+    )
+    .unwrap()
+});
+
+static INSERTION_QUEUE_DEPTH: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "insertion_queue_depth",
+        "Identities waiting to be inserted into the tree"
+    )
+    .unwrap()
+});
+
+static INSERTION_QUEUE_SATURATION: Lazy<Gauge> = Lazy::new(|| {
+    register_gauge!(
+        "insertion_queue_saturation",
+        "Identities waiting to be inserted into the tree as a fraction of --max-queue-depth"
+    )
+    .unwrap()
+});
+
+static INSERTED_IDENTITIES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "inserted_identities",
+        "Number of identities accepted for insertion"
+    )
+    .unwrap()
+});
+
+static RESERVED_IDENTITIES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "reserved_identities",
+        "Number of identities reserved for insertion"
+    )
+    .unwrap()
+});
+
+static TREE_LEAVES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "tree_leaves",
+        "Number of leaves in each version of the tree",
+        &["version"]
+    )
+    .unwrap()
+});
+
+static MINED_ROOT_AGE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "mined_root_age_seconds",
+        "Seconds since the latest mined root was mined"
+    )
+    .unwrap()
+});
 
 #[derive(Serialize)]
-#[serde(transparent)]
-pub struct InclusionProofResponse(InclusionProof);
+#[serde(rename_all = "camelCase")]
+pub struct InclusionProofResponse {
+    #[serde(flatten)]
+    proof:                    InclusionProof,
+    /// When an identity that isn't on chain yet is expected to be.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimated_inclusion_time: Option<DateTime<Utc>>,
+    /// The transaction that published the root of the proof on mainnet, once
+    /// it has been seen.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transaction:              Option<RootTransactionResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature:                Option<ProofSignature>,
+}
 
 impl InclusionProofResponse {
     #[must_use]
     pub fn hide_processed_status(mut self) -> Self {
-        self.0.status = if self.0.status == Status::Processed {
-            Status::Pending
+        self.proof.status = if self.proof.status == Status::Processed(ProcessedStatus::Processed) {
+            Status::Processed(ProcessedStatus::Pending)
         } else {
-            self.0.status
+            self.proof.status
         };
 
         self
     }
+
+    /// Identifies the proof of `commitment` for conditional requests. The
+    /// proof only changes with the root and the status, so it's derived from
+    /// those and the leaf. Absent if there is no proof yet.
+    #[must_use]
+    pub fn etag(&self, commitment: &Hash) -> Option<String> {
+        let root = self.proof.root?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(root.to_be_bytes::<32>());
+        hasher.update(commitment.to_be_bytes::<32>());
+        hasher.update(<&str>::from(self.proof.status));
+        if let Some(estimate) = self.estimated_inclusion_time {
+            hasher.update(estimate.timestamp().to_be_bytes());
+        }
+
+        Some(format!("\"{}\"", hex::encode(&hasher.finalize()[..16])))
+    }
+
+    #[must_use]
+    pub fn is_mined(&self) -> bool {
+        self.proof.status == Status::Processed(ProcessedStatus::Mined)
+    }
+
+    /// The root the proof is for, and the Merkle proof, if there is one yet.
+    #[must_use]
+    pub fn merkle_proof(&self) -> Option<(&Hash, &Proof)> {
+        self.proof.root.as_ref().zip(self.proof.proof.as_ref())
+    }
 }
 
 impl From<InclusionProof> for InclusionProofResponse {
     fn from(value: InclusionProof) -> Self {
-        Self(value)
+        Self {
+            proof:                    value,
+            estimated_inclusion_time: None,
+            transaction:              None,
+            signature:                None,
+        }
     }
 }
 
 impl ToResponseCode for InclusionProofResponse {
     fn to_response_code(&self) -> StatusCode {
-        match self.0.status {
-            Status::Failed => StatusCode::BAD_REQUEST,
-            Status::New | Status::Pending => StatusCode::ACCEPTED,
-            Status::Mined | Status::Processed => StatusCode::OK,
+        match self.proof.status {
+            Status::Unprocessed(UnprocessedStatus::Failed) => StatusCode::BAD_REQUEST,
+            Status::Unprocessed(UnprocessedStatus::New)
+            | Status::Processed(ProcessedStatus::Pending) => StatusCode::ACCEPTED,
+            Status::Processed(ProcessedStatus::Mined | ProcessedStatus::Processed) => {
+                StatusCode::OK
+            }
         }
     }
 }
@@ -75,6 +225,431 @@ impl ToResponseCode for ListBatchSizesResponse {
     }
 }
 
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct ScheduledJobsResponse(Vec<JobStatus>);
+
+impl ToResponseCode for ScheduledJobsResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct TasksResponse(Vec<TaskStatus>);
+
+impl ToResponseCode for TasksResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct DeadLetterEventsResponse(Vec<StoredEvent>);
+
+impl ToResponseCode for DeadLetterEventsResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedriveEventsResponse {
+    redriven: u64,
+}
+
+impl ToResponseCode for RedriveEventsResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+/// The settings a reload of the config file changed.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReloadConfigResponse {
+    batch_timeout_seconds:    Option<u64>,
+    batch_max_gas_price_gwei: Option<u64>,
+    added_provers:            Vec<ProverConfiguration>,
+    removed_provers:          Vec<ProverConfiguration>,
+}
+
+impl ToResponseCode for ReloadConfigResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchTimeoutResponse {
+    batch_timeout_seconds: u64,
+}
+
+impl ToResponseCode for BatchTimeoutResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResponse {
+    root:            Hash,
+    /// The ledger entry of the batch, absent for batches submitted before the
+    /// ledger existed.
+    batch:           Option<Batch>,
+    /// Every attempt at proving the batch that was rejected by a prover, oldest
+    /// first.
+    prover_failures: Vec<FailedBatch>,
+}
+
+impl ToResponseCode for BatchResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelBatchResponse {
+    root:     Hash,
+    /// The commitments moved back to the queue, in leaf order. These include
+    /// the identities inserted after the batch.
+    requeued: Vec<Hash>,
+}
+
+impl ToResponseCode for CancelBatchResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertIdentityResponse {
+    /// When the identity is expected to be included on chain.
+    estimated_inclusion_time: DateTime<Utc>,
+}
+
+impl ToResponseCode for InsertIdentityResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReserveIdentityResponse {
+    /// When the reservation expires unless it is confirmed.
+    expires_at: DateTime<Utc>,
+}
+
+impl ToResponseCode for ReserveIdentityResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SloReportResponse {
+    window_seconds: u64,
+    /// The percentiles of the seconds from receiving an identity to each stage
+    /// of its lifecycle, over the identities mined within the window.
+    stages:         Vec<StageLatency>,
+}
+
+impl ToResponseCode for SloReportResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityStatusResponse {
+    status:       Status,
+    /// The leaf of the identity, once it is in the tree.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    leaf_index:   Option<usize>,
+    received_at:  Option<DateTime<Utc>>,
+    /// When the identity was inserted into the tree.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pending_at:   Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mined_at:     Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message:      Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error:        Option<CommitmentError>,
+    /// The commitment as it was submitted, if it wasn't an element of the
+    /// field and was reduced.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reduced_from: Option<Hash>,
+}
+
+impl ToResponseCode for IdentityStatusResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CostsResponse {
+    window_seconds: u64,
+    /// The costs of the batches submitted within the window, per day and per
+    /// week, newest first. Only batches whose transactions are final are
+    /// counted.
+    daily:          Vec<BatchCosts>,
+    weekly:         Vec<BatchCosts>,
+}
+
+impl ToResponseCode for CostsResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogResponse {
+    /// Newest first.
+    entries: Vec<StoredAuditEntry>,
+    /// Pass as `before` to get the next page, if there may be more entries.
+    next:    Option<i64>,
+}
+
+impl ToResponseCode for AuditLogResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+/// How much of the quota of an API key is left in a period, as reported in
+/// the `X-Quota-*` headers of insertions.
+#[derive(Clone, Copy, Debug)]
+pub struct QuotaAllowance {
+    pub period:    QuotaPeriod,
+    pub limit:     u64,
+    pub remaining: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaUsage {
+    /// The limit that applies to the key, which is the default unless the key
+    /// has its own. Unlimited if not set.
+    limit:     Option<u64>,
+    /// The number of identities inserted with the key in the current period.
+    used:      u64,
+    /// When the current period ends and the quota is replenished.
+    resets_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyQuotaResponse {
+    /// The fingerprint of the key, as in the audit log.
+    api_key: String,
+    daily:   QuotaUsage,
+    monthly: QuotaUsage,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotasResponse {
+    quotas: Vec<ApiKeyQuotaResponse>,
+}
+
+impl ToResponseCode for QuotasResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+/// Whether batching is paused, and if so why and since when.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchingPauseResponse {
+    paused: bool,
+    #[serde(flatten)]
+    pause:  Option<BatchingPause>,
+}
+
+impl ToResponseCode for BatchingPauseResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BanCommitmentResponse {
+    commitment:          Hash,
+    /// The leaf of the commitment, if it is already in the tree.
+    leaf_index:          Option<usize>,
+    queued_for_deletion: bool,
+}
+
+impl ToResponseCode for BanCommitmentResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LatestRootResponse {
+    root:   Hash,
+    status: ProcessedStatus,
+}
+
+impl ToResponseCode for LatestRootResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootTransactionResponse {
+    #[serde(flatten)]
+    transaction:  RootTransaction,
+    /// The page of the transaction on the configured block explorer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explorer_url: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootResponse {
+    #[serde(flatten)]
+    state:        RootItem,
+    /// The propagation of the root to each secondary chain with a state
+    /// bridge. Empty until the root is final on mainnet.
+    propagations: Vec<RootPropagation>,
+    /// The transaction that published the root on mainnet, once it has been
+    /// seen.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transaction:  Option<RootTransactionResponse>,
+}
+
+impl ToResponseCode for RootResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncedLeaf {
+    leaf_index: usize,
+    commitment: Hash,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncedRoot {
+    root:       Hash,
+    leaf_index: usize,
+    status:     ProcessedStatus,
+}
+
+/// Changes since a mirror's checkpoint. Mirrors request the next page with
+/// `since_leaf` set to `nextLeaf` until `hasMore` is false.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncResponse {
+    /// Leaves inserted at or after `since_leaf`.
+    leaves:    Vec<SyncedLeaf>,
+    /// The roots after `since_root`, whose status may have changed, and the
+    /// roots of the new leaves.
+    roots:     Vec<SyncedRoot>,
+    /// The leaf indices among those that have been zeroed.
+    deletions: Vec<usize>,
+    next_leaf: usize,
+    has_more:  bool,
+}
+
+impl ToResponseCode for SyncResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+/// The part of a proof bundle that is covered by the sequencer's signature.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofBundle {
+    commitment:       Hash,
+    leaf_index:       usize,
+    status:           Status,
+    root:             Option<Hash>,
+    proof:            Option<Proof>,
+    /// The transaction that published `root` on mainnet, if it's been seen.
+    transaction_hash: Option<H256>,
+    block_number:     Option<u64>,
+}
+
+/// The part of an inclusion proof that is signed with
+/// `--sign-inclusion-proofs`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SignedInclusionProof<'a> {
+    root:      &'a Hash,
+    leaf:      &'a Hash,
+    proof:     &'a Proof,
+    timestamp: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofSignature {
+    /// When the proof was signed, in seconds since the epoch.
+    timestamp: i64,
+    /// The address that signed the JSON encoding of the root, the leaf, the
+    /// proof and the timestamp, in order, with an EIP-191 personal message
+    /// signature.
+    signer:    Address,
+    signature: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicKeyResponse {
+    address:    Address,
+    /// The hex encoded uncompressed public key.
+    public_key: String,
+}
+
+impl ToResponseCode for PublicKeyResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofBundleResponse {
+    #[serde(flatten)]
+    bundle:    ProofBundle,
+    /// The address that signed the JSON encoding of `bundle` with an EIP-191
+    /// personal message signature. Absent if no signing key is configured.
+    signer:    Option<Address>,
+    signature: Option<String>,
+}
+
+impl ToResponseCode for ProofBundleResponse {
+    fn to_response_code(&self) -> StatusCode {
+        StatusCode::OK
+    }
+}
+
 #[derive(Serialize)]
 #[serde(transparent)]
 pub struct VerifySemaphoreProofResponse(RootItem);
@@ -82,8 +657,8 @@ pub struct VerifySemaphoreProofResponse(RootItem);
 impl VerifySemaphoreProofResponse {
     #[must_use]
     pub fn hide_processed_status(mut self) -> Self {
-        self.0.status = if self.0.status == Status::Processed {
-            Status::Pending
+        self.0.status = if self.0.status == ProcessedStatus::Processed {
+            ProcessedStatus::Pending
         } else {
             self.0.status
         };
@@ -98,6 +673,84 @@ impl ToResponseCode for VerifySemaphoreProofResponse {
     }
 }
 
+#[derive(Debug, Error)]
+#[error("invalid deleted commitment policy {0:?}, expected `reject` or `allow`")]
+pub struct InvalidDeletedCommitmentPolicy(String);
+
+/// What to do with insertions of commitments that have been deleted before.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeletedCommitmentPolicy {
+    /// Reject them, as a re-inserted identity could produce the nullifiers of
+    /// the deleted one again.
+    Reject,
+    /// Insert them like any other commitment.
+    Allow,
+}
+
+impl FromStr for DeletedCommitmentPolicy {
+    type Err = InvalidDeletedCommitmentPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "allow" => Ok(Self::Allow),
+            _ => Err(InvalidDeletedCommitmentPolicy(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid unreduced commitment policy {0:?}, expected `reject` or `reduce`")]
+pub struct InvalidUnreducedCommitmentPolicy(String);
+
+/// What to do with commitments that aren't elements of the scalar field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnreducedCommitmentPolicy {
+    /// Reject them, so that clients notice that they compute commitments
+    /// incorrectly.
+    Reject,
+    /// Insert them reduced modulo the order of the field, which is the
+    /// element they stand for in the circuits, and record the reduction.
+    Reduce,
+}
+
+impl FromStr for UnreducedCommitmentPolicy {
+    type Err = InvalidUnreducedCommitmentPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "reduce" => Ok(Self::Reduce),
+            _ => Err(InvalidUnreducedCommitmentPolicy(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid mode {0:?}, expected `sequencer` or `observer`")]
+pub struct InvalidMode(String);
+
+/// Whether the sequencer accepts and submits identities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Sequencer,
+    /// Serve inclusion proofs and roots built from the database and the
+    /// chain, but refuse changes and never submit transactions.
+    Observer,
+}
+
+impl FromStr for Mode {
+    type Err = InvalidMode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequencer" => Ok(Self::Sequencer),
+            "observer" => Ok(Self::Observer),
+            _ => Err(InvalidMode(s.to_string())),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Parser)]
 #[group(skip)]
 pub struct Options {
@@ -116,6 +769,21 @@ pub struct Options {
     #[clap(flatten)]
     pub committer: task_monitor::Options,
 
+    #[clap(flatten)]
+    pub events: events::Options,
+
+    #[clap(flatten)]
+    pub leader: leader::Options,
+
+    #[clap(flatten)]
+    pub retention: retention::Options,
+
+    #[clap(flatten)]
+    pub standby: standby::Options,
+
+    #[clap(flatten)]
+    pub quotas: quotas::Options,
+
     /// Block number to start syncing from
     #[clap(long, env, default_value = "0")]
     pub starting_block: u64,
@@ -124,21 +792,158 @@ pub struct Options {
     #[clap(long, env, default_value = "120")]
     pub lock_timeout: u64,
 
-    /// The depth of the tree prefix that is vectorized.
+    /// The minimum depth of the tree prefix that is vectorized. It is grown on
+    /// startup to hold every mined leaf, as the vectorized prefix is hashed in
+    /// parallel while the leaves beyond it are applied one at a time.
     #[clap(long, env, default_value = "20")]
     pub dense_tree_prefix_depth: usize,
 
+    /// The file snapshots of the mined tree are written to. On startup the
+    /// tree is built from the snapshot and the leaves mined after it, if the
+    /// snapshot is still in line with the database.
+    #[clap(long, env)]
+    pub tree_snapshot_file: Option<PathBuf>,
+
+    /// How often to write a snapshot of the mined tree, if it changed
+    /// (seconds)
+    #[clap(long, env, default_value = "3600")]
+    pub tree_snapshot_interval_seconds: u64,
+
     /// The number of updates to trigger garbage collection.
     #[clap(long, env, default_value = "10000")]
     pub tree_gc_threshold: usize,
-}
 
-pub struct App {
-    database:           Arc<Database>,
-    identity_manager:   SharedIdentityManager,
+    /// The number of parallel queries loading the tree from the database at
+    /// startup, each reading a contiguous range of leaves.
+    #[clap(long, env, default_value = "8")]
+    pub restore_concurrency: usize,
+
+    /// The number of threads computing inclusion proofs and verifying semaphore
+    /// proofs, outside of the async executor. Defaults to one thread per CPU.
+    #[clap(long, env, default_value = "0")]
+    pub proof_workers: usize,
+
+    /// Pin every inclusion proof thread to a single CPU.
+    #[clap(long, env)]
+    pub proof_workers_cpu_pinning: bool,
+
+    /// The number of inclusion proofs cached for repeated requests. Zero
+    /// disables the cache.
+    #[clap(long, env, default_value = "10000")]
+    pub proof_cache_size: usize,
+
+    /// The hex encoded private key proof bundles are signed with. Bundles are
+    /// served unsigned if it's not set.
+    #[clap(long, env)]
+    pub proof_bundle_signing_key: Option<SecretString>,
+
+    /// Sign `/inclusionProof` responses with `--proof-bundle-signing-key`,
+    /// which is required then.
+    #[clap(long, env, default_value = "false")]
+    pub sign_inclusion_proofs: bool,
+
+    /// The URL of transactions on a block explorer, with `{}` in place of the
+    /// transaction hash, e.g. `https://etherscan.io/tx/{}`. Root transactions
+    /// link to it when set.
+    #[clap(long, env)]
+    pub explorer_transaction_url: Option<String>,
+
+    /// The number of leaves to keep free at the end of the tree. Insertions
+    /// are rejected once fewer leaves remain. Must be at least the largest
+    /// batch size, as batches are padded with zero leaves.
+    #[clap(long, env, default_value = "10000")]
+    pub tree_capacity_margin: usize,
+
+    /// The number of identities waiting to be inserted into the tree beyond
+    /// which insertions are refused with `503 Service Unavailable`. Unlimited
+    /// if not set.
+    #[clap(long, env)]
+    pub max_queue_depth: Option<usize>,
+
+    /// The `Retry-After` of insertions refused because the queue is full
+    /// (seconds)
+    #[clap(long, env, default_value = "30")]
+    pub queue_full_retry_after_seconds: u64,
+
+    /// Whether insertions of commitments that have been deleted before are
+    /// `reject`ed or `allow`ed.
+    #[clap(long, env, default_value = "reject")]
+    pub deleted_commitment_policy: DeletedCommitmentPolicy,
+
+    /// Whether commitments that aren't elements of the scalar field are
+    /// `reject`ed or `reduce`d modulo its order before they are queued.
+    #[clap(long, env, default_value = "reject")]
+    pub unreduced_commitment_policy: UnreducedCommitmentPolicy,
+
+    /// How long a reservation made through `/reserveIdentity` lasts unless
+    /// the request asks for another duration (seconds)
+    #[clap(long, env, default_value = "86400")]
+    pub reservation_ttl_seconds: u64,
+
+    /// The longest a reservation may last (seconds)
+    #[clap(long, env, default_value = "604800")]
+    pub max_reservation_ttl_seconds: u64,
+
+    /// Run as a replica serving proofs: keep the tree in sync by tailing this
+    /// logical replication slot of the identities table, instead of
+    /// committing identities. The slot is created if it doesn't exist and
+    /// requires `wal_level = logical`.
+    #[clap(long, env)]
+    pub replication_slot: Option<String>,
+
+    /// How often to poll the replication slot when there are no changes
+    /// (milliseconds)
+    #[clap(long, env, default_value = "200")]
+    pub replication_poll_interval_ms: u64,
+
+    /// What to do on startup when the database has identities processed or
+    /// mined after the latest root on chain: `repair` batches them again,
+    /// `refuse` refuses to start. Roots on chain that are not in the database
+    /// always refuse to start.
+    #[clap(long, env, default_value = "repair")]
+    pub startup_reconciliation: ReconciliationPolicy,
+
+    /// `sequencer` or `observer`. Observers serve proofs and roots built from
+    /// the database and the chain, without a signer. They never write to the
+    /// database or submit transactions, and refuse insertions, except for
+    /// standbys mirroring a primary into their database.
+    #[clap(long, env, default_value = "sequencer")]
+    pub mode: Mode,
+
+    /// How often an observer polls the database and the chain (seconds)
+    #[clap(long, env, default_value = "5")]
+    pub observer_poll_interval_seconds: u64,
+}
+
+pub struct App {
+    database: Arc<Database>,
+    identity_manager: SharedIdentityManager,
     identity_committer: Arc<TaskMonitor>,
-    tree_state:         TreeState,
-    snark_scalar_field: Hash,
+    tree_state: TreeState,
+    proof_workers: WorkerPool,
+    proof_cache: ProofCache,
+    inclusion_estimator: InclusionEstimator,
+    proof_bundle_key: Option<LocalWallet>,
+    sign_inclusion_proofs: bool,
+    explorer_transaction_url: Option<String>,
+    tree_capacity_margin: usize,
+    max_queue_depth: Option<usize>,
+    queue_full_retry_after: u64,
+    deleted_commitment_policy: DeletedCommitmentPolicy,
+    unreduced_commitment_policy: UnreducedCommitmentPolicy,
+    reservation_ttl: u64,
+    max_reservation_ttl: u64,
+    quotas: quotas::Options,
+    read_only: bool,
+    follower: Option<tokio::task::JoinHandle<()>>,
+    leader_lease: Option<Arc<LeaderLease>>,
+    elector: Option<tokio::task::JoinHandle<()>>,
+    snapshotter: Option<tokio::task::JoinHandle<()>>,
+    retention: Option<tokio::task::JoinHandle<()>>,
+    standby: Option<tokio::task::JoinHandle<()>>,
+    /// The config file of the group this app sequences, if it isn't the
+    /// default group, see [`crate::groups`].
+    group_config: Option<PathBuf>,
 }
 
 impl App {
@@ -148,42 +953,118 @@ impl App {
     /// `options.storage_file` is not accessible.
     #[instrument(name = "App::new", level = "debug")]
     pub async fn new(options: Options) -> AnyhowResult<Self> {
-        let ethereum = Ethereum::new(options.ethereum);
-        let db = Database::new(options.database);
+        #[cfg(not(feature = "submission"))]
+        ensure!(
+            options.mode == Mode::Observer,
+            "this build only serves proofs, run it with --mode observer or build it with the \
+             `submission` feature"
+        );
+
+        let is_standby = options.standby.standby_primary_database.is_some();
+        ensure!(
+            !is_standby || (options.mode == Mode::Observer && options.replication_slot.is_none()),
+            "--standby-primary-database requires --mode observer and no --replication-slot"
+        );
+
+        // Observers leave the schema to the sequencer, except for standbys,
+        // which have a database of their own.
+        let database = Arc::new(
+            Database::new(database::Options {
+                database_migrate: options.database.database_migrate
+                    && (options.mode != Mode::Observer || is_standby),
+                ..options.database.clone()
+            })
+            .await?,
+        );
+        let standby = Standby::new(database.clone(), &options.database, &options.standby).await?;
 
-        let (ethereum, db) = tokio::try_join!(ethereum, db)?;
+        let leader_lease = (options.leader.leader_election && options.mode == Mode::Sequencer)
+            .then(|| LeaderLease::new(database.clone(), &options.leader));
+        let is_leader = match &leader_lease {
+            Some(lease) => lease.try_acquire().await?,
+            None => true,
+        };
+        // Renewed from now on, as starting up may take longer than the lease.
+        let elector = leader_lease
+            .clone()
+            .map(|lease| tokio::spawn(lease.run(is_leader)));
+
+        // Only the leader batches and submits identities.
+        let observer = options.mode == Mode::Observer || !is_leader;
+        if !is_leader {
+            info!("Another instance is the leader, running as an observer");
+        }
 
-        let database = Arc::new(db);
-        let mut provers = database.get_provers().await?;
-        let non_inserted_provers = Self::merge_env_provers(options.batch_provers, &mut provers);
+        // Simulated batches never reach the chain, so the tree in a database a
+        // dry run wrote to can't be submitted for real.
+        if options.ethereum.dry_run {
+            warn!("Dry run: transactions are only simulated, the database is marked as simulated");
+            if !observer {
+                database.mark_dry_run().await?;
+            }
+        } else if !observer && database.is_dry_run().await? {
+            bail!(
+                "the database was written to by a dry run, whose batches never reached the chain, \
+                 so it can only be used for dry runs"
+            );
+        }
 
-        database.insert_provers(non_inserted_provers).await?;
+        // Locally held signing keys keep their nonces in the database.
+        #[cfg(feature = "submission")]
+        let ethereum = if observer {
+            Ethereum::new_read_only(options.ethereum).await?
+        } else {
+            Ethereum::new(options.ethereum, database.clone()).await?
+        };
+        #[cfg(not(feature = "submission"))]
+        let ethereum = Ethereum::new_read_only(options.ethereum).await?;
 
-        let insertion_prover_map = make_insertion_map(provers)?;
-        let identity_manager =
-            IdentityManager::new(options.contracts, ethereum.clone(), insertion_prover_map).await?;
+        #[cfg(feature = "submission")]
+        let identity_manager = {
+            let mut provers = database.get_provers().await?;
+            let non_inserted_provers = Self::merge_env_provers(options.batch_provers, &mut provers);
+
+            if !observer {
+                database.insert_provers(non_inserted_provers).await?;
+            }
+
+            let insertion_prover_map = make_insertion_map(provers)?;
+            IdentityManager::new(options.contracts, ethereum.clone(), insertion_prover_map).await?
+        };
+        #[cfg(not(feature = "submission"))]
+        let identity_manager = IdentityManager::new(options.contracts, ethereum.clone()).await?;
 
         let identity_manager = Arc::new(identity_manager);
 
         // Await for all pending transactions
-        identity_manager.await_clean_slate().await?;
+        if !observer {
+            identity_manager.await_clean_slate().await?;
+        }
 
         // Prefetch latest root & mark it as mined
         let root_hash = identity_manager.latest_root().await?;
         let root_hash = root_hash.into();
 
-        let initial_root_hash = LazyPoseidonTree::new(
+        let initial_root_hash = RootAccumulator::new(
             identity_manager.tree_depth(),
             identity_manager.initial_leaf_value(),
         )
         .root();
 
-        // We don't store the initial root in the database, so we have to skip this step
-        // if the contract root hash is equal to initial root hash
-        if root_hash != initial_root_hash {
-            // Note that we don't have a way of queuing a root here for finalization.
-            // so it's going to stay as "processed" until the next root is mined.
-            database.mark_root_as_processed(&root_hash).await?;
+        if let Some(slot) = &options.replication_slot {
+            // Changes made from now on are applied on top of the tree loaded
+            // below. Replicas leave the database to the primary.
+            database.reset_replication_slot(slot).await?;
+        } else if !observer {
+            reconciliation::reconcile(
+                &database,
+                root_hash,
+                initial_root_hash,
+                options.startup_reconciliation,
+            )
+            .await?;
+
+            Self::reconcile_batches(&database, &identity_manager).await?;
         }
 
         let timer = Instant::now();
@@ -193,7 +1074,9 @@ impl App {
             identity_manager.tree_depth(),
             options.dense_tree_prefix_depth,
             options.tree_gc_threshold,
+            options.restore_concurrency,
             identity_manager.initial_leaf_value(),
+            options.tree_snapshot_file.as_deref(),
         )
         .await?;
         info!("Tree state initialization took: {:?}", timer.elapsed());
@@ -203,18 +1086,70 @@ impl App {
             identity_manager.clone(),
             tree_state.clone(),
             &options.committer,
+            &options.events,
         ));
 
-        // TODO Export the reduced-ness check that this is enabling from the
-        //  `semaphore-rs` library when we bump the version.
-        let snark_scalar_field = Hash::from_str_radix(
-            "21888242871839275222246405745257275088548364400416034343698204186575808495617",
-            10,
-        )
-        .expect("This should just parse.");
+        if let Some(pause) = database.get_batching_pause().await? {
+            warn!(reason = ?pause.reason, paused_at = %pause.paused_at, "Batching is paused");
+            identity_committer.set_paused(true);
+        }
+
+        let proof_workers =
+            WorkerPool::new(options.proof_workers, options.proof_workers_cpu_pinning)?;
+
+        let proof_bundle_key = options
+            .proof_bundle_signing_key
+            .map(|key| key.expose().parse::<LocalWallet>())
+            .transpose()?;
+        ensure!(
+            !options.sign_inclusion_proofs || proof_bundle_key.is_some(),
+            "--sign-inclusion-proofs requires --proof-bundle-signing-key"
+        );
 
-        // Process to push new identities to Ethereum
-        identity_committer.start().await;
+        let follower = if let Some(slot) = options.replication_slot {
+            let follower = Follower::new(
+                database.clone(),
+                tree_state.clone(),
+                slot,
+                std::time::Duration::from_millis(options.replication_poll_interval_ms),
+            );
+            Some(tokio::spawn(follower.run()))
+        } else if observer {
+            let observer = Observer::new(
+                database.clone(),
+                identity_manager.clone(),
+                tree_state.clone(),
+                std::time::Duration::from_secs(options.observer_poll_interval_seconds),
+            );
+            Some(tokio::spawn(observer.run()))
+        } else {
+            // Process to push new identities to Ethereum
+            #[cfg(feature = "submission")]
+            identity_committer.start().await;
+            None
+        };
+
+        let snapshotter = options.tree_snapshot_file.map(|path| {
+            let snapshotter = Snapshotter::new(
+                tree_state.clone(),
+                path,
+                std::time::Duration::from_secs(options.tree_snapshot_interval_seconds),
+            );
+            tokio::spawn(snapshotter.run())
+        });
+
+        // Observers don't write to the database.
+        let retention = Retention::new(database.clone(), &options.retention)
+            .filter(|_| !observer)
+            .map(|retention| tokio::spawn(retention.run()));
+
+        // Mirrors the primary into the database the observer reads from.
+        let standby = standby.map(|standby| tokio::spawn(standby.run()));
+
+        let inclusion_estimator = InclusionEstimator::new(
+            database.clone(),
+            options.committer.submission_windows.clone(),
+        );
 
         // Sync with chain on start up
         let app = Self {
@@ -222,214 +1157,1751 @@ impl App {
             identity_manager,
             identity_committer,
             tree_state,
-            snark_scalar_field,
+            proof_workers,
+            proof_cache: ProofCache::new(options.proof_cache_size),
+            inclusion_estimator,
+            proof_bundle_key,
+            sign_inclusion_proofs: options.sign_inclusion_proofs,
+            explorer_transaction_url: options.explorer_transaction_url,
+            tree_capacity_margin: options.tree_capacity_margin,
+            max_queue_depth: options.max_queue_depth,
+            queue_full_retry_after: options.queue_full_retry_after_seconds,
+            deleted_commitment_policy: options.deleted_commitment_policy,
+            unreduced_commitment_policy: options.unreduced_commitment_policy,
+            reservation_ttl: options.reservation_ttl_seconds,
+            max_reservation_ttl: options.max_reservation_ttl_seconds.max(1),
+            quotas: options.quotas,
+            read_only: observer,
+            follower,
+            leader_lease,
+            elector,
+            snapshotter,
+            retention,
+            standby,
+            group_config: None,
         };
 
+        // Export the remaining capacity without waiting for the first insertion.
+        app.remaining_tree_capacity().await?;
+
         Ok(app)
     }
 
+    /// Brings the batch ledger in line with the chain after a restart.
+    ///
+    /// Pending transactions have been awaited by now, so submitted batches are
+    /// either mined or their transaction failed, in which case their identities
+    /// are batched again. Batches that were still being proven are abandoned
+    /// the same way. Proven batches are resumed by the identity processor.
+    async fn reconcile_batches(
+        database: &Database,
+        identity_manager: &IdentityManager,
+    ) -> AnyhowResult<()> {
+        for batch in database
+            .get_batches_by_status(BatchStatus::Submitted)
+            .await?
+        {
+            if identity_manager
+                .is_root_mined(batch.post_root.into())
+                .await?
+            {
+                info!(post_root = ?batch.post_root, "Submitted batch was mined during restart.");
+
+                database
+                    .update_batch_status(&batch.post_root, BatchStatus::Mined)
+                    .await?;
+                database.remove_prover_results(&batch.post_root).await?;
+            } else {
+                warn!(post_root = ?batch.post_root, "Submitted batch was not mined, batching its identities again.");
+
+                database
+                    .update_batch_status(&batch.post_root, BatchStatus::Failed)
+                    .await?;
+            }
+        }
+
+        for batch in database.get_batches_by_status(BatchStatus::Proving).await? {
+            warn!(post_root = ?batch.post_root, "Abandoning batch that was being proven.");
+
+            database
+                .update_batch_status(&batch.post_root, BatchStatus::Failed)
+                .await?;
+        }
+
+        Ok(())
+    }
+
     async fn initialize_tree(
         database: &Database,
         tree_depth: usize,
         dense_prefix_depth: usize,
         gc_threshold: usize,
+        restore_concurrency: usize,
         initial_leaf_value: Hash,
+        snapshot_file: Option<&Path>,
     ) -> AnyhowResult<TreeState> {
-        let mut mined_items = database.get_commitments_by_status(Status::Mined).await?;
+        let next_leaf = database
+            .get_max_leaf_index_by_status(ProcessedStatus::Mined)
+            .await?
+            .map_or(0, |leaf_index| leaf_index + 1);
+
+        // The dense prefix is hashed in parallel, while the leaves beyond it
+        // are applied one at a time, so it's grown to hold every mined leaf.
+        let min_dense_prefix_depth = dense_prefix_depth;
+        let dense_prefix_depth = dense_prefix_depth_for(dense_prefix_depth, tree_depth, next_leaf);
+        if dense_prefix_depth > min_dense_prefix_depth {
+            info!(dense_prefix_depth, "Growing the dense prefix");
+        }
 
-        let initial_leaves = if mined_items.is_empty() {
-            vec![]
+        let snapshot = match snapshot_file {
+            Some(snapshot_file) if next_leaf > 0 => {
+                Self::read_snapshot(database, snapshot_file, next_leaf).await?
+            }
+            _ => None,
+        };
+
+        let restored = match snapshot {
+            Some(mut leaves) => {
+                let from_snapshot = leaves.len();
+                leaves.extend(
+                    Self::load_mined_leaves(
+                        database,
+                        from_snapshot..next_leaf,
+                        restore_concurrency,
+                        initial_leaf_value,
+                    )
+                    .await?,
+                );
+
+                let restored = CanonicalTreeBuilder::new(
+                    tree_depth,
+                    dense_prefix_depth,
+                    gc_threshold,
+                    initial_leaf_value,
+                    &leaves,
+                );
+
+                Self::check_restored_tree(database, next_leaf, "snapshot", restored).await?
+            }
+            None => None,
+        };
+
+        let mined_builder = if let Some(restored) = restored {
+            restored
         } else {
-            mined_items.sort_by_key(|item| item.leaf_index);
+            let initial_leaves = Self::load_mined_leaves(
+                database,
+                0..next_leaf,
+                restore_concurrency,
+                initial_leaf_value,
+            )
+            .await?;
+
+            CanonicalTreeBuilder::new(
+                tree_depth,
+                dense_prefix_depth,
+                gc_threshold,
+                initial_leaf_value,
+                &initial_leaves,
+            )
+        };
+
+        let (mined, mut processed_builder) = mined_builder.seal();
+
+        let mut processed_items = database
+            .get_commitments_by_status(ProcessedStatus::Processed)
+            .await?;
+        processed_items.sort_by_key(|item| item.leaf_index);
+
+        for processed_item in processed_items {
+            processed_builder.update(&processed_item);
+        }
+
+        let (processed, batching_builder) = processed_builder.seal_and_continue();
+        let (batching, mut latest_builder) = batching_builder.seal_and_continue();
+
+        let pending_items = database
+            .get_commitments_by_status(ProcessedStatus::Pending)
+            .await?;
+        for update in pending_items {
+            latest_builder.update(&update);
+        }
+
+        let latest = latest_builder.seal();
+
+        Ok(TreeState::new(mined, processed, batching, latest))
+    }
 
-            let max_leaf = mined_items.last().map(|item| item.leaf_index).unwrap();
-            let mut leaves = vec![initial_leaf_value; max_leaf + 1];
+    /// Only accepts a tree restored from `source` if its root is the root
+    /// after the last mined leaf, as the file may have been left behind by
+    /// another deployment, before the database was repaired or before
+    /// identities were deleted.
+    async fn check_restored_tree(
+        database: &Database,
+        next_leaf: usize,
+        source: &str,
+        restored: CanonicalTreeBuilder,
+    ) -> AnyhowResult<Option<CanonicalTreeBuilder>> {
+        let identities = database
+            .get_identities(next_leaf - 1, Some(next_leaf), 1)
+            .await?;
+        let expected_root = identities.first().map(|identity| identity.root);
+
+        if expected_root != Some(restored.root()) {
+            warn!(
+                ?expected_root,
+                actual_root = ?restored.root(),
+                source,
+                "The restored tree doesn't match the database, rebuilding it"
+            );
+            return Ok(None);
+        }
+
+        info!(next_leaf, source, "Restored the tree");
+
+        Ok(Some(restored))
+    }
 
-            for item in mined_items {
-                leaves[item.leaf_index] = item.element;
+    /// Reads the leaves of the tree snapshot at `snapshot_file`, unless it is
+    /// missing, unreadable or doesn't match the database.
+    async fn read_snapshot(
+        database: &Database,
+        snapshot_file: &Path,
+        next_leaf: usize,
+    ) -> AnyhowResult<Option<Vec<Hash>>> {
+        let snapshot = match Snapshot::read(snapshot_file) {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => return Ok(None),
+            Err(error) => {
+                warn!(?error, "Failed to read the tree snapshot, ignoring it");
+                return Ok(None);
             }
+        };
+
+        let leaf_count = snapshot.leaves.len();
+        if leaf_count == 0 || leaf_count > next_leaf {
+            return Ok(None);
+        }
+
+        let identities = database
+            .get_identities(leaf_count - 1, Some(leaf_count), 1)
+            .await?;
+        if identities.first().map(|identity| identity.root) != Some(snapshot.root) {
+            warn!(
+                leaf_count,
+                "The tree snapshot doesn't match the database, ignoring it"
+            );
+            return Ok(None);
+        }
+
+        Ok(Some(snapshot.leaves))
+    }
+
+    /// Loads the mined leaves in `leaves` by splitting them into `concurrency`
+    /// ranges that are queried in parallel.
+    async fn load_mined_leaves(
+        database: &Database,
+        leaves: Range<usize>,
+        concurrency: usize,
+        initial_leaf_value: Hash,
+    ) -> AnyhowResult<Vec<Hash>> {
+        if leaves.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let concurrency = concurrency.max(1);
+        let chunk_size = (leaves.len() + concurrency - 1) / concurrency;
+
+        let chunks = try_join_all(leaves.clone().step_by(chunk_size).map(|start| {
+            let end = (start + chunk_size).min(leaves.end);
+            database.get_commitments_by_status_in_range(ProcessedStatus::Mined, start..end)
+        }))
+        .await?;
+
+        let mut loaded = vec![initial_leaf_value; leaves.len()];
+        for item in chunks.into_iter().flatten() {
+            loaded[item.leaf_index - leaves.start] = item.element;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Queues an insert into the merkle tree.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if identity is already queued, or in the tree, or the
+    /// queue malfunctions.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn insert_identity(
+        &self,
+        commitment: Hash,
+        request_id: Option<&str>,
+    ) -> Result<InsertIdentityResponse, ServerError> {
+        self.ensure_writable()?;
+
+        let original = commitment;
+        let commitment = self.reduce_commitment(commitment)?;
+
+        if commitment == self.identity_manager.initial_leaf_value() {
+            warn!(?commitment, "Attempt to insert initial leaf.");
+            return Err(ServerError::InvalidCommitment);
+        }
+
+        self.ensure_provers(&commitment).await?;
+        self.check_queue_depth().await?;
+        self.check_insertable(&commitment).await?;
+
+        if self.database.is_commitment_reserved(&commitment).await? {
+            warn!(?commitment, "Attempt to insert a reserved commitment.");
+            return Err(ServerError::CommitmentReserved);
+        }
+
+        self.check_tree_capacity().await?;
+
+        self.database
+            .insert_new_identity(commitment, request_id)
+            .await?;
+
+        self.queued(commitment, original).await
+    }
+
+    /// Reserves a slot in the tree for `commitment` for `ttl_seconds`, up to
+    /// `--max-reservation-ttl-seconds`, without queueing it, e.g. while the
+    /// identity is being verified. The commitment is queued once the
+    /// reservation is confirmed with [`Self::confirm_identity`].
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the commitment can't be inserted, is already
+    /// reserved, or the tree is full.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn reserve_identity(
+        &self,
+        commitment: Hash,
+        ttl_seconds: Option<u64>,
+    ) -> Result<ReserveIdentityResponse, ServerError> {
+        self.ensure_writable()?;
+
+        let commitment = self.reduce_commitment(commitment)?;
+
+        if commitment == self.identity_manager.initial_leaf_value() {
+            warn!(?commitment, "Attempt to reserve initial leaf.");
+            return Err(ServerError::InvalidCommitment);
+        }
+
+        self.check_insertable(&commitment).await?;
+        self.check_tree_capacity().await?;
+
+        let ttl_seconds = ttl_seconds
+            .unwrap_or(self.reservation_ttl)
+            .clamp(1, self.max_reservation_ttl);
+        let expires_at = Utc::now() + Duration::seconds(ttl_seconds.try_into().unwrap_or(i64::MAX));
+
+        if !self
+            .database
+            .reserve_identity(&commitment, expires_at)
+            .await?
+        {
+            return Err(ServerError::CommitmentReserved);
+        }
+
+        RESERVED_IDENTITIES.inc();
+
+        Ok(ReserveIdentityResponse { expires_at })
+    }
+
+    /// Queues the reserved `commitment` for insertion.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the commitment isn't reserved or its reservation
+    /// expired.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn confirm_identity(
+        &self,
+        commitment: Hash,
+        request_id: Option<&str>,
+    ) -> Result<InsertIdentityResponse, ServerError> {
+        self.ensure_writable()?;
+
+        let original = commitment;
+        let commitment = self.reduce_commitment(commitment)?;
+
+        self.ensure_provers(&commitment).await?;
+        self.check_queue_depth().await?;
+
+        // The slot was taken when the commitment was reserved, so the
+        // capacity of the tree isn't checked again.
+        if !self
+            .database
+            .confirm_reservation(&commitment, request_id)
+            .await?
+        {
+            return Err(ServerError::ReservationNotFound);
+        }
+
+        self.queued(commitment, original).await
+    }
+
+    /// Reduces `commitment` according to `--unreduced-commitment-policy`.
+    fn reduce_commitment(&self, commitment: Hash) -> Result<Hash, ServerError> {
+        if is_reduced(&commitment) {
+            Ok(commitment)
+        } else if self.unreduced_commitment_policy == UnreducedCommitmentPolicy::Reduce {
+            let reduced = reduce(commitment);
+            info!(
+                ?commitment,
+                ?reduced,
+                "Reducing a commitment that is not an element of the field."
+            );
+            Ok(reduced)
+        } else {
+            warn!(
+                ?commitment,
+                "The provided commitment is not an element of the field."
+            );
+            Err(ServerError::UnreducedCommitment)
+        }
+    }
+
+    async fn ensure_provers(&self, commitment: &Hash) -> Result<(), ServerError> {
+        if !self.identity_manager.has_provers().await {
+            warn!(
+                ?commitment,
+                "Identity Manager has no provers. Add provers with /addBatchSize request."
+            );
+            return Err(ServerError::NoProversOnIdInsert);
+        }
+
+        Ok(())
+    }
+
+    /// Rejects commitments that are already in the tree, or may not be
+    /// inserted because they were deleted or banned.
+    async fn check_insertable(&self, commitment: &Hash) -> Result<(), ServerError> {
+        match self.database.find_commitment(commitment).await? {
+            // Whether a failed insertion may be replaced is decided when the
+            // commitment is queued, according to `--duplicate-window-seconds`.
+            None | Some(Status::Unprocessed(UnprocessedStatus::Failed)) => {}
+            Some(status) => {
+                warn!(?commitment, %status, "Attempt to insert a duplicate commitment.");
+                return Err(ServerError::DuplicateCommitment { status });
+            }
+        }
+
+        if self.deleted_commitment_policy == DeletedCommitmentPolicy::Reject
+            && self.database.is_commitment_deleted(commitment).await?
+        {
+            warn!(?commitment, "Attempt to re-insert a deleted commitment.");
+            return Err(ServerError::DeletedCommitment);
+        }
+
+        if self.database.is_commitment_banned(commitment).await? {
+            warn!(?commitment, "Attempt to insert a banned commitment.");
+            return Err(ServerError::BannedCommitment);
+        }
+
+        Ok(())
+    }
+
+    async fn check_tree_capacity(&self) -> Result<(), ServerError> {
+        let remaining_capacity = self.remaining_tree_capacity().await?;
+        if remaining_capacity <= self.tree_capacity_margin {
+            error!(
+                remaining_capacity,
+                margin = self.tree_capacity_margin,
+                "The tree is full, rejecting insertions."
+            );
+            return Err(ServerError::TreeFull);
+        }
+
+        Ok(())
+    }
+
+    /// Records a freshly queued `commitment` and estimates when it is
+    /// included.
+    async fn queued(
+        &self,
+        commitment: Hash,
+        original: Hash,
+    ) -> Result<InsertIdentityResponse, ServerError> {
+        if commitment != original {
+            self.database
+                .record_commitment_reduction(&commitment, &original)
+                .await?;
+        }
+
+        INSERTED_IDENTITIES.inc();
+
+        let position = self.queued_position().await?;
+        let estimated_inclusion_time = self.estimate_inclusion_time(position).await?;
+
+        Ok(InsertIdentityResponse {
+            estimated_inclusion_time,
+        })
+    }
+
+    /// The number of identities not on chain yet, counting the queue. An
+    /// identity queued now is included after all of them.
+    async fn queued_position(&self) -> Result<usize, ServerError> {
+        let queued = usize::try_from(self.database.count_unprocessed_identities().await?)
+            .unwrap_or_default();
+        let in_tree = self
+            .tree_state
+            .get_latest_tree()
+            .next_leaf()
+            .saturating_sub(self.tree_state.get_processed_tree().next_leaf());
+
+        Ok(in_tree + queued)
+    }
+
+    async fn estimate_inclusion_time(&self, position: usize) -> Result<DateTime<Utc>, ServerError> {
+        let max_batch_size = self.identity_manager.max_batch_size().await;
+        let batch_timeout = self.identity_committer.batch_timeout();
 
-            leaves
+        Ok(self
+            .inclusion_estimator
+            .estimate(position, max_batch_size, batch_timeout)
+            .await?)
+    }
+
+    /// Refuses insertions while more than `--max-queue-depth` identities are
+    /// waiting to be inserted, e.g. because the provers are down, instead of
+    /// queueing them for ever longer.
+    async fn check_queue_depth(&self) -> Result<(), ServerError> {
+        let Some(max_queue_depth) = self.max_queue_depth else {
+            return Ok(());
         };
 
-        let mined_builder = CanonicalTreeBuilder::new(
-            tree_depth,
-            dense_prefix_depth,
-            gc_threshold,
-            initial_leaf_value,
-            &initial_leaves,
-        );
+        let depth =
+            usize::try_from(self.database.count_queued_identities().await?).unwrap_or_default();
+        INSERTION_QUEUE_DEPTH.set(depth.try_into().unwrap_or(i64::MAX));
+        #[allow(clippy::cast_precision_loss)]
+        let saturation = depth as f64 / max_queue_depth.max(1) as f64;
+        INSERTION_QUEUE_SATURATION.set(saturation);
+
+        if depth >= max_queue_depth {
+            warn!(
+                depth,
+                max_queue_depth, "The queue is full, refusing insertions."
+            );
+            return Err(ServerError::QueueFull {
+                retry_after_seconds: self.queue_full_retry_after,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// The number of leaves left in the tree after all the queued and reserved
+    /// identities have been inserted.
+    async fn remaining_tree_capacity(&self) -> Result<usize, ServerError> {
+        let capacity = 1_usize << self.identity_manager.tree_depth();
+        let inserted = self.tree_state.get_latest_tree().next_leaf();
+        let queued = usize::try_from(self.database.count_unprocessed_identities().await?)
+            .unwrap_or_default();
+        let reserved =
+            usize::try_from(self.database.count_reservations().await?).unwrap_or_default();
+
+        let remaining = capacity.saturating_sub(inserted + queued + reserved);
+        REMAINING_TREE_CAPACITY.set(remaining.try_into().unwrap_or(i64::MAX));
+
+        Ok(remaining)
+    }
+
+    #[cfg(feature = "submission")]
+    pub(crate) fn merge_env_provers(
+        options: batch_insertion::Options,
+        existing_provers: &mut Provers,
+    ) -> Provers {
+        let options_set: HashSet<DbProverConf> = options
+            .prover_urls
+            .0
+            .into_iter()
+            .map(|opt| DbProverConf {
+                url:        opt.url,
+                batch_size: opt.batch_size,
+                timeout_s:  opt.timeout_s,
+            })
+            .collect();
+
+        let env_provers: HashSet<_> = options_set.difference(existing_provers).cloned().collect();
+
+        for unique in &env_provers {
+            existing_provers.insert(unique.clone());
+        }
+
+        env_provers
+    }
+
+    /// Maps `commitment` to the commitment it is stored as. Unreduced
+    /// commitments are only stored reduced under the `reduce` policy.
+    fn stored_commitment(&self, commitment: &Hash) -> Hash {
+        if self.unreduced_commitment_policy == UnreducedCommitmentPolicy::Reduce {
+            reduce(*commitment)
+        } else {
+            *commitment
+        }
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the provided batch size already exists.
+    /// Will return `Err` if the batch size fails to write to database.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn add_batch_size(
+        &self,
+        url: String,
+        batch_size: usize,
+        timeout_seconds: u64,
+    ) -> Result<(), ServerError> {
+        self.ensure_writable()?;
+
+        self.identity_manager
+            .add_batch_size(&url, batch_size, timeout_seconds)
+            .await?;
+
+        self.database
+            .insert_prover_configuration(batch_size, url, timeout_seconds)
+            .await?;
+
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if the requested batch size does not exist.
+    /// Will return `Err` if batch size fails to be removed from database.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn remove_batch_size(
+        &self,
+        batch_size: usize,
+        url: Option<String>,
+    ) -> Result<(), ServerError> {
+        self.ensure_writable()?;
+
+        self.identity_manager
+            .remove_batch_size(batch_size, url.as_deref())
+            .await?;
+
+        if let Some(url) = url {
+            self.database.remove_prover_url(batch_size, &url).await?;
+        } else {
+            self.database.remove_prover(batch_size).await?;
+        }
+
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Will return `Err` if something unknown went wrong.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn list_batch_sizes(&self) -> Result<ListBatchSizesResponse, ServerError> {
+        let batches = self.identity_manager.list_batch_sizes().await?;
+
+        Ok(ListBatchSizesResponse::from(batches))
+    }
+
+    /// Lists the events that could not be delivered to the webhook.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the events can't be read from the database.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn dead_letter_events(&self) -> Result<DeadLetterEventsResponse, ServerError> {
+        let events = self.database.replica().get_dead_events().await?;
+
+        Ok(DeadLetterEventsResponse(events))
+    }
+
+    /// Moves all dead-letter events back to delivery.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the events can't be updated in the database.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn redrive_dead_letter_events(&self) -> Result<RedriveEventsResponse, ServerError> {
+        self.ensure_writable()?;
+
+        let redriven = self.database.redrive_dead_events().await?;
+
+        info!(redriven, "Redriving dead-letter events.");
+
+        Ok(RedriveEventsResponse { redriven })
+    }
+
+    /// Refuses changes in observer mode.
+    fn ensure_writable(&self) -> Result<(), ServerError> {
+        if self.read_only {
+            return Err(ServerError::ObserverMode);
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the sequencer is ready to serve requests.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the identity manager root diverged from the
+    /// processed root.
+    pub fn readiness(&self) -> Result<(), ServerError> {
+        if self.identity_committer.is_root_mismatched() {
+            return Err(ServerError::RootMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Updates the metrics that are only computed when they are scraped.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database fails.
+    pub async fn update_metrics(&self) -> Result<(), ServerError> {
+        let versions = [
+            ("mined", self.tree_state.get_mined_tree().next_leaf()),
+            (
+                "processed",
+                self.tree_state.get_processed_tree().next_leaf(),
+            ),
+            ("batching", self.tree_state.get_batching_tree().next_leaf()),
+            ("latest", self.tree_state.get_latest_tree().next_leaf()),
+        ];
+        for (version, leaves) in versions {
+            TREE_LEAVES
+                .with_label_values(&[version])
+                .set(leaves.try_into().unwrap_or(i64::MAX));
+        }
+
+        let mined_root = self.tree_state.get_mined_tree().get_root();
+        let mined_at = self
+            .database
+            .get_root_state(&mined_root)
+            .await?
+            .and_then(|root| root.mined_valid_as_of);
+        if let Some(mined_at) = mined_at {
+            MINED_ROOT_AGE.set((chrono::Utc::now() - mined_at).num_seconds());
+        }
+
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn scheduled_jobs(&self) -> ScheduledJobsResponse {
+        ScheduledJobsResponse(self.identity_committer.scheduled_jobs())
+    }
+
+    #[must_use]
+    pub fn tasks(&self) -> TasksResponse {
+        TasksResponse(self.identity_committer.tasks())
+    }
+
+    #[must_use]
+    pub fn batch_timeout(&self) -> BatchTimeoutResponse {
+        BatchTimeoutResponse {
+            batch_timeout_seconds: self.identity_committer.batch_timeout().as_secs(),
+        }
+    }
+
+    /// Changes how long to wait before submitting a batch that is not full.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the timeout is zero.
+    pub fn set_batch_timeout(&self, batch_timeout_seconds: u64) -> Result<(), ServerError> {
+        self.ensure_writable()?;
+
+        if batch_timeout_seconds == 0 {
+            return Err(ServerError::InvalidBatchTimeout);
+        }
+
+        self.identity_committer
+            .set_batch_timeout(std::time::Duration::from_secs(batch_timeout_seconds));
+
+        Ok(())
+    }
+
+    /// Makes the app the app of a group configured in `path`, whose options are
+    /// reloaded from it too.
+    #[must_use]
+    pub fn with_group_config(mut self, path: PathBuf) -> Self {
+        self.group_config = Some(path);
+        self
+    }
+
+    /// Reads the config file again and applies the batch timeout, the maximum
+    /// gas price and the provers in it. Provers that are no longer in the file
+    /// are removed after the new ones are added, so that there is always a
+    /// prover for some batch size. The other options only change on restart.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the config file can't be loaded, or if a setting
+    /// in it can't be applied. Settings applied before that are kept.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn reload_config(&self) -> Result<ReloadConfigResponse, ServerError> {
+        self.ensure_writable()?;
+
+        let reloadable = config::reload(self.group_config.as_deref())
+            .map_err(|error| ServerError::InvalidConfig(format!("{error:#}")))?;
+
+        if let Some(batch_timeout_seconds) = reloadable.batch_timeout_seconds {
+            self.set_batch_timeout(batch_timeout_seconds)?;
+        }
+
+        if let Some(max_gas_price_gwei) = reloadable.batch_max_gas_price_gwei {
+            self.identity_committer
+                .set_max_gas_price(Some(max_gas_price_gwei));
+        }
+
+        let mut added_provers = vec![];
+        let mut removed_provers = vec![];
+        if let Some(provers) = reloadable.prover_urls {
+            let current = self.identity_manager.list_batch_sizes().await?;
+
+            for prover in provers.iter().filter(|prover| !current.contains(prover)) {
+                // A prover whose timeout changed has to be replaced.
+                if current.iter().any(|existing| {
+                    existing.url == prover.url && existing.batch_size == prover.batch_size
+                }) {
+                    self.remove_batch_size(prover.batch_size, Some(prover.url.clone()))
+                        .await?;
+                }
+                self.add_batch_size(prover.url.clone(), prover.batch_size, prover.timeout_s)
+                    .await?;
+                added_provers.push(prover.clone());
+            }
+
+            for prover in current {
+                let kept = provers
+                    .iter()
+                    .any(|new| new.url == prover.url && new.batch_size == prover.batch_size);
+                if !kept {
+                    self.remove_batch_size(prover.batch_size, Some(prover.url.clone()))
+                        .await?;
+                    removed_provers.push(prover);
+                }
+            }
+        }
+
+        info!(
+            batch_timeout_seconds = ?reloadable.batch_timeout_seconds,
+            batch_max_gas_price_gwei = ?reloadable.batch_max_gas_price_gwei,
+            added_provers = added_provers.len(),
+            removed_provers = removed_provers.len(),
+            "Reloaded the config file."
+        );
+
+        Ok(ReloadConfigResponse {
+            batch_timeout_seconds: reloadable.batch_timeout_seconds,
+            batch_max_gas_price_gwei: reloadable.batch_max_gas_price_gwei,
+            added_provers,
+            removed_provers,
+        })
+    }
+
+    /// Returns the record of the batch resulting in `root`, including the full
+    /// response of any prover that rejected it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the failures can't be read from the database.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn batch(&self, root: &Hash) -> Result<BatchResponse, ServerError> {
+        let batch = self.database.replica().get_batch(root).await?;
+        let prover_failures = self.database.replica().get_failed_batches(root).await?;
+
+        Ok(BatchResponse {
+            root: *root,
+            batch,
+            prover_failures,
+        })
+    }
+
+    /// Cancels the batch resulting in `root` before it is submitted. Its
+    /// identities, and any inserted after them, are removed from the tree and
+    /// queued again.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if there is no such batch, or it or a later batch has
+    /// already been submitted.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn cancel_batch(&self, root: &Hash) -> Result<CancelBatchResponse, ServerError> {
+        self.ensure_writable()?;
+
+        let batch = self
+            .database
+            .get_batch(root)
+            .await?
+            .ok_or(ServerError::BatchNotFound)?;
+
+        if !matches!(batch.status, BatchStatus::Proving | BatchStatus::Proven) {
+            return Err(ServerError::BatchNotCancellable);
+        }
+
+        let requeued = self
+            .identity_committer
+            .cancel_batch(&batch)
+            .await
+            .map_err(|error| match error.downcast::<database::Error>() {
+                Ok(error) => ServerError::from(error),
+                Err(error) => ServerError::from(error),
+            })?
+            .ok_or(ServerError::BatchNotCancellable)?;
+
+        Ok(CancelBatchResponse {
+            root: *root,
+            requeued,
+        })
+    }
+
+    /// Stops building and submitting batches until [`Self::resume_batching`]
+    /// is called, also across restarts. Identities are still accepted and
+    /// queued, and submitted batches are still mined.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if this sequencer is an observer or the database
+    /// fails.
+    pub async fn pause_batching(
+        &self,
+        reason: Option<String>,
+    ) -> Result<BatchingPauseResponse, ServerError> {
+        self.ensure_writable()?;
+
+        self.database.pause_batching(reason.as_deref()).await?;
+        self.identity_committer.set_paused(true);
+
+        warn!(?reason, "Paused batching");
+
+        self.batching_pause().await
+    }
+
+    /// Resumes building and submitting batches.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if this sequencer is an observer or the database
+    /// fails.
+    pub async fn resume_batching(&self) -> Result<BatchingPauseResponse, ServerError> {
+        self.ensure_writable()?;
+
+        if self.database.resume_batching().await? {
+            info!("Resumed batching");
+        }
+        self.identity_committer.set_paused(false);
+
+        self.batching_pause().await
+    }
+
+    /// Whether batching is paused.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database fails.
+    pub async fn batching_pause(&self) -> Result<BatchingPauseResponse, ServerError> {
+        let pause = self.database.get_batching_pause().await?;
+
+        Ok(BatchingPauseResponse {
+            paused: pause.is_some(),
+            pause,
+        })
+    }
+
+    /// Bans `commitment` from being inserted and drops it from the insertion
+    /// queue. If it is already in the tree, its leaf is queued for deletion
+    /// when `queue_deletion` is set.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if this sequencer is an observer or the database
+    /// fails.
+    pub async fn ban_commitment(
+        &self,
+        commitment: Hash,
+        reason: Option<String>,
+        queue_deletion: bool,
+    ) -> Result<BanCommitmentResponse, ServerError> {
+        self.ensure_writable()?;
+
+        let commitment = self.stored_commitment(&commitment);
+
+        self.database
+            .ban_commitment(&commitment, reason.as_deref())
+            .await?;
+        self.database
+            .remove_unprocessed_identity(&commitment)
+            .await?;
+
+        let leaf_index = self
+            .database
+            .get_identity_leaf_index(&commitment)
+            .await?
+            .map(|item| item.leaf_index);
+
+        let queued_for_deletion = queue_deletion && leaf_index.is_some();
+        if let (Some(leaf_index), true) = (leaf_index, queue_deletion) {
+            self.database
+                .queue_deletion(&commitment, leaf_index)
+                .await?;
+        }
+
+        warn!(?commitment, ?reason, "Banned a commitment");
 
-        let (mined, mut processed_builder) = mined_builder.seal();
+        Ok(BanCommitmentResponse {
+            commitment,
+            leaf_index,
+            queued_for_deletion,
+        })
+    }
 
-        let mut processed_items = database
-            .get_commitments_by_status(Status::Processed)
+    /// Reports how long identities mined in the last `window_seconds` took to
+    /// reach each stage of their lifecycle.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database fails.
+    pub async fn slo_report(&self, window_seconds: u64) -> Result<SloReportResponse, ServerError> {
+        let stages = self
+            .database
+            .replica()
+            .get_stage_latencies(std::time::Duration::from_secs(window_seconds))
             .await?;
-        processed_items.sort_by_key(|item| item.leaf_index);
 
-        for processed_item in processed_items {
-            processed_builder.update(&processed_item);
-        }
+        Ok(SloReportResponse {
+            window_seconds,
+            stages,
+        })
+    }
 
-        let (processed, batching_builder) = processed_builder.seal_and_continue();
-        let (batching, mut latest_builder) = batching_builder.seal_and_continue();
+    /// Reports the gas costs of the batches submitted in the last
+    /// `window_seconds`, per day and per week.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database fails.
+    pub async fn costs(&self, window_seconds: u64) -> Result<CostsResponse, ServerError> {
+        let window = std::time::Duration::from_secs(window_seconds);
+        let database = self.database.replica();
+
+        Ok(CostsResponse {
+            window_seconds,
+            daily: database.get_batch_costs("day", window).await?,
+            weekly: database.get_batch_costs("week", window).await?,
+        })
+    }
 
-        let pending_items = database.get_commitments_by_status(Status::Pending).await?;
-        for update in pending_items {
-            latest_builder.update(&update);
+    /// Records a mutating API call in the audit log. Observers don't write to
+    /// the database, and reject such calls anyway.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database fails.
+    pub async fn record_audit_entry(&self, entry: &AuditEntry) -> Result<(), ServerError> {
+        if self.read_only {
+            return Ok(());
         }
 
-        let latest = latest_builder.seal();
+        self.database.insert_audit_entry(entry).await?;
 
-        Ok(TreeState::new(mined, processed, batching, latest))
+        Ok(())
     }
 
-    /// Queues an insert into the merkle tree.
+    /// Returns the audit entries before the entry `before`, newest first.
     ///
     /// # Errors
     ///
-    /// Will return `Err` if identity is already queued, or in the tree, or the
-    /// queue malfunctions.
-    #[instrument(level = "debug", skip(self))]
-    pub async fn insert_identity(&self, commitment: Hash) -> Result<(), ServerError> {
-        if commitment == self.identity_manager.initial_leaf_value() {
-            warn!(?commitment, "Attempt to insert initial leaf.");
-            return Err(ServerError::InvalidCommitment);
-        }
+    /// Will return `Err` if the database fails.
+    pub async fn audit_log(
+        &self,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<AuditLogResponse, ServerError> {
+        let limit = limit.clamp(1, MAX_AUDIT_PAGE_SIZE);
 
-        if !self.identity_manager.has_provers().await {
-            warn!(
-                ?commitment,
-                "Identity Manager has no provers. Add provers with /addBatchSize request."
-            );
-            return Err(ServerError::NoProversOnIdInsert);
-        }
+        let entries = self
+            .database
+            .replica()
+            .get_audit_entries(before, limit)
+            .await?;
+        let next = (entries.len() == limit)
+            .then(|| entries.last().map(|entry| entry.id))
+            .flatten();
 
-        if !self.identity_is_reduced(commitment) {
-            warn!(
-                ?commitment,
-                "The provided commitment is not an element of the field."
-            );
-            return Err(ServerError::UnreducedCommitment);
+        Ok(AuditLogResponse { entries, next })
+    }
+
+    /// Counts an insertion made with `api_key` against its daily and monthly
+    /// quotas. Returns what is left of the limited ones.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if one of the quotas is used up.
+    pub async fn consume_insert_quota(
+        &self,
+        api_key: &str,
+    ) -> Result<Vec<QuotaAllowance>, ServerError> {
+        // Observers refuse insertions anyway.
+        if self.read_only {
+            return Ok(vec![]);
         }
 
-        let identity_exists = self.database.identity_exists(commitment).await?;
-        if identity_exists {
-            return Err(ServerError::DuplicateCommitment);
+        let now = Utc::now();
+        let periods = quotas::current_periods(now);
+        let (daily_limit, monthly_limit) = self.quota_limits(api_key, periods).await?;
+
+        let (daily, monthly) = match self
+            .database
+            .consume_insert_quota(api_key, periods, daily_limit, monthly_limit)
+            .await?
+        {
+            QuotaConsumption::Consumed { daily, monthly } => (daily, monthly),
+            QuotaConsumption::Exceeded(period) => {
+                let limit = match period {
+                    QuotaPeriod::Day => daily_limit,
+                    QuotaPeriod::Month => monthly_limit,
+                }
+                .unwrap_or_default();
+                let retry_after = quotas::period_end(period, now) - now;
+
+                warn!(api_key, %period, limit, "Insertion quota exceeded");
+                return Err(ServerError::QuotaExceeded {
+                    period,
+                    limit,
+                    retry_after_seconds: retry_after.num_seconds().try_into().unwrap_or_default(),
+                });
+            }
+        };
+
+        Ok([
+            (QuotaPeriod::Day, daily_limit, daily),
+            (QuotaPeriod::Month, monthly_limit, monthly),
+        ]
+        .into_iter()
+        .filter_map(|(period, limit, used)| {
+            limit.map(|limit| QuotaAllowance {
+                period,
+                limit,
+                remaining: limit.saturating_sub(used),
+            })
+        })
+        .collect())
+    }
+
+    /// Takes back an insertion counted with [`Self::consume_insert_quota`]
+    /// that failed, so that only successful insertions are counted.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database fails.
+    pub async fn refund_insert_quota(&self, api_key: &str) -> Result<(), ServerError> {
+        if self.read_only {
+            return Ok(());
         }
 
-        self.database.insert_new_identity(commitment).await?;
+        let periods = quotas::current_periods(Utc::now());
+        self.database.refund_insert_quota(api_key, periods).await?;
 
         Ok(())
     }
 
-    fn merge_env_provers(
-        options: batch_insertion::Options,
-        existing_provers: &mut Provers,
-    ) -> Provers {
-        let options_set: HashSet<DbProverConf> = options
-            .prover_urls
-            .0
+    /// The daily and monthly limits of `api_key`, falling back to the
+    /// defaults.
+    async fn quota_limits(
+        &self,
+        api_key: &str,
+        periods: (NaiveDate, NaiveDate),
+    ) -> Result<(Option<u64>, Option<u64>), ServerError> {
+        let quota = self
+            .database
+            .get_api_key_quotas(Some(api_key), periods)
+            .await?
             .into_iter()
-            .map(|opt| DbProverConf {
-                url:        opt.url,
-                batch_size: opt.batch_size,
-                timeout_s:  opt.timeout_s,
+            .next();
+
+        Ok((
+            quota
+                .as_ref()
+                .and_then(|quota| quota.daily_limit)
+                .or(self.quotas.default_daily_insert_quota),
+            quota
+                .as_ref()
+                .and_then(|quota| quota.monthly_limit)
+                .or(self.quotas.default_monthly_insert_quota),
+        ))
+    }
+
+    /// Returns the quotas and current usage of the API keys that have a quota
+    /// of their own or were used this month, or only of `api_key`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database fails.
+    pub async fn quotas(&self, api_key: Option<&str>) -> Result<QuotasResponse, ServerError> {
+        let now = Utc::now();
+        let periods = quotas::current_periods(now);
+
+        let quotas = self
+            .database
+            .get_api_key_quotas(api_key, periods)
+            .await?
+            .into_iter()
+            .map(|quota| ApiKeyQuotaResponse {
+                api_key: quota.api_key,
+                daily:   QuotaUsage {
+                    limit:     quota.daily_limit.or(self.quotas.default_daily_insert_quota),
+                    used:      quota.daily_usage,
+                    resets_at: quotas::period_end(QuotaPeriod::Day, now),
+                },
+                monthly: QuotaUsage {
+                    limit:     quota
+                        .monthly_limit
+                        .or(self.quotas.default_monthly_insert_quota),
+                    used:      quota.monthly_usage,
+                    resets_at: quotas::period_end(QuotaPeriod::Month, now),
+                },
             })
             .collect();
 
-        let env_provers: HashSet<_> = options_set.difference(existing_provers).cloned().collect();
+        Ok(QuotasResponse { quotas })
+    }
 
-        for unique in &env_provers {
-            existing_provers.insert(unique.clone());
+    /// Sets the daily and monthly limits of `api_key`, the fingerprint of its
+    /// credentials. Limits that aren't given fall back to the defaults. With
+    /// `reset_usage` the insertions of the current day and month are
+    /// forgotten.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database fails.
+    pub async fn set_quota(
+        &self,
+        api_key: &str,
+        daily_limit: Option<u64>,
+        monthly_limit: Option<u64>,
+        reset_usage: bool,
+    ) -> Result<QuotasResponse, ServerError> {
+        self.ensure_writable()?;
+
+        self.database
+            .set_api_key_quota(api_key, daily_limit, monthly_limit)
+            .await?;
+        if reset_usage {
+            let periods = quotas::current_periods(Utc::now());
+            self.database.reset_api_key_usage(api_key, periods).await?;
         }
 
-        env_provers
+        info!(
+            api_key,
+            daily_limit, monthly_limit, reset_usage, "Set the quota of an API key"
+        );
+
+        self.quotas(Some(api_key)).await
+    }
+
+    /// Lifts the ban on `commitment`. Leaves already queued for deletion stay
+    /// queued.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the commitment isn't banned.
+    pub async fn unban_commitment(&self, commitment: Hash) -> Result<(), ServerError> {
+        self.ensure_writable()?;
+
+        let commitment = self.stored_commitment(&commitment);
+
+        if !self.database.unban_commitment(&commitment).await? {
+            return Err(ServerError::CommitmentNotBanned);
+        }
+
+        info!(?commitment, "Unbanned a commitment");
+
+        Ok(())
     }
 
-    fn identity_is_reduced(&self, commitment: Hash) -> bool {
-        commitment.lt(&self.snark_scalar_field)
+    /// Returns the latest root with `status`, read from the in-memory tree
+    /// rather than the database.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if `status` isn't one of mined, processed or pending.
+    pub fn latest_root(&self, status: Status) -> Result<LatestRootResponse, ServerError> {
+        let Status::Processed(status) = status else {
+            return Err(ServerError::InvalidRootStatus);
+        };
+
+        let root = match status {
+            ProcessedStatus::Mined => self.tree_state.get_mined_tree().get_root(),
+            ProcessedStatus::Processed => self.tree_state.get_processed_tree().get_root(),
+            ProcessedStatus::Pending => self.tree_state.get_latest_tree().get_root(),
+        };
+
+        Ok(LatestRootResponse { root, status })
     }
 
+    /// Returns the leaves inserted at or after `since_leaf`, and the status of
+    /// every root after `since_root`, so that mirrors can stay in sync
+    /// without reading the database.
+    ///
     /// # Errors
     ///
-    /// Will return `Err` if the provided batch size already exists.
-    /// Will return `Err` if the batch size fails to write to database.
+    /// Will return `Err` if `since_root` is unknown or the database can't be
+    /// read.
     #[instrument(level = "debug", skip(self))]
-    pub async fn add_batch_size(
+    pub async fn sync(
         &self,
-        url: String,
-        batch_size: usize,
-        timeout_seconds: u64,
-    ) -> Result<(), ServerError> {
-        self.identity_manager
-            .add_batch_size(&url, batch_size, timeout_seconds)
-            .await?;
+        since_leaf: usize,
+        since_root: Option<Hash>,
+        limit: usize,
+    ) -> Result<SyncResponse, ServerError> {
+        let limit = limit.clamp(1, MAX_SYNC_PAGE_SIZE);
+
+        let roots_from = match since_root {
+            Some(root) => {
+                self.database
+                    .replica()
+                    .get_root_leaf_index(&root)
+                    .await?
+                    .ok_or(ServerError::RootNotFound)?
+                    + 1
+            }
+            None => since_leaf,
+        };
 
-        self.database
-            .insert_prover_configuration(batch_size, url, timeout_seconds)
+        let updated = if roots_from < since_leaf {
+            self.database
+                .replica()
+                .get_identities(roots_from, Some(since_leaf), limit)
+                .await?
+        } else {
+            vec![]
+        };
+        let inserted = self
+            .database
+            .replica()
+            .get_identities(since_leaf, None, limit)
             .await?;
 
-        Ok(())
+        let has_more = inserted.len() == limit;
+        let next_leaf = inserted
+            .last()
+            .map_or(since_leaf, |identity| identity.leaf_index + 1);
+
+        let deletions = updated
+            .iter()
+            .chain(&inserted)
+            .filter(|identity| identity.commitment == Hash::ZERO)
+            .map(|identity| identity.leaf_index)
+            .collect();
+        let roots = updated
+            .iter()
+            .chain(&inserted)
+            .map(|identity| SyncedRoot {
+                root:       identity.root,
+                leaf_index: identity.leaf_index,
+                status:     identity.status,
+            })
+            .collect();
+        let leaves = inserted
+            .into_iter()
+            .filter(|identity| identity.commitment != Hash::ZERO)
+            .map(|identity| SyncedLeaf {
+                leaf_index: identity.leaf_index,
+                commitment: identity.commitment,
+            })
+            .collect();
+
+        Ok(SyncResponse {
+            leaves,
+            roots,
+            deletions,
+            next_leaf,
+            has_more,
+        })
     }
 
+    /// Returns the state of `root` and its propagation to every secondary
+    /// chain.
+    ///
     /// # Errors
     ///
-    /// Will return `Err` if the requested batch size does not exist.
-    /// Will return `Err` if batch size fails to be removed from database.
+    /// Will return `Err` if the root is unknown or can't be read from the
+    /// database.
     #[instrument(level = "debug", skip(self))]
-    pub async fn remove_batch_size(&self, batch_size: usize) -> Result<(), ServerError> {
-        self.identity_manager.remove_batch_size(batch_size).await?;
+    pub async fn root(&self, root: &Hash) -> Result<RootResponse, ServerError> {
+        let state = self
+            .database
+            .replica()
+            .get_root_state(root)
+            .await?
+            .ok_or(ServerError::RootNotFound)?;
+        let propagations = self.database.replica().get_root_propagations(root).await?;
+        let transaction = self.root_transaction(root).await?;
+
+        Ok(RootResponse {
+            state,
+            propagations,
+            transaction,
+        })
+    }
 
-        self.database.remove_prover(batch_size).await?;
+    /// Returns the transaction that published `root` on mainnet, linked to
+    /// the block explorer.
+    async fn root_transaction(
+        &self,
+        root: &Hash,
+    ) -> Result<Option<RootTransactionResponse>, ServerError> {
+        let Some(transaction) = self.database.replica().get_root_transaction(root).await? else {
+            return Ok(None);
+        };
 
-        Ok(())
+        let explorer_url = self
+            .explorer_transaction_url
+            .as_ref()
+            .map(|url| url.replace("{}", &format!("{:?}", transaction.transaction_hash)));
+
+        Ok(Some(RootTransactionResponse {
+            transaction,
+            explorer_url,
+        }))
     }
 
+    /// Returns the proof of `commitment` against the tree matching its status,
+    /// against the tree with `status` if given, or as of `root` if given.
+    /// Identities that aren't on chain yet come with an estimate of when they
+    /// will be.
+    ///
     /// # Errors
     ///
-    /// Will return `Err` if something unknown went wrong.
+    /// Will return `Err` if the provided index is out of bounds, if the
+    /// identity isn't in the tree with `status` or at `root`, or if `root` is
+    /// older than the mined root.
     #[instrument(level = "debug", skip(self))]
-    pub async fn list_batch_sizes(&self) -> Result<ListBatchSizesResponse, ServerError> {
-        let batches = self.identity_manager.list_batch_sizes().await?;
+    pub async fn inclusion_proof(
+        &self,
+        commitment: &Hash,
+        status: Option<Status>,
+        root: Option<Hash>,
+    ) -> Result<InclusionProofResponse, ServerError> {
+        let commitment = &self.stored_commitment(commitment);
+        let mut response = self.prove_inclusion(commitment, status, root).await?;
+
+        request_deadline::check()?;
+        let position = match response.proof.status {
+            Status::Unprocessed(UnprocessedStatus::New) => Some(self.queued_position().await?),
+            Status::Processed(ProcessedStatus::Pending) => self
+                .database
+                .replica()
+                .get_identity_leaf_index(commitment)
+                .await?
+                .map(|item| {
+                    (item.leaf_index + 1)
+                        .saturating_sub(self.tree_state.get_processed_tree().next_leaf())
+                }),
+            Status::Processed(ProcessedStatus::Processed | ProcessedStatus::Mined)
+            | Status::Unprocessed(UnprocessedStatus::Failed) => None,
+        };
+        if let Some(position) = position {
+            response.estimated_inclusion_time = Some(self.estimate_inclusion_time(position).await?);
+        }
 
-        Ok(ListBatchSizesResponse::from(batches))
+        if let Some(root) = &response.proof.root {
+            request_deadline::check()?;
+            response.transaction = self.root_transaction(root).await?;
+        }
+
+        if self.sign_inclusion_proofs {
+            request_deadline::check()?;
+            response.signature = self
+                .sign_inclusion_proof(commitment, &response.proof)
+                .await?;
+        }
+
+        Ok(response)
+    }
+
+    /// Signs the root, the leaf and the proof of `proof` as of now, if it has
+    /// a proof.
+    async fn sign_inclusion_proof(
+        &self,
+        leaf: &Hash,
+        proof: &InclusionProof,
+    ) -> Result<Option<ProofSignature>, ServerError> {
+        let (Some(key), Some(root), Some(merkle_proof)) =
+            (&self.proof_bundle_key, &proof.root, &proof.proof)
+        else {
+            return Ok(None);
+        };
+
+        let timestamp = Utc::now().timestamp();
+        let message = serde_json::to_vec(&SignedInclusionProof {
+            root,
+            leaf,
+            proof: merkle_proof,
+            timestamp,
+        })
+        .map_err(anyhow::Error::from)?;
+        let signature = key
+            .sign_message(message)
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        Ok(Some(ProofSignature {
+            timestamp,
+            signer: key.address(),
+            signature: format!("0x{signature}"),
+        }))
     }
 
+    /// Returns the key that proof bundles and inclusion proofs are signed
+    /// with.
+    ///
     /// # Errors
     ///
-    /// Will return `Err` if the provided index is out of bounds.
-    #[instrument(level = "debug", skip(self))]
-    pub async fn inclusion_proof(
+    /// Will return `Err` if no signing key is configured.
+    pub fn public_key(&self) -> Result<PublicKeyResponse, ServerError> {
+        let key = self
+            .proof_bundle_key
+            .as_ref()
+            .ok_or(ServerError::NoSigningKey)?;
+        let public_key = key.signer().verifying_key().to_encoded_point(false);
+
+        Ok(PublicKeyResponse {
+            address:    key.address(),
+            public_key: format!("0x{}", hex::encode(public_key.as_bytes())),
+        })
+    }
+
+    /// Returns where `commitment` is in its lifecycle, without proving its
+    /// inclusion.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the identity is unknown or the database fails.
+    pub async fn identity_status(
+        &self,
+        commitment: &Hash,
+    ) -> Result<IdentityStatusResponse, ServerError> {
+        let commitment = &self.stored_commitment(commitment);
+        if commitment == &self.identity_manager.initial_leaf_value() {
+            return Err(ServerError::InvalidCommitment);
+        }
+
+        let database = self.database.replica();
+        let reduced_from = database.get_commitment_reduction(commitment).await?;
+
+        if let Some(unprocessed) = database.get_unprocessed_commitment(commitment).await? {
+            return Ok(IdentityStatusResponse {
+                status: unprocessed.status.into(),
+                leaf_index: None,
+                received_at: Some(unprocessed.created_at),
+                pending_at: None,
+                mined_at: None,
+                message: unprocessed.error.as_ref().map(CommitmentError::message),
+                error: unprocessed.error,
+                reduced_from,
+            });
+        }
+
+        let identity = database
+            .get_identity_record(commitment)
+            .await?
+            .ok_or(ServerError::IdentityCommitmentNotFound)?;
+
+        Ok(IdentityStatusResponse {
+            status: self
+                .tree_state
+                .status_of(identity.leaf_index, identity.status)
+                .into(),
+            leaf_index: Some(identity.leaf_index),
+            received_at: identity.received_at,
+            pending_at: Some(identity.pending_as_of),
+            mined_at: identity.mined_at,
+            message: None,
+            error: None,
+            reduced_from,
+        })
+    }
+
+    async fn prove_inclusion(
         &self,
         commitment: &Hash,
+        status: Option<Status>,
+        root: Option<Hash>,
     ) -> Result<InclusionProofResponse, ServerError> {
         if commitment == &self.identity_manager.initial_leaf_value() {
             return Err(ServerError::InvalidCommitment);
         }
 
-        if let Some((status, error_message)) = self
+        let status = match status {
+            Some(Status::Processed(status)) => Some(status),
+            Some(Status::Unprocessed(_)) => return Err(ServerError::InvalidRootStatus),
+            None => None,
+        };
+
+        if let Some((status, error)) = self
             .database
+            .replica()
             .get_unprocessed_commit_status(commitment)
             .await?
         {
-            return Ok(InclusionProofResponse(InclusionProof {
-                status,
+            return Ok(InclusionProofResponse::from(InclusionProof {
+                status: status.into(),
                 root: None,
                 proof: None,
-                message: Some(error_message),
+                message: error.as_ref().map(CommitmentError::message),
+                error,
+            }));
+        }
+
+        let item = self
+            .database
+            .replica()
+            .get_identity_leaf_index(commitment)
+            .await?
+            .ok_or(ServerError::IdentityCommitmentNotFound)?;
+        let item = self.tree_state.checked_item(&item);
+
+        if let Some(root) = root {
+            return self.inclusion_proof_at(commitment, &item, root).await;
+        }
+
+        let root = self.tree_state.get_root_for(status.unwrap_or(item.status));
+        let roots = self.tree_state.get_roots();
+        if let Some(proof) = self.proof_cache.get(&roots, &root, item.leaf_index) {
+            return Ok(InclusionProofResponse::from(InclusionProof {
+                status:  item.status.into(),
+                root:    Some(root),
+                proof:   Some(proof),
+                message: None,
+                error:   None,
             }));
         }
 
+        // Computing the merkle path is CPU heavy, so it's done on the worker pool.
+        request_deadline::check()?;
+        let tree_state = self.tree_state.clone();
+        let leaf_index = item.leaf_index;
+        let proof = self
+            .proof_workers
+            .run(move || match status {
+                Some(status) => tree_state.get_proof_in(&item, status),
+                None => Some(tree_state.get_proof_for(&item)),
+            })
+            .await
+            .ok_or(ServerError::NotInRequestedTree)?;
+
+        if let (Some(root), Some(merkle_proof)) = (proof.root, &proof.proof) {
+            self.proof_cache
+                .insert(root, leaf_index, merkle_proof.clone());
+        }
+
+        Ok(InclusionProofResponse::from(proof))
+    }
+
+    async fn inclusion_proof_at(
+        &self,
+        commitment: &Hash,
+        item: &TreeItem,
+        root: Hash,
+    ) -> Result<InclusionProofResponse, ServerError> {
+        let tree_state = self.tree_state.clone();
+        let leaf_index = item.leaf_index;
+        let at_root = self
+            .proof_workers
+            .run(move || tree_state.get_proof_at(&root, leaf_index))
+            .await;
+
+        let Some((leaf, proof)) = at_root else {
+            let root_state = self.database.replica().get_root_state(&root).await?;
+            return Err(if root_state.is_some() {
+                ServerError::ProofRootTooOld
+            } else {
+                ServerError::RootNotFound
+            });
+        };
+
+        if leaf != *commitment {
+            return Err(ServerError::NotAtRequestedRoot);
+        }
+
+        Ok(InclusionProofResponse::from(InclusionProof {
+            status:  item.status.into(),
+            root:    Some(root),
+            proof:   Some(proof),
+            message: None,
+            error:   None,
+        }))
+    }
+
+    /// Returns everything needed to verify the inclusion of `commitment`
+    /// offline, signed by the sequencer.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the commitment isn't in the tree or if the bundle
+    /// can't be signed.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn proof_bundle(
+        &self,
+        commitment: &Hash,
+    ) -> Result<ProofBundleResponse, ServerError> {
+        let commitment = &self.stored_commitment(commitment);
         let item = self
             .database
+            .replica()
             .get_identity_leaf_index(commitment)
             .await?
             .ok_or(ServerError::IdentityCommitmentNotFound)?;
+        let item = self.tree_state.checked_item(&item);
+
+        let tree_state = self.tree_state.clone();
+        let leaf_index = item.leaf_index;
+        let proof = self
+            .proof_workers
+            .run(move || tree_state.get_proof_for(&item))
+            .await;
+
+        let root_transaction = match &proof.root {
+            Some(root) => self.database.replica().get_root_transaction(root).await?,
+            None => None,
+        };
 
-        let proof = self.tree_state.get_proof_for(&item);
+        let bundle = ProofBundle {
+            commitment: *commitment,
+            leaf_index,
+            status: proof.status,
+            root: proof.root,
+            proof: proof.proof,
+            transaction_hash: root_transaction
+                .as_ref()
+                .map(|transaction| transaction.transaction_hash),
+            block_number: root_transaction.map(|transaction| transaction.block_number),
+        };
+
+        let Some(key) = &self.proof_bundle_key else {
+            return Ok(ProofBundleResponse {
+                bundle,
+                signer: None,
+                signature: None,
+            });
+        };
 
-        Ok(InclusionProofResponse(proof))
+        let message = serde_json::to_vec(&bundle).map_err(anyhow::Error::from)?;
+        let signature = key
+            .sign_message(message)
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        Ok(ProofBundleResponse {
+            bundle,
+            signer: Some(key.address()),
+            signature: Some(format!("0x{signature}")),
+        })
     }
 
     /// # Errors
@@ -441,7 +2913,8 @@ impl App {
         request: &VerifySemaphoreProofRequest,
         query: &VerifySemaphoreProofQuery,
     ) -> Result<VerifySemaphoreProofResponse, ServerError> {
-        let Some(root_state) = self.database.get_root_state(&request.root).await? else {
+        let database = self.database.replica();
+        let Some(root_state) = database.get_root_state(&request.root).await? else {
             return Err(ServerError::InvalidRoot);
         };
 
@@ -450,14 +2923,24 @@ impl App {
             self.validate_root_age(max_root_age, &root_state)?;
         }
 
-        let checked = verify_proof(
-            request.root,
-            request.nullifier_hash,
-            request.signal_hash,
-            request.external_nullifier_hash,
-            &request.proof,
-            self.identity_manager.tree_depth(),
-        );
+        // Verifying is CPU heavy, so it's done on the worker pool, where it is
+        // skipped if the request times out while it waits for a thread.
+        request_deadline::check()?;
+        let request = request.clone();
+        let tree_depth = self.identity_manager.tree_depth();
+        let checked = self
+            .proof_workers
+            .run(move || {
+                verify_proof(
+                    request.root,
+                    request.nullifier_hash,
+                    request.signal_hash,
+                    request.external_nullifier_hash,
+                    &request.proof,
+                    tree_depth,
+                )
+            })
+            .await;
 
         match checked {
             Ok(true) => Ok(VerifySemaphoreProofResponse(root_state)),
@@ -483,17 +2966,22 @@ impl App {
 
         match root_state.status {
             // Pending status implies the batching or latest tree
-            Status::Pending if latest_root == root || batching_root == root => return Ok(()),
+            ProcessedStatus::Pending if latest_root == root || batching_root == root => {
+                return Ok(())
+            }
             // Processed status is hidden - this should never happen
-            Status::Processed if processed_root == root => return Ok(()),
+            ProcessedStatus::Processed if processed_root == root => return Ok(()),
             // Processed status is hidden so it could be either processed or mined
-            Status::Mined if processed_root == root || mined_root == root => return Ok(()),
+            ProcessedStatus::Mined if processed_root == root || mined_root == root => return Ok(()),
             _ => (),
         }
 
         let now = chrono::Utc::now();
 
-        let root_age = if matches!(root_state.status, Status::Pending | Status::Processed) {
+        let root_age = if matches!(
+            root_state.status,
+            ProcessedStatus::Pending | ProcessedStatus::Processed
+        ) {
             now - root_state.pending_valid_as_of
         } else {
             let mined_at = root_state
@@ -514,7 +3002,40 @@ impl App {
     /// Will return an Error if any of the components cannot be shut down
     /// gracefully.
     pub async fn shutdown(&self) -> AnyhowResult<()> {
+        if let Some(follower) = &self.follower {
+            info!("Shutting down follower.");
+            follower.abort();
+        }
+
+        if let Some(elector) = &self.elector {
+            elector.abort();
+        }
+
+        if let Some(snapshotter) = &self.snapshotter {
+            snapshotter.abort();
+        }
+
+        if let Some(retention) = &self.retention {
+            retention.abort();
+        }
+
+        if let Some(standby) = &self.standby {
+            info!("Shutting down standby.");
+            standby.abort();
+        }
+
         info!("Shutting down identity committer.");
-        self.identity_committer.shutdown().await
+        self.identity_committer.shutdown().await?;
+
+        // Only once the committer stopped, so that the next leader doesn't
+        // submit batches alongside it.
+        if let Some(lease) = &self.leader_lease {
+            if !self.read_only {
+                info!("Releasing the leader lease.");
+                lease.release().await?;
+            }
+        }
+
+        Ok(())
     }
 }
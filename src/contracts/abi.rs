@@ -10,10 +10,20 @@ abigen!(
         function registerIdentities(uint256[8] calldata insertionProof, uint256 preRoot, uint32 startIndex, uint256[] calldata identityCommitments, uint256 postRoot) public virtual
         function latestRoot() public view virtual returns (uint256 root)
         function owner() public view virtual returns (address)
+        function identityOperator() public view virtual returns (address)
+        function getRegisterIdentitiesVerifierLookupTableAddress() public view virtual returns (address)
+        function getTreeDepth() public view virtual returns (uint8)
         function queryRoot(uint256 root) public view virtual returns (RootInfo memory)
     ]"#,
 );
 
+abigen!(
+    VerifierLookupTable,
+    r#"[
+        function getVerifierFor(uint256 batchSize) public view returns (address)
+    ]"#
+);
+
 abigen!(
     BridgedWorldId,
     r#"[
@@ -22,3 +32,22 @@ abigen!(
         function latestRoot() public view returns (uint256 root)
     ]"#
 );
+
+abigen!(
+    StateBridge,
+    r#"[
+        function propagateRoot() external
+    ]"#
+);
+
+abigen!(
+    Semaphore,
+    r#"[
+        event MemberAdded(uint256 indexed groupId, uint256 index, uint256 identityCommitment, uint256 merkleTreeRoot)
+        function addMembers(uint256 groupId, uint256[] calldata identityCommitments) external
+        function groups(uint256 groupId) public view returns (address admin, uint256 merkleTreeDuration)
+        function getMerkleTreeRoot(uint256 groupId) public view returns (uint256)
+        function getMerkleTreeDepth(uint256 groupId) public view returns (uint256)
+        function getNumberOfMerkleTreeLeaves(uint256 groupId) public view returns (uint256)
+    ]"#
+);
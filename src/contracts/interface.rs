@@ -0,0 +1,324 @@
+//! The interfaces of the contracts that the sequencer can manage a tree of.
+use std::str::FromStr;
+use std::sync::Arc;
+
+use ethers::abi::RawLog;
+use ethers::contract::EthEvent;
+use ethers::providers::Middleware;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, Topic, H256, U256};
+use thiserror::Error;
+use tracing::warn;
+
+use super::abi::{MemberAddedFilter, Semaphore, TreeChangedFilter, VerifierLookupTable, WorldId};
+use super::Options;
+use crate::ethereum::ReadProvider;
+
+#[derive(Debug, Error)]
+#[error("invalid contract interface {0:?}, expected `world-id` or `semaphore`")]
+pub struct InvalidContractInterface(String);
+
+/// The interface of the contract that holds the tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContractInterface {
+    /// The WorldID identity manager, which verifies an insertion proof for
+    /// every batch and keeps the history of its roots.
+    WorldId,
+    /// A group of a Semaphore (v3) contract, which the sequencer adds members
+    /// to as the admin of the group.
+    Semaphore,
+}
+
+impl FromStr for ContractInterface {
+    type Err = InvalidContractInterface;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "world-id" => Ok(Self::WorldId),
+            "semaphore" => Ok(Self::Semaphore),
+            _ => Err(InvalidContractInterface(s.to_string())),
+        }
+    }
+}
+
+/// The contract that holds the tree, behind the interface configured with
+/// `--contract-interface`.
+#[derive(Clone, Debug)]
+pub enum IdentityContract {
+    WorldId(WorldId<ReadProvider>),
+    Semaphore {
+        abi:                 Semaphore<ReadProvider>,
+        group_id:            U256,
+        /// The contract doesn't expose the history of the roots of a group,
+        /// so superseded roots are looked up in the events of this many
+        /// blocks.
+        root_history_blocks: u64,
+    },
+}
+
+impl IdentityContract {
+    #[must_use]
+    pub fn new(options: &Options, provider: Arc<ReadProvider>) -> Self {
+        let address = options.identity_manager_address;
+
+        match options.contract_interface {
+            ContractInterface::WorldId => Self::WorldId(WorldId::new(address, provider)),
+            ContractInterface::Semaphore => Self::Semaphore {
+                abi:                 Semaphore::new(address, provider),
+                group_id:            options.semaphore_group_id.into(),
+                root_history_blocks: options.semaphore_root_history_blocks,
+            },
+        }
+    }
+
+    #[must_use]
+    pub const fn interface(&self) -> ContractInterface {
+        match self {
+            Self::WorldId(_) => ContractInterface::WorldId,
+            Self::Semaphore { .. } => ContractInterface::Semaphore,
+        }
+    }
+
+    #[must_use]
+    pub fn address(&self) -> Address {
+        match self {
+            Self::WorldId(abi) => abi.address(),
+            Self::Semaphore { abi, .. } => abi.address(),
+        }
+    }
+
+    #[must_use]
+    pub fn client(&self) -> Arc<ReadProvider> {
+        match self {
+            Self::WorldId(abi) => abi.client().clone(),
+            Self::Semaphore { abi, .. } => abi.client().clone(),
+        }
+    }
+
+    /// The account that is allowed to insert identities, i.e. the identity
+    /// operator of the identity manager or the admin of the Semaphore group.
+    pub async fn identity_operator(&self) -> anyhow::Result<Address> {
+        match self {
+            Self::WorldId(abi) => Ok(abi.identity_operator().call().await?),
+            Self::Semaphore { abi, group_id, .. } => {
+                let (admin, _) = abi.groups(*group_id).call().await?;
+                Ok(admin)
+            }
+        }
+    }
+
+    pub async fn tree_depth(&self) -> anyhow::Result<usize> {
+        match self {
+            Self::WorldId(abi) => Ok(usize::from(abi.get_tree_depth().call().await?)),
+            Self::Semaphore { abi, group_id, .. } => Ok(abi
+                .get_merkle_tree_depth(*group_id)
+                .call()
+                .await?
+                .as_usize()),
+        }
+    }
+
+    /// Returns the batch sizes in `batch_sizes` that the contract has no
+    /// verifier for. A Semaphore contract doesn't verify insertion proofs, so
+    /// it accepts batches of any size.
+    pub async fn missing_verifiers(&self, batch_sizes: &[usize]) -> anyhow::Result<Vec<usize>> {
+        let Self::WorldId(abi) = self else {
+            return Ok(vec![]);
+        };
+
+        let lookup_table = VerifierLookupTable::new(
+            abi.get_register_identities_verifier_lookup_table_address()
+                .call()
+                .await?,
+            abi.client().clone(),
+        );
+
+        let mut missing = vec![];
+        for &batch_size in batch_sizes {
+            // The lookup table reverts for batch sizes without a verifier.
+            match lookup_table
+                .get_verifier_for(batch_size.into())
+                .call()
+                .await
+            {
+                Ok(verifier) if !verifier.is_zero() => {}
+                Ok(_) => missing.push(batch_size),
+                // `ethers` reports reverts as errors of the node, which only
+                // tell them apart from failures to reach it by their message.
+                Err(error) if error.to_string().contains("revert") => missing.push(batch_size),
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        Ok(missing)
+    }
+
+    pub async fn latest_root(&self) -> anyhow::Result<U256> {
+        match self {
+            Self::WorldId(abi) => Ok(abi.latest_root().call().await?),
+            Self::Semaphore { abi, group_id, .. } => {
+                Ok(abi.get_merkle_tree_root(*group_id).call().await?)
+            }
+        }
+    }
+
+    /// Checks whether `root` is, or has been, a root of the tree as of
+    /// `block`, or the latest block if none is given.
+    pub async fn is_root_known(&self, root: U256, block: Option<u64>) -> anyhow::Result<bool> {
+        match self {
+            Self::WorldId(abi) => {
+                let mut query_root = abi.query_root(root);
+                if let Some(block) = block {
+                    query_root = query_root.block(block);
+                }
+
+                let (root_on_chain, ..) = query_root.call().await?;
+
+                Ok(!root_on_chain.is_zero())
+            }
+            Self::Semaphore {
+                abi,
+                group_id,
+                root_history_blocks,
+            } => {
+                let block = match block {
+                    Some(block) => block,
+                    None => abi.client().get_block_number().await?.as_u64(),
+                };
+
+                let latest_root = abi
+                    .get_merkle_tree_root(*group_id)
+                    .block(block)
+                    .call()
+                    .await?;
+                if latest_root == root {
+                    return Ok(true);
+                }
+
+                let members_added = abi
+                    .member_added_filter()
+                    .topic1(group_topic(group_id))
+                    .from_block(block.saturating_sub(*root_history_blocks))
+                    .to_block(block)
+                    .query()
+                    .await?;
+
+                Ok(members_added
+                    .iter()
+                    .any(|member_added| member_added.merkle_tree_root == root))
+            }
+        }
+    }
+
+    /// Checks whether the root of the empty tree is known to the contract. A
+    /// Semaphore group only knows it while it is empty, so the check passes
+    /// for groups that already have members.
+    pub async fn is_initial_root_known(&self, initial_root: U256) -> anyhow::Result<bool> {
+        match self {
+            Self::WorldId(_) => self.is_root_known(initial_root, None).await,
+            Self::Semaphore { abi, group_id, .. } => {
+                let leaves = abi
+                    .get_number_of_merkle_tree_leaves(*group_id)
+                    .call()
+                    .await?;
+                if !leaves.is_zero() {
+                    warn!(
+                        ?leaves,
+                        "The Semaphore group has members, the initial leaf value can't be \
+                         validated"
+                    );
+                    return Ok(true);
+                }
+
+                Ok(self.latest_root().await? == initial_root)
+            }
+        }
+    }
+
+    /// Builds the transaction that inserts `identities` at `start_index`. The
+    /// Semaphore contract computes the roots itself, so it neither takes the
+    /// roots nor the insertion proof.
+    #[must_use]
+    pub fn insert_identities(
+        &self,
+        start_index: u32,
+        pre_root: U256,
+        post_root: U256,
+        identities: Vec<U256>,
+        insertion_proof: [U256; 8],
+    ) -> TypedTransaction {
+        match self {
+            Self::WorldId(abi) => {
+                abi.register_identities(
+                    insertion_proof,
+                    pre_root,
+                    start_index,
+                    identities,
+                    post_root,
+                )
+                .tx
+            }
+            Self::Semaphore { abi, group_id, .. } => abi.add_members(*group_id, identities).tx,
+        }
+    }
+
+    /// The topics of the events that publish a new root.
+    #[must_use]
+    pub fn root_topics(&self) -> [Option<Topic>; 4] {
+        match self {
+            Self::WorldId(_) => [
+                Some(Topic::from(TreeChangedFilter::signature())),
+                None,
+                None,
+                None,
+            ],
+            Self::Semaphore { group_id, .. } => [
+                Some(Topic::from(MemberAddedFilter::signature())),
+                Some(Topic::from(group_topic(group_id))),
+                None,
+                None,
+            ],
+        }
+    }
+
+    /// Returns the new root published by `log`, if it is one of the events of
+    /// [`Self::root_topics`]. A Semaphore contract publishes the root after
+    /// every member, so a batch publishes all of its intermediate roots.
+    #[must_use]
+    pub fn decode_root(&self, log: &RawLog) -> Option<U256> {
+        match self {
+            Self::WorldId(_) => TreeChangedFilter::decode_log(log)
+                .ok()
+                .map(|event| event.post_root),
+            Self::Semaphore { group_id, .. } => MemberAddedFilter::decode_log(log)
+                .ok()
+                .filter(|event| event.group_id == *group_id)
+                .map(|event| event.merkle_tree_root),
+        }
+    }
+}
+
+/// The topic that Semaphore events of `group_id` are indexed by.
+fn group_topic(group_id: &U256) -> H256 {
+    let mut topic = H256::zero();
+    group_id.to_big_endian(topic.as_bytes_mut());
+    topic
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contract_interfaces_are_parsed() {
+        assert_eq!(
+            "world-id".parse::<ContractInterface>().unwrap(),
+            ContractInterface::WorldId
+        );
+        assert_eq!(
+            "semaphore".parse::<ContractInterface>().unwrap(),
+            ContractInterface::Semaphore
+        );
+        assert!("semaphore-v4".parse::<ContractInterface>().is_err());
+    }
+}
@@ -1,24 +1,33 @@
 //! Functionality for interacting with smart contracts deployed on chain.
+// The batch submission calls and the bridge configuration are only used when
+// the sequencer submits batches itself.
+#![cfg_attr(not(feature = "submission"), allow(dead_code))]
+
 pub mod abi;
+pub mod interface;
 pub mod scanner;
 
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, ensure};
 use clap::Parser;
 use ethers::providers::Middleware;
-use ethers::types::{Address, U256};
+use ethers::types::{Address, H256, U256};
 use semaphore::Field;
+#[cfg(feature = "submission")]
 use tokio::sync::RwLockReadGuard;
-use tracing::{error, info, instrument, warn};
+use tracing::{info, instrument, warn};
 
-use self::abi::{BridgedWorldId, WorldId};
+use self::abi::{BridgedWorldId, StateBridge};
+use self::interface::{ContractInterface, IdentityContract};
 use crate::ethereum::write::TransactionId;
 use crate::ethereum::{Ethereum, ReadProvider};
+use crate::identity_tree::{RootAccumulator, TreeHash};
 use crate::prover::batch_insertion::ProverConfiguration;
-use crate::prover::map::{InsertionProverMap, ReadOnlyInsertionProver};
-use crate::prover::{batch_insertion, Proof, ReadOnlyProver};
+#[cfg(feature = "submission")]
+use crate::prover::map::{check_health, InsertionProverMap, ProverGroup, ReadOnlyInsertionProver};
+use crate::prover::{batch_insertion, Proof};
 use crate::serde_utils::JsonStrWrapper;
 use crate::server::error::Error as ServerError;
 
@@ -31,19 +40,51 @@ pub struct Options {
     #[clap(long, env)]
     pub identity_manager_address: Address,
 
+    /// The interface of the identity manager contract: `world-id` for the
+    /// WorldID identity manager, or `semaphore` for a group of a Semaphore
+    /// (v3) contract, which the sequencer adds members to as the group admin.
+    #[clap(long, env, default_value = "world-id")]
+    pub contract_interface: ContractInterface,
+
+    /// The group of the Semaphore contract that the sequencer manages. Only
+    /// used with `--contract-interface semaphore`.
+    #[clap(long, env, default_value = "0")]
+    pub semaphore_group_id: u64,
+
+    /// How many blocks back the roots of the Semaphore group are looked up in
+    /// its events, as the contract only exposes the latest root. Only used
+    /// with `--contract-interface semaphore`.
+    #[clap(long, env, default_value = "10000")]
+    pub semaphore_root_history_blocks: u64,
+
     /// The addresses of world id contracts on secondary chains
     /// mapped by chain id
     #[clap(long, env, default_value = "{}")]
     pub relayed_identity_manager_addresses: JsonStrWrapper<HashMap<u64, Address>>,
 
+    /// The addresses of the state bridges on mainnet that propagate roots to
+    /// the secondary chains, mapped by the chain id of the secondary chain.
+    /// Roots are propagated by the sequencer once they are final on mainnet.
+    #[clap(long, env, default_value = "{}")]
+    pub state_bridge_addresses: JsonStrWrapper<HashMap<u64, Address>>,
+
     /// The depth of the tree that the contract is working with. This needs to
     /// agree with the verifier in the deployed contract, and also with
-    /// `semaphore-mtb`.
+    /// `semaphore-mtb`. The sequencer refuses to start if the identity manager
+    /// has a different depth.
     #[clap(long, env, default_value = "10")]
     pub tree_depth: usize,
 
+    /// The hash function of the tree and its parameters. Only
+    /// `poseidon-bn254` is implemented. The sequencer refuses to start if
+    /// this build hashes with other parameters, and a hash function that the
+    /// identity manager doesn't use fails the check of the initial root.
+    #[clap(long, env, default_value = "poseidon-bn254")]
+    pub tree_hash: TreeHash,
+
     /// Initial value of the Merkle tree leaves. Defaults to the initial value
-    /// used in the identity manager contract.
+    /// used in the identity manager contract. The sequencer refuses to start
+    /// if the root of the empty tree isn't known to the identity manager.
     #[clap(
         long,
         env,
@@ -57,17 +98,23 @@ pub struct Options {
 #[derive(Debug)]
 pub struct IdentityManager {
     ethereum:             Ethereum,
+    #[cfg(feature = "submission")]
     insertion_prover_map: InsertionProverMap,
-    abi:                  WorldId<ReadProvider>,
+    contract:             IdentityContract,
     secondary_abis:       Vec<BridgedWorldId<ReadProvider>>,
+    bridged_world_ids:    HashMap<u64, BridgedWorldId<ReadProvider>>,
+    state_bridges:        HashMap<u64, StateBridge<ReadProvider>>,
+    /// Batches can only be submitted by the identity operator of the identity
+    /// manager.
+    owner:                Address,
     initial_leaf_value:   Field,
     tree_depth:           usize,
 }
 
 impl IdentityManager {
     // TODO: I don't like these public getters
-    pub fn abi(&self) -> &WorldId<ReadProvider> {
-        &self.abi
+    pub fn contract(&self) -> &IdentityContract {
+        &self.contract
     }
 
     pub fn secondary_abis(&self) -> &[BridgedWorldId<ReadProvider>] {
@@ -78,7 +125,7 @@ impl IdentityManager {
     pub async fn new(
         options: Options,
         ethereum: Ethereum,
-        insertion_prover_map: InsertionProverMap,
+        #[cfg(feature = "submission")] insertion_prover_map: InsertionProverMap,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
@@ -86,40 +133,79 @@ impl IdentityManager {
         // Check that there is code deployed at the target address.
         let address = options.identity_manager_address;
         let code = ethereum.provider().get_code(address, None).await?;
-        if code.as_ref().is_empty() {
-            error!(
-                ?address,
-                "No contract code is deployed at the provided address."
-            );
-        }
-
-        // Connect to the running batching contract.
-        let abi = WorldId::new(
-            options.identity_manager_address,
-            ethereum.provider().clone(),
+        ensure!(
+            !code.as_ref().is_empty(),
+            "No contract is deployed at {:?} on chain {}, check --identity-manager-address and \
+             --ethereum-provider",
+            address,
+            ethereum.provider().chain_id
         );
 
-        let owner = abi.owner().call().await?;
-        if owner != ethereum.address() {
-            error!(?owner, signer = ?ethereum.address(), "Signer is not the owner of the identity manager contract.");
-            panic!("Cannot currently continue in read-only mode.")
+        // Connect to the running batching contract.
+        let contract = IdentityContract::new(&options, ethereum.provider().clone());
+
+        let owner = contract.identity_operator().await?;
+        if ethereum.is_read_only() {
+            info!(?owner, "Observing the identity manager without a signer");
+        } else if ethereum.is_simulated() {
+            warn!(
+                ?owner,
+                "Dry run: transactions are simulated as the identity operator and never sent"
+            );
+        } else if !ethereum.addresses().contains(&owner) {
+            bail!(
+                "No signer is the identity operator {:?} of the identity manager, the signers are \
+                 {:?}. Configure the signing key or relayer of the identity operator, or make one \
+                 of the signers the identity operator",
+                owner,
+                ethereum.addresses()
+            );
         }
         info!(
             ?address,
             ?owner,
-            "Connected to the WorldID Identity Manager"
+            interface = ?contract.interface(),
+            "Connected to the identity manager"
+        );
+
+        Self::validate_tree_parameters(&contract, &options).await?;
+        #[cfg(feature = "submission")]
+        Self::validate_verifiers(&contract, &insertion_prover_map.read().await.batch_sizes())
+            .await?;
+
+        // Roots can only be bridged from the WorldID identity manager.
+        ensure!(
+            contract.interface() == ContractInterface::WorldId
+                || (options.relayed_identity_manager_addresses.0.is_empty()
+                    && options.state_bridge_addresses.0.is_empty()),
+            "Secondary chains are only supported with the WorldID identity manager"
         );
 
         let secondary_providers = ethereum.secondary_providers();
 
         let mut secondary_abis = Vec::new();
+        let mut bridged_world_ids = HashMap::new();
         for (chain_id, address) in options.relayed_identity_manager_addresses.0 {
             let provider = secondary_providers
                 .get(&chain_id)
                 .ok_or_else(|| anyhow!("No provider for chain id: {}", chain_id))?;
 
             let abi = BridgedWorldId::new(address, provider.clone());
-            secondary_abis.push(abi);
+            secondary_abis.push(abi.clone());
+            bridged_world_ids.insert(chain_id, abi);
+        }
+
+        let mut state_bridges = HashMap::new();
+        for (chain_id, address) in options.state_bridge_addresses.0 {
+            if !bridged_world_ids.contains_key(&chain_id) {
+                return Err(anyhow!(
+                    "No relayed identity manager for state bridge chain id: {}",
+                    chain_id
+                ));
+            }
+
+            let abi = StateBridge::new(address, ethereum.provider().clone());
+            state_bridges.insert(chain_id, abi);
         }
 
         let initial_leaf_value = options.initial_leaf_value;
@@ -127,9 +213,13 @@ impl IdentityManager {
 
         let identity_manager = Self {
             ethereum,
+            #[cfg(feature = "submission")]
             insertion_prover_map,
-            abi,
+            contract,
             secondary_abis,
+            bridged_world_ids,
+            state_bridges,
+            owner,
             initial_leaf_value,
             tree_depth,
         };
@@ -137,15 +227,81 @@ impl IdentityManager {
         Ok(identity_manager)
     }
 
+    /// Checks that the tree depth and initial leaf agree with the identity
+    /// manager. A tree of another depth produces proofs that the verifier
+    /// rejects, and another initial leaf produces roots the contract never
+    /// had.
+    async fn validate_tree_parameters(
+        contract: &IdentityContract,
+        options: &Options,
+    ) -> anyhow::Result<()> {
+        options.tree_hash.validate()?;
+
+        let tree_depth = contract.tree_depth().await?;
+        if tree_depth != options.tree_depth {
+            return Err(anyhow!(
+                "The identity manager has a tree depth of {}, but the configured tree depth is {}",
+                tree_depth,
+                options.tree_depth
+            ));
+        }
+
+        let initial_root =
+            RootAccumulator::new(options.tree_depth, options.initial_leaf_value).root();
+        if !contract.is_initial_root_known(initial_root.into()).await? {
+            return Err(anyhow!(
+                "The initial root {:?} is unknown to the identity manager, the configured initial \
+                 leaf value {:?} or tree hash {:?} is likely wrong",
+                initial_root,
+                options.initial_leaf_value,
+                options.tree_hash
+            ));
+        }
+
+        info!(
+            tree_depth,
+            "Validated the tree against the identity manager"
+        );
+
+        Ok(())
+    }
+
+    /// Checks that the identity manager has a verifier for every batch size
+    /// that there is a prover for, as it rejects the batches of other sizes.
+    async fn validate_verifiers(
+        contract: &IdentityContract,
+        batch_sizes: &[usize],
+    ) -> anyhow::Result<()> {
+        let missing = contract.missing_verifiers(batch_sizes).await?;
+        ensure!(
+            missing.is_empty(),
+            "The identity manager has no verifier for the batch sizes {:?}, register verifiers \
+             for them in its verifier lookup table or remove their provers",
+            missing
+        );
+
+        Ok(())
+    }
+
     #[must_use]
     pub const fn tree_depth(&self) -> usize {
         self.tree_depth
     }
 
+    #[cfg(feature = "submission")]
     pub async fn max_batch_size(&self) -> usize {
         self.insertion_prover_map.read().await.max_batch_size()
     }
 
+    #[cfg(feature = "submission")]
+    pub async fn batch_sizes(&self) -> Vec<usize> {
+        self.insertion_prover_map.read().await.batch_sizes()
+    }
+
+    pub async fn gas_price(&self) -> anyhow::Result<U256> {
+        Ok(self.ethereum.provider().get_gas_price().await?)
+    }
+
     #[must_use]
     pub const fn initial_leaf_value(&self) -> Field {
         self.initial_leaf_value
@@ -170,13 +326,14 @@ impl IdentityManager {
         Ok(())
     }
 
+    #[cfg(feature = "submission")]
     pub async fn get_suitable_prover(
         &self,
         num_identities: usize,
-    ) -> anyhow::Result<ReadOnlyProver<batch_insertion::Prover>> {
+    ) -> anyhow::Result<ReadOnlyInsertionProver> {
         let prover_map = self.insertion_prover_map.read().await;
 
-        match RwLockReadGuard::try_map(prover_map, |map| map.get(num_identities)) {
+        match RwLockReadGuard::try_map(prover_map, |map| map.get_group(num_identities)) {
             Ok(p) => anyhow::Ok(p),
             Err(_) => Err(anyhow!(
                 "No available prover for batch size: {num_identities}"
@@ -184,6 +341,7 @@ impl IdentityManager {
         }
     }
 
+    #[cfg(feature = "submission")]
     #[instrument(level = "debug", skip(prover, identity_commitments))]
     pub async fn prepare_proof(
         prover: ReadOnlyInsertionProver<'_>,
@@ -191,7 +349,7 @@ impl IdentityManager {
         pre_root: U256,
         post_root: U256,
         identity_commitments: &[batch_insertion::Identity],
-    ) -> anyhow::Result<Proof> {
+    ) -> anyhow::Result<(Proof, String)> {
         let batch_size = identity_commitments.len();
 
         let actual_start_index: u32 = start_index.try_into()?;
@@ -202,16 +360,14 @@ impl IdentityManager {
             prover.batch_size()
         );
 
-        let proof_data: Proof = prover
+        prover
             .generate_proof(
                 actual_start_index,
                 pre_root,
                 post_root,
                 identity_commitments,
             )
-            .await?;
-
-        Ok(proof_data)
+            .await
     }
 
     #[instrument(level = "debug", skip(self, identity_commitments, proof_data))]
@@ -234,16 +390,14 @@ impl IdentityManager {
         // We want to send the transaction through our ethereum provider rather than
         // directly now. To that end, we create it, and then send it later, waiting for
         // it to complete.
-        let register_identities_transaction = self
-            .abi
-            .register_identities(
-                proof_points_array,
-                pre_root,
-                actual_start_index,
-                identities,
-                post_root,
-            )
-            .tx;
+        let mut register_identities_transaction = self.contract.insert_identities(
+            actual_start_index,
+            pre_root,
+            post_root,
+            identities,
+            proof_points_array,
+        );
+        register_identities_transaction.set_from(self.owner);
 
         self.ethereum
             .send_transaction(register_identities_transaction, true)
@@ -281,25 +435,138 @@ impl IdentityManager {
 
     #[instrument(level = "debug", skip_all)]
     pub async fn latest_root(&self) -> anyhow::Result<U256> {
-        let latest_root = self.abi.latest_root().call().await?;
+        let latest_root = self.contract.latest_root().await?;
 
         Ok(latest_root)
     }
 
+    /// Whether transactions are only simulated, see
+    /// [`Ethereum::is_simulated`].
+    #[must_use]
+    pub const fn is_simulated(&self) -> bool {
+        self.ethereum.is_simulated()
+    }
+
+    /// The signer that submits batches.
+    #[must_use]
+    pub const fn owner(&self) -> Address {
+        self.owner
+    }
+
+    /// Returns the gas used by the mined transaction `transaction_hash` and
+    /// the price it paid per gas, in wei.
+    pub async fn transaction_gas(
+        &self,
+        transaction_hash: H256,
+    ) -> anyhow::Result<Option<(U256, U256)>> {
+        let provider = self.ethereum.provider();
+
+        let Some(receipt) = provider.get_transaction_receipt(transaction_hash).await? else {
+            return Ok(None);
+        };
+        let Some(gas_used) = receipt.gas_used else {
+            return Ok(None);
+        };
+
+        // Receipts from before EIP-1559 have no effective gas price.
+        let gas_price = match receipt.effective_gas_price {
+            Some(gas_price) => Some(gas_price),
+            None => provider
+                .get_transaction(transaction_hash)
+                .await?
+                .and_then(|transaction| transaction.gas_price),
+        };
+
+        Ok(gas_price.map(|gas_price| (gas_used, gas_price)))
+    }
+
+    /// The balance of every signer.
+    pub async fn signer_balances(&self) -> anyhow::Result<Vec<(Address, U256)>> {
+        let mut balances = Vec::new();
+
+        for address in self.ethereum.addresses() {
+            let balance = self.ethereum.provider().get_balance(address, None).await?;
+            balances.push((address, balance));
+        }
+
+        Ok(balances)
+    }
+
+    /// The chain ids of the secondary chains the sequencer propagates roots
+    /// to.
+    pub fn state_bridge_chain_ids(&self) -> Vec<u64> {
+        self.state_bridges.keys().copied().collect()
+    }
+
+    /// Propagates the latest root of the identity manager to the secondary
+    /// chain `chain_id` through its state bridge.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn propagate_root(&self, chain_id: u64) -> anyhow::Result<TransactionId> {
+        let state_bridge = self
+            .state_bridges
+            .get(&chain_id)
+            .ok_or_else(|| anyhow!("No state bridge for chain id: {}", chain_id))?;
+
+        self.ethereum
+            .send_transaction(state_bridge.propagate_root().tx, false)
+            .await
+            .map_err(|tx_err| anyhow!("{}", tx_err.to_string()))
+    }
+
+    /// Checks whether `root` is, or has been, the latest root on the secondary
+    /// chain `chain_id`.
+    #[instrument(level = "debug", skip(self))]
+    pub async fn is_root_on_secondary_chain(
+        &self,
+        chain_id: u64,
+        root: U256,
+    ) -> anyhow::Result<bool> {
+        let bridged_world_id = self
+            .bridged_world_ids
+            .get(&chain_id)
+            .ok_or_else(|| anyhow!("No relayed identity manager for chain id: {}", chain_id))?;
+
+        if bridged_world_id.root_history(root).call().await? != 0 {
+            return Ok(true);
+        }
+
+        Ok(bridged_world_id.latest_root().call().await? == root)
+    }
+
+    /// Checks whether `root` is known to the identity manager on mainnet.
     #[instrument(level = "debug", skip_all)]
-    pub async fn is_root_mined_multi_chain(&self, root: U256) -> anyhow::Result<bool> {
-        let (root_on_mainnet, ..) = self.abi.query_root(root).call().await?;
+    pub async fn is_root_mined(&self, root: U256) -> anyhow::Result<bool> {
+        self.contract.is_root_known(root, None).await
+    }
+
+    /// Checks whether `root` is known on mainnet and all secondary chains as of
+    /// the given block of each chain, keyed by contract address. Chains without
+    /// a block are checked at their latest block.
+    #[instrument(level = "debug", skip_all)]
+    pub async fn is_root_mined_multi_chain(
+        &self,
+        root: U256,
+        blocks: &HashMap<Address, u64>,
+    ) -> anyhow::Result<bool> {
+        let block = blocks.get(&self.contract.address()).copied();
 
-        if root_on_mainnet.is_zero() {
+        if !self.contract.is_root_known(root, block).await? {
             return Ok(false);
         }
 
         for bridged_world_id in &self.secondary_abis {
-            let root_timestamp = bridged_world_id.root_history(root).call().await?;
+            let mut root_history = bridged_world_id.root_history(root);
+            let mut latest_root = bridged_world_id.latest_root();
+            if let Some(block) = blocks.get(&bridged_world_id.address()) {
+                root_history = root_history.block(*block);
+                latest_root = latest_root.block(*block);
+            }
+
+            let root_timestamp = root_history.call().await?;
 
             // root_history only returns superseded roots, so we must also check the latest
             // root
-            let latest_root = bridged_world_id.latest_root().call().await?;
+            let latest_root = latest_root.call().await?;
 
             // If root is not superseded and it's not the latest root
             // then it's not mined
@@ -311,9 +578,14 @@ impl IdentityManager {
         Ok(true)
     }
 
+    /// Registers a prover for the provided batch size. If the batch size
+    /// already has provers, the new one is load-balanced together with them.
+    ///
     /// # Errors
     ///
-    /// Will return `Err` if the provided batch size already exists.
+    /// Will return `Err` if the provided prover is already registered for the
+    /// batch size.
+    #[cfg(feature = "submission")]
     pub async fn add_batch_size(
         &self,
         url: &impl ToString,
@@ -322,8 +594,8 @@ impl IdentityManager {
     ) -> Result<(), ServerError> {
         let mut map = self.insertion_prover_map.write().await;
 
-        if map.batch_size_exists(batch_size) {
-            return Err(ServerError::BatchSizeAlreadyExists);
+        if map.prover_exists(batch_size, &url.to_string()) {
+            return Err(ServerError::ProverAlreadyExists);
         }
 
         let prover = batch_insertion::Prover::new(&ProverConfiguration {
@@ -337,24 +609,51 @@ impl IdentityManager {
         Ok(())
     }
 
+    /// Removes the prover at `url` from the provided batch size, or all of the
+    /// batch size's provers if no `url` is given.
+    ///
     /// # Errors
     ///
     /// Will return `Err` if the batch size requested for removal doesn't exist
-    /// in the prover map.
-    pub async fn remove_batch_size(&self, batch_size: usize) -> Result<(), ServerError> {
+    /// in the prover map, or if the removal would leave no provers at all.
+    #[cfg(feature = "submission")]
+    pub async fn remove_batch_size(
+        &self,
+        batch_size: usize,
+        url: Option<&str>,
+    ) -> Result<(), ServerError> {
         let mut map = self.insertion_prover_map.write().await;
 
-        if map.len() == 1 {
+        // Removing a single prover only drops the batch size if it is the last
+        // prover serving it.
+        let removes_batch_size = match url {
+            Some(_) => {
+                map.batch_size_exists(batch_size)
+                    && map.get_group(batch_size).map_or(0, ProverGroup::len) == 1
+            }
+            None => true,
+        };
+
+        if removes_batch_size && map.len() == 1 {
             warn!("Attempting to remove the last batch size.");
             return Err(ServerError::CannotRemoveLastBatchSize);
         }
 
-        match map.remove(batch_size) {
-            Some(_) => Ok(()),
-            None => Err(ServerError::NoSuchBatchSize),
+        let removed = match url {
+            Some(url) => map
+                .remove_where(batch_size, |prover| prover.has_url(url))
+                .is_some(),
+            None => map.remove(batch_size).is_some(),
+        };
+
+        if removed {
+            Ok(())
+        } else {
+            Err(ServerError::NoSuchBatchSize)
         }
     }
 
+    #[cfg(feature = "submission")]
     pub async fn list_batch_sizes(&self) -> Result<Vec<ProverConfiguration>, ServerError> {
         Ok(self
             .insertion_prover_map
@@ -363,9 +662,51 @@ impl IdentityManager {
             .as_configuration_vec())
     }
 
+    #[cfg(feature = "submission")]
     pub async fn has_provers(&self) -> bool {
         self.insertion_prover_map.read().await.len() > 0
     }
+
+    /// Probes all registered provers so that unhealthy ones are skipped when
+    /// generating proofs.
+    #[cfg(feature = "submission")]
+    pub async fn check_prover_health(&self) {
+        check_health(&self.insertion_prover_map).await;
+    }
+}
+
+/// Proof-only builds have no provers, so there is nothing to list, and the
+/// routes that change them are refused like in observer mode.
+#[cfg(not(feature = "submission"))]
+impl IdentityManager {
+    pub async fn max_batch_size(&self) -> usize {
+        0
+    }
+
+    pub async fn list_batch_sizes(&self) -> Result<Vec<ProverConfiguration>, ServerError> {
+        Ok(vec![])
+    }
+
+    pub async fn has_provers(&self) -> bool {
+        false
+    }
+
+    pub async fn add_batch_size(
+        &self,
+        _url: &impl ToString,
+        _batch_size: usize,
+        _timeout_seconds: u64,
+    ) -> Result<(), ServerError> {
+        Err(ServerError::ObserverMode)
+    }
+
+    pub async fn remove_batch_size(
+        &self,
+        _batch_size: usize,
+        _url: Option<&str>,
+    ) -> Result<(), ServerError> {
+        Err(ServerError::ObserverMode)
+    }
 }
 
 /// A type for an identity manager object that can be sent across threads.
@@ -1,10 +1,46 @@
+use std::str::FromStr;
+
+use anyhow::Context;
 use ethers::providers::Middleware;
 use ethers::types::{Address, BlockNumber, Filter, FilterBlockOption, Log, Topic, ValueOrArray};
+use thiserror::Error;
+use tracing::warn;
+
+/// Decides which blocks of a chain are final.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FinalizationStrategy {
+    /// Blocks with at least this many blocks on top of them are final.
+    Confirmations(u64),
+    /// Blocks up to the one the node tags as `finalized` are final.
+    Finalized,
+}
+
+#[derive(Debug, Error)]
+#[error("expected `finalized` or a number of confirmations, got {0:?}")]
+pub struct InvalidFinalizationStrategy(String);
 
+impl FromStr for FinalizationStrategy {
+    type Err = InvalidFinalizationStrategy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "finalized" {
+            return Ok(Self::Finalized);
+        }
+
+        s.parse()
+            .map(Self::Confirmations)
+            .map_err(|_| InvalidFinalizationStrategy(s.to_owned()))
+    }
+}
+
+/// Scans the logs of final blocks, one window at a time. The window shrinks
+/// whenever the provider refuses to return the logs of a range, e.g. because
+/// of a limit on the number of blocks or logs per request.
 pub struct BlockScanner<T> {
     read_provider: T,
     current_block: u64,
     window_size:   u64,
+    finalization:  FinalizationStrategy,
 }
 
 impl<T> BlockScanner<T>
@@ -12,14 +48,66 @@ where
     T: Middleware,
     <T as Middleware>::Error: 'static,
 {
-    pub async fn new_latest(read_provider: T, window_size: u64) -> anyhow::Result<Self> {
-        let latest_block = read_provider.get_block_number().await?;
+    /// Creates a scanner starting at the latest final block.
+    pub async fn new_latest(
+        read_provider: T,
+        window_size: u64,
+        finalization: FinalizationStrategy,
+    ) -> anyhow::Result<Self> {
+        let mut scanner = Self {
+            read_provider,
+            current_block: 0,
+            window_size,
+            finalization,
+        };
+
+        scanner.current_block = scanner.final_block().await?;
+
+        Ok(scanner)
+    }
 
-        Ok(Self {
+    /// Creates a scanner starting at `next_block`, e.g. where a previous
+    /// scanner left off.
+    pub const fn new_at(
+        read_provider: T,
+        next_block: u64,
+        window_size: u64,
+        finalization: FinalizationStrategy,
+    ) -> Self {
+        Self {
             read_provider,
-            current_block: latest_block.as_u64(),
+            current_block: next_block,
             window_size,
-        })
+            finalization,
+        }
+    }
+
+    /// The next block to scan.
+    pub const fn next_block(&self) -> u64 {
+        self.current_block
+    }
+
+    /// Returns the number of the latest final block.
+    pub async fn final_block(&self) -> anyhow::Result<u64> {
+        match self.finalization {
+            FinalizationStrategy::Confirmations(confirmations) => {
+                let latest_block = self.read_provider.get_block_number().await?.as_u64();
+
+                Ok(latest_block.saturating_sub(confirmations))
+            }
+            FinalizationStrategy::Finalized => {
+                let block = self
+                    .read_provider
+                    .get_block(BlockNumber::Finalized)
+                    .await?
+                    .context("Node returned no finalized block")?;
+
+                Ok(block
+                    .number
+                    .context("Finalized block has no number")?
+                    .as_u64())
+            }
+        }
     }
 
     pub async fn next(
@@ -27,34 +115,59 @@ where
         address: Option<ValueOrArray<Address>>,
         topics: [Option<Topic>; 4],
     ) -> anyhow::Result<Vec<Log>> {
-        let latest_block = self.read_provider.get_block_number().await?.as_u64();
+        let final_block = self.final_block().await?;
 
-        if self.current_block >= latest_block {
+        if self.current_block >= final_block {
             return Ok(Vec::new());
         }
 
-        let from_block = self.current_block;
-        let to_block = latest_block.min(from_block + self.window_size);
-
-        let next_current_block = to_block + 1;
+        loop {
+            let from_block = self.current_block;
+            let to_block = final_block.min(from_block + self.window_size);
 
-        let from_block = Some(BlockNumber::Number(from_block.into()));
-        let to_block = Some(BlockNumber::Number(to_block.into()));
-
-        let logs = self
-            .read_provider
-            .get_logs(&Filter {
+            let filter = Filter {
                 block_option: FilterBlockOption::Range {
-                    from_block,
-                    to_block,
+                    from_block: Some(BlockNumber::Number(from_block.into())),
+                    to_block:   Some(BlockNumber::Number(to_block.into())),
                 },
-                address,
-                topics,
-            })
-            .await?;
+                address:      address.clone(),
+                topics:       topics.clone(),
+            };
+
+            match self.read_provider.get_logs(&filter).await {
+                Ok(logs) => {
+                    self.current_block = to_block + 1;
+                    return Ok(logs);
+                }
+                Err(error) if self.window_size > 0 => {
+                    self.window_size /= 2;
+                    warn!(
+                        ?error,
+                        from_block,
+                        window_size = self.window_size,
+                        "Failed to fetch logs, shrinking the scanning window"
+                    );
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+}
 
-        self.current_block = next_current_block;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok(logs)
+    #[test]
+    fn parses_finalization_strategy() {
+        assert_eq!(
+            "finalized".parse::<FinalizationStrategy>().unwrap(),
+            FinalizationStrategy::Finalized
+        );
+        assert_eq!(
+            "12".parse::<FinalizationStrategy>().unwrap(),
+            FinalizationStrategy::Confirmations(12)
+        );
+        assert!("safe".parse::<FinalizationStrategy>().is_err());
     }
 }
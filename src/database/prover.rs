@@ -13,12 +13,13 @@ pub struct ProverConfiguration {
 impl Hash for ProverConfiguration {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.batch_size.hash(state);
+        self.url.hash(state);
     }
 }
 
 impl PartialEq for ProverConfiguration {
     fn eq(&self, other: &Self) -> bool {
-        self.batch_size == other.batch_size
+        self.batch_size == other.batch_size && self.url == other.url
     }
 }
 
@@ -0,0 +1,221 @@
+//! Parses the changes to the identities and unprocessed identities tables
+//! emitted by the `test_decoding` logical decoding plugin.
+
+use crate::identity_tree::{Hash, ProcessedStatus};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IdentityChange {
+    Inserted {
+        leaf_index: usize,
+        commitment: Hash,
+        root:       Hash,
+    },
+    StatusChanged {
+        leaf_index: usize,
+        root:       Hash,
+        status:     ProcessedStatus,
+    },
+}
+
+/// Parses a single line of `test_decoding` output, such as
+/// `table public.identities: INSERT: leaf_index[bigint]:0 ...`. Returns `None`
+/// for anything that isn't an insert or update of an identity.
+#[must_use]
+pub fn parse_identity_change(data: &str) -> Option<IdentityChange> {
+    let rest = data.strip_prefix("table public.identities: ")?;
+    let (action, columns) = rest.split_once(": ")?;
+    let columns = parse_columns(columns);
+
+    let column = |name: &str| {
+        columns
+            .iter()
+            .find(|(column, _)| *column == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let leaf_index = column("leaf_index")?.parse().ok()?;
+    let root = parse_bytea(column("root")?)?;
+
+    match action {
+        "INSERT" => Some(IdentityChange::Inserted {
+            leaf_index,
+            commitment: parse_bytea(column("commitment")?)?,
+            root,
+        }),
+        "UPDATE" => Some(IdentityChange::StatusChanged {
+            leaf_index,
+            root,
+            status: column("status")?.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// An identity queued for insertion, as mirrored by a standby.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueuedIdentity {
+    pub commitment: Hash,
+    pub request_id: Option<String>,
+}
+
+/// A change mirrored by a standby: either to the tree, or to the queue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Identity(IdentityChange),
+    Queued(QueuedIdentity),
+}
+
+/// Parses a single line of `test_decoding` output into a change to the
+/// identities table or an insert into the unprocessed identities table.
+/// Returns `None` for anything else.
+#[must_use]
+pub fn parse_change(data: &str) -> Option<Change> {
+    if let Some(change) = parse_identity_change(data) {
+        return Some(Change::Identity(change));
+    }
+
+    let columns = data.strip_prefix("table public.unprocessed_identities: INSERT: ")?;
+    let columns = parse_columns(columns);
+
+    let column = |name: &str| {
+        columns
+            .iter()
+            .find(|(column, _)| *column == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    Some(Change::Queued(QueuedIdentity {
+        commitment: parse_bytea(column("commitment")?)?,
+        request_id: column("request_id")
+            .filter(|request_id| *request_id != "null")
+            .map(ToString::to_string),
+    }))
+}
+
+/// Splits `name[type]:value` pairs. Quoted values may contain spaces and
+/// escape quotes by doubling them.
+fn parse_columns(mut input: &str) -> Vec<(&str, String)> {
+    let mut columns = Vec::new();
+
+    loop {
+        input = input.trim_start();
+        let Some(type_start) = input.find('[') else {
+            return columns;
+        };
+        let Some(value_start) = input.find("]:") else {
+            return columns;
+        };
+
+        let name = &input[..type_start];
+        input = &input[value_start + 2..];
+
+        let value = if let Some(quoted) = input.strip_prefix('\'') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices().peekable();
+            let mut end = quoted.len();
+
+            while let Some((i, c)) = chars.next() {
+                if c == '\'' {
+                    if matches!(chars.peek(), Some((_, '\''))) {
+                        chars.next();
+                    } else {
+                        end = i + 1;
+                        break;
+                    }
+                }
+                value.push(c);
+            }
+
+            input = &quoted[end..];
+            value
+        } else {
+            let end = input.find(' ').unwrap_or(input.len());
+            let value = input[..end].to_string();
+            input = &input[end..];
+            value
+        };
+
+        columns.push((name, value));
+    }
+}
+
+fn parse_bytea(value: &str) -> Option<Hash> {
+    let bytes = hex::decode(value.strip_prefix("\\x")?).ok()?;
+    Hash::try_from_be_slice(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_inserts_and_updates() {
+        let insert = "table public.identities: INSERT: leaf_index[bigint]:3 \
+                      commitment[bytea]:'\\x0a' root[bytea]:'\\x0b' status[character \
+                      varying]:'pending' pending_as_of[timestamp with time zone]:'2023-09-01 \
+                      10:00:00+00' mined_at[timestamp with time zone]:null";
+
+        assert_eq!(
+            parse_identity_change(insert),
+            Some(IdentityChange::Inserted {
+                leaf_index: 3,
+                commitment: Hash::from(10),
+                root:       Hash::from(11),
+            })
+        );
+
+        let update = "table public.identities: UPDATE: leaf_index[bigint]:3 \
+                      commitment[bytea]:'\\x0a' root[bytea]:'\\x0b' status[character \
+                      varying]:'mined' pending_as_of[timestamp with time zone]:'2023-09-01 \
+                      10:00:00+00' mined_at[timestamp with time zone]:null";
+
+        assert_eq!(
+            parse_identity_change(update),
+            Some(IdentityChange::StatusChanged {
+                leaf_index: 3,
+                root:       Hash::from(11),
+                status:     ProcessedStatus::Mined,
+            })
+        );
+
+        assert_eq!(
+            parse_identity_change("table public.identities: DELETE: leaf_index[bigint]:3"),
+            None
+        );
+        assert_eq!(
+            parse_identity_change("table public.batches: INSERT: batch_size[integer]:3"),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_queued_identities() {
+        let insert = "table public.unprocessed_identities: INSERT: commitment[bytea]:'\\x0a' \
+                      status[character varying]:'new' created_at[timestamp with time \
+                      zone]:'2023-09-01 10:00:00+00' request_id[text]:'abc'";
+
+        assert_eq!(
+            parse_change(insert),
+            Some(Change::Queued(QueuedIdentity {
+                commitment: Hash::from(10),
+                request_id: Some("abc".to_string()),
+            }))
+        );
+
+        let without_request_id = "table public.unprocessed_identities: INSERT: \
+                                  commitment[bytea]:'\\x0a' request_id[text]:null";
+
+        assert_eq!(
+            parse_change(without_request_id),
+            Some(Change::Queued(QueuedIdentity {
+                commitment: Hash::from(10),
+                request_id: None,
+            }))
+        );
+
+        assert_eq!(
+            parse_change("table public.unprocessed_identities: UPDATE: commitment[bytea]:'\\x0a'"),
+            None
+        );
+    }
+}
@@ -0,0 +1,460 @@
+//! Typed rows of the queries in this module. Columns are read by name rather
+//! than position, so that reordering the columns of a query or table can't
+//! silently shift values into the wrong fields, and integers are converted
+//! with range checks instead of casts.
+
+use chrono::{DateTime, Utc};
+use ethers::types::{Address, H256, U256};
+use sqlx::FromRow;
+
+use super::{proof_from_bytes, prover, types, Error};
+use crate::identity_tree::{CommitmentError, Hash, TreeItem, TreeUpdate, UnprocessedStatus};
+
+/// Converts the integer in `column` to the type of a field.
+pub(super) fn convert<T, U: TryFrom<T>>(value: T, column: &'static str) -> Result<U, Error> {
+    U::try_from(value).map_err(|_| Error::OutOfRange { column })
+}
+
+fn commitment_error(
+    code: Option<String>,
+    details: Option<String>,
+) -> Result<Option<CommitmentError>, Error> {
+    let Some(code) = code else {
+        return Ok(None);
+    };
+
+    let details = details
+        .and_then(|details| serde_json::from_str(&details).ok())
+        .unwrap_or_default();
+
+    Ok(Some(CommitmentError::new(code.parse()?, details)))
+}
+
+#[derive(FromRow)]
+pub struct TreeItemRow {
+    leaf_index: i64,
+    status:     String,
+}
+
+impl TryFrom<TreeItemRow> for TreeItem {
+    type Error = Error;
+
+    fn try_from(row: TreeItemRow) -> Result<Self, Error> {
+        Ok(Self {
+            status:     row.status.parse()?,
+            leaf_index: convert(row.leaf_index, "leaf_index")?,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct TreeUpdateRow {
+    leaf_index: i64,
+    commitment: Hash,
+}
+
+impl TryFrom<TreeUpdateRow> for TreeUpdate {
+    type Error = Error;
+
+    fn try_from(row: TreeUpdateRow) -> Result<Self, Error> {
+        Ok(Self {
+            leaf_index: convert(row.leaf_index, "leaf_index")?,
+            element:    row.commitment,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct IdentityRow {
+    leaf_index: i64,
+    commitment: Hash,
+    root:       Hash,
+    status:     String,
+}
+
+impl TryFrom<IdentityRow> for types::Identity {
+    type Error = Error;
+
+    fn try_from(row: IdentityRow) -> Result<Self, Error> {
+        Ok(Self {
+            leaf_index: convert(row.leaf_index, "leaf_index")?,
+            commitment: row.commitment,
+            root:       row.root,
+            status:     row.status.parse()?,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct IdentityRecordRow {
+    leaf_index:    i64,
+    commitment:    Hash,
+    root:          Hash,
+    status:        String,
+    pending_as_of: DateTime<Utc>,
+    mined_at:      Option<DateTime<Utc>>,
+    received_at:   Option<DateTime<Utc>>,
+}
+
+impl TryFrom<IdentityRecordRow> for types::IdentityRecord {
+    type Error = Error;
+
+    fn try_from(row: IdentityRecordRow) -> Result<Self, Error> {
+        Ok(Self {
+            leaf_index:    convert(row.leaf_index, "leaf_index")?,
+            commitment:    row.commitment,
+            root:          row.root,
+            status:        row.status.parse()?,
+            pending_as_of: row.pending_as_of,
+            mined_at:      row.mined_at,
+            received_at:   row.received_at,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct RootStateRow {
+    pub status:              String,
+    pub pending_valid_as_of: DateTime<Utc>,
+    pub mined_valid_as_of:   Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow)]
+pub struct UnprocessedRow {
+    commitment:    Hash,
+    status:        String,
+    created_at:    DateTime<Utc>,
+    processed_at:  Option<DateTime<Utc>>,
+    error_code:    Option<String>,
+    error_details: Option<String>,
+    request_id:    Option<String>,
+}
+
+impl TryFrom<UnprocessedRow> for types::UnprocessedCommitment {
+    type Error = Error;
+
+    fn try_from(row: UnprocessedRow) -> Result<Self, Error> {
+        Ok(Self {
+            commitment:   row.commitment,
+            status:       row.status.parse()?,
+            created_at:   row.created_at,
+            processed_at: row.processed_at,
+            error:        commitment_error(row.error_code, row.error_details)?,
+            request_id:   row.request_id,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct UnprocessedStatusRow {
+    status:        String,
+    error_code:    Option<String>,
+    error_details: Option<String>,
+}
+
+impl TryFrom<UnprocessedStatusRow> for (UnprocessedStatus, Option<CommitmentError>) {
+    type Error = Error;
+
+    fn try_from(row: UnprocessedStatusRow) -> Result<Self, Error> {
+        Ok((
+            row.status.parse()?,
+            commitment_error(row.error_code, row.error_details)?,
+        ))
+    }
+}
+
+#[derive(FromRow)]
+pub struct FailedBatchRow {
+    post_root:   Hash,
+    pre_root:    Hash,
+    start_index: i64,
+    prover_url:  String,
+    status_code: i32,
+    error_body:  String,
+    failed_at:   DateTime<Utc>,
+}
+
+impl TryFrom<FailedBatchRow> for types::FailedBatch {
+    type Error = Error;
+
+    fn try_from(row: FailedBatchRow) -> Result<Self, Error> {
+        Ok(Self {
+            post_root:   row.post_root,
+            pre_root:    row.pre_root,
+            start_index: convert(row.start_index, "start_index")?,
+            prover_url:  row.prover_url,
+            status_code: convert(row.status_code, "status_code")?,
+            error_body:  row.error_body,
+            failed_at:   row.failed_at,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct BatchRow {
+    post_root:        Hash,
+    pre_root:         Hash,
+    start_index:      i64,
+    end_index:        i64,
+    batch_size:       i32,
+    prover_url:       Option<String>,
+    proof:            Option<Vec<u8>>,
+    transaction_id:   Option<String>,
+    transaction_hash: Option<Vec<u8>>,
+    status:           String,
+    created_at:       DateTime<Utc>,
+    updated_at:       DateTime<Utc>,
+}
+
+impl TryFrom<BatchRow> for types::Batch {
+    type Error = Error;
+
+    fn try_from(row: BatchRow) -> Result<Self, Error> {
+        Ok(Self {
+            post_root:        row.post_root,
+            pre_root:         row.pre_root,
+            start_index:      convert(row.start_index, "start_index")?,
+            end_index:        convert(row.end_index, "end_index")?,
+            batch_size:       convert(row.batch_size, "batch_size")?,
+            prover_url:       row.prover_url,
+            proof:            row
+                .proof
+                .map(|proof| proof_from_bytes(&proof))
+                .transpose()?,
+            transaction_id:   row.transaction_id,
+            transaction_hash: row.transaction_hash.map(|hash| H256::from_slice(&hash)),
+            status:           row.status.parse()?,
+            created_at:       row.created_at,
+            updated_at:       row.updated_at,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct ProverRow {
+    batch_size: i64,
+    url:        String,
+    timeout_s:  i64,
+}
+
+impl TryFrom<ProverRow> for prover::ProverConfiguration {
+    type Error = Error;
+
+    fn try_from(row: ProverRow) -> Result<Self, Error> {
+        Ok(Self {
+            url:        row.url,
+            batch_size: convert(row.batch_size, "batch_size")?,
+            timeout_s:  convert(row.timeout_s, "timeout_s")?,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct BatchCostsRow {
+    period_start: DateTime<Utc>,
+    batches:      i64,
+    identities:   i64,
+    gas_used:     i64,
+    cost_wei:     String,
+    cost_eth:     f64,
+}
+
+impl From<BatchCostsRow> for types::BatchCosts {
+    fn from(row: BatchCostsRow) -> Self {
+        #[allow(clippy::cast_precision_loss)]
+        let cost_per_identity_eth =
+            (row.identities > 0).then(|| row.cost_eth / row.identities as f64);
+
+        Self {
+            period_start: row.period_start,
+            batches: row.batches,
+            identities: row.identities,
+            gas_used: row.gas_used,
+            cost_wei: row.cost_wei,
+            cost_eth: row.cost_eth,
+            cost_per_identity_eth,
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct ApiKeyQuotaRow {
+    api_key:       String,
+    daily_limit:   Option<i64>,
+    monthly_limit: Option<i64>,
+    daily_usage:   i64,
+    monthly_usage: i64,
+}
+
+impl TryFrom<ApiKeyQuotaRow> for types::ApiKeyQuota {
+    type Error = Error;
+
+    fn try_from(row: ApiKeyQuotaRow) -> Result<Self, Error> {
+        Ok(Self {
+            api_key:       row.api_key,
+            daily_limit:   row
+                .daily_limit
+                .map(|limit| convert(limit, "daily_limit"))
+                .transpose()?,
+            monthly_limit: row
+                .monthly_limit
+                .map(|limit| convert(limit, "monthly_limit"))
+                .transpose()?,
+            daily_usage:   convert(row.daily_usage, "daily_usage")?,
+            monthly_usage: convert(row.monthly_usage, "monthly_usage")?,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct RootPropagationRow {
+    id:             i64,
+    root:           Hash,
+    chain_id:       i64,
+    status:         String,
+    transaction_id: Option<String>,
+    updated_at:     DateTime<Utc>,
+}
+
+impl TryFrom<RootPropagationRow> for types::RootPropagation {
+    type Error = Error;
+
+    fn try_from(row: RootPropagationRow) -> Result<Self, Error> {
+        Ok(Self {
+            id:             row.id,
+            root:           row.root,
+            chain_id:       convert(row.chain_id, "chain_id")?,
+            status:         row.status.parse()?,
+            transaction_id: row.transaction_id,
+            updated_at:     row.updated_at,
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct StoredEventRow {
+    id:         i64,
+    payload:    String,
+    attempts:   i32,
+    last_error: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<StoredEventRow> for types::StoredEvent {
+    fn from(row: StoredEventRow) -> Self {
+        Self {
+            id:         row.id,
+            payload:    row.payload,
+            attempts:   row.attempts,
+            last_error: row.last_error,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct AuditEntryRow {
+    id:           i64,
+    created_at:   DateTime<Utc>,
+    method:       String,
+    path:         String,
+    api_key:      Option<String>,
+    source_ip:    String,
+    payload_hash: String,
+    outcome:      i32,
+}
+
+impl TryFrom<AuditEntryRow> for types::StoredAuditEntry {
+    type Error = Error;
+
+    fn try_from(row: AuditEntryRow) -> Result<Self, Error> {
+        Ok(Self {
+            id:         row.id,
+            created_at: row.created_at,
+            entry:      types::AuditEntry {
+                method:       row.method,
+                path:         row.path,
+                api_key:      row.api_key,
+                source_ip:    row.source_ip,
+                payload_hash: row.payload_hash,
+                outcome:      convert(row.outcome, "outcome")?,
+            },
+        })
+    }
+}
+
+#[derive(FromRow)]
+pub struct SignerTransactionRow {
+    transaction_hash: Vec<u8>,
+    address: Vec<u8>,
+    nonce: i64,
+    data: Option<Vec<u8>>,
+    raw: Vec<u8>,
+    sent_at_block: Option<i64>,
+    max_fee_per_gas: Option<Vec<u8>>,
+    max_priority_fee_per_gas: Option<Vec<u8>>,
+}
+
+impl TryFrom<SignerTransactionRow> for types::SignerTransaction {
+    type Error = Error;
+
+    fn try_from(row: SignerTransactionRow) -> Result<Self, Error> {
+        let attempt = row
+            .sent_at_block
+            .map(|sent_at_block| {
+                Ok::<_, Error>(types::TransactionAttempt {
+                    sent_at_block:            convert(sent_at_block, "sent_at_block")?,
+                    max_fee_per_gas:          row
+                        .max_fee_per_gas
+                        .map_or_else(U256::zero, |bytes| U256::from_big_endian(&bytes)),
+                    max_priority_fee_per_gas: row
+                        .max_priority_fee_per_gas
+                        .map(|bytes| U256::from_big_endian(&bytes)),
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            transaction_hash: H256::from_slice(&row.transaction_hash),
+            address: Address::from_slice(&row.address),
+            nonce: convert(row.nonce, "nonce")?,
+            data: row.data,
+            raw: row.raw,
+            attempt,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_negative_leaf_indices() {
+        let row = TreeUpdateRow {
+            leaf_index: -1,
+            commitment: Hash::ZERO,
+        };
+
+        assert!(matches!(
+            TreeUpdate::try_from(row),
+            Err(Error::OutOfRange {
+                column: "leaf_index",
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_statuses() {
+        let row = UnprocessedStatusRow {
+            status:        "lost".to_string(),
+            error_code:    None,
+            error_details: None,
+        };
+
+        assert!(matches!(
+            <(UnprocessedStatus, Option<CommitmentError>)>::try_from(row),
+            Err(Error::UnknownStatus(_))
+        ));
+    }
+}
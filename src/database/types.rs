@@ -1,11 +1,337 @@
+use std::str::FromStr;
+
 use chrono::{DateTime, Utc};
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-use crate::identity_tree::{Hash, Status};
+use crate::identity_tree::{CommitmentError, Hash, ProcessedStatus, UnprocessedStatus};
+use crate::prover::Proof;
 
+#[derive(Clone)]
 pub struct UnprocessedCommitment {
+    pub commitment:   Hash,
+    pub status:       UnprocessedStatus,
+    pub created_at:   DateTime<Utc>,
+    pub processed_at: Option<DateTime<Utc>>,
+    pub error:        Option<CommitmentError>,
+    /// The id of the API request that queued the identity.
+    pub request_id:   Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedBatch {
+    pub post_root:   Hash,
+    pub pre_root:    Hash,
+    pub start_index: usize,
+    pub prover_url:  String,
+    pub status_code: u16,
+    pub error_body:  String,
+    pub failed_at:   DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredEvent {
+    pub id:         i64,
+    /// The JSON encoded event.
+    pub payload:    String,
+    pub attempts:   i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A mutating API call, as recorded in the audit log.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub method:       String,
+    pub path:         String,
+    /// A fingerprint of the credentials the call was made with.
+    pub api_key:      Option<String>,
+    pub source_ip:    String,
+    /// The hex encoded SHA-256 of the request body.
+    pub payload_hash: String,
+    /// The HTTP status code of the response.
+    pub outcome:      u16,
+}
+
+/// A period over which the insertions made with an API key are counted, in
+/// UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum QuotaPeriod {
+    Day,
+    Month,
+}
+
+impl From<QuotaPeriod> for &str {
+    fn from(period: QuotaPeriod) -> Self {
+        match period {
+            QuotaPeriod::Day => "day",
+            QuotaPeriod::Month => "month",
+        }
+    }
+}
+
+impl std::fmt::Display for QuotaPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+/// The outcome of counting an insertion against the quotas of an API key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaConsumption {
+    /// The insertion was counted, bringing the usage to `daily` and `monthly`.
+    Consumed { daily: u64, monthly: u64 },
+    /// The quota of the period is used up, and nothing was counted.
+    Exceeded(QuotaPeriod),
+}
+
+/// The quota of an API key and its usage in the current periods.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApiKeyQuota {
+    pub api_key:       String,
+    pub daily_limit:   Option<u64>,
+    pub monthly_limit: Option<u64>,
+    pub daily_usage:   u64,
+    pub monthly_usage: u64,
+}
+
+/// Why and since when batching has been paused.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchingPause {
+    pub reason:    Option<String>,
+    pub paused_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredAuditEntry {
+    pub id:         i64,
+    pub created_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub entry:      AuditEntry,
+}
+
+/// Percentiles of the seconds from receiving an identity to reaching `stage`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StageLatency {
+    pub stage: String,
+    pub count: i64,
+    pub p50:   f64,
+    pub p90:   f64,
+    pub p99:   f64,
+}
+
+/// The costs of the batches submitted in the period starting at
+/// `period_start`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchCosts {
+    pub period_start:          DateTime<Utc>,
+    pub batches:               i64,
+    /// Excluding padding.
+    pub identities:            i64,
+    pub gas_used:              i64,
+    /// A decimal string, as the costs can exceed 64 bits.
+    pub cost_wei:              String,
+    pub cost_eth:              f64,
+    /// The cost amortized over the identities of the batches.
+    pub cost_per_identity_eth: Option<f64>,
+}
+
+/// Where a batch is in its lifecycle. Batches move through the statuses in
+/// declaration order, unless they fail.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum BatchStatus {
+    /// The batch has been built and is being proven.
+    Proving,
+    /// A proof has been generated but the batch has not been submitted yet.
+    Proven,
+    /// The batch has been handed to the transaction relayer.
+    Submitted,
+    /// The transaction has been mined on mainnet.
+    Mined,
+    /// The root has been mined and relayed to all secondary chains.
+    Finalized,
+    /// Proving or submitting the batch failed.
+    Failed,
+    /// An operator cancelled the batch before it was submitted.
+    Cancelled,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown batch status")]
+pub struct UnknownBatchStatus;
+
+impl FromStr for BatchStatus {
+    type Err = UnknownBatchStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "proving" => Ok(Self::Proving),
+            "proven" => Ok(Self::Proven),
+            "submitted" => Ok(Self::Submitted),
+            "mined" => Ok(Self::Mined),
+            "finalized" => Ok(Self::Finalized),
+            "failed" => Ok(Self::Failed),
+            "cancelled" => Ok(Self::Cancelled),
+            _ => Err(UnknownBatchStatus),
+        }
+    }
+}
+
+impl From<BatchStatus> for &str {
+    fn from(status: BatchStatus) -> Self {
+        match status {
+            BatchStatus::Proving => "proving",
+            BatchStatus::Proven => "proven",
+            BatchStatus::Submitted => "submitted",
+            BatchStatus::Mined => "mined",
+            BatchStatus::Finalized => "finalized",
+            BatchStatus::Failed => "failed",
+            BatchStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Batch {
+    pub post_root:        Hash,
+    pub pre_root:         Hash,
+    /// The leaves of the identities in the batch, excluding padding.
+    pub start_index:      usize,
+    pub end_index:        usize,
+    pub batch_size:       usize,
+    pub prover_url:       Option<String>,
+    pub proof:            Option<Proof>,
+    /// The id of the transaction in the relayer.
+    pub transaction_id:   Option<String>,
+    pub transaction_hash: Option<H256>,
+    pub status:           BatchStatus,
+    pub created_at:       DateTime<Utc>,
+    pub updated_at:       DateTime<Utc>,
+}
+
+/// Where the propagation of a root to a secondary chain is.
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum PropagationStatus {
+    /// The root is final on mainnet and waiting to be propagated.
+    Pending,
+    /// The state bridge transaction has been mined.
+    Submitted,
+    /// The root is known on the secondary chain.
+    Propagated,
+    /// A later root was propagated first, so this one never will be.
+    Superseded,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown propagation status")]
+pub struct UnknownPropagationStatus;
+
+impl FromStr for PropagationStatus {
+    type Err = UnknownPropagationStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "submitted" => Ok(Self::Submitted),
+            "propagated" => Ok(Self::Propagated),
+            "superseded" => Ok(Self::Superseded),
+            _ => Err(UnknownPropagationStatus),
+        }
+    }
+}
+
+impl From<PropagationStatus> for &str {
+    fn from(status: PropagationStatus) -> Self {
+        match status {
+            PropagationStatus::Pending => "pending",
+            PropagationStatus::Submitted => "submitted",
+            PropagationStatus::Propagated => "propagated",
+            PropagationStatus::Superseded => "superseded",
+        }
+    }
+}
+
+/// The transaction that published a root on mainnet.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RootTransaction {
+    /// The id of the transaction the batch of the root was submitted with in
+    /// the relayer, if the root was published by a batch of this sequencer.
+    /// The relayer may have mined a replacement with another hash.
+    pub submission_id:    Option<String>,
+    pub transaction_hash: H256,
+    pub block_number:     u64,
+    /// Absent for roots mined before block hashes were recorded.
+    pub block_hash:       Option<H256>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RootPropagation {
+    #[serde(skip)]
+    pub id:             i64,
+    pub root:           Hash,
+    pub chain_id:       u64,
+    pub status:         PropagationStatus,
+    /// The id of the state bridge transaction in the relayer.
+    pub transaction_id: Option<String>,
+    pub updated_at:     DateTime<Utc>,
+}
+
+/// A row of the identities table, with its timestamps.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityRecord {
+    pub leaf_index:    usize,
     pub commitment:    Hash,
-    pub status:        Status,
-    pub created_at:    DateTime<Utc>,
-    pub processed_at:  Option<DateTime<Utc>>,
-    pub error_message: Option<String>,
+    pub root:          Hash,
+    pub status:        ProcessedStatus,
+    pub pending_as_of: DateTime<Utc>,
+    pub mined_at:      Option<DateTime<Utc>>,
+    pub received_at:   Option<DateTime<Utc>>,
+}
+
+/// A row of the identities table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub leaf_index: usize,
+    pub commitment: Hash,
+    /// The root after inserting the commitment.
+    pub root:       Hash,
+    pub status:     ProcessedStatus,
+}
+
+/// A transaction signed by a locally held key.
+#[derive(Clone, Debug)]
+pub struct SignerTransaction {
+    pub transaction_hash: H256,
+    pub address:          Address,
+    pub nonce:            u64,
+    pub data:             Option<Vec<u8>>,
+    /// The RLP encoded signed transaction.
+    pub raw:              Vec<u8>,
+    /// `None` for transactions signed before attempts were recorded.
+    pub attempt:          Option<TransactionAttempt>,
+}
+
+/// When a signed transaction was sent and the fees it pays.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransactionAttempt {
+    pub sent_at_block:            u64,
+    /// The gas price of legacy transactions.
+    pub max_fee_per_gas:          U256,
+    /// `None` for legacy transactions.
+    pub max_priority_fee_per_gas: Option<U256>,
 }
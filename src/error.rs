@@ -0,0 +1,151 @@
+//! The errors of the sequencer. Subsystems keep their own error types, which
+//! [`SequencerError`] wraps with their sources intact for logging. API clients
+//! only ever see the [`ErrorCategory`] and code of an error, which are stable,
+//! and a message that may change.
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+
+use hyper::StatusCode;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::database;
+use crate::ethereum::{EventError, TxError};
+use crate::prover::batch_insertion::ProverRejection;
+
+/// What kind of failure an error is, as reported to API clients. The names are
+/// part of the API and never change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// The request is malformed or refers to something it can't be applied to.
+    InvalidRequest,
+    /// The request refers to something that doesn't exist.
+    NotFound,
+    /// The request conflicts with the state of the sequencer.
+    Conflict,
+    /// The request isn't allowed.
+    Forbidden,
+    /// The tree has no room left for the request.
+    CapacityExhausted,
+    /// The client has used up its quota for now.
+    QuotaExceeded,
+    /// The sequencer or one of its dependencies is temporarily unavailable, so
+    /// the request may succeed when retried.
+    Unavailable,
+    /// The sequencer failed.
+    Internal,
+}
+
+impl ErrorCategory {
+    #[must_use]
+    pub const fn status_code(self) -> StatusCode {
+        match self {
+            Self::InvalidRequest => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict => StatusCode::CONFLICT,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::CapacityExhausted => StatusCode::INSUFFICIENT_STORAGE,
+            Self::QuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+            Self::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// An error of one of the subsystems of the sequencer.
+#[derive(Debug, Error)]
+pub enum SequencerError {
+    #[error("database error")]
+    Database(#[from] database::Error),
+    #[error("transaction error")]
+    Transaction(#[from] TxError),
+    #[error("error reading contract events")]
+    Events(#[from] EventError),
+    #[error("prover error")]
+    Prover(#[from] ProverRejection),
+    #[error("http error")]
+    Hyper(#[from] hyper::Error),
+    #[error("http error")]
+    Http(#[from] hyper::http::Error),
+    #[error("timed out")]
+    Timeout(#[from] tokio::time::error::Elapsed),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl SequencerError {
+    #[must_use]
+    pub const fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Database(
+                database::Error::DuplicateCommitment { .. }
+                | database::Error::CommitmentExists { .. },
+            ) => ErrorCategory::Conflict,
+            Self::Database(database::Error::Transient { .. }) => ErrorCategory::Unavailable,
+            _ => ErrorCategory::Internal,
+        }
+    }
+
+    /// The stable code of the error, for clients to match on.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::Database(
+                database::Error::DuplicateCommitment { .. }
+                | database::Error::CommitmentExists { .. },
+            ) => "duplicate_commitment",
+            Self::Database(database::Error::Transient { .. }) => "database_unavailable",
+            Self::Database(_) => "database_error",
+            Self::Transaction(_) => "transaction_error",
+            Self::Events(_) => "event_error",
+            Self::Prover(_) => "prover_error",
+            Self::Hyper(_) | Self::Http(_) => "http_error",
+            Self::Timeout(_) => "timeout",
+            Self::Other(_) => "internal_error",
+        }
+    }
+}
+
+/// Displays an error followed by its sources, e.g. `database error: pool timed
+/// out while waiting for an open connection`. Sources that an error already
+/// includes in its message are skipped.
+pub struct Chain<'a>(pub &'a (dyn StdError + 'static));
+
+impl Display for Chain<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut message = self.0.to_string();
+        write!(f, "{message}")?;
+
+        let mut source = self.0.source();
+        while let Some(error) = source {
+            let next = error.to_string();
+            if !message.ends_with(&next) {
+                write!(f, ": {next}")?;
+            }
+            message = next;
+            source = error.source();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn chains_include_the_sources() {
+        let error = SequencerError::from(anyhow!("connection refused").context("fetching roots"));
+
+        assert_eq!(
+            Chain(&error).to_string(),
+            "fetching roots: connection refused"
+        );
+        assert_eq!(error.category(), ErrorCategory::Internal);
+        assert_eq!(error.code(), "internal_error");
+    }
+}
@@ -1,22 +1,65 @@
+// Sending transactions is only needed to submit batches.
+#![cfg_attr(not(feature = "submission"), allow(dead_code))]
+
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
+#[cfg(all(feature = "submission", not(feature = "oz")))]
+use anyhow::bail;
+#[cfg(feature = "submission")]
+use anyhow::ensure;
 use anyhow::Result as AnyhowResult;
 use clap::Parser;
 use ethers::types::transaction::eip2718::TypedTransaction;
 use ethers::types::Address;
 pub use read::{EventError, ReadProvider};
+use thiserror::Error;
 use tracing::instrument;
 use url::Url;
 pub use write::TxError;
 
-use self::write::{TransactionId, WriteProvider};
+use self::read::failover::FailoverConfig;
+use self::write::{ReadOnly, TransactionId, WriteProvider};
+#[cfg(feature = "submission")]
+use crate::database::Database;
 use crate::serde_utils::JsonStrWrapper;
 
 pub mod read;
 pub mod write;
 
+#[cfg(feature = "submission")]
+mod write_local;
+#[cfg(feature = "oz")]
 mod write_oz;
+#[cfg(feature = "submission")]
+mod write_simulated;
+
+#[derive(Debug, Error)]
+#[error("invalid transaction backend {0:?}, expected `oz` or `local`")]
+pub struct InvalidTxBackend(String);
+
+/// How transactions are signed and submitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxBackend {
+    /// Through the OpenZeppelin Defender relayer, which holds the key.
+    Oz,
+    /// Signed with `--signing-key` and sent to the Ethereum provider.
+    Local,
+}
+
+impl FromStr for TxBackend {
+    type Err = InvalidTxBackend;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oz" => Ok(Self::Oz),
+            "local" => Ok(Self::Local),
+            _ => Err(InvalidTxBackend(s.to_string())),
+        }
+    }
+}
 
 // TODO: Log and metrics for signer / nonces.
 #[derive(Clone, Debug, PartialEq, Parser)]
@@ -26,12 +69,74 @@ pub struct Options {
     #[clap(long, env, default_value = "http://localhost:8545")]
     pub ethereum_provider: Url,
 
+    /// Additional Ethereum API providers, separated by commas. Requests fail
+    /// over to them in order when `--ethereum-provider` errors, is rate
+    /// limited or falls behind.
+    #[clap(long, env, value_delimiter = ',')]
+    pub ethereum_fallback_providers: Vec<Url>,
+
+    /// Providers that locally signed transactions are sent to, separated by
+    /// commas, e.g. a private mempool. Defaults to the Ethereum providers.
+    #[clap(long, env, value_delimiter = ',')]
+    pub transaction_providers: Vec<Url>,
+
+    /// How many blocks a provider may be behind the others before requests
+    /// fail over from it
+    #[clap(long, env, default_value = "5")]
+    pub provider_max_block_lag: u64,
+
+    /// How long a provider is skipped after a failed request (seconds)
+    #[clap(long, env, default_value = "30")]
+    pub provider_cooldown_seconds: u64,
+
+    /// How often the block numbers of the providers are compared (seconds)
+    #[clap(long, env, default_value = "15")]
+    pub provider_check_interval_seconds: u64,
+
     /// Provider urls for the secondary chains
     #[clap(long, env, default_value = "[]")]
     pub secondary_providers: JsonStrWrapper<Vec<Url>>,
 
+    /// How transactions are submitted: `oz` to go through a relayer, so that
+    /// no private key is held by the sequencer, or `local` to sign them with
+    /// `--signing-key`. Builds without the `oz` feature only support `local`.
+    #[cfg_attr(feature = "oz", clap(long, env, default_value = "oz"))]
+    #[cfg_attr(not(feature = "oz"), clap(long, env, default_value = "local"))]
+    pub tx_backend: TxBackend,
+
+    /// Runs the whole pipeline, but only simulates transactions with
+    /// `eth_estimateGas` and `eth_call` and logs the results, instead of
+    /// sending them. The database is marked as simulated, and a sequencer that
+    /// isn't a dry run refuses to use it afterwards.
+    #[clap(long, env)]
+    pub dry_run: bool,
+
+    #[cfg(feature = "oz")]
     #[clap(flatten)]
     pub write_options: write_oz::Options,
+
+    #[cfg(feature = "submission")]
+    #[clap(flatten)]
+    pub local_write_options: write_local::Options,
+}
+
+impl Options {
+    /// The Ethereum providers in the order requests fail over between them.
+    #[must_use]
+    pub fn provider_urls(&self) -> Vec<Url> {
+        let mut urls = vec![self.ethereum_provider.clone()];
+        urls.extend(self.ethereum_fallback_providers.iter().cloned());
+        urls
+    }
+
+    #[must_use]
+    pub const fn failover_config(&self) -> FailoverConfig {
+        FailoverConfig {
+            max_block_lag:  self.provider_max_block_lag,
+            cooldown:       Duration::from_secs(self.provider_cooldown_seconds),
+            check_interval: Duration::from_secs(self.provider_check_interval_seconds),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -40,31 +145,95 @@ pub struct Ethereum {
     // Mapping of chain id to provider
     secondary_read_providers: HashMap<u64, Arc<ReadProvider>>,
     write_provider:           Arc<dyn WriteProvider>,
+    read_only:                bool,
+    simulated:                bool,
 }
 
 impl Ethereum {
+    #[cfg(feature = "submission")]
     #[instrument(name = "Ethereum::new", level = "debug", skip_all)]
-    pub async fn new(options: Options) -> AnyhowResult<Self> {
-        let read_provider = ReadProvider::new(options.ethereum_provider).await?;
+    pub async fn new(options: Options, database: Arc<Database>) -> AnyhowResult<Self> {
+        let failover = options.failover_config();
+        let (read_provider, secondary_read_providers) = Self::connect(&options).await?;
+
+        let write_provider: Arc<dyn WriteProvider> = match options.tx_backend {
+            _ if options.dry_run => Arc::new(write_simulated::Provider::new(read_provider.clone())),
+            #[cfg(feature = "oz")]
+            TxBackend::Oz => Arc::new(
+                write_oz::Provider::new(read_provider.clone(), &options.write_options).await?,
+            ),
+            #[cfg(not(feature = "oz"))]
+            TxBackend::Oz => bail!("the `oz` transaction backend requires the `oz` feature"),
+            TxBackend::Local => {
+                let transaction_provider = if options.transaction_providers.is_empty() {
+                    read_provider.clone()
+                } else {
+                    let transaction_provider =
+                        ReadProvider::new(&options.transaction_providers, failover).await?;
+                    ensure!(
+                        transaction_provider.chain_id == read_provider.chain_id,
+                        "transaction providers are on chain {}, expected {}",
+                        transaction_provider.chain_id,
+                        read_provider.chain_id
+                    );
+                    transaction_provider
+                };
+
+                Arc::new(
+                    write_local::Provider::new(
+                        read_provider.clone(),
+                        transaction_provider,
+                        &options.local_write_options,
+                        database,
+                    )
+                    .await?,
+                )
+            }
+        };
+
+        Ok(Self {
+            read_provider: Arc::new(read_provider),
+            secondary_read_providers,
+            write_provider,
+            read_only: false,
+            simulated: options.dry_run,
+        })
+    }
+
+    /// Connects to the providers without any signer, for observers that
+    /// never submit transactions. The only way to connect in proof-only
+    /// builds, which have no signers.
+    #[instrument(name = "Ethereum::new_read_only", level = "debug", skip_all)]
+    pub async fn new_read_only(options: Options) -> AnyhowResult<Self> {
+        let (read_provider, secondary_read_providers) = Self::connect(&options).await?;
+
+        Ok(Self {
+            read_provider: Arc::new(read_provider),
+            secondary_read_providers,
+            write_provider: Arc::new(ReadOnly),
+            read_only: true,
+            simulated: false,
+        })
+    }
+
+    async fn connect(
+        options: &Options,
+    ) -> AnyhowResult<(ReadProvider, HashMap<u64, Arc<ReadProvider>>)> {
+        let failover = options.failover_config();
+        let read_provider = ReadProvider::new(&options.provider_urls(), failover).await?;
 
         let mut secondary_read_providers = HashMap::new();
 
         for secondary_url in &options.secondary_providers.0 {
-            let secondary_read_provider = ReadProvider::new(secondary_url.clone()).await?;
+            let secondary_read_provider =
+                ReadProvider::new(&[secondary_url.clone()], failover).await?;
             secondary_read_providers.insert(
                 secondary_read_provider.chain_id.as_u64(),
                 Arc::new(secondary_read_provider),
             );
         }
 
-        let write_provider: Arc<dyn WriteProvider> =
-            Arc::new(write_oz::Provider::new(read_provider.clone(), &options.write_options).await?);
-
-        Ok(Self {
-            read_provider: Arc::new(read_provider),
-            secondary_read_providers,
-            write_provider,
-        })
+        Ok((read_provider, secondary_read_providers))
     }
 
     #[must_use]
@@ -77,9 +246,21 @@ impl Ethereum {
         &self.secondary_read_providers
     }
 
+    /// Whether transactions can't be sent, see [`Self::new_read_only`].
+    #[must_use]
+    pub const fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether transactions are only simulated, see [`Options::dry_run`].
+    #[must_use]
+    pub const fn is_simulated(&self) -> bool {
+        self.simulated
+    }
+
     #[must_use]
-    pub fn address(&self) -> Address {
-        self.write_provider.address()
+    pub fn addresses(&self) -> Vec<Address> {
+        self.write_provider.addresses()
     }
 
     pub async fn send_transaction(
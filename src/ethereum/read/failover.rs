@@ -0,0 +1,304 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ::prometheus::{register_int_counter_vec, IntCounterVec};
+use anyhow::{bail, Result as AnyhowResult};
+use async_trait::async_trait;
+use ethers::providers::JsonRpcClient;
+use ethers::types::U64;
+use futures::future::join_all;
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::{info, warn};
+use url::Url;
+
+use super::transport::{Transport, TransportError};
+
+static FAILOVERS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "eth_rpc_failovers",
+        "Number of Ethereum provider requests that failed over to the next provider, by the index \
+         of the provider that failed.",
+        &["provider"]
+    )
+    .unwrap()
+});
+
+/// When requests fail over from a provider.
+#[derive(Clone, Copy, Debug)]
+pub struct FailoverConfig {
+    /// How many blocks a provider may be behind the most recent one before it
+    /// is skipped.
+    pub max_block_lag:  u64,
+    /// How long a provider is skipped after a failed request.
+    pub cooldown:       Duration,
+    /// How often the block numbers of the providers are compared.
+    pub check_interval: Duration,
+}
+
+#[derive(Debug, Default)]
+struct EndpointState {
+    failed_until: Option<Instant>,
+    stale:        bool,
+}
+
+impl EndpointState {
+    fn is_available(&self, now: Instant) -> bool {
+        !self.stale && self.failed_until.map_or(true, |until| until <= now)
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url:       Url,
+    transport: Transport,
+    state:     Mutex<EndpointState>,
+}
+
+/// Sends every request to the first available provider, in the order they
+/// were configured, and fails over to the next one on transport errors, rate
+/// limits or when a provider falls behind the others. Errors returned by the
+/// node itself, such as reverts, are returned as is.
+#[derive(Clone, Debug)]
+pub struct Failover {
+    endpoints:  Arc<Vec<Endpoint>>,
+    config:     FailoverConfig,
+    active:     Arc<AtomicUsize>,
+    checked_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Failover {
+    /// Connects to every provider in `urls`, which must all be on the same
+    /// chain.
+    pub async fn connect(urls: &[Url], config: FailoverConfig) -> AnyhowResult<Self> {
+        if urls.is_empty() {
+            bail!("at least one Ethereum provider is required");
+        }
+
+        let mut endpoints = Vec::with_capacity(urls.len());
+        let mut chain_id = None;
+
+        for url in urls {
+            info!(provider = %redacted(url), "Connecting to provider");
+            let transport = Transport::new(url.clone()).await?;
+
+            let endpoint_chain_id: U64 = transport.request("eth_chainId", ()).await?;
+            if chain_id.map_or(false, |chain_id| chain_id != endpoint_chain_id) {
+                bail!(
+                    "Ethereum provider {} is on chain {endpoint_chain_id}, expected {}",
+                    redacted(url),
+                    chain_id.unwrap_or_default()
+                );
+            }
+            chain_id = Some(endpoint_chain_id);
+
+            endpoints.push(Endpoint {
+                url: url.clone(),
+                transport,
+                state: Mutex::new(EndpointState::default()),
+            });
+        }
+
+        Ok(Self {
+            endpoints: Arc::new(endpoints),
+            config,
+            active: Arc::new(AtomicUsize::new(0)),
+            checked_at: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// The available providers in order of preference, followed by the
+    /// unavailable ones as a last resort.
+    fn candidates(&self) -> Vec<usize> {
+        let now = Instant::now();
+        let mut candidates: Vec<usize> = (0..self.endpoints.len()).collect();
+
+        candidates.sort_by_key(|&index| {
+            !self.endpoints[index]
+                .state
+                .lock()
+                .expect("no lock poisoning")
+                .is_available(now)
+        });
+
+        candidates
+    }
+
+    /// Compares the block numbers of the providers once the check interval has
+    /// passed, and skips those lagging behind until the next check.
+    async fn check_block_numbers(&self) {
+        if self.endpoints.len() < 2 {
+            return;
+        }
+
+        {
+            let mut checked_at = self.checked_at.lock().expect("no lock poisoning");
+            if checked_at.map_or(false, |checked_at| {
+                checked_at.elapsed() < self.config.check_interval
+            }) {
+                return;
+            }
+            *checked_at = Some(Instant::now());
+        }
+
+        let block_numbers = join_all(
+            self.endpoints
+                .iter()
+                .map(|endpoint| endpoint.transport.request::<_, U64>("eth_blockNumber", ())),
+        )
+        .await;
+
+        let Some(latest) = block_numbers
+            .iter()
+            .filter_map(|block_number| block_number.as_ref().ok())
+            .max()
+            .copied()
+        else {
+            return;
+        };
+
+        for (index, (endpoint, block_number)) in
+            self.endpoints.iter().zip(block_numbers).enumerate()
+        {
+            let lagging = match block_number {
+                Ok(block_number) => {
+                    latest.saturating_sub(block_number).as_u64() > self.config.max_block_lag
+                }
+                Err(_) => true,
+            };
+
+            let mut state = endpoint.state.lock().expect("no lock poisoning");
+            if lagging && !state.stale {
+                warn!(
+                    provider = index,
+                    url = %redacted(&endpoint.url),
+                    %latest,
+                    "Ethereum provider fell behind, skipping it"
+                );
+            }
+            state.stale = lagging;
+        }
+    }
+
+    fn mark_failed(&self, index: usize, error: &TransportError) {
+        let endpoint = &self.endpoints[index];
+
+        warn!(
+            provider = index,
+            url = %redacted(&endpoint.url),
+            %error,
+            "Ethereum provider request failed, failing over"
+        );
+        FAILOVERS.with_label_values(&[&index.to_string()]).inc();
+
+        endpoint
+            .state
+            .lock()
+            .expect("no lock poisoning")
+            .failed_until = Some(Instant::now() + self.config.cooldown);
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for Failover {
+    type Error = TransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        self.check_block_numbers().await;
+
+        // Serialized once, as the request may be sent to several providers.
+        let params = serde_json::to_value(params)?;
+        let mut last_error = None;
+
+        for index in self.candidates() {
+            let endpoint = &self.endpoints[index];
+
+            match endpoint.transport.request(method, &params).await {
+                Ok(result) => {
+                    let previous = self.active.swap(index, Ordering::Relaxed);
+                    if previous != index {
+                        info!(
+                            provider = index,
+                            url = %redacted(&endpoint.url),
+                            "Switched Ethereum provider"
+                        );
+                    }
+                    return Ok(result);
+                }
+                Err(error) if !should_fail_over(&error) => return Err(error),
+                Err(error) => {
+                    self.mark_failed(index, &error);
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error.expect("there is at least one provider"))
+    }
+}
+
+fn should_fail_over(error: &TransportError) -> bool {
+    error
+        .json_rpc_error()
+        .map_or(true, |(code, message)| is_rate_limited(code, message))
+}
+
+fn is_rate_limited(code: i64, message: &str) -> bool {
+    let message = message.to_lowercase();
+
+    code == -32005
+        || code == 429
+        || message.contains("rate limit")
+        || message.contains("too many requests")
+}
+
+/// Provider urls often carry an API key in their path or query, so only the
+/// host is logged.
+fn redacted(url: &Url) -> &str {
+    url.host_str().unwrap_or_else(|| url.scheme())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_rate_limits_fail_over_among_node_errors() {
+        assert!(is_rate_limited(-32005, "limit exceeded"));
+        assert!(is_rate_limited(
+            -32000,
+            "Too Many Requests, please slow down"
+        ));
+        assert!(!is_rate_limited(3, "execution reverted"));
+        assert!(!is_rate_limited(-32000, "nonce too low"));
+    }
+
+    #[test]
+    fn cooling_down_or_stale_providers_are_unavailable() {
+        let now = Instant::now();
+
+        assert!(EndpointState::default().is_available(now));
+        assert!(EndpointState {
+            failed_until: Some(now),
+            stale:        false,
+        }
+        .is_available(now));
+        assert!(!EndpointState {
+            failed_until: Some(now + Duration::from_secs(1)),
+            stale:        false,
+        }
+        .is_available(now));
+        assert!(!EndpointState {
+            failed_until: None,
+            stale:        true,
+        }
+        .is_available(now));
+    }
+}
@@ -8,13 +8,14 @@ use thiserror::Error;
 use tracing::{error, info};
 use url::Url;
 
+use self::failover::{Failover, FailoverConfig};
 use self::rpc_logger::RpcLogger;
-use self::transport::Transport;
 
+pub mod failover;
 pub mod rpc_logger;
 pub mod transport;
 
-type InnerProvider = Provider<RpcLogger<Transport>>;
+type InnerProvider = Provider<RpcLogger<Failover>>;
 
 #[derive(Clone, Debug)]
 pub struct ReadProvider {
@@ -24,20 +25,16 @@ pub struct ReadProvider {
 }
 
 impl ReadProvider {
-    pub async fn new(url: Url) -> AnyhowResult<Self> {
-        // Connect to the Ethereum provider
-        // TODO: Allow multiple providers with failover / broadcast.
+    /// Connects to the providers in `urls`, failing over from one to the next
+    /// in order.
+    pub async fn new(urls: &[Url], failover: FailoverConfig) -> AnyhowResult<Self> {
         // TODO: Requests don't seem to process in parallel. Check if this is
         // a limitation client side or server side.
         // TODO: Does the WebSocket impl handle dropped connections by
         // reconnecting? What is the timeout on stalled connections? What is
         // the retry policy?
         let (provider, chain_id, eip1559) = {
-            info!(
-                provider = %url,
-                "Connecting to provider"
-            );
-            let transport = Transport::new(url).await?;
+            let transport = Failover::connect(urls, failover).await?;
             let logger = RpcLogger::new(transport);
             let provider = Provider::new(logger);
 
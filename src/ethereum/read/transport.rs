@@ -1,7 +1,9 @@
 use std::fmt::Debug;
 
 use async_trait::async_trait;
-use ethers::providers::{Http, Ipc, JsonRpcClient, ProviderError, Ws};
+use ethers::providers::{
+    Http, HttpClientError, Ipc, IpcError, JsonRpcClient, ProviderError, Ws, WsClientError,
+};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use thiserror::Error;
@@ -29,6 +31,23 @@ pub enum TransportError {
 
     #[error("Unsupported transport: {0}")]
     InvalidScheme(Url),
+
+    #[error("Failed to serialize request: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+impl TransportError {
+    /// The code and message of the error returned by the node, as opposed to
+    /// a failure to reach it. `ethers` doesn't export the type of the error
+    /// itself.
+    pub fn json_rpc_error(&self) -> Option<(i64, &str)> {
+        match self {
+            Self::Http(HttpClientError::JsonRpcError(error))
+            | Self::Ws(WsClientError::JsonRpcError(error))
+            | Self::Ipc(IpcError::JsonRpcError(error)) => Some((error.code, &error.message)),
+            _ => None,
+        }
+    }
 }
 
 impl Transport {
@@ -57,6 +76,7 @@ impl From<TransportError> for ProviderError {
             TransportError::InvalidScheme(url) => {
                 Self::CustomError(format!("Unsupported transport: {url}"))
             }
+            TransportError::Serialization(error) => Self::SerdeJson(error),
         }
     }
 }
@@ -51,6 +51,9 @@ pub enum TxError {
 
     #[error("Error parsing transaction id: {0}")]
     Parse(Box<dyn Error + Send + Sync + 'static>),
+
+    #[error("Transactions are not sent in observer mode")]
+    ReadOnly,
 }
 
 #[async_trait]
@@ -65,5 +68,43 @@ pub trait WriteProvider: Sync + Send + fmt::Debug {
 
     async fn mine_transaction(&self, tx: TransactionId) -> Result<bool, TxError>;
 
+    /// The address transactions are sent from by default.
     fn address(&self) -> Address;
+
+    /// Every address transactions may be sent from.
+    fn addresses(&self) -> Vec<Address> {
+        vec![self.address()]
+    }
+}
+
+/// Stands in for a write provider in observer mode, where nothing is ever
+/// submitted.
+#[derive(Debug)]
+pub struct ReadOnly;
+
+#[async_trait]
+impl WriteProvider for ReadOnly {
+    async fn send_transaction(
+        &self,
+        _tx: TypedTransaction,
+        _only_once: bool,
+    ) -> Result<TransactionId, TxError> {
+        Err(TxError::ReadOnly)
+    }
+
+    async fn fetch_pending_transactions(&self) -> Result<Vec<TransactionId>, TxError> {
+        Ok(Vec::new())
+    }
+
+    async fn mine_transaction(&self, _tx: TransactionId) -> Result<bool, TxError> {
+        Err(TxError::ReadOnly)
+    }
+
+    fn address(&self) -> Address {
+        Address::zero()
+    }
+
+    fn addresses(&self) -> Vec<Address> {
+        Vec::new()
+    }
 }
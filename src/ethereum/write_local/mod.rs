@@ -0,0 +1,737 @@
+use std::cmp::Reverse;
+use std::ops::Range;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Result as AnyhowResult;
+use async_trait::async_trait;
+use clap::Parser;
+use ethers::providers::Middleware;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, BlockNumber, Bytes, Eip1559TransactionRequest, H256, U256, U64};
+use ethers::utils::keccak256;
+use ethers::utils::rlp::Rlp;
+use futures::future::try_join_all;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, IntCounter};
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tracing::{error, info, warn};
+use url::Url;
+
+use self::nonce::NonceManager;
+use self::signer::TransactionSigner;
+use super::write::{TransactionId, WriteProvider};
+use super::{ReadProvider, TxError};
+use crate::database::types::{SignerTransaction, TransactionAttempt};
+use crate::database::Database;
+use crate::secret::SecretString;
+
+mod nonce;
+mod signer;
+
+const GWEI: u64 = 1_000_000_000;
+
+static REPLACEMENTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "eth_tx_replacements",
+        "Number of pending transactions replaced with higher fees."
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Debug, Eq, PartialEq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Private keys used to sign transactions, separated by commas. The
+    /// `local` transaction backend requires at least one signer from
+    /// `--signing-keys`, `--aws-kms-key-ids` or `--remote-signer-url`.
+    #[clap(long, env, alias = "signing-key", value_delimiter = ',')]
+    pub signing_keys: Vec<SecretString>,
+
+    /// IDs, ARNs or aliases of AWS KMS keys used to sign transactions,
+    /// separated by commas. The region and credentials are read from the
+    /// environment.
+    #[clap(long, env, alias = "aws-kms-key-id", value_delimiter = ',')]
+    pub aws_kms_key_ids: Vec<String>,
+
+    /// URL of a remote signer implementing `eth_signTransaction`
+    #[clap(long, env)]
+    pub remote_signer_url: Option<Url>,
+
+    /// Accounts of the remote signer to sign with, separated by commas.
+    /// Defaults to all of its accounts.
+    #[clap(long, env, alias = "remote-signer-address", value_delimiter = ',')]
+    pub remote_signer_addresses: Vec<Address>,
+
+    /// How often to check whether the signing key was rotated, e.g. by
+    /// pointing a KMS alias at a new key (seconds)
+    #[clap(long, env, default_value = "300")]
+    pub signer_refresh_seconds: u64,
+
+    /// How long to wait for a locally signed transaction to be mined (seconds)
+    #[clap(long, env, default_value = "300")]
+    pub local_mine_timeout_seconds: u64,
+
+    /// Number of blocks after which a transaction that is still pending is
+    /// replaced with one paying higher fees
+    #[clap(long, env, default_value = "10")]
+    pub local_replacement_blocks: u64,
+
+    /// Percentage by which the fees of a replacement are raised. Nodes only
+    /// accept replacements that raise fees by at least 10%.
+    #[clap(long, env, default_value = "20")]
+    pub local_fee_bump_percent: u64,
+
+    /// Upper bound on the max fee per gas of replacements (in gwei). Pending
+    /// transactions are no longer replaced once it is reached.
+    #[clap(long, env)]
+    pub local_max_fee_per_gas: Option<u64>,
+}
+
+/// A signer together with the nonces of its address.
+#[derive(Debug)]
+struct Account {
+    signer: Arc<dyn TransactionSigner>,
+    nonces: NonceManager,
+}
+
+impl Account {
+    async fn new(
+        database: Arc<Database>,
+        read_provider: &ReadProvider,
+        signer: Arc<dyn TransactionSigner>,
+    ) -> AnyhowResult<(Self, Range<u64>)> {
+        let (nonces, gaps) = NonceManager::new(database, read_provider, signer.address()).await?;
+
+        Ok((Self { signer, nonces }, gaps))
+    }
+
+    fn address(&self) -> Address {
+        self.signer.address()
+    }
+}
+
+/// Signs with a pool of accounts. New transactions go to the funded account
+/// with the fewest pending transactions, so that a stuck transaction only
+/// holds up the transactions of its own account.
+#[derive(Debug)]
+pub struct Provider {
+    read_provider:        ReadProvider,
+    /// Where signed transactions are sent, which may differ from where they
+    /// are read from.
+    transaction_provider: ReadProvider,
+    database:             Arc<Database>,
+    options:              Options,
+    accounts:             RwLock<Vec<Arc<Account>>>,
+    refreshed_at:         Mutex<Instant>,
+    refresh_interval:     Duration,
+    mine_timeout:         Duration,
+}
+
+impl Provider {
+    pub async fn new(
+        read_provider: ReadProvider,
+        transaction_provider: ReadProvider,
+        options: &Options,
+        database: Arc<Database>,
+    ) -> AnyhowResult<Self> {
+        let signers = signer::connect(options, read_provider.chain_id.as_u64()).await?;
+
+        let provider = Self {
+            read_provider,
+            transaction_provider,
+            database,
+            options: options.clone(),
+            accounts: RwLock::new(Vec::new()),
+            refreshed_at: Mutex::new(Instant::now()),
+            refresh_interval: Duration::from_secs(options.signer_refresh_seconds),
+            mine_timeout: Duration::from_secs(options.local_mine_timeout_seconds),
+        };
+
+        let accounts = provider.open_accounts(&[], signers).await?;
+        *provider.accounts.write().expect("no lock poisoning") = accounts;
+
+        Ok(provider)
+    }
+
+    fn accounts(&self) -> Vec<Arc<Account>> {
+        self.accounts.read().expect("no lock poisoning").clone()
+    }
+
+    /// Opens an account for every signer, reusing the `current` account of
+    /// an address. New accounts have their nonce gaps repaired.
+    async fn open_accounts(
+        &self,
+        current: &[Arc<Account>],
+        signers: Vec<Arc<dyn TransactionSigner>>,
+    ) -> AnyhowResult<Vec<Arc<Account>>> {
+        let mut accounts = Vec::with_capacity(signers.len());
+
+        for signer in signers {
+            if let Some(account) = current
+                .iter()
+                .find(|account| account.address() == signer.address())
+            {
+                accounts.push(account.clone());
+                continue;
+            }
+
+            info!(address = ?signer.address(), "Signing transactions locally");
+
+            let (account, gaps) =
+                Account::new(self.database.clone(), &self.read_provider, signer).await?;
+            let account = Arc::new(account);
+
+            self.repair_nonce_gaps(&account, gaps).await?;
+            accounts.push(account);
+        }
+
+        Ok(accounts)
+    }
+
+    /// Reconnects to the signers once the refresh interval has passed and
+    /// switches to the new addresses if keys were rotated. Transactions
+    /// signed with a previous key are still mined, but new transactions use
+    /// the nonces of the new addresses.
+    async fn refresh_accounts(&self) -> AnyhowResult<Vec<Arc<Account>>> {
+        let mut refreshed_at = self.refreshed_at.lock().await;
+        let current = self.accounts();
+
+        if refreshed_at.elapsed() < self.refresh_interval {
+            return Ok(current);
+        }
+
+        let signers = signer::connect(&self.options, self.read_provider.chain_id.as_u64()).await?;
+        *refreshed_at = Instant::now();
+
+        let accounts = self.open_accounts(&current, signers).await?;
+
+        for account in &current {
+            if !accounts.iter().any(|a| a.address() == account.address()) {
+                info!(address = ?account.address(), "Signing key was rotated out");
+            }
+        }
+
+        *self.accounts.write().expect("no lock poisoning") = accounts.clone();
+
+        Ok(accounts)
+    }
+
+    /// Picks the account for a new transaction: the one `from` asks for, or
+    /// else the funded account with the fewest pending transactions, breaking
+    /// ties by balance.
+    async fn select_account(
+        &self,
+        accounts: &[Arc<Account>],
+        from: Option<Address>,
+    ) -> Result<Arc<Account>, TxError> {
+        if let Some(from) = from {
+            return accounts
+                .iter()
+                .find(|account| account.address() == from)
+                .cloned()
+                .ok_or_else(|| TxError::Send(From::from(format!("No signer for {from:?}"))));
+        }
+
+        let loads = try_join_all(accounts.iter().map(|account| async move {
+            let pending = self.pending_transactions(account.address()).await?.len();
+            let balance = self.balance(account.address()).await?;
+
+            Ok::<_, TxError>((account, pending, balance))
+        }))
+        .await?;
+
+        loads
+            .iter()
+            .filter(|(_, _, balance)| !balance.is_zero())
+            .min_by_key(|(_, pending, balance)| (*pending, Reverse(*balance)))
+            .map(|(account, ..)| Arc::clone(account))
+            .ok_or_else(|| TxError::Send(From::from("No signer has any funds")))
+    }
+
+    async fn balance(&self, address: Address) -> Result<U256, TxError> {
+        let balance = self
+            .read_provider
+            .get_balance(address, None)
+            .await
+            .map_err(|error| TxError::Fetch(Box::new(error)))?;
+
+        Ok(balance)
+    }
+
+    /// Rebroadcasts the transactions signed with the given nonces. Nonces that
+    /// were handed out but never used are filled with empty transactions.
+    async fn repair_nonce_gaps(&self, account: &Account, gaps: Range<u64>) -> AnyhowResult<()> {
+        let transactions = self
+            .database
+            .get_signer_transactions(account.address(), gaps.start)
+            .await?;
+
+        for nonce in gaps {
+            let latest = transactions.iter().rev().find(|tx| tx.nonce == nonce);
+
+            if let Some(latest) = latest {
+                info!(nonce, tx_hash = ?latest.transaction_hash, "Rebroadcasting transaction");
+                self.rebroadcast(latest).await;
+            } else {
+                warn!(
+                    nonce,
+                    "Nonce was never used, filling it with an empty transaction"
+                );
+                self.cancel(account, nonce).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces whatever transaction was sent with `nonce` by an empty
+    /// transfer to ourselves.
+    async fn cancel(&self, account: &Account, nonce: u64) -> Result<H256, TxError> {
+        let tx = TypedTransaction::Eip1559(Eip1559TransactionRequest {
+            to: Some(account.address().into()),
+            ..Eip1559TransactionRequest::default()
+        });
+
+        self.replace(account, nonce, tx).await
+    }
+
+    /// Signs and sends `tx` with a nonce that has been used before.
+    async fn replace(
+        &self,
+        account: &Account,
+        nonce: u64,
+        tx: TypedTransaction,
+    ) -> Result<H256, TxError> {
+        let tx = self.fill(account, tx).await?;
+        self.sign_and_send(account, tx, nonce).await
+    }
+
+    async fn fill(
+        &self,
+        account: &Account,
+        mut tx: TypedTransaction,
+    ) -> Result<TypedTransaction, TxError> {
+        tx.set_from(account.address());
+        tx.set_chain_id(self.read_provider.chain_id.as_u64());
+
+        self.read_provider
+            .fill_transaction(&mut tx, None)
+            .await
+            .map_err(|error| TxError::Fill(Box::new(error)))?;
+
+        Ok(tx)
+    }
+
+    async fn sign_and_send(
+        &self,
+        account: &Account,
+        mut tx: TypedTransaction,
+        nonce: u64,
+    ) -> Result<H256, TxError> {
+        tx.set_nonce(nonce);
+
+        let raw = account
+            .signer
+            .sign_transaction(&tx)
+            .await
+            .map_err(|error| TxError::Fill(Box::new(error)))?;
+        let tx_hash = H256::from(keccak256(&raw));
+        let sent_at_block = self
+            .read_provider
+            .get_block_number()
+            .await
+            .map_err(|error| TxError::Fetch(Box::new(error)))?;
+
+        // Recorded before sending, so that the transaction can still be
+        // rebroadcast if the node doesn't accept it or we crash.
+        self.database
+            .insert_signer_transaction(
+                tx_hash,
+                account.address(),
+                nonce,
+                tx.data().map(AsRef::as_ref),
+                &raw,
+                &attempt(&tx, sent_at_block.as_u64()),
+            )
+            .await
+            .map_err(|error| TxError::Send(Box::new(error)))?;
+
+        info!(?tx_hash, nonce, "Sending transaction");
+
+        self.transaction_provider
+            .send_raw_transaction(raw)
+            .await
+            .map_err(|error| {
+                error!(?error, ?tx_hash, "Failed to send transaction");
+                TxError::Send(Box::new(error))
+            })?;
+
+        Ok(tx_hash)
+    }
+
+    async fn rebroadcast(&self, tx: &SignerTransaction) {
+        if let Err(error) = self
+            .transaction_provider
+            .send_raw_transaction(Bytes::from(tx.raw.clone()))
+            .await
+        {
+            // Most likely the node already knows the transaction.
+            warn!(?error, tx_hash = ?tx.transaction_hash, "Failed to rebroadcast transaction");
+        }
+    }
+
+    /// Replaces `tx` with a copy paying higher fees once it has been pending
+    /// for `--local-replacement-blocks`.
+    async fn replace_if_stuck(&self, tx: &SignerTransaction) -> Result<(), TxError> {
+        let block_number = self
+            .read_provider
+            .get_block_number()
+            .await
+            .map_err(|error| TxError::Fetch(Box::new(error)))?
+            .as_u64();
+
+        let pending_blocks = tx.attempt.map_or(u64::MAX, |attempt| {
+            block_number.saturating_sub(attempt.sent_at_block)
+        });
+        if pending_blocks < self.options.local_replacement_blocks {
+            return Ok(());
+        }
+
+        let Some(account) = self
+            .accounts()
+            .into_iter()
+            .find(|account| account.address() == tx.address)
+        else {
+            warn!(address = ?tx.address, "Signing key was rotated out, can't replace transaction");
+            return Ok(());
+        };
+
+        let (mut replacement, _) = TypedTransaction::decode_signed(&Rlp::new(&tx.raw))
+            .map_err(|error| TxError::Parse(Box::new(error)))?;
+        replacement.set_from(account.address());
+
+        let current = if self.read_provider.legacy {
+            let gas_price = self
+                .read_provider
+                .get_gas_price()
+                .await
+                .map_err(|error| TxError::Fetch(Box::new(error)))?;
+            (gas_price, U256::zero())
+        } else {
+            self.read_provider
+                .estimate_eip1559_fees(None)
+                .await
+                .map_err(|error| TxError::Fetch(Box::new(error)))?
+        };
+
+        let cap = self
+            .options
+            .local_max_fee_per_gas
+            .map(|gwei| U256::from(gwei) * GWEI);
+        if !bump_fees(
+            &mut replacement,
+            self.options.local_fee_bump_percent,
+            current,
+            cap,
+        ) {
+            warn!(
+                tx_hash = ?tx.transaction_hash,
+                "Transaction is stuck, but its fees can't be raised past --local-max-fee-per-gas"
+            );
+            return Ok(());
+        }
+
+        info!(
+            tx_hash = ?tx.transaction_hash,
+            nonce = tx.nonce,
+            pending_blocks,
+            "Replacing pending transaction with higher fees"
+        );
+        REPLACEMENTS.inc();
+
+        self.sign_and_send(&account, replacement, tx.nonce).await?;
+
+        Ok(())
+    }
+
+    async fn mined_nonce(&self, address: Address) -> Result<u64, TxError> {
+        let count = self
+            .read_provider
+            .get_transaction_count(address, Some(BlockNumber::Latest.into()))
+            .await
+            .map_err(|error| TxError::Fetch(Box::new(error)))?;
+
+        Ok(count.as_u64())
+    }
+
+    async fn all_pending_transactions(
+        &self,
+        accounts: &[Arc<Account>],
+    ) -> Result<Vec<SignerTransaction>, TxError> {
+        let pending = try_join_all(
+            accounts
+                .iter()
+                .map(|account| self.pending_transactions(account.address())),
+        )
+        .await?;
+
+        Ok(pending.into_iter().flatten().collect())
+    }
+
+    /// Returns the first transaction signed for each nonce that hasn't been
+    /// mined yet. Replacements are tracked under the id of the first one.
+    async fn pending_transactions(
+        &self,
+        address: Address,
+    ) -> Result<Vec<SignerTransaction>, TxError> {
+        let mined_nonce = self.mined_nonce(address).await?;
+
+        let mut transactions = self
+            .database
+            .get_signer_transactions(address, mined_nonce)
+            .await
+            .map_err(|error| TxError::Fetch(Box::new(error)))?;
+        transactions.dedup_by_key(|tx| tx.nonce);
+
+        Ok(transactions)
+    }
+
+    async fn mine_transaction_unchecked(&self, tx: &SignerTransaction) -> Result<bool, TxError> {
+        loop {
+            let attempts = self
+                .database
+                .get_signer_transactions(tx.address, tx.nonce)
+                .await
+                .map_err(|error| TxError::Fetch(Box::new(error)))?
+                .into_iter()
+                .filter(|attempt| attempt.nonce == tx.nonce)
+                .collect::<Vec<_>>();
+
+            for attempt in &attempts {
+                let receipt = self
+                    .read_provider
+                    .get_transaction_receipt(attempt.transaction_hash)
+                    .await
+                    .map_err(|error| TxError::Fetch(Box::new(error)))?;
+
+                if let Some(receipt) = receipt {
+                    return Ok(receipt.status == Some(U64::from(1u64)));
+                }
+            }
+
+            if self.mined_nonce(tx.address).await? > tx.nonce {
+                warn!(nonce = tx.nonce, "Nonce was used by an unknown transaction");
+                return Ok(false);
+            }
+
+            // The node may have dropped the transaction from its mempool.
+            if let Some(latest) = attempts.last() {
+                let known = self
+                    .read_provider
+                    .get_transaction(latest.transaction_hash)
+                    .await
+                    .map_err(|error| TxError::Fetch(Box::new(error)))?;
+
+                if known.is_none() {
+                    self.rebroadcast(latest).await;
+                } else if let Err(error) = self.replace_if_stuck(latest).await {
+                    // The transaction may have been mined in the meantime.
+                    warn!(?error, tx_hash = ?latest.transaction_hash, "Failed to replace transaction");
+                }
+            }
+
+            info!("waiting 5 s to mine");
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    }
+}
+
+#[async_trait]
+impl WriteProvider for Provider {
+    async fn send_transaction(
+        &self,
+        tx: TypedTransaction,
+        only_once: bool,
+    ) -> Result<TransactionId, TxError> {
+        let accounts = match self.refresh_accounts().await {
+            Ok(accounts) => accounts,
+            Err(error) => {
+                warn!(?error, "Failed to refresh signers, using the current keys");
+                self.accounts()
+            }
+        };
+
+        if only_once {
+            let pending = self.all_pending_transactions(&accounts).await?;
+            let existing = pending.iter().find(|pending| {
+                matches!((&pending.data, tx.data()), (Some(a), Some(b)) if a == b.as_ref())
+            });
+
+            if let Some(existing) = existing {
+                info!(only_once, "mining previously submitted transaction");
+
+                return Ok(TransactionId(format!("{:?}", existing.transaction_hash)));
+            }
+        }
+
+        let account = self.select_account(&accounts, tx.from().copied()).await?;
+
+        // Filled before a nonce is taken, so that transactions that fail gas
+        // estimation don't leave gaps.
+        let tx = self.fill(&account, tx).await?;
+        let nonce = account
+            .nonces
+            .next()
+            .await
+            .map_err(|error| TxError::Send(Box::new(error)))?;
+
+        let tx_hash = self.sign_and_send(&account, tx, nonce).await?;
+
+        Ok(TransactionId(format!("{tx_hash:?}")))
+    }
+
+    async fn fetch_pending_transactions(&self) -> Result<Vec<TransactionId>, TxError> {
+        let pending = self.all_pending_transactions(&self.accounts()).await?;
+
+        Ok(pending
+            .into_iter()
+            .map(|tx| TransactionId(format!("{:?}", tx.transaction_hash)))
+            .collect())
+    }
+
+    async fn mine_transaction(&self, tx: TransactionId) -> Result<bool, TxError> {
+        let tx_hash =
+            tx.0.parse::<H256>()
+                .map_err(|error| TxError::Parse(Box::new(error)))?;
+
+        let tx = self
+            .database
+            .get_signer_transaction(tx_hash)
+            .await
+            .map_err(|error| TxError::Fetch(Box::new(error)))?
+            .ok_or_else(|| {
+                TxError::Fetch(From::from(format!("Unknown transaction {tx_hash:?}")))
+            })?;
+
+        timeout(self.mine_timeout, self.mine_transaction_unchecked(&tx))
+            .await
+            .map_err(|_| TxError::ConfirmationTimeout)?
+    }
+
+    fn address(&self) -> Address {
+        self.accounts()[0].address()
+    }
+
+    fn addresses(&self) -> Vec<Address> {
+        self.accounts()
+            .iter()
+            .map(|account| account.address())
+            .collect()
+    }
+}
+
+/// The fees `tx` pays, as recorded with it.
+fn attempt(tx: &TypedTransaction, sent_at_block: u64) -> TransactionAttempt {
+    match tx {
+        TypedTransaction::Eip1559(tx) => TransactionAttempt {
+            sent_at_block,
+            max_fee_per_gas: tx.max_fee_per_gas.unwrap_or_default(),
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+        },
+        tx => TransactionAttempt {
+            sent_at_block,
+            max_fee_per_gas: tx.gas_price().unwrap_or_default(),
+            max_priority_fee_per_gas: None,
+        },
+    }
+}
+
+/// Raises the fees of `tx` by `percent`, or to the `current` max fee and
+/// priority fee if those are higher. Returns `false` if that would exceed
+/// `cap`, in which case `tx` is left as is.
+fn bump_fees(
+    tx: &mut TypedTransaction,
+    percent: u64,
+    current: (U256, U256),
+    cap: Option<U256>,
+) -> bool {
+    let bump = |fee: Option<U256>| fee.unwrap_or_default() * (100 + percent) / 100;
+    let (current_max_fee, current_priority_fee) = current;
+
+    match tx {
+        TypedTransaction::Eip1559(tx) => {
+            let max_fee = bump(tx.max_fee_per_gas).max(current_max_fee);
+            if cap.map_or(false, |cap| max_fee > cap) {
+                return false;
+            }
+
+            let priority_fee = bump(tx.max_priority_fee_per_gas).max(current_priority_fee);
+            tx.max_fee_per_gas = Some(max_fee);
+            tx.max_priority_fee_per_gas = Some(priority_fee.min(max_fee));
+        }
+        tx => {
+            let gas_price = bump(tx.gas_price()).max(current_max_fee);
+            if cap.map_or(false, |cap| gas_price > cap) {
+                return false;
+            }
+
+            tx.set_gas_price(gas_price);
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::TransactionRequest;
+
+    use super::*;
+
+    fn eip1559(max_fee: u64, priority_fee: u64) -> TypedTransaction {
+        TypedTransaction::Eip1559(
+            Eip1559TransactionRequest::new()
+                .max_fee_per_gas(max_fee)
+                .max_priority_fee_per_gas(priority_fee),
+        )
+    }
+
+    #[test]
+    fn replacements_pay_at_least_the_bumped_or_current_fees() {
+        let mut tx = eip1559(100, 10);
+        assert!(bump_fees(
+            &mut tx,
+            20,
+            (U256::from(50), U256::from(30)),
+            None
+        ));
+        assert_eq!(attempt(&tx, 1), TransactionAttempt {
+            sent_at_block:            1,
+            max_fee_per_gas:          U256::from(120),
+            max_priority_fee_per_gas: Some(U256::from(30)),
+        });
+
+        let mut tx = TypedTransaction::Legacy(TransactionRequest::new().gas_price(100));
+        assert!(bump_fees(
+            &mut tx,
+            20,
+            (U256::from(200), U256::zero()),
+            None
+        ));
+        assert_eq!(tx.gas_price(), Some(U256::from(200)));
+    }
+
+    #[test]
+    fn replacements_respect_the_fee_cap() {
+        let mut tx = eip1559(100, 10);
+        assert!(!bump_fees(
+            &mut tx,
+            20,
+            (U256::zero(), U256::zero()),
+            Some(U256::from(110))
+        ));
+        assert_eq!(tx, eip1559(100, 10));
+    }
+}
@@ -0,0 +1,83 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use anyhow::Result as AnyhowResult;
+use ethers::providers::Middleware;
+use ethers::types::{Address, BlockNumber};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::database::{self, Database};
+use crate::ethereum::ReadProvider;
+
+/// Hands out nonces for a signer. The next nonce is persisted before a nonce
+/// is handed out, so that a restart never reuses the nonce of a transaction
+/// that may still be in flight.
+#[derive(Debug)]
+pub struct NonceManager {
+    database:   Arc<Database>,
+    address:    Address,
+    next_nonce: Mutex<u64>,
+}
+
+impl NonceManager {
+    /// Also returns the nonces that were handed out before a restart but
+    /// aren't known to the node. Later transactions can't be mined until
+    /// these gaps are repaired.
+    pub async fn new(
+        database: Arc<Database>,
+        read_provider: &ReadProvider,
+        address: Address,
+    ) -> AnyhowResult<(Self, Range<u64>)> {
+        let stored = database.get_signer_nonce(address).await?;
+        let mined = read_provider
+            .get_transaction_count(address, Some(BlockNumber::Latest.into()))
+            .await?
+            .as_u64();
+        let pending = read_provider
+            .get_transaction_count(address, Some(BlockNumber::Pending.into()))
+            .await?
+            .as_u64();
+
+        if pending > mined {
+            info!(
+                ?address,
+                mined, pending, "Signer has transactions in the mempool"
+            );
+        }
+
+        let next_nonce = stored.map_or(pending, |stored| stored.max(pending));
+        let gaps = pending..next_nonce;
+
+        if !gaps.is_empty() {
+            warn!(
+                ?address,
+                ?gaps,
+                "Signer has nonces missing from the mempool"
+            );
+        }
+
+        database.set_signer_nonce(address, next_nonce).await?;
+
+        Ok((
+            Self {
+                database,
+                address,
+                next_nonce: Mutex::new(next_nonce),
+            },
+            gaps,
+        ))
+    }
+
+    pub async fn next(&self) -> Result<u64, database::Error> {
+        let mut next_nonce = self.next_nonce.lock().await;
+        let nonce = *next_nonce;
+
+        self.database
+            .set_signer_nonce(self.address, nonce + 1)
+            .await?;
+        *next_nonce += 1;
+
+        Ok(nonce)
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{ensure, Result as AnyhowResult};
+use async_trait::async_trait;
+#[cfg(feature = "aws-kms")]
+use ethers::signers::{AwsSigner, AwsSignerError};
+use ethers::signers::{LocalWallet, Signer, WalletError};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Address, Bytes};
+#[cfg(feature = "aws-kms")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "aws-kms")]
+use rusoto_core::Region;
+#[cfg(feature = "aws-kms")]
+use rusoto_kms::KmsClient;
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+use url::Url;
+
+use super::Options;
+
+/// Shared by all KMS signers, which borrow it for their whole lifetime.
+#[cfg(feature = "aws-kms")]
+static KMS_CLIENT: Lazy<KmsClient> = Lazy::new(|| KmsClient::new(Region::default()));
+
+#[derive(Debug, Error)]
+pub enum SignerError {
+    #[error("local signer error: {0}")]
+    Wallet(#[from] WalletError),
+
+    #[cfg(feature = "aws-kms")]
+    #[error("AWS KMS error: {0}")]
+    Kms(#[from] AwsSignerError),
+
+    #[error("remote signer request failed: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("remote signer error: {0}")]
+    Remote(String),
+}
+
+/// Signs transactions without the caller having to hold the key.
+#[async_trait]
+pub trait TransactionSigner: fmt::Debug + Send + Sync {
+    fn address(&self) -> Address;
+
+    /// Returns the RLP encoded signed transaction.
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Bytes, SignerError>;
+}
+
+/// Connects to every signer configured in `options`. Called again to pick up
+/// rotated keys.
+pub async fn connect(
+    options: &Options,
+    chain_id: u64,
+) -> AnyhowResult<Vec<Arc<dyn TransactionSigner>>> {
+    let mut signers: Vec<Arc<dyn TransactionSigner>> = Vec::new();
+
+    for signing_key in &options.signing_keys {
+        let wallet = signing_key
+            .expose()
+            .parse::<LocalWallet>()?
+            .with_chain_id(chain_id);
+        signers.push(Arc::new(wallet));
+    }
+
+    #[cfg(feature = "aws-kms")]
+    for key_id in &options.aws_kms_key_ids {
+        let signer = AwsSigner::new(&KMS_CLIENT, key_id, chain_id).await?;
+        signers.push(Arc::new(KmsSigner(signer)));
+    }
+
+    #[cfg(not(feature = "aws-kms"))]
+    ensure!(
+        options.aws_kms_key_ids.is_empty(),
+        "--aws-kms-key-ids requires the `aws-kms` feature"
+    );
+
+    if let Some(url) = &options.remote_signer_url {
+        for signer in RemoteSigner::connect(url, &options.remote_signer_addresses).await? {
+            signers.push(Arc::new(signer));
+        }
+    }
+
+    ensure!(
+        !signers.is_empty(),
+        "the local transaction backend requires --signing-keys, --aws-kms-key-ids or \
+         --remote-signer-url"
+    );
+
+    for (i, signer) in signers.iter().enumerate() {
+        ensure!(
+            signers[..i]
+                .iter()
+                .all(|other| other.address() != signer.address()),
+            "signer {:?} is configured more than once",
+            signer.address()
+        );
+    }
+
+    Ok(signers)
+}
+
+#[async_trait]
+impl TransactionSigner for LocalWallet {
+    fn address(&self) -> Address {
+        Signer::address(self)
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Bytes, SignerError> {
+        let signature = Signer::sign_transaction(self, tx).await?;
+
+        Ok(tx.rlp_signed(&signature))
+    }
+}
+
+/// Signs with a key held in AWS KMS.
+#[cfg(feature = "aws-kms")]
+#[derive(Debug)]
+pub struct KmsSigner(AwsSigner<'static>);
+
+#[cfg(feature = "aws-kms")]
+#[async_trait]
+impl TransactionSigner for KmsSigner {
+    fn address(&self) -> Address {
+        self.0.address()
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Bytes, SignerError> {
+        let signature = self.0.sign_transaction(tx).await?;
+
+        Ok(tx.rlp_signed(&signature))
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error:  Option<serde_json::Value>,
+}
+
+impl<T> RpcResponse<T> {
+    fn into_result(self) -> Result<T, SignerError> {
+        match (self.result, self.error) {
+            (Some(result), None) => Ok(result),
+            (_, error) => Err(SignerError::Remote(
+                error.map_or_else(|| "empty response".to_string(), |error| error.to_string()),
+            )),
+        }
+    }
+}
+
+/// Signs through a remote signer implementing `eth_signTransaction`, such as
+/// Web3Signer or Clef, which can in turn keep the key in an HSM.
+#[derive(Debug)]
+pub struct RemoteSigner {
+    client:  reqwest::Client,
+    url:     Url,
+    address: Address,
+}
+
+impl RemoteSigner {
+    /// Signs with each of `addresses`, or with every account of the signer if
+    /// none are given.
+    async fn connect(url: &Url, addresses: &[Address]) -> Result<Vec<Self>, SignerError> {
+        let client = reqwest::Client::new();
+
+        let addresses = if addresses.is_empty() {
+            Self::request::<Vec<Address>>(&client, url, "eth_accounts", json!([])).await?
+        } else {
+            addresses.to_vec()
+        };
+
+        if addresses.is_empty() {
+            return Err(SignerError::Remote("signer has no accounts".to_string()));
+        }
+
+        Ok(addresses
+            .into_iter()
+            .map(|address| Self {
+                client: client.clone(),
+                url: url.clone(),
+                address,
+            })
+            .collect())
+    }
+
+    async fn request<T: serde::de::DeserializeOwned>(
+        client: &reqwest::Client,
+        url: &Url,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T, SignerError> {
+        let response = client
+            .post(url.clone())
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<RpcResponse<T>>()
+            .await?;
+
+        response.into_result()
+    }
+}
+
+#[async_trait]
+impl TransactionSigner for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Bytes, SignerError> {
+        Self::request(&self.client, &self.url, "eth_signTransaction", json!([tx])).await
+    }
+}
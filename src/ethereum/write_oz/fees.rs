@@ -0,0 +1,225 @@
+use std::str::FromStr;
+
+use ethers::providers::{Middleware, ProviderError};
+use ethers::types::{BlockNumber, U256};
+use thiserror::Error;
+
+use crate::ethereum::ReadProvider;
+
+/// Number of recent blocks to sample priority fees from.
+const FEE_HISTORY_BLOCKS: u64 = 10;
+
+const GWEI: u64 = 1_000_000_000;
+
+#[derive(Debug, Error)]
+#[error("invalid priority fee strategy {0:?}, expected `fixed:<gwei>` or `percentile:<0-100>`")]
+pub struct InvalidPriorityFeeStrategy(String);
+
+/// How the priority fee of a transaction is chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriorityFeeStrategy {
+    /// A fixed priority fee in gwei.
+    Fixed(u64),
+    /// A percentile of the priority fees paid in recent blocks.
+    Percentile(u8),
+}
+
+impl FromStr for PriorityFeeStrategy {
+    type Err = InvalidPriorityFeeStrategy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidPriorityFeeStrategy(s.to_string());
+
+        match s.split_once(':').ok_or_else(invalid)? {
+            ("fixed", gwei) => gwei.parse().map(Self::Fixed).map_err(|_| invalid()),
+            ("percentile", percentile) => match percentile.parse() {
+                Ok(percentile) if percentile <= 100 => Ok(Self::Percentile(percentile)),
+                _ => Err(invalid()),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fees {
+    pub max_fee_per_gas:          U256,
+    pub max_priority_fee_per_gas: U256,
+}
+
+impl Fees {
+    fn max(self, other: Self) -> Self {
+        Self {
+            max_fee_per_gas:          self.max_fee_per_gas.max(other.max_fee_per_gas),
+            max_priority_fee_per_gas: self
+                .max_priority_fee_per_gas
+                .max(other.max_priority_fee_per_gas),
+        }
+    }
+
+    fn bumped(self, percent: u64) -> Self {
+        let bump = |fee: U256| fee * (100 + percent) / 100;
+
+        Self {
+            max_fee_per_gas:          bump(self.max_fee_per_gas),
+            max_priority_fee_per_gas: bump(self.max_priority_fee_per_gas),
+        }
+    }
+
+    fn capped(self, max_fee_per_gas: Option<U256>) -> Self {
+        let max_fee_per_gas =
+            max_fee_per_gas.map_or(self.max_fee_per_gas, |cap| self.max_fee_per_gas.min(cap));
+
+        Self {
+            max_fee_per_gas,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas.min(max_fee_per_gas),
+        }
+    }
+}
+
+/// Chooses EIP-1559 fees for new transactions and escalates the fees of
+/// transactions that are stuck.
+#[derive(Debug)]
+pub struct FeeStrategy {
+    read_provider:   ReadProvider,
+    max_fee_per_gas: Option<U256>,
+    priority_fee:    PriorityFeeStrategy,
+    bump_percent:    u64,
+}
+
+impl FeeStrategy {
+    pub fn new(
+        read_provider: ReadProvider,
+        max_fee_per_gas_gwei: Option<u64>,
+        priority_fee: PriorityFeeStrategy,
+        bump_percent: u64,
+    ) -> Self {
+        Self {
+            read_provider,
+            max_fee_per_gas: max_fee_per_gas_gwei.map(|gwei| U256::from(gwei) * GWEI),
+            priority_fee,
+            bump_percent,
+        }
+    }
+
+    /// Estimates fees for a new transaction. Returns `None` on chains without
+    /// EIP-1559, in which case pricing is left to the relayer.
+    pub async fn estimate(&self) -> Result<Option<Fees>, ProviderError> {
+        if self.read_provider.legacy {
+            return Ok(None);
+        }
+
+        let percentile = match self.priority_fee {
+            PriorityFeeStrategy::Fixed(_) => vec![],
+            PriorityFeeStrategy::Percentile(percentile) => vec![f64::from(percentile)],
+        };
+
+        let history = self
+            .read_provider
+            .fee_history(FEE_HISTORY_BLOCKS, BlockNumber::Latest, &percentile)
+            .await?;
+
+        // The last entry is the base fee of the next block.
+        let base_fee = history.base_fee_per_gas.last().copied().unwrap_or_default();
+
+        let priority_fee = match self.priority_fee {
+            PriorityFeeStrategy::Fixed(gwei) => U256::from(gwei) * GWEI,
+            PriorityFeeStrategy::Percentile(_) => {
+                let mut rewards = history
+                    .reward
+                    .iter()
+                    .filter_map(|rewards| rewards.first().copied())
+                    .collect::<Vec<_>>();
+                rewards.sort_unstable();
+                rewards.get(rewards.len() / 2).copied().unwrap_or_default()
+            }
+        };
+
+        // Leave room for the base fee to double before the transaction gets
+        // stuck.
+        let fees = Fees {
+            max_fee_per_gas:          base_fee * 2 + priority_fee,
+            max_priority_fee_per_gas: priority_fee,
+        };
+
+        Ok(Some(fees.capped(self.max_fee_per_gas)))
+    }
+
+    /// Fees to resubmit a stuck transaction with. Returns `None` if the fees
+    /// can't be raised any further.
+    pub async fn escalate(&self, current: Fees) -> Result<Option<Fees>, ProviderError> {
+        let estimate = self.estimate().await?;
+
+        Ok(escalated(
+            current,
+            estimate,
+            self.bump_percent,
+            self.max_fee_per_gas,
+        ))
+    }
+}
+
+/// Bumps the current fees, catching up with the estimate if fees on the
+/// network rose faster than that.
+fn escalated(
+    current: Fees,
+    estimate: Option<Fees>,
+    bump_percent: u64,
+    max_fee_per_gas: Option<U256>,
+) -> Option<Fees> {
+    let bumped = current.bumped(bump_percent);
+    let fees = estimate
+        .map_or(bumped, |estimate| bumped.max(estimate))
+        .capped(max_fee_per_gas);
+
+    (fees.max_fee_per_gas > current.max_fee_per_gas).then_some(fees)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fees(max_fee_per_gas: u64, max_priority_fee_per_gas: u64) -> Fees {
+        Fees {
+            max_fee_per_gas:          max_fee_per_gas.into(),
+            max_priority_fee_per_gas: max_priority_fee_per_gas.into(),
+        }
+    }
+
+    #[test]
+    fn parse_priority_fee_strategy() {
+        assert_eq!(
+            "fixed:2".parse::<PriorityFeeStrategy>().unwrap(),
+            PriorityFeeStrategy::Fixed(2)
+        );
+        assert_eq!(
+            "percentile:50".parse::<PriorityFeeStrategy>().unwrap(),
+            PriorityFeeStrategy::Percentile(50)
+        );
+        assert!("percentile:101".parse::<PriorityFeeStrategy>().is_err());
+        assert!("fast".parse::<PriorityFeeStrategy>().is_err());
+    }
+
+    #[test]
+    fn escalation_bumps_and_caps_fees() {
+        assert_eq!(
+            escalated(fees(100, 10), None, 20, None),
+            Some(fees(120, 12))
+        );
+
+        // Catches up with the network
+        assert_eq!(
+            escalated(fees(100, 10), Some(fees(200, 5)), 20, None),
+            Some(fees(200, 12))
+        );
+
+        // Capped at the configured max fee
+        assert_eq!(
+            escalated(fees(100, 10), None, 20, Some(110.into())),
+            Some(fees(110, 12))
+        );
+
+        // Already at the cap
+        assert_eq!(escalated(fees(110, 10), None, 20, Some(110.into())), None);
+    }
+}
@@ -2,7 +2,7 @@ use std::num::ParseIntError;
 use std::str::FromStr;
 use std::time::Duration;
 
-use anyhow::Result as AnyhowResult;
+use anyhow::{Context, Result as AnyhowResult};
 use async_trait::async_trait;
 use clap::Parser;
 use ethers::providers::Middleware;
@@ -10,11 +10,13 @@ use ethers::types::transaction::eip2718::TypedTransaction;
 use ethers::types::{Address, H160, U64};
 use tracing::{info, warn};
 
+use self::fees::PriorityFeeStrategy;
 use self::openzeppelin::OzRelay;
 use super::write::{TransactionId, WriteProvider};
 use super::{ReadProvider, TxError};
 
 mod error;
+mod fees;
 mod openzeppelin;
 
 fn duration_from_str(value: &str) -> Result<Duration, ParseIntError> {
@@ -29,16 +31,17 @@ pub struct Options {
     pub oz_api_url: String,
 
     /// OpenZeppelin Defender API Key
-    #[clap(long, env)]
+    #[clap(long, env, default_value = "")]
     pub oz_api_key: String,
 
     /// OpenZeppelin Defender API Secret
-    #[clap(long, env)]
+    #[clap(long, env, default_value = "")]
     pub oz_api_secret: String,
 
-    /// OpenZeppelin Defender API Secret
+    /// Address of the OpenZeppelin Defender relayer. Required by the `oz`
+    /// transaction backend.
     #[clap(long, env)]
-    pub oz_address: H160,
+    pub oz_address: Option<H160>,
 
     /// For how long OpenZeppelin should track and retry the transaction (in
     /// seconds) Default: 7 days (7 * 24 * 60 * 60 = 604800 seconds)
@@ -53,6 +56,25 @@ pub struct Options {
 
     #[clap(long, env)]
     pub oz_gas_limit: Option<u64>,
+
+    /// Upper bound on the max fee per gas of submitted transactions (in gwei)
+    #[clap(long, env)]
+    pub oz_max_fee_per_gas: Option<u64>,
+
+    /// How the priority fee is chosen: `fixed:<gwei>` or
+    /// `percentile:<0-100>` of the priority fees paid in recent blocks
+    #[clap(long, env, default_value = "percentile:50")]
+    pub oz_priority_fee_strategy: PriorityFeeStrategy,
+
+    /// How long a transaction may stay unmined before it's resubmitted with
+    /// higher fees (in seconds)
+    #[clap(long, env, value_parser=duration_from_str, default_value="120")]
+    pub oz_fee_bump_interval: Duration,
+
+    /// Percentage by which the fees of a stuck transaction are raised. Nodes
+    /// only accept replacements that raise fees by at least 10%.
+    #[clap(long, env, default_value = "20")]
+    pub oz_fee_bump_percent: u64,
 }
 
 #[derive(Debug)]
@@ -64,12 +86,15 @@ pub struct Provider {
 
 impl Provider {
     pub async fn new(read_provider: ReadProvider, options: &Options) -> AnyhowResult<Self> {
-        let relay = OzRelay::new(options).await?;
+        let address = options
+            .oz_address
+            .context("--oz-address is required by the oz transaction backend")?;
+        let relay = OzRelay::new(read_provider.clone(), options).await?;
 
         Ok(Self {
             read_provider,
             inner: relay,
-            address: options.oz_address,
+            address,
         })
     }
 }
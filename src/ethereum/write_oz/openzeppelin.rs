@@ -3,17 +3,19 @@ use std::time::Duration;
 
 use anyhow::Result as AnyhowResult;
 use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::U256;
 use once_cell::sync::Lazy;
 use oz_api::data::transactions::{RelayerTransactionBase, SendBaseTransactionRequest, Status};
 use oz_api::OzApi;
-use prometheus::{register_int_counter_vec, IntCounterVec};
+use prometheus::{register_int_counter, register_int_counter_vec, IntCounter, IntCounterVec};
 use tokio::time::timeout;
-use tracing::{error, info, info_span, Instrument};
+use tracing::{error, info, info_span, warn, Instrument};
 
 use super::error::Error;
+use super::fees::{FeeStrategy, Fees};
 use super::Options;
 use crate::ethereum::write::TransactionId;
-use crate::ethereum::TxError;
+use crate::ethereum::{ReadProvider, TxError};
 
 static TX_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     register_int_counter_vec!("eth_tx_count", "The transaction count by bytes4.", &[
@@ -22,6 +24,14 @@ static TX_COUNT: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
+static FEE_BUMPS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "eth_tx_fee_bumps",
+        "Number of stuck transactions resubmitted with higher fees."
+    )
+    .unwrap()
+});
+
 #[derive(Debug)]
 pub struct OzRelay {
     oz_api:               OzApi,
@@ -29,10 +39,12 @@ pub struct OzRelay {
     send_timeout:         Duration,
     mine_timeout:         Duration,
     gas_limit:            Option<u64>,
+    fees:                 FeeStrategy,
+    fee_bump_interval:    chrono::Duration,
 }
 
 impl OzRelay {
-    pub async fn new(options: &Options) -> AnyhowResult<Self> {
+    pub async fn new(read_provider: ReadProvider, options: &Options) -> AnyhowResult<Self> {
         let oz_api = if options.oz_api_key.is_empty() && options.oz_api_secret.is_empty() {
             tracing::warn!(
                 "OpenZeppelin Defender API Key and Secret are empty. Connection will operate \
@@ -55,6 +67,13 @@ impl OzRelay {
             send_timeout: options.oz_send_timeout,
             mine_timeout: options.oz_mine_timeout,
             gas_limit: options.oz_gas_limit,
+            fees: FeeStrategy::new(
+                read_provider,
+                options.oz_max_fee_per_gas,
+                options.oz_priority_fee_strategy,
+                options.oz_fee_bump_percent,
+            ),
+            fee_bump_interval: chrono::Duration::from_std(options.oz_fee_bump_interval)?,
         })
     }
 
@@ -88,6 +107,10 @@ impl OzRelay {
                 Status::Failed => return Err(TxError::Failed(None)),
                 Status::Mined | Status::Confirmed => return Ok(transaction),
                 _ => {
+                    if let Err(error) = self.bump_fees_if_stuck(&transaction).await {
+                        warn!(?error, id, "Failed to bump transaction fees");
+                    }
+
                     info!("waiting 5 s to mine");
                     tokio::time::sleep(Duration::from_secs(5)).await;
                 }
@@ -95,6 +118,66 @@ impl OzRelay {
         }
     }
 
+    /// Resubmits the transaction with higher fees once it has been pending for
+    /// longer than the fee bump interval.
+    async fn bump_fees_if_stuck(&self, transaction: &RelayerTransactionBase) -> AnyhowResult<()> {
+        let Some(sent_at) = transaction.sent_at else {
+            return Ok(());
+        };
+
+        if chrono::Utc::now() - sent_at < self.fee_bump_interval {
+            return Ok(());
+        }
+
+        // Transactions without EIP-1559 fees are priced by the relayer.
+        let (Some(max_fee_per_gas), Some(max_priority_fee_per_gas)) = (
+            transaction.max_fee_per_gas,
+            transaction.max_priority_fee_per_gas,
+        ) else {
+            return Ok(());
+        };
+
+        let current = Fees {
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        };
+
+        let Some(fees) = self.fees.escalate(current).await? else {
+            warn!(
+                id = %transaction.transaction_id,
+                ?current,
+                "Transaction is stuck, but its fees are already at the cap"
+            );
+            return Ok(());
+        };
+
+        info!(
+            id = %transaction.transaction_id,
+            ?current,
+            ?fees,
+            "Resubmitting stuck transaction with higher fees"
+        );
+
+        let gas_limit = U256::from(transaction.gas_limit);
+        let api_tx = SendBaseTransactionRequest {
+            to: Some(&transaction.to),
+            value: transaction.value.as_ref(),
+            gas_limit: Some(&gas_limit),
+            data: transaction.data.as_ref(),
+            valid_until: Some(transaction.valid_until),
+            max_fee_per_gas: Some(&fees.max_fee_per_gas),
+            max_priority_fee_per_gas: Some(&fees.max_priority_fee_per_gas),
+        };
+
+        self.oz_api
+            .replace_transaction(&transaction.transaction_id, api_tx)
+            .await?;
+
+        FEE_BUMPS.inc();
+
+        Ok(())
+    }
+
     async fn mine_transaction_id(&self, id: &str) -> Result<RelayerTransactionBase, TxError> {
         timeout(self.mine_timeout, self.mine_transaction_id_unchecked(id))
             .await
@@ -104,14 +187,17 @@ impl OzRelay {
     async fn send_oz_transaction<T: Into<TypedTransaction> + Send + Sync>(
         &self,
         tx: T,
+        fees: Option<Fees>,
     ) -> Result<String, Error> {
         let tx: TypedTransaction = tx.into();
         let api_tx = SendBaseTransactionRequest {
-            to:          tx.to(),
-            value:       tx.value(),
-            gas_limit:   tx.gas(),
-            data:        tx.data(),
+            to: tx.to(),
+            value: tx.value(),
+            gas_limit: tx.gas(),
+            data: tx.data(),
             valid_until: Some(chrono::Utc::now() + self.transaction_validity),
+            max_fee_per_gas: fees.as_ref().map(|fees| &fees.max_fee_per_gas),
+            max_priority_fee_per_gas: fees.as_ref().map(|fees| &fees.max_priority_fee_per_gas),
         };
 
         let tx = self.oz_api.send_transaction(api_tx).await?;
@@ -175,18 +261,28 @@ impl OzRelay {
         let bytes4 = format!("{bytes4:8x}");
         TX_COUNT.with_label_values(&[&bytes4]).inc();
 
+        let fees = self.fees.estimate().await.map_err(|error| {
+            error!(?error, "Failed to estimate transaction fees");
+            TxError::Fill(Box::new(error))
+        })?;
+
+        info!(?fees, "Estimated transaction fees");
+
         // Send TX to OZ Relay
-        let tx_id = timeout(self.send_timeout, self.send_oz_transaction(tx.clone()))
-            .instrument(info_span!("Send TX to mempool"))
-            .await
-            .map_err(|elapsed| {
-                error!(?elapsed, "Send transaction timed out");
-                TxError::SendTimeout
-            })?
-            .map_err(|error| {
-                error!(?error, "Failed to send transaction");
-                TxError::Send(Box::new(error))
-            })?;
+        let tx_id = timeout(
+            self.send_timeout,
+            self.send_oz_transaction(tx.clone(), fees),
+        )
+        .instrument(info_span!("Send TX to mempool"))
+        .await
+        .map_err(|elapsed| {
+            error!(?elapsed, "Send transaction timed out");
+            TxError::SendTimeout
+        })?
+        .map_err(|error| {
+            error!(?error, "Failed to send transaction");
+            TxError::Send(Box::new(error))
+        })?;
 
         info!(?tx_id, "Transaction submitted to OZ Relay");
 
@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use ethers::providers::Middleware;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::Address;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use tracing::{info, warn};
+
+use super::write::{TransactionId, WriteProvider};
+use super::{ReadProvider, TxError};
+
+static SIMULATED_TRANSACTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "eth_tx_simulated",
+        "Number of transactions simulated in dry-run mode, by whether they would have succeeded.",
+        &["outcome"]
+    )
+    .unwrap()
+});
+
+/// Simulates transactions with `eth_estimateGas` and `eth_call` instead of
+/// sending them, for dry runs. Every transaction counts as mined, whether or
+/// not it would have succeeded, so that the pipeline keeps going.
+#[derive(Debug)]
+pub struct Provider {
+    read_provider: ReadProvider,
+    simulated:     AtomicU64,
+}
+
+impl Provider {
+    pub fn new(read_provider: ReadProvider) -> Self {
+        Self {
+            read_provider,
+            simulated: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl WriteProvider for Provider {
+    async fn send_transaction(
+        &self,
+        tx: TypedTransaction,
+        _only_once: bool,
+    ) -> Result<TransactionId, TxError> {
+        let id = TransactionId(format!(
+            "simulated-{}",
+            self.simulated.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        let simulation = async {
+            let gas = self.read_provider.estimate_gas(&tx, None).await?;
+            let output = self.read_provider.call(&tx, None).await?;
+            Ok::<_, <ReadProvider as Middleware>::Error>((gas, output))
+        };
+
+        match simulation.await {
+            Ok((gas, output)) => {
+                SIMULATED_TRANSACTIONS.with_label_values(&["success"]).inc();
+                info!(%id, to = ?tx.to(), %gas, %output, "Simulated transaction would succeed");
+            }
+            Err(error) => {
+                SIMULATED_TRANSACTIONS
+                    .with_label_values(&["reverted"])
+                    .inc();
+                warn!(%id, to = ?tx.to(), %error, "Simulated transaction would fail");
+            }
+        }
+
+        Ok(id)
+    }
+
+    async fn fetch_pending_transactions(&self) -> Result<Vec<TransactionId>, TxError> {
+        Ok(Vec::new())
+    }
+
+    async fn mine_transaction(&self, _tx: TransactionId) -> Result<bool, TxError> {
+        Ok(true)
+    }
+
+    fn address(&self) -> Address {
+        Address::zero()
+    }
+
+    fn addresses(&self) -> Vec<Address> {
+        Vec::new()
+    }
+}
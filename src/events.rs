@@ -0,0 +1,95 @@
+//! Notifications about the progress of identities, delivered to a webhook.
+//!
+//! Events are stored in the database when they happen and delivered in batches
+//! by a background task, so delivery is at-least-once. Consumers should use the
+//! event id to deduplicate. Every request carries an HMAC-SHA256 signature of
+//! its body. Events that can't be delivered after the configured number of
+//! attempts are moved to a dead-letter queue, from which they can be redriven.
+// Events are only recorded and delivered by the batching tasks.
+#![cfg_attr(not(feature = "submission"), allow(dead_code))]
+
+use clap::Parser;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use url::Url;
+
+use crate::identity_tree::Hash;
+use crate::secret::SecretString;
+
+/// The header containing the signature of the request body.
+pub const SIGNATURE_HEADER: &str = "x-sequencer-signature";
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// The URL events are delivered to. No events are recorded if it's not
+    /// set.
+    #[clap(long, env)]
+    pub webhook_url: Option<Url>,
+
+    /// The key used to sign the events delivered to the webhook.
+    #[clap(long, env)]
+    pub webhook_secret: Option<SecretString>,
+
+    /// The maximum number of events delivered in a single request.
+    #[clap(long, env, default_value = "100")]
+    pub webhook_batch_size: i64,
+
+    /// The number of delivery attempts after which an event is moved to the
+    /// dead-letter queue.
+    #[clap(long, env, default_value = "10")]
+    pub webhook_max_attempts: i32,
+
+    /// The delay before retrying a failed delivery (seconds). It doubles with
+    /// every attempt, up to an hour.
+    #[clap(long, env, default_value = "1")]
+    pub webhook_initial_backoff_seconds: u64,
+}
+
+impl Options {
+    #[must_use]
+    pub const fn is_enabled(&self) -> bool {
+        self.webhook_url.is_some()
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    /// A batch of identities was mined on chain.
+    #[serde(rename_all = "camelCase")]
+    BatchMined {
+        pre_root:    Hash,
+        post_root:   Hash,
+        start_index: usize,
+    },
+}
+
+/// Computes the value of [`SIGNATURE_HEADER`] for a request body.
+#[must_use]
+pub fn sign(secret: &SecretString, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose().as_bytes())
+        .expect("HMAC accepts keys of any size");
+    mac.update(body);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // Test case 2 from RFC 4231.
+        let secret = SecretString::from_str("Jefe").unwrap();
+
+        assert_eq!(
+            sign(&secret, b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
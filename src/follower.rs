@@ -0,0 +1,142 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::database::replication::IdentityChange;
+use crate::database::Database;
+use crate::identity_tree::{
+    Hash, ProcessedStatus, TreeState, TreeVersionReadOps, TreeWithNextVersion,
+};
+
+/// Maximum number of changes consumed from the replication slot at once.
+const MAX_CHANGES: usize = 10_000;
+
+/// Keeps the in-memory tree of a replica in sync with the identities table by
+/// tailing a logical replication slot, instead of running the identity
+/// committer.
+pub struct Follower {
+    database:      Arc<Database>,
+    tree_state:    TreeState,
+    slot:          String,
+    poll_interval: Duration,
+}
+
+impl Follower {
+    pub fn new(
+        database: Arc<Database>,
+        tree_state: TreeState,
+        slot: String,
+        poll_interval: Duration,
+    ) -> Self {
+        Self {
+            database,
+            tree_state,
+            slot,
+            poll_interval,
+        }
+    }
+
+    pub async fn run(self) {
+        info!(slot = self.slot, "Following identity changes");
+
+        loop {
+            if let Err(error) = self.follow().await {
+                error!(?error, slot = self.slot, "Failed to read identity changes");
+                sleep(self.poll_interval).await;
+            }
+        }
+    }
+
+    async fn follow(&self) -> AnyhowResult<()> {
+        loop {
+            let changes = self
+                .database
+                .get_identity_changes(&self.slot, MAX_CHANGES)
+                .await?;
+
+            if changes.is_empty() {
+                sleep(self.poll_interval).await;
+                continue;
+            }
+
+            self.apply(changes);
+        }
+    }
+
+    /// Appends new leaves to the latest tree and advances the processed and
+    /// mined trees. Changes that are already reflected in the tree are
+    /// skipped, as the slot may replay changes included in the tree loaded at
+    /// startup.
+    fn apply(&self, changes: Vec<IdentityChange>) {
+        let latest_tree = self.tree_state.get_latest_tree();
+
+        let mut inserted = Vec::new();
+        let mut processed: Option<(usize, Hash)> = None;
+        let mut mined: Option<(usize, Hash)> = None;
+
+        for change in changes {
+            match change {
+                IdentityChange::Inserted {
+                    leaf_index,
+                    commitment,
+                    ..
+                } => {
+                    if leaf_index >= latest_tree.next_leaf() + inserted.len() {
+                        inserted.push((leaf_index, commitment));
+                    }
+                }
+                IdentityChange::StatusChanged {
+                    leaf_index,
+                    root,
+                    status,
+                } => {
+                    let latest = |current: Option<(usize, Hash)>| match current {
+                        Some((index, _)) if index >= leaf_index => current,
+                        _ => Some((leaf_index, root)),
+                    };
+
+                    match status {
+                        ProcessedStatus::Processed => processed = latest(processed),
+                        ProcessedStatus::Mined => {
+                            processed = latest(processed);
+                            mined = latest(mined);
+                        }
+                        ProcessedStatus::Pending => {}
+                    }
+                }
+            }
+        }
+
+        if let Some((first, _)) = inserted.first() {
+            if *first != latest_tree.next_leaf() {
+                warn!(
+                    expected = latest_tree.next_leaf(),
+                    actual = first,
+                    "Replicated leaves don't follow the latest tree"
+                );
+            }
+
+            let commitments = inserted
+                .iter()
+                .map(|(_, commitment)| *commitment)
+                .collect::<Vec<_>>();
+            _ = latest_tree.append_many(&commitments);
+        }
+
+        if let Some((_, root)) = processed {
+            self.tree_state
+                .get_batching_tree()
+                .apply_updates_up_to(root);
+            self.tree_state
+                .get_processed_tree()
+                .apply_updates_up_to(root);
+        }
+
+        if let Some((_, root)) = mined {
+            self.tree_state.get_mined_tree().apply_updates_up_to(root);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Runs several identity trees in one sequencer. Every group has its own
+//! contract, tree parameters, database and batching, and its API is served
+//! under `/groups/<group id>/`.
+//!
+//! A group is configured with a TOML file of the options that differ from the
+//! options of the sequencer, in the format of the config file, e.g.
+//! `identity-manager-address`, `tree-depth` and `database`.
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{ensure, Result as AnyhowResult};
+use clap::Parser;
+use thiserror::Error;
+
+use crate::{app, config, Options};
+
+/// A group given as `<group id>=<config file>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupConfig {
+    pub id:   u64,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid group {0:?}, expected `<group id>=<config file>`")]
+pub struct InvalidGroupConfig(String);
+
+impl FromStr for GroupConfig {
+    type Err = InvalidGroupConfig;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, path) = s
+            .split_once('=')
+            .ok_or_else(|| InvalidGroupConfig(s.to_owned()))?;
+        let id = id.parse().map_err(|_| InvalidGroupConfig(s.to_owned()))?;
+        if path.is_empty() {
+            return Err(InvalidGroupConfig(s.to_owned()));
+        }
+
+        Ok(Self {
+            id,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// Returns the options of `group`, which are the options the sequencer was
+/// started with in `args` with the options in the config file of the group
+/// replacing them.
+///
+/// # Errors
+///
+/// Will return `Err` if the config file can't be loaded, if the options are
+/// invalid, or if the group would share the database of the sequencer.
+pub fn options(
+    args: &[String],
+    base: &app::Options,
+    group: &GroupConfig,
+) -> AnyhowResult<app::Options> {
+    let args = config::group_args(args, &group.path)?;
+    let options = Options::try_parse_from(args)?.app;
+
+    ensure!(
+        options.database.database != base.database.database,
+        "group {} has to have a database of its own",
+        group.id
+    );
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_are_parsed() {
+        assert_eq!(
+            "2=groups/two.toml".parse::<GroupConfig>().unwrap(),
+            GroupConfig {
+                id:   2,
+                path: PathBuf::from("groups/two.toml"),
+            }
+        );
+        assert!("two=groups/two.toml".parse::<GroupConfig>().is_err());
+        assert!("2".parse::<GroupConfig>().is_err());
+        assert!("2=".parse::<GroupConfig>().is_err());
+    }
+}
@@ -1,18 +1,71 @@
 use std::cmp::min;
+use std::fmt;
+use std::ops::Range;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex, MutexGuard};
 
+use anyhow::{anyhow, Result as AnyhowResult};
 use chrono::Utc;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, register_int_gauge, IntCounter, IntGauge};
 use semaphore::lazy_merkle_tree::{Derived, LazyMerkleTree};
 use semaphore::merkle_tree::Hasher;
 use semaphore::poseidon_tree::{PoseidonHash, Proof};
 use semaphore::{lazy_merkle_tree, Field};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
+
+/// The hash function of the tree. Deployments with other Poseidon parameters
+/// or another hash function implement [`Hasher`] for it, point this at the
+/// implementation and add it to [`TreeHash`].
+pub type TreeHasher = PoseidonHash;
+pub type PoseidonTree<Version> = LazyMerkleTree<TreeHasher, Version>;
+pub type Hash = <TreeHasher as Hasher>::Hash;
+
+// TODO Export the reduced-ness check that this is enabling from the
+//  `semaphore-rs` library when we bump the version.
+/// The order of the scalar field. Commitments must be elements of the field to
+/// be inserted into the tree.
+pub static SNARK_SCALAR_FIELD: Lazy<Hash> = Lazy::new(|| {
+    Hash::from_str_radix(
+        "21888242871839275222246405745257275088548364400416034343698204186575808495617",
+        10,
+    )
+    .expect("This should just parse.")
+});
+
+/// Whether `commitment` is an element of the scalar field.
+#[must_use]
+pub fn is_reduced(commitment: &Hash) -> bool {
+    commitment < &*SNARK_SCALAR_FIELD
+}
+
+/// Reduces `commitment` modulo the order of the scalar field.
+#[must_use]
+pub fn reduce(commitment: Hash) -> Hash {
+    commitment % *SNARK_SCALAR_FIELD
+}
 
-pub type PoseidonTree<Version> = LazyMerkleTree<PoseidonHash, Version>;
-pub type Hash = <PoseidonHash as Hasher>::Hash;
+/// How often progress is logged while leaves are applied one at a time.
+const PROGRESS_INTERVAL: usize = 100_000;
+
+static RESTORE_REMAINING_LEAVES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "tree_restore_remaining_leaves",
+        "Leaves beyond the dense prefix left to apply while the tree is built"
+    )
+    .unwrap()
+});
+
+static MINED_REGRESSIONS_REFUSED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "tree_mined_regressions_refused",
+        "Identities reported as mined because the mined tree contains them, although the database \
+         says otherwise"
+    )
+    .unwrap()
+});
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct TreeUpdate {
@@ -32,26 +85,15 @@ impl TreeUpdate {
 
 #[derive(Debug)]
 pub struct TreeItem {
-    pub status:     Status,
+    pub status:     ProcessedStatus,
     pub leaf_index: usize,
 }
 
-// TODO: Failed and New seem to only be used for "unprocessed" identities
-//       we should create a separate enum with just those 2 variants
-
-/// The status pertains to the status of the root.
-/// But it can also be used interchangeably with the status of an identity
-/// as all identity commitments has an associated root.
-#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq, Hash)]
+/// The status of an identity in the tree, and of the root after it.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
-pub enum Status {
-    /// An unprocessed identity that failed to be included`
-    Failed,
-    /// Root is unprocessed - i.e. not included in sequencer's
-    /// in-memory tree.
-    New,
+pub enum ProcessedStatus {
     /// Root is included in sequencer's in-memory tree but not yet mined.
-    /// The
     Pending,
     /// Root is mined on mainnet but is still waiting for confirmation on
     /// relayed chains
@@ -66,17 +108,35 @@ pub enum Status {
     Mined,
 }
 
+/// The status of an identity that is queued for insertion into the tree.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum UnprocessedStatus {
+    /// An unprocessed identity that failed to be included
+    Failed,
+    /// Root is unprocessed - i.e. not included in sequencer's
+    /// in-memory tree.
+    New,
+}
+
+/// The status of an identity anywhere in its lifecycle, as reported by the
+/// API. It serializes to the name of the inner status.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum Status {
+    Unprocessed(UnprocessedStatus),
+    Processed(ProcessedStatus),
+}
+
 #[derive(Debug, Error)]
 #[error("unknown status")]
 pub struct UnknownStatus;
 
-impl FromStr for Status {
+impl FromStr for ProcessedStatus {
     type Err = UnknownStatus;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "new" => Ok(Self::New),
-            "failed" => Ok(Self::Failed),
             "pending" => Ok(Self::Pending),
             "mined" => Ok(Self::Mined),
             "processed" => Ok(Self::Processed),
@@ -85,14 +145,212 @@ impl FromStr for Status {
     }
 }
 
+impl From<ProcessedStatus> for &str {
+    fn from(scope: ProcessedStatus) -> Self {
+        match scope {
+            ProcessedStatus::Pending => "pending",
+            ProcessedStatus::Mined => "mined",
+            ProcessedStatus::Processed => "processed",
+        }
+    }
+}
+
+impl FromStr for UnprocessedStatus {
+    type Err = UnknownStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "new" => Ok(Self::New),
+            "failed" => Ok(Self::Failed),
+            _ => Err(UnknownStatus),
+        }
+    }
+}
+
+impl From<UnprocessedStatus> for &str {
+    fn from(scope: UnprocessedStatus) -> Self {
+        match scope {
+            UnprocessedStatus::New => "new",
+            UnprocessedStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = UnknownStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .map(Self::Processed)
+            .or_else(|_| s.parse().map(Self::Unprocessed))
+    }
+}
+
 impl From<Status> for &str {
     fn from(scope: Status) -> Self {
         match scope {
-            Status::New => "new",
-            Status::Failed => "failed",
-            Status::Pending => "pending",
-            Status::Mined => "mined",
-            Status::Processed => "processed",
+            Status::Unprocessed(status) => status.into(),
+            Status::Processed(status) => status.into(),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str((*self).into())
+    }
+}
+
+impl From<ProcessedStatus> for Status {
+    fn from(status: ProcessedStatus) -> Self {
+        Self::Processed(status)
+    }
+}
+
+impl From<UnprocessedStatus> for Status {
+    fn from(status: UnprocessedStatus) -> Self {
+        Self::Unprocessed(status)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid tree hash {0:?}, expected `poseidon-bn254`")]
+pub struct InvalidTreeHash(String);
+
+/// The hash functions, with their parameters, that a tree can be built with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeHash {
+    /// Poseidon over the scalar field of BN254 with the parameters of
+    /// circomlib, as used by Semaphore and WorldID.
+    PoseidonBn254,
+}
+
+impl FromStr for TreeHash {
+    type Err = InvalidTreeHash;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "poseidon-bn254" => Ok(Self::PoseidonBn254),
+            _ => Err(InvalidTreeHash(s.to_string())),
+        }
+    }
+}
+
+impl TreeHash {
+    /// The hash of two zero nodes, which identifies the parameters.
+    fn zero_node_hash(self) -> Hash {
+        match self {
+            Self::PoseidonBn254 => Hash::from_str_radix(
+                "14744269619966411208579211824598458697587494354926760081771325075741142829156",
+                10,
+            )
+            .expect("This should just parse."),
+        }
+    }
+
+    /// Checks that [`TreeHasher`] hashes with these parameters.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the tree of this build uses another hash function.
+    pub fn validate(self) -> AnyhowResult<()> {
+        let zero_node_hash = TreeHasher::hash_node(&Hash::ZERO, &Hash::ZERO);
+        if zero_node_hash != self.zero_node_hash() {
+            return Err(anyhow!(
+                "The tree hash is configured as {self:?}, but the tree of this build hashes two \
+                 zero nodes to {zero_node_hash:?}"
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Why an unprocessed identity failed to be included.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The commitment was submitted more than once or is already in the tree.
+    DuplicateCommitment,
+    /// The commitment can never be inserted.
+    InvalidCommitment,
+    /// Proving the batch failed. The commitment will be retried.
+    ProverFailure,
+    /// An error recorded before error codes were introduced.
+    Unknown,
+}
+
+impl ErrorCode {
+    /// Whether the sequencer will retry inserting the commitment.
+    #[must_use]
+    pub const fn is_retryable(self) -> bool {
+        matches!(self, Self::ProverFailure)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown error code")]
+pub struct UnknownErrorCode;
+
+impl FromStr for ErrorCode {
+    type Err = UnknownErrorCode;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "duplicate_commitment" => Ok(Self::DuplicateCommitment),
+            "invalid_commitment" => Ok(Self::InvalidCommitment),
+            "prover_failure" => Ok(Self::ProverFailure),
+            "unknown" => Ok(Self::Unknown),
+            _ => Err(UnknownErrorCode),
+        }
+    }
+}
+
+impl From<ErrorCode> for &str {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::DuplicateCommitment => "duplicate_commitment",
+            ErrorCode::InvalidCommitment => "invalid_commitment",
+            ErrorCode::ProverFailure => "prover_failure",
+            ErrorCode::Unknown => "unknown",
+        }
+    }
+}
+
+/// A structured error attached to an unprocessed identity.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitmentError {
+    pub code:      ErrorCode,
+    pub retryable: bool,
+    /// Code specific details, e.g. the leaf index of the existing commitment
+    /// for duplicates.
+    pub details:   serde_json::Value,
+}
+
+impl CommitmentError {
+    #[must_use]
+    pub const fn new(code: ErrorCode, details: serde_json::Value) -> Self {
+        Self {
+            code,
+            retryable: code.is_retryable(),
+            details,
+        }
+    }
+
+    /// A human readable description of the error.
+    #[must_use]
+    pub fn message(&self) -> String {
+        match self.code {
+            ErrorCode::DuplicateCommitment => "Duplicate commitment.".to_string(),
+            ErrorCode::InvalidCommitment => "Invalid commitment.".to_string(),
+            ErrorCode::ProverFailure => "Prover failure, will retry.".to_string(),
+            ErrorCode::Unknown => self
+                .details
+                .get("message")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or("Unknown error.")
+                .to_string(),
         }
     }
 }
@@ -101,7 +359,7 @@ impl From<Status> for &str {
 #[serde(rename_all = "camelCase")]
 pub struct RootItem {
     pub root:                Field,
-    pub status:              Status,
+    pub status:              ProcessedStatus,
     pub pending_valid_as_of: chrono::DateTime<Utc>,
     pub mined_valid_as_of:   Option<chrono::DateTime<Utc>>,
 }
@@ -113,6 +371,8 @@ pub struct InclusionProof {
     pub root:    Option<Field>,
     pub proof:   Option<Proof>,
     pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error:   Option<CommitmentError>,
 }
 
 /// Additional data held by the canonical tree version. It includes data
@@ -189,6 +449,11 @@ where
         (self.tree.root(), proof)
     }
 
+    /// Gets the elements of the given leaves.
+    fn get_leaves(&self, leaves: Range<usize>) -> Vec<Hash> {
+        leaves.map(|leaf| self.tree.get_leaf(leaf)).collect()
+    }
+
     /// Returns _up to_ `maximum_update_count` updates that are to be applied to
     /// the tree.
     fn peek_next_updates(&self, maximum_update_count: usize) -> Vec<AppliedTreeUpdate> {
@@ -389,6 +654,17 @@ impl<V: Version<TreeVersion = lazy_merkle_tree::Derived>> TreeVersion<V> {
     fn as_derived(&self) -> TreeVersion<AnyDerived> {
         TreeVersion(self.0.clone())
     }
+
+    /// Returns the tree as of `root`, if `root` is one of the roots this
+    /// version added on top of its predecessor.
+    fn find_root(&self, root: &Hash) -> Option<PoseidonTree<lazy_merkle_tree::Derived>> {
+        self.get_data()
+            .metadata
+            .diff
+            .iter()
+            .find(|update| update.result.root() == *root)
+            .map(|update| update.result.clone())
+    }
 }
 
 /// The public-facing API for reading from a tree version. It is implemented for
@@ -401,6 +677,8 @@ pub trait TreeVersionReadOps {
     fn next_leaf(&self) -> usize;
     /// Returns the merkle proof and element at the given leaf.
     fn get_proof(&self, leaf: usize) -> (Hash, Proof);
+    /// Returns the elements of the given leaves.
+    fn get_leaves(&self, leaves: Range<usize>) -> Vec<Hash>;
 }
 
 impl<V: Version> TreeVersionReadOps for TreeVersion<V>
@@ -419,6 +697,10 @@ where
         let tree = self.get_data();
         tree.get_proof(leaf)
     }
+
+    fn get_leaves(&self, leaves: Range<usize>) -> Vec<Hash> {
+        self.get_data().get_leaves(leaves)
+    }
 }
 
 impl<V: Version> TreeVersion<V> {
@@ -448,6 +730,26 @@ impl TreeVersion<Latest> {
 
         output
     }
+
+    /// Returns what [`Self::append_many`] would, without changing the tree, so
+    /// that the identities can be stored before they are appended.
+    #[must_use]
+    pub fn simulate_append_many(&self, identities: &[Hash]) -> Vec<(Hash, Proof, usize)> {
+        let data = self.get_data();
+        let mut tree = data.tree.clone();
+
+        identities
+            .iter()
+            .enumerate()
+            .map(|(idx, identity)| {
+                let leaf_index = data.next_leaf + idx;
+
+                tree = tree.update(leaf_index, identity);
+
+                (tree.root(), tree.proof(leaf_index), leaf_index)
+            })
+            .collect()
+    }
 }
 
 /// Public API for working with versions that have a successor. Such versions
@@ -515,23 +817,236 @@ impl TreeState {
         self.batching.clone()
     }
 
+    /// Removes the leaves from `next_leaf` on from the latest tree, e.g. when
+    /// the batch they were to be submitted in is cancelled. Leaves that have
+    /// already been picked up by the batching tree can't be removed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `next_leaf` is before the next leaf of the batching tree.
+    pub fn truncate_latest(&self, next_leaf: usize) {
+        let batching = self.batching.get_data();
+        let mut latest = self.latest.get_data();
+
+        assert!(
+            next_leaf >= batching.next_leaf,
+            "Cannot remove leaves that have already been batched. Next leaf in the batching tree \
+             is: {}, truncating to: {next_leaf}",
+            batching.next_leaf
+        );
+
+        let kept = latest
+            .metadata
+            .diff
+            .iter()
+            .position(|update| update.update.leaf_index >= next_leaf)
+            .unwrap_or(latest.metadata.diff.len());
+        latest.metadata.diff.truncate(kept);
+
+        latest.tree = latest
+            .metadata
+            .diff
+            .last()
+            .map_or_else(|| batching.tree.clone(), |update| update.result.clone());
+        latest.next_leaf = next_leaf;
+    }
+
+    /// Returns the status of the identity at `leaf_index`, which the database
+    /// says is `status`. Mined identities are final, so an identity that the
+    /// mined tree contains is always mined: anything else can only be a bug,
+    /// e.g. in handling a reorg, and is reported loudly instead of to
+    /// verifiers.
+    #[must_use]
+    pub fn status_of(&self, leaf_index: usize, status: ProcessedStatus) -> ProcessedStatus {
+        let mined_next_leaf = self.mined.next_leaf();
+        if status == ProcessedStatus::Mined || leaf_index >= mined_next_leaf {
+            return status;
+        }
+
+        MINED_REGRESSIONS_REFUSED.inc();
+        error!(
+            leaf_index,
+            ?status,
+            mined_next_leaf,
+            "CRITICAL: The database regressed a mined identity, reporting it as mined"
+        );
+
+        ProcessedStatus::Mined
+    }
+
+    /// Returns `item` with the status of [`Self::status_of`].
+    #[must_use]
+    pub fn checked_item(&self, item: &TreeItem) -> TreeItem {
+        TreeItem {
+            status:     self.status_of(item.leaf_index, item.status),
+            leaf_index: item.leaf_index,
+        }
+    }
+
     #[must_use]
     pub fn get_proof_for(&self, item: &TreeItem) -> InclusionProof {
         let (root, proof) = match item.status {
-            Status::Pending | Status::New | Status::Failed => {
-                self.latest.get_proof(item.leaf_index)
-            }
-            Status::Processed => self.processed.get_proof(item.leaf_index),
-            Status::Mined => self.mined.get_proof(item.leaf_index),
+            ProcessedStatus::Pending => self.latest.get_proof(item.leaf_index),
+            ProcessedStatus::Processed => self.processed.get_proof(item.leaf_index),
+            ProcessedStatus::Mined => self.mined.get_proof(item.leaf_index),
         };
 
         InclusionProof {
-            status:  item.status,
+            status:  item.status.into(),
             root:    Some(root),
             proof:   Some(proof),
             message: None,
+            error:   None,
+        }
+    }
+
+    /// Returns the root of the tree with `status`. Pending refers to the
+    /// latest tree.
+    #[must_use]
+    pub fn get_root_for(&self, status: ProcessedStatus) -> Hash {
+        match status {
+            ProcessedStatus::Pending => self.latest.get_root(),
+            ProcessedStatus::Processed => self.processed.get_root(),
+            ProcessedStatus::Mined => self.mined.get_root(),
         }
     }
+
+    /// Returns the roots of all tree versions.
+    #[must_use]
+    pub fn get_roots(&self) -> [Hash; 4] {
+        [
+            self.mined.get_root(),
+            self.processed.get_root(),
+            self.batching.get_root(),
+            self.latest.get_root(),
+        ]
+    }
+
+    /// Returns the proof of `item` against the tree with `status` rather than
+    /// the tree matching the status of the item, e.g. against the latest tree
+    /// for a mined identity. Pending refers to the latest tree. Returns `None`
+    /// if the leaf isn't in that tree yet.
+    #[must_use]
+    pub fn get_proof_in(&self, item: &TreeItem, status: ProcessedStatus) -> Option<InclusionProof> {
+        let (root, proof) = match status {
+            ProcessedStatus::Pending => proof_if_contains(&self.latest, item.leaf_index),
+            ProcessedStatus::Processed => proof_if_contains(&self.processed, item.leaf_index),
+            ProcessedStatus::Mined => proof_if_contains(&self.mined, item.leaf_index),
+        }?;
+
+        Some(InclusionProof {
+            status:  item.status.into(),
+            root:    Some(root),
+            proof:   Some(proof),
+            message: None,
+            error:   None,
+        })
+    }
+
+    /// Returns the element of the leaf and its proof as of `root`, if `root`
+    /// is the mined root or one of the roots after it. Older roots are not
+    /// kept in memory.
+    #[must_use]
+    pub fn get_proof_at(&self, root: &Hash, leaf_index: usize) -> Option<(Hash, Proof)> {
+        {
+            let mined = self.mined.get_data();
+            if mined.tree.root() == *root {
+                return Some((
+                    mined.tree.get_leaf(leaf_index),
+                    mined.tree.proof(leaf_index),
+                ));
+            }
+        }
+
+        let tree = self
+            .processed
+            .find_root(root)
+            .or_else(|| self.batching.find_root(root))
+            .or_else(|| self.latest.find_root(root))?;
+
+        Some((tree.get_leaf(leaf_index), tree.proof(leaf_index)))
+    }
+}
+
+/// Computes the roots of a tree that leaves are only appended to, one leaf at
+/// a time, while only keeping one node per level in memory.
+pub struct RootAccumulator {
+    /// The empty subtree of every height, starting at the leaves.
+    zeros:    Vec<Hash>,
+    /// The last left node of every height whose right sibling is still
+    /// missing.
+    frontier: Vec<Hash>,
+    len:      usize,
+    root:     Hash,
+}
+
+impl RootAccumulator {
+    #[must_use]
+    pub fn new(tree_depth: usize, initial_leaf_value: Hash) -> Self {
+        let mut zeros = vec![initial_leaf_value];
+        for height in 0..tree_depth {
+            zeros.push(TreeHasher::hash_node(&zeros[height], &zeros[height]));
+        }
+
+        Self {
+            root: zeros[tree_depth],
+            frontier: zeros[..tree_depth].to_vec(),
+            zeros,
+            len: 0,
+        }
+    }
+
+    /// The number of leaves appended so far.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The root of the tree with the leaves appended so far.
+    #[must_use]
+    pub const fn root(&self) -> Hash {
+        self.root
+    }
+
+    /// Appends `leaf` and returns the new root.
+    pub fn push(&mut self, leaf: Hash) -> Hash {
+        let mut node = leaf;
+
+        for height in 0..self.frontier.len() {
+            if (self.len >> height) & 1 == 0 {
+                self.frontier[height] = node;
+                node = TreeHasher::hash_node(&node, &self.zeros[height]);
+            } else {
+                node = TreeHasher::hash_node(&self.frontier[height], &node);
+            }
+        }
+
+        self.len += 1;
+        self.root = node;
+
+        node
+    }
+}
+
+/// The depth of the smallest dense prefix that holds `leaf_count` leaves, but
+/// at least `min_depth` and at most `tree_depth`.
+#[must_use]
+pub fn dense_prefix_depth_for(min_depth: usize, tree_depth: usize, leaf_count: usize) -> usize {
+    let covering = leaf_count.next_power_of_two().trailing_zeros() as usize;
+
+    covering.max(min_depth).min(tree_depth)
+}
+
+fn proof_if_contains<V: Version>(tree: &TreeVersion<V>, leaf: usize) -> Option<(Hash, Proof)>
+where
+    TreeVersion<V>: TreeVersionReadOps,
+{
+    (leaf < tree.next_leaf()).then(|| tree.get_proof(leaf))
 }
 
 /// A helper for building the first tree version. Exposes a type-safe API over
@@ -569,25 +1084,58 @@ impl CanonicalTreeBuilder {
                 &initial_leaf,
                 initial_leaves_in_dense,
             );
+
+        Self::with_leftover_leaves(
+            tree,
+            flattening_threshold,
+            initial_leaves_in_dense_count,
+            leftover_initial_leaves,
+        )
+    }
+
+    /// Wraps a tree whose dense prefix holds the first `dense_leaves` leaves,
+    /// and appends `leftover_leaves` after them.
+    fn with_leftover_leaves(
+        tree: PoseidonTree<lazy_merkle_tree::Canonical>,
+        flattening_threshold: usize,
+        dense_leaves: usize,
+        leftover_leaves: &[Field],
+    ) -> Self {
         let metadata = CanonicalTreeMetadata {
             flatten_threshold:        flattening_threshold,
             count_since_last_flatten: 0,
         };
         let mut builder = Self(TreeVersionData {
             tree,
-            next_leaf: initial_leaves_in_dense_count,
+            next_leaf: dense_leaves,
             metadata,
             next: None,
         });
-        for (index, leaf) in leftover_initial_leaves.iter().enumerate() {
+        // Unlike the dense prefix, which is hashed in parallel, these are
+        // applied one at a time.
+        for (index, leaf) in leftover_leaves.iter().enumerate() {
+            if index % PROGRESS_INTERVAL == 0 {
+                let remaining = leftover_leaves.len() - index;
+                RESTORE_REMAINING_LEAVES.set(remaining.try_into().unwrap_or(i64::MAX));
+                info!(remaining, "Applying leaves beyond the dense prefix");
+            }
+
             builder.update(&TreeUpdate {
-                leaf_index: index + initial_leaves_in_dense_count,
+                leaf_index: index + dense_leaves,
                 element:    *leaf,
             });
         }
+        RESTORE_REMAINING_LEAVES.set(0);
+
         builder
     }
 
+    /// The root of the tree built so far.
+    #[must_use]
+    pub fn root(&self) -> Hash {
+        self.0.get_root()
+    }
+
     /// Updates a leaf in the resulting tree.
     pub fn update(&mut self, update: &TreeUpdate) {
         self.0.update(update.leaf_index, update.element);
@@ -656,3 +1204,70 @@ impl<P: Version> DerivedTreeBuilder<P> {
         sealed
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_hash_matches_the_tree() {
+        "poseidon-bn254"
+            .parse::<TreeHash>()
+            .unwrap()
+            .validate()
+            .unwrap();
+        assert!("poseidon-bls12-381".parse::<TreeHash>().is_err());
+    }
+
+    #[test]
+    fn commitments_are_reduced_into_the_field() {
+        let order = *SNARK_SCALAR_FIELD;
+        let one = Hash::from(1);
+
+        assert!(is_reduced(&(order - one)));
+        assert!(!is_reduced(&order));
+
+        assert_eq!(reduce(order), Hash::ZERO);
+        assert_eq!(reduce(order + one), one);
+        assert_eq!(reduce(order - one), order - one);
+    }
+
+    #[test]
+    fn statuses_serialize_to_their_names() {
+        for (status, name) in [
+            (Status::Unprocessed(UnprocessedStatus::New), "new"),
+            (Status::Unprocessed(UnprocessedStatus::Failed), "failed"),
+            (Status::Processed(ProcessedStatus::Pending), "pending"),
+            (Status::Processed(ProcessedStatus::Processed), "processed"),
+            (Status::Processed(ProcessedStatus::Mined), "mined"),
+        ] {
+            assert_eq!(serde_json::to_value(status).unwrap(), name);
+            assert_eq!(
+                serde_json::from_value::<Status>(name.into()).unwrap(),
+                status
+            );
+            assert_eq!(name.parse::<Status>().unwrap(), status);
+            assert_eq!(<&str>::from(status), name);
+        }
+
+        assert!("pending".parse::<UnprocessedStatus>().is_err());
+        assert!("new".parse::<ProcessedStatus>().is_err());
+    }
+
+    #[test]
+    fn accumulated_roots_match_the_tree() {
+        let initial_leaf_value = Hash::from(7_u64);
+        let mut tree =
+            semaphore::poseidon_tree::LazyPoseidonTree::new(4, initial_leaf_value).derived();
+        let mut accumulator = RootAccumulator::new(4, initial_leaf_value);
+        assert_eq!(accumulator.root(), tree.root());
+
+        for leaf_index in 0..16 {
+            let leaf = Hash::from(leaf_index as u64 + 100);
+            tree = tree.update(leaf_index, &leaf);
+
+            assert_eq!(accumulator.push(leaf), tree.root());
+        }
+        assert_eq!(accumulator.len(), 16);
+    }
+}
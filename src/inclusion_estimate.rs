@@ -0,0 +1,143 @@
+//! Estimates when queued identities will be included on chain, from the
+//! number of identities ahead of them, how long recent batches took to be
+//! proven and mined, and when the next submission window opens.
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::database::{self, Database};
+use crate::task_monitor::submission_windows::SubmissionWindows;
+
+/// Batches submitted within this window are averaged.
+const TIMINGS_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// How long averaged timings are reused before they are read again.
+const TIMINGS_TTL: Duration = Duration::from_secs(30);
+
+/// Assumed when no batch has been proven within the window.
+const DEFAULT_PROVING_TIME: Duration = Duration::from_secs(60);
+
+/// Assumed when no batch has been mined within the window.
+const DEFAULT_CONFIRMATION_TIME: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BatchTimings {
+    /// From creating a batch to its proof.
+    proving:      Duration,
+    /// From submitting a batch to it being mined.
+    confirmation: Duration,
+}
+
+pub struct InclusionEstimator {
+    database:           Arc<Database>,
+    submission_windows: Option<SubmissionWindows>,
+    cached:             Mutex<Option<(Instant, BatchTimings)>>,
+}
+
+impl InclusionEstimator {
+    pub fn new(database: Arc<Database>, submission_windows: Option<SubmissionWindows>) -> Self {
+        Self {
+            database,
+            submission_windows,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Estimates when an identity will be included on chain. `position` is
+    /// the number of identities not on chain yet up to and including it.
+    /// Batches are proven one after another, and a batch that isn't full
+    /// waits for `batch_timeout`, and then for the next submission window.
+    pub async fn estimate(
+        &self,
+        position: usize,
+        max_batch_size: usize,
+        batch_timeout: Duration,
+    ) -> Result<DateTime<Utc>, database::Error> {
+        let timings = self.timings().await?;
+        let remaining = time_to_inclusion(position, max_batch_size, batch_timeout, timings);
+
+        let now = Utc::now();
+        let flush_at = now + to_chrono(flush_wait(position, max_batch_size, batch_timeout));
+        let window_wait = self
+            .submission_windows
+            .as_ref()
+            .map_or(chrono::Duration::zero(), |windows| {
+                windows.next_open(flush_at) - flush_at
+            });
+
+        Ok(now + to_chrono(remaining) + window_wait)
+    }
+
+    async fn timings(&self) -> Result<BatchTimings, database::Error> {
+        if let Some((read_at, timings)) = *self.cached.lock().expect("no lock poisoning") {
+            if read_at.elapsed() < TIMINGS_TTL {
+                return Ok(timings);
+            }
+        }
+
+        let (proving, confirmation) = self
+            .database
+            .get_average_batch_timings(TIMINGS_WINDOW)
+            .await?;
+        let timings = BatchTimings {
+            proving:      proving.unwrap_or(DEFAULT_PROVING_TIME),
+            confirmation: confirmation.unwrap_or(DEFAULT_CONFIRMATION_TIME),
+        };
+
+        *self.cached.lock().expect("no lock poisoning") = Some((Instant::now(), timings));
+
+        Ok(timings)
+    }
+}
+
+fn to_chrono(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::zero())
+}
+
+/// How long the batch of the identity at `position` waits to be flushed.
+fn flush_wait(position: usize, max_batch_size: usize, batch_timeout: Duration) -> Duration {
+    if position % max_batch_size.max(1) == 0 {
+        Duration::ZERO
+    } else {
+        batch_timeout
+    }
+}
+
+fn time_to_inclusion(
+    position: usize,
+    max_batch_size: usize,
+    batch_timeout: Duration,
+    timings: BatchTimings,
+) -> Duration {
+    let batches = position.div_ceil(max_batch_size.max(1));
+    let waiting = flush_wait(position, max_batch_size, batch_timeout);
+
+    waiting + timings.proving * u32::try_from(batches).unwrap_or(u32::MAX) + timings.confirmation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMINGS: BatchTimings = BatchTimings {
+        proving:      Duration::from_secs(60),
+        confirmation: Duration::from_secs(30),
+    };
+
+    #[test]
+    fn partial_batches_wait_for_the_batch_timeout() {
+        assert_eq!(
+            time_to_inclusion(3, 10, Duration::from_secs(180), TIMINGS),
+            Duration::from_secs(180 + 60 + 30)
+        );
+    }
+
+    #[test]
+    fn full_batches_are_proven_one_after_another() {
+        assert_eq!(
+            time_to_inclusion(30, 10, Duration::from_secs(180), TIMINGS),
+            Duration::from_secs(3 * 60 + 30)
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Lease based leader election between sequencer instances sharing a
+//! database. Only the leader batches and submits identities, the other
+//! instances serve reads as observers. Instances restart when their role
+//! changes, as the startup of a leader repairs the database before the tree is
+//! built from it.
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result as AnyhowResult;
+use clap::Parser;
+use cli_batteries::{await_shutdown, shutdown};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge, IntGauge};
+use tokio::select;
+use tokio::time::{sleep, timeout_at};
+use tracing::{error, info, warn};
+
+use crate::database::Database;
+
+static IS_LEADER: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!("leader", "Whether this instance holds the leader lease").unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Elect a leader among the instances sharing the database. Only the
+    /// leader batches and submits identities, the others run as observers.
+    #[clap(long, env)]
+    pub leader_election: bool,
+
+    /// How long the leader lease lasts without being renewed (seconds). The
+    /// lease is renewed four times per period, and a leader that can't renew
+    /// it steps down a quarter of the period before it expires.
+    #[clap(long, env, default_value = "30")]
+    pub leader_lease_seconds: u64,
+
+    /// Identifies this instance in the leader lease. Must stay the same across
+    /// restarts, so that an instance restarting to become the leader keeps
+    /// the lease it took. Defaults to `$HOSTNAME`, or a random id.
+    #[clap(long, env)]
+    pub instance_id: Option<String>,
+}
+
+pub struct LeaderLease {
+    database: Arc<Database>,
+    holder:   String,
+    ttl:      Duration,
+}
+
+impl LeaderLease {
+    pub fn new(database: Arc<Database>, options: &Options) -> Arc<Self> {
+        let holder = options
+            .instance_id
+            .clone()
+            .or_else(|| env::var("HOSTNAME").ok())
+            .unwrap_or_else(|| format!("{:016x}", rand::random::<u64>()));
+
+        Arc::new(Self {
+            database,
+            holder,
+            ttl: Duration::from_secs(options.leader_lease_seconds),
+        })
+    }
+
+    /// Takes or renews the lease. Returns whether this instance is the
+    /// leader.
+    pub async fn try_acquire(&self) -> AnyhowResult<bool> {
+        let is_leader = self
+            .database
+            .try_acquire_leader_lease(&self.holder, self.ttl)
+            .await?;
+
+        IS_LEADER.set(i64::from(is_leader));
+
+        Ok(is_leader)
+    }
+
+    /// Renews the lease of a leader, or keeps trying to take it over for an
+    /// observer. Shuts down once the role changes, including when a leader
+    /// can't renew its lease in time, so that no two instances submit batches
+    /// at once.
+    pub async fn run(self: Arc<Self>, is_leader: bool) {
+        info!(
+            holder = self.holder,
+            is_leader, "Taking part in leader election"
+        );
+
+        if self.watch(is_leader).await {
+            shutdown();
+        }
+    }
+
+    /// Renews or tries to take the lease until the role of this instance
+    /// changes, and returns `true` then, or `false` on shutdown. A leader
+    /// steps down a quarter of the lease before it expires if it can't renew
+    /// it, leaving time for the batch it is submitting to be dropped before
+    /// another instance can take over.
+    async fn watch(&self, is_leader: bool) -> bool {
+        let step_down_after = self.ttl - self.ttl / 4;
+        let mut renewed_at = Instant::now();
+
+        loop {
+            select! {
+                () = sleep(self.ttl / 4) => {}
+                () = await_shutdown() => return false,
+            }
+
+            // The lease runs from when it is renewed in the database, which
+            // is no earlier than the attempt.
+            let attempted_at = Instant::now();
+            let deadline = if is_leader {
+                renewed_at + step_down_after
+            } else {
+                attempted_at + self.ttl
+            };
+
+            match timeout_at(deadline.into(), self.try_acquire()).await {
+                Ok(Ok(acquired)) if acquired == is_leader => renewed_at = attempted_at,
+                Ok(Ok(acquired)) => {
+                    warn!(
+                        holder = self.holder,
+                        acquired, "Leadership changed, restarting"
+                    );
+                    return true;
+                }
+                Ok(Err(error)) => error!(?error, "Failed to renew the leader lease"),
+                Err(_elapsed) => error!("Timed out renewing the leader lease"),
+            }
+
+            if is_leader && renewed_at.elapsed() >= step_down_after {
+                IS_LEADER.set(0);
+                warn!(
+                    holder = self.holder,
+                    "Leader lease about to expire, restarting"
+                );
+                return true;
+            }
+        }
+    }
+
+    /// Gives up the lease so that another instance can take over right away.
+    pub async fn release(&self) -> AnyhowResult<()> {
+        self.database.release_leader_lease(&self.holder).await?;
+
+        IS_LEADER.set(0);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test::setup_db;
+
+    #[tokio::test]
+    async fn leader_steps_down_before_its_lease_expires() -> anyhow::Result<()> {
+        let (db, db_container) = setup_db().await?;
+
+        let lease = LeaderLease::new(Arc::new(db), &Options {
+            leader_election:      true,
+            leader_lease_seconds: 4,
+            instance_id:          Some("leader".to_string()),
+        });
+
+        assert!(lease.try_acquire().await?);
+
+        // Renewing the lease fails once the database is gone.
+        drop(db_container);
+
+        let started_at = Instant::now();
+        assert!(lease.watch(true).await);
+        assert!(started_at.elapsed() < lease.ttl);
+
+        Ok(())
+    }
+}
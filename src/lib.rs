@@ -3,28 +3,72 @@
 #![allow(clippy::module_name_repetitions, clippy::wildcard_imports)]
 
 pub mod app;
+pub mod config;
 mod contracts;
 mod database;
+pub mod error;
 mod ethereum;
+mod events;
+mod follower;
+pub mod groups;
 pub mod identity_tree;
+mod inclusion_estimate;
+mod leader;
+mod maintenance;
+mod observer;
 mod prover;
+mod quotas;
+mod reconciliation;
+mod reconstruction;
+mod retention;
 pub mod secret;
 mod serde_utils;
 pub mod server;
+mod snapshot;
+mod standby;
+mod state_transfer;
 mod task_monitor;
+#[cfg(feature = "test_utils")]
+pub mod test_utils;
 mod utils;
+pub mod verification;
+mod watchdog;
 
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use anyhow::Result as AnyhowResult;
-use clap::Parser;
-use tracing::info;
+use anyhow::{ensure, Result as AnyhowResult};
+use clap::{Parser, Subcommand};
+use tracing::{error, info};
 
 use crate::app::App;
+use crate::groups::GroupConfig;
 
 #[derive(Clone, Debug, PartialEq, Parser)]
 #[group(skip)]
 pub struct Options {
+    /// A TOML file with options, e.g. `tree-depth = 20`. Options given on the
+    /// command line or in the environment take precedence over it.
+    #[clap(long, env)]
+    pub config: Option<PathBuf>,
+
+    /// The group id of the tree the other options configure, which is served
+    /// both at the root and under `/groups/<group id>/`.
+    #[clap(long, env, default_value = "1")]
+    pub group_id: u64,
+
+    /// Additional trees to sequence, as `<group id>=<config file>` separated by
+    /// commas. The options in the file of a group replace the other options
+    /// for that group, and each group needs a database of its own.
+    #[clap(long, env, value_delimiter = ',')]
+    pub groups: Vec<GroupConfig>,
+
+    /// Migrate the databases of all groups and exit without starting the
+    /// sequencer, regardless of `--database-migrate`.
+    #[clap(long, env)]
+    pub migrate_only: bool,
+
     #[clap(flatten)]
     pub app: app::Options,
 
@@ -32,24 +76,194 @@ pub struct Options {
     pub server: server::Options,
 }
 
+/// The options of the commands that run instead of the sequencer.
+#[derive(Clone, Debug, PartialEq, Parser)]
+#[group(skip)]
+pub struct ToolOptions {
+    #[clap(subcommand)]
+    pub command: ToolCommand,
+}
+
+// The options are only parsed once, so their size doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Subcommand)]
+pub enum ToolCommand {
+    /// Runs the sequencer, like running it without a command.
+    Serve(Options),
+    /// Compares the database of a sequencer with the identity manager and
+    /// alerts on divergence, without serving the API or submitting anything.
+    Watch(watchdog::Options),
+    /// Migrates or checks the database.
+    #[clap(subcommand)]
+    Db(DbCommand),
+    /// Moves the tree state between databases.
+    #[clap(subcommand)]
+    State(StateCommand),
+    /// Checks the provers.
+    #[cfg(feature = "submission")]
+    #[clap(subcommand)]
+    Prover(ProverCommand),
+    /// Same as `state export`.
+    #[clap(hide = true)]
+    ExportState(state_transfer::ExportOptions),
+    /// Same as `state import`.
+    #[clap(hide = true)]
+    ImportState(state_transfer::ImportOptions),
+    /// Writes the tree as it was at a root or point in time.
+    ReconstructTree(reconstruction::Options),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+pub enum DbCommand {
+    /// Creates the database if needed and migrates its schema.
+    Migrate(maintenance::MigrateOptions),
+    /// Checks that the schema is up to date and that every root follows from
+    /// the leaves before it.
+    Verify(state_transfer::VerifyOptions),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+pub enum StateCommand {
+    /// Writes the identities and batches of a database to a file.
+    Export(state_transfer::ExportOptions),
+    /// Checks a file written by `state export` and imports it into an empty
+    /// database.
+    Import(state_transfer::ImportOptions),
+}
+
+#[cfg(feature = "submission")]
+#[derive(Clone, Debug, PartialEq, Eq, Subcommand)]
+pub enum ProverCommand {
+    /// Probes the health of every prover the sequencer would use.
+    Check(maintenance::ProverCheckOptions),
+}
+
+impl ToolCommand {
+    /// The names of the commands, as the first argument.
+    pub const NAMES: [&'static str; 8] = [
+        Self::SERVE,
+        "watch",
+        "db",
+        "state",
+        "prover",
+        "export-state",
+        "import-state",
+        "reconstruct-tree",
+    ];
+    /// The command that runs the sequencer.
+    pub const SERVE: &'static str = "serve";
+}
+
+/// Runs a command other than the sequencer.
+#[allow(clippy::missing_errors_doc)]
+pub async fn tool(options: ToolOptions) -> AnyhowResult<()> {
+    match options.command {
+        ToolCommand::Serve(options) => Box::pin(main(options)).await,
+        ToolCommand::Watch(options) => watchdog::main(options).await,
+        ToolCommand::Db(DbCommand::Migrate(options)) => maintenance::migrate(options).await,
+        ToolCommand::Db(DbCommand::Verify(options)) => state_transfer::verify(options).await,
+        ToolCommand::State(StateCommand::Export(options)) | ToolCommand::ExportState(options) => {
+            state_transfer::export(options).await
+        }
+        ToolCommand::State(StateCommand::Import(options)) | ToolCommand::ImportState(options) => {
+            state_transfer::import(options).await
+        }
+        #[cfg(feature = "submission")]
+        ToolCommand::Prover(ProverCommand::Check(options)) => {
+            maintenance::check_provers(options).await
+        }
+        ToolCommand::ReconstructTree(options) => reconstruction::main(options).await,
+    }
+}
+
 /// ```
 /// assert!(true);
 /// ```
 #[allow(clippy::missing_errors_doc)]
 pub async fn main(options: Options) -> AnyhowResult<()> {
+    let mut args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some(ToolCommand::SERVE) {
+        args.remove(1);
+    }
+
+    let mut group_options = Vec::new();
+    for group in &options.groups {
+        ensure!(
+            group.id != options.group_id && group_options.iter().all(|(id, _)| *id != group.id),
+            "group {} is configured twice",
+            group.id
+        );
+        let app_options = groups::options(&args, &options.app, group)?;
+        group_options.push((group.id, app_options.clone()));
+        for (id, other) in &group_options {
+            ensure!(
+                *id == group.id || other.database.database != app_options.database.database,
+                "groups {id} and {} share a database",
+                group.id
+            );
+        }
+    }
+
+    if options.migrate_only {
+        maintenance::migrate_database(options.app.database).await?;
+        for (_, app_options) in group_options {
+            maintenance::migrate_database(app_options.database).await?;
+        }
+        return Ok(());
+    }
+
     // Create App struct
     let app = Arc::new(App::new(options.app).await?);
-    let app_for_server = app.clone();
+    let mut groups = BTreeMap::from([(options.group_id, app.clone())]);
+    for ((id, app_options), group) in group_options.into_iter().zip(&options.groups) {
+        info!(group_id = id, "Starting group");
+        let app = App::new(app_options)
+            .await?
+            .with_group_config(group.path.clone());
+        groups.insert(id, Arc::new(app));
+    }
+
+    #[cfg(unix)]
+    tokio::spawn(reload_config_on_hangup(groups.values().cloned().collect()));
 
     // Start server (will stop on shutdown signal)
-    server::main(app_for_server, options.server).await?;
+    server::main(app, groups.clone(), options.server).await?;
 
-    info!("Stopping the app");
-    app.shutdown().await?;
+    for (group_id, app) in groups {
+        info!(group_id, "Stopping the app");
+        app.shutdown().await?;
+    }
 
     Ok(())
 }
 
+/// Reloads the config files of every group whenever the process receives
+/// `SIGHUP`.
+#[cfg(unix)]
+async fn reload_config_on_hangup(apps: Vec<Arc<App>>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(error) => {
+            error!(
+                ?error,
+                "Failed to listen for SIGHUP, the config can't be reloaded with it"
+            );
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        info!("Received SIGHUP, reloading the config file");
+        for app in &apps {
+            if let Err(error) = app.reload_config().await {
+                error!(%error, "Failed to reload the config file");
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use tracing::{error, warn};
@@ -3,7 +3,13 @@
 #![allow(clippy::module_name_repetitions, clippy::wildcard_imports)]
 
 use cli_batteries::{run, version};
-use signup_sequencer::{main as sequencer_app, Options};
+use signup_sequencer::{
+    config, main as sequencer_app, tool as sequencer_tool, Options, ToolCommand, ToolOptions,
+};
+
+/// Prints the options the sequencer would run with, including those from the
+/// config file, instead of running it.
+const PRINT_CONFIG: &str = "print-config";
 
 async fn app(options: Options) -> eyre::Result<()> {
     sequencer_app(options)
@@ -11,6 +17,40 @@ async fn app(options: Options) -> eyre::Result<()> {
         .map_err(|e| eyre::eyre!("{:?}", e))
 }
 
+async fn tool(options: ToolOptions) -> eyre::Result<()> {
+    sequencer_tool(options)
+        .await
+        .map_err(|e| eyre::eyre!("{:?}", e))
+}
+
 fn main() {
-    run(version!(semaphore, ethers), app);
+    let mut args: Vec<String> = std::env::args().collect();
+
+    // The other commands take different options, so they have to be picked
+    // before they are parsed.
+    let command = args.get(1).cloned().unwrap_or_default();
+    if command == PRINT_CONFIG {
+        args.remove(1);
+        if let Err(error) = config::print(&args) {
+            eprintln!("{error:?}");
+            std::process::exit(1);
+        }
+    } else if ToolCommand::NAMES.contains(&command.as_str()) {
+        if command == ToolCommand::SERVE {
+            load_config(&args);
+        }
+        run(version!(semaphore, ethers), tool);
+    } else {
+        load_config(&args);
+        run(version!(semaphore, ethers), app);
+    }
+}
+
+/// The options in the config file are read from the environment, so it has to
+/// be loaded before they are parsed.
+fn load_config(args: &[String]) {
+    if let Err(error) = config::load(args) {
+        eprintln!("{error:?}");
+        std::process::exit(1);
+    }
 }
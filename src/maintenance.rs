@@ -0,0 +1,146 @@
+//! Operational commands that run against the database or the provers of a
+//! sequencer instead of running it.
+#[cfg(feature = "submission")]
+use anyhow::ensure;
+use anyhow::Result as AnyhowResult;
+use clap::Parser;
+#[cfg(feature = "submission")]
+use futures::future::join_all;
+#[cfg(feature = "submission")]
+use tracing::error;
+use tracing::info;
+
+#[cfg(feature = "submission")]
+use crate::app::App;
+use crate::database::{self, Database};
+#[cfg(feature = "submission")]
+use crate::prover::batch_insertion;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct MigrateOptions {
+    /// The database to migrate. It is created if it doesn't exist.
+    #[clap(flatten)]
+    pub database: database::Options,
+
+    /// Only print the migrations that would be applied
+    #[clap(long)]
+    pub plan: bool,
+}
+
+#[cfg(feature = "submission")]
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct ProverCheckOptions {
+    /// The database the provers are registered in. It is never migrated.
+    #[clap(flatten)]
+    pub database: database::Options,
+
+    #[clap(flatten)]
+    pub batch_provers: batch_insertion::Options,
+}
+
+/// Creates the database if it doesn't exist and migrates its schema to the
+/// latest version, so that sequencers can run without `--database-migrate`.
+/// With `--plan`, prints the pending migrations instead.
+///
+/// # Errors
+///
+/// Will return `Err` if the database can't be reached or a migration fails.
+pub async fn migrate(options: MigrateOptions) -> AnyhowResult<()> {
+    if options.plan {
+        let pending = Database::pending_migrations(&options.database).await?;
+        if pending.is_empty() {
+            println!("The database schema is up to date");
+        }
+        for migration in pending {
+            println!("{} {}", migration.version, migration.description);
+        }
+
+        return Ok(());
+    }
+
+    migrate_database(options.database).await
+}
+
+/// Creates the database if it doesn't exist and migrates its schema.
+///
+/// # Errors
+///
+/// Will return `Err` if the database can't be reached or a migration fails.
+pub async fn migrate_database(options: database::Options) -> AnyhowResult<()> {
+    let url = options.database.clone();
+    Database::new(database::Options {
+        database_migrate: true,
+        ..options
+    })
+    .await?;
+
+    info!(%url, "The database schema is up to date");
+
+    Ok(())
+}
+
+/// Probes the health of every prover the sequencer would use: those
+/// registered in the database and those in `--prover-urls`.
+///
+/// # Errors
+///
+/// Will return `Err` if there are no provers, or any of them is unhealthy.
+#[cfg(feature = "submission")]
+pub async fn check_provers(options: ProverCheckOptions) -> AnyhowResult<()> {
+    let database = Database::new(database::Options {
+        database_migrate: false,
+        ..options.database
+    })
+    .await?;
+
+    let mut provers = database.get_provers().await?;
+    App::merge_env_provers(options.batch_provers, &mut provers);
+    ensure!(!provers.is_empty(), "No provers are configured");
+
+    let mut provers: Vec<_> = provers.into_iter().collect();
+    provers.sort_by(|a, b| (a.batch_size, &a.url).cmp(&(b.batch_size, &b.url)));
+
+    let results = join_all(provers.iter().map(|configuration| async move {
+        let prover = batch_insertion::Prover::from_prover_conf(configuration)?;
+        Ok::<_, anyhow::Error>(prover.is_healthy().await)
+    }))
+    .await;
+
+    let mut unhealthy = 0;
+    for (prover, result) in provers.iter().zip(results) {
+        match result {
+            Ok(true) => info!(
+                url = prover.url,
+                batch_size = prover.batch_size,
+                "Prover is healthy"
+            ),
+            Ok(false) => {
+                error!(
+                    url = prover.url,
+                    batch_size = prover.batch_size,
+                    "Prover failed its health check"
+                );
+                unhealthy += 1;
+            }
+            Err(error) => {
+                error!(
+                    url = prover.url,
+                    batch_size = prover.batch_size,
+                    ?error,
+                    "Prover is misconfigured"
+                );
+                unhealthy += 1;
+            }
+        }
+    }
+
+    ensure!(
+        unhealthy == 0,
+        "{unhealthy} of {} provers are unhealthy",
+        provers.len()
+    );
+
+    Ok(())
+}
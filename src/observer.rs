@@ -0,0 +1,122 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::contracts::SharedIdentityManager;
+use crate::database::Database;
+use crate::identity_tree::{
+    Hash, ProcessedStatus, TreeState, TreeVersionReadOps, TreeWithNextVersion,
+};
+
+/// Maximum number of leaves read from the database at once.
+const MAX_LEAVES: usize = 10_000;
+
+/// Keeps the in-memory tree of an observer in sync by polling the database
+/// for new leaves and the identity manager for its latest root. Observers
+/// never write to the database or submit transactions.
+pub struct Observer {
+    database:         Arc<Database>,
+    identity_manager: SharedIdentityManager,
+    tree_state:       TreeState,
+    poll_interval:    Duration,
+}
+
+impl Observer {
+    pub fn new(
+        database: Arc<Database>,
+        identity_manager: SharedIdentityManager,
+        tree_state: TreeState,
+        poll_interval: Duration,
+    ) -> Self {
+        Self {
+            database,
+            identity_manager,
+            tree_state,
+            poll_interval,
+        }
+    }
+
+    pub async fn run(self) {
+        info!("Observing the database and the identity manager");
+
+        loop {
+            if let Err(error) = self.sync().await {
+                error!(?error, "Failed to sync the observed tree");
+            }
+
+            sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn sync(&self) -> AnyhowResult<()> {
+        let latest_tree = self.tree_state.get_latest_tree();
+
+        loop {
+            let identities = self
+                .database
+                .get_identities(latest_tree.next_leaf(), None, MAX_LEAVES)
+                .await?;
+
+            if identities.is_empty() {
+                break;
+            }
+
+            let commitments = identities
+                .iter()
+                .map(|identity| identity.commitment)
+                .collect::<Vec<_>>();
+            _ = latest_tree.append_many(&commitments);
+        }
+
+        // The latest root on chain is the processed root, as far as the
+        // observer is concerned.
+        let chain_root: Hash = self.identity_manager.latest_root().await?.into();
+        if self.tree_state.get_processed_tree().get_root() != chain_root {
+            if self.is_in_tree(&chain_root).await? {
+                self.tree_state
+                    .get_batching_tree()
+                    .apply_updates_up_to(chain_root);
+                self.tree_state
+                    .get_processed_tree()
+                    .apply_updates_up_to(chain_root);
+            } else {
+                warn!(
+                    ?chain_root,
+                    "The latest root on chain is not in the tree yet"
+                );
+            }
+        }
+
+        if let Some(leaf_index) = self
+            .database
+            .get_max_leaf_index_by_status(ProcessedStatus::Mined)
+            .await?
+        {
+            let identities = self
+                .database
+                .get_identities(leaf_index, Some(leaf_index + 1), 1)
+                .await?;
+
+            if let Some(identity) = identities.first() {
+                let mined_tree = self.tree_state.get_mined_tree();
+                if mined_tree.get_root() != identity.root && leaf_index < latest_tree.next_leaf() {
+                    mined_tree.apply_updates_up_to(identity.root);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether `root` is the root after one of the leaves in the latest tree.
+    async fn is_in_tree(&self, root: &Hash) -> AnyhowResult<bool> {
+        let leaf_index = self.database.get_root_leaf_index(root).await?;
+
+        Ok(leaf_index.map_or(false, |leaf_index| {
+            leaf_index < self.tree_state.get_latest_tree().next_leaf()
+        }))
+    }
+}
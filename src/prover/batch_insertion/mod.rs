@@ -1,3 +1,7 @@
+// Everything but the configuration is only used to prove batches before they
+// are submitted.
+#![cfg_attr(not(feature = "submission"), allow(dead_code))]
+
 mod identity;
 
 use std::fmt::{Display, Formatter};
@@ -10,16 +14,28 @@ use ethers::utils::keccak256;
 use once_cell::sync::Lazy;
 use prometheus::{exponential_buckets, register_histogram, Histogram};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+#[cfg(feature = "submission")]
+use tracing::debug;
+#[cfg(feature = "submission")]
 use url::Url;
 
+#[cfg(feature = "submission")]
 use crate::database::prover::ProverConfiguration as DbProverConfiguration;
 pub use crate::prover::batch_insertion::identity::Identity;
+#[cfg(feature = "submission")]
 use crate::prover::Proof;
 use crate::serde_utils::JsonStrWrapper;
 
 /// The endpoint used for proving operations.
 const MTB_PROVE_ENDPOINT: &str = "prove";
 
+/// The endpoint used for health checks.
+const MTB_HEALTH_ENDPOINT: &str = "health";
+
+/// How long to wait for a prover to respond to a health check.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
 static TOTAL_PROVING_TIME: Lazy<Histogram> = Lazy::new(|| {
     register_histogram!(
         "total_proving_time",
@@ -70,6 +86,7 @@ pub struct ProverConfiguration {
 }
 
 /// A representation of the connection to the MTB prover service.
+#[cfg(feature = "submission")]
 #[derive(Clone, Debug)]
 pub struct Prover {
     target_url: Url,
@@ -78,6 +95,7 @@ pub struct Prover {
     timeout_s:  u64,
 }
 
+#[cfg(feature = "submission")]
 impl Prover {
     /// Constructs a new instance of the Merkle Tree Batcher (or Mtb).
     ///
@@ -120,10 +138,6 @@ impl Prover {
         })
     }
 
-    pub fn batch_size(&self) -> usize {
-        self.batch_size
-    }
-
     pub fn timeout_s(&self) -> u64 {
         self.timeout_s
     }
@@ -181,14 +195,30 @@ impl Prover {
 
         let prover_proving_time_timer = PROVER_PROVING_TIME.start_timer();
         let proof_term = self.client.execute(request).await?;
-        let proof_term = proof_term.error_for_status()?;
+        let status = proof_term.status();
         prover_proving_time_timer.observe_duration();
 
         let json = proof_term.text().await?;
 
+        if !status.is_success() {
+            return Err(ProverRejection {
+                url:     self.url(),
+                status:  status.as_u16(),
+                message: format!("HTTP status {status}"),
+                body:    json,
+            }
+            .into());
+        }
+
         let Ok(proof) = serde_json::from_str::<Proof>(&json) else {
             let error: ProverError = serde_json::from_str(&json)?;
-            return Err(anyhow::Error::msg(format!("{error}")));
+            return Err(ProverRejection {
+                url:     self.url(),
+                status:  status.as_u16(),
+                message: error.to_string(),
+                body:    json,
+            }
+            .into());
         };
 
         total_proving_time_timer.observe_duration();
@@ -199,6 +229,35 @@ impl Prover {
     pub fn url(&self) -> String {
         self.target_url.to_string()
     }
+
+    /// Probes the health endpoint of the prover service. Any non-successful
+    /// response, or no response within `HEALTH_CHECK_TIMEOUT`, makes the prover
+    /// unhealthy.
+    pub async fn is_healthy(&self) -> bool {
+        let Ok(health_url) = self.target_url.join(MTB_HEALTH_ENDPOINT) else {
+            return false;
+        };
+
+        match self
+            .client
+            .get(health_url)
+            .timeout(HEALTH_CHECK_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(response) => response.status().is_success(),
+            Err(error) => {
+                debug!(url = self.url(), ?error, "Prover health check failed.");
+                false
+            }
+        }
+    }
+
+    /// Checks whether this prover is reachable at the provided `url`. The URLs
+    /// are compared in their normalized form.
+    pub fn has_url(&self, url: &str) -> bool {
+        Url::parse(url).map_or(false, |url| url == self.target_url)
+    }
 }
 
 /// Computes the input hash to the prover.
@@ -264,6 +323,17 @@ impl Display for ProverError {
     }
 }
 
+/// A batch that was rejected by the prover service, together with the full
+/// response body so that the failure can be inspected after the fact.
+#[derive(Clone, Debug, Error)]
+#[error("prover at {url} rejected the batch: {message}")]
+pub struct ProverRejection {
+    pub url:     String,
+    pub status:  u16,
+    pub message: String,
+    pub body:    String,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ProofInput {
@@ -275,7 +345,7 @@ struct ProofInput {
     merkle_proofs:        Vec<Vec<U256>>,
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "submission"))]
 mod test {
     use super::*;
 
@@ -337,6 +407,12 @@ mod test {
         mock_service.stop();
         assert!(prover_result.is_err());
 
+        let error = prover_result.unwrap_err();
+        let rejection = error
+            .downcast_ref::<ProverRejection>()
+            .expect("prover failures carry the response body");
+        assert!(rejection.body.contains("Things went wrong."));
+
         Ok(())
     }
 
@@ -524,7 +600,7 @@ mod test {
 "#;
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "submission"))]
 pub mod mock {
     use std::net::SocketAddr;
 
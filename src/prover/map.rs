@@ -1,10 +1,17 @@
 use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+use anyhow::anyhow;
+use ethers::types::U256;
+use futures::future::join_all;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge_vec, IntGaugeVec};
 use tokio::sync::{RwLock, RwLockReadGuard};
+use tracing::warn;
 
 use crate::database::prover;
-use crate::prover::batch_insertion;
-use crate::prover::batch_insertion::ProverConfiguration;
+use crate::prover::batch_insertion::{ProverConfiguration, ProverRejection};
+use crate::prover::{batch_insertion, Proof};
 
 /// The type of a map containing a mapping from a usize to a locked item.
 type SharedProverMap<P> = RwLock<ProverMap<P>>;
@@ -12,38 +19,264 @@ type SharedProverMap<P> = RwLock<ProverMap<P>>;
 /// A prover that can have read-only operations performed on it.
 pub type ReadOnlyProver<'a, P> = RwLockReadGuard<'a, P>;
 
-/// A map that contains a prover for each batch size.
+static PROVER_HEALTHY: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "prover_healthy",
+        "Whether a prover is considered healthy (1) or not (0).",
+        &["batch_size", "url"]
+    )
+    .unwrap()
+});
+
+/// A set of provers that are all set up to work with the same batch size.
+///
+/// Requests are spread over the members in a round-robin fashion. Members that
+/// failed their last request are considered unhealthy and are only tried after
+/// all the healthy ones.
+#[derive(Debug)]
+pub struct ProverGroup<P> {
+    batch_size: usize,
+    members:    Vec<GroupMember<P>>,
+    cursor:     AtomicUsize,
+}
+
+#[derive(Debug)]
+struct GroupMember<P> {
+    prover:  P,
+    healthy: AtomicBool,
+}
+
+impl<P> ProverGroup<P> {
+    fn new(batch_size: usize) -> Self {
+        Self {
+            batch_size,
+            members: Vec::new(),
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    fn push(&mut self, prover: P) {
+        self.members.push(GroupMember {
+            prover,
+            healthy: AtomicBool::new(true),
+        });
+    }
+
+    fn remove_where(&mut self, predicate: impl Fn(&P) -> bool) -> Option<P> {
+        let index = self
+            .members
+            .iter()
+            .position(|member| predicate(&member.prover))?;
+
+        Some(self.members.remove(index).prover)
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &P> {
+        self.members.iter().map(|member| &member.prover)
+    }
+
+    /// Returns the members of the group in the order in which they should be
+    /// tried for the next request, together with their index in the group.
+    ///
+    /// Every call advances the round-robin cursor by one. Healthy members are
+    /// always ordered before unhealthy ones.
+    pub fn candidates(&self) -> Vec<(usize, &P)> {
+        let len = self.members.len();
+        if len == 0 {
+            return Vec::new();
+        }
+
+        let start = self.cursor.fetch_add(1, Ordering::Relaxed) % len;
+
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) = (0..len)
+            .map(|offset| (start + offset) % len)
+            .partition(|index| self.members[*index].healthy.load(Ordering::Relaxed));
+
+        healthy
+            .into_iter()
+            .chain(unhealthy)
+            .map(|index| (index, &self.members[index].prover))
+            .collect()
+    }
+
+    /// Records the outcome of the last request sent to the member at `index`.
+    pub fn report(&self, index: usize, healthy: bool) {
+        if let Some(member) = self.members.get(index) {
+            member.healthy.store(healthy, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether at least one member of the group is healthy.
+    pub fn has_healthy(&self) -> bool {
+        self.members
+            .iter()
+            .any(|member| member.healthy.load(Ordering::Relaxed))
+    }
+}
+
+impl ProverGroup<batch_insertion::Prover> {
+    /// Records the health of the member at `index` and exports it as a metric.
+    fn record(&self, index: usize, healthy: bool) {
+        self.report(index, healthy);
+
+        if let Some(member) = self.members.get(index) {
+            PROVER_HEALTHY
+                .with_label_values(&[&self.batch_size.to_string(), &member.prover.url()])
+                .set(i64::from(healthy));
+        }
+    }
+
+    /// Records the health of the member reachable at `url`, if it is still
+    /// part of the group.
+    fn record_url(&self, url: &str, healthy: bool) {
+        if let Some(index) = self
+            .members
+            .iter()
+            .position(|member| member.prover.has_url(url))
+        {
+            self.record(index, healthy);
+        }
+    }
+
+    /// Generates a proof using the members of this group, failing over to the
+    /// next member whenever one of them can't be reached, times out or fails
+    /// with a server error. A member rejecting the batch itself, e.g. as
+    /// invalid input, stays healthy and its rejection is returned right away,
+    /// as every other member would reject the batch too.
+    ///
+    /// Returns the proof along with the url of the member that generated it.
+    /// The error of the last member tried is returned if all of them fail.
+    pub async fn generate_proof(
+        &self,
+        start_index: u32,
+        pre_root: U256,
+        post_root: U256,
+        identities: &[batch_insertion::Identity],
+    ) -> anyhow::Result<(Proof, String)> {
+        let mut last_error = None;
+
+        for (index, prover) in self.candidates() {
+            match prover
+                .generate_proof(start_index, pre_root, post_root, identities)
+                .await
+            {
+                Ok(proof) => {
+                    self.record(index, true);
+                    return Ok((proof, prover.url()));
+                }
+                Err(error) if is_rejection(&error) => {
+                    self.record(index, true);
+                    return Err(error);
+                }
+                Err(error) => {
+                    warn!(
+                        url = prover.url(),
+                        batch_size = self.batch_size,
+                        ?error,
+                        "Prover failed to generate proof."
+                    );
+                    self.record(index, false);
+                    last_error = Some(error);
+                }
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| anyhow!("No provers registered for batch size {}", self.batch_size)))
+    }
+}
+
+/// Whether `error` is a prover answering that it won't prove the batch, as
+/// opposed to a transport error, a timeout or a server error.
+fn is_rejection(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<ProverRejection>()
+        .map_or(false, |rejection| !(500..600).contains(&rejection.status))
+}
+
+/// A map that contains a group of provers for each batch size.
 ///
 /// Provides utility methods for getting the appropriate provers
 ///
 /// The struct is generic over P for testing purposes.
 #[derive(Debug)]
 pub struct ProverMap<P> {
-    map: BTreeMap<usize, P>,
+    map: BTreeMap<usize, ProverGroup<P>>,
 }
 
 impl<P> ProverMap<P> {
-    /// Get the smallest prover that can handle the given batch size.
+    /// Get the next prover from the smallest group that can handle the given
+    /// batch size.
+    #[allow(dead_code)] // Provers are picked through `get_group` outside of tests
     pub fn get(&self, batch_size: usize) -> Option<&P> {
-        for (size, prover) in &self.map {
-            if batch_size <= *size {
-                return Some(prover);
-            }
+        self.get_group(batch_size)?
+            .candidates()
+            .first()
+            .map(|(_, prover)| *prover)
+    }
+
+    /// Get the smallest group of provers that can handle the given batch size.
+    ///
+    /// Groups without any healthy provers are skipped in favour of larger ones,
+    /// unless none of the suitable groups are healthy.
+    pub fn get_group(&self, batch_size: usize) -> Option<&ProverGroup<P>> {
+        let mut suitable = self.map.range(batch_size..).map(|(_, group)| group);
+
+        let smallest = suitable.next()?;
+        if smallest.has_healthy() {
+            return Some(smallest);
         }
 
-        None
+        Some(
+            suitable
+                .find(|group| group.has_healthy())
+                .unwrap_or(smallest),
+        )
     }
 
     /// Registers the provided `prover` for the given `batch_size` in the map.
+    ///
+    /// If there already are provers for this batch size, the new one joins
+    /// their group.
     pub fn add(&mut self, batch_size: usize, prover: P) {
-        self.map.insert(batch_size, prover);
+        self.map
+            .entry(batch_size)
+            .or_insert_with(|| ProverGroup::new(batch_size))
+            .push(prover);
     }
 
-    /// Removes the prover for the provided `batch_size` from the prover map.
-    pub fn remove(&mut self, batch_size: usize) -> Option<P> {
+    /// Removes all the provers for the provided `batch_size` from the prover
+    /// map.
+    pub fn remove(&mut self, batch_size: usize) -> Option<ProverGroup<P>> {
         self.map.remove(&batch_size)
     }
 
+    /// Removes the first prover for the provided `batch_size` that matches
+    /// `predicate`. The batch size is dropped from the map together with its
+    /// last prover.
+    pub fn remove_where(&mut self, batch_size: usize, predicate: impl Fn(&P) -> bool) -> Option<P> {
+        let group = self.map.get_mut(&batch_size)?;
+        let prover = group.remove_where(predicate)?;
+
+        if group.is_empty() {
+            self.map.remove(&batch_size);
+        }
+
+        Some(prover)
+    }
+
     pub fn len(&self) -> usize {
         self.map.len()
     }
@@ -52,6 +285,11 @@ impl<P> ProverMap<P> {
         self.map.iter().next_back().map_or(0, |(size, _)| *size)
     }
 
+    /// The batch sizes with registered provers, in ascending order.
+    pub fn batch_sizes(&self) -> Vec<usize> {
+        self.map.keys().copied().collect()
+    }
+
     pub fn batch_size_exists(&self, batch_size: usize) -> bool {
         self.map.contains_key(&batch_size)
     }
@@ -61,42 +299,105 @@ impl ProverMap<batch_insertion::Prover> {
     pub fn as_configuration_vec(&self) -> Vec<ProverConfiguration> {
         self.map
             .iter()
-            .map(|(k, v)| ProverConfiguration {
-                url:        v.url(),
-                timeout_s:  v.timeout_s(),
-                batch_size: *k,
+            .flat_map(|(k, group)| {
+                group.iter().map(|v| ProverConfiguration {
+                    url:        v.url(),
+                    timeout_s:  v.timeout_s(),
+                    batch_size: *k,
+                })
             })
             .collect()
     }
+
+    /// The registered provers together with their batch size.
+    fn provers(&self) -> Vec<(usize, batch_insertion::Prover)> {
+        self.map
+            .iter()
+            .flat_map(|(batch_size, group)| {
+                group.iter().map(|prover| (*batch_size, prover.clone()))
+            })
+            .collect()
+    }
+
+    /// Records the health of the prover reachable at `url` for `batch_size`,
+    /// unless it has been removed in the meantime.
+    fn record_health(&self, batch_size: usize, url: &str, healthy: bool) {
+        if let Some(group) = self.map.get(&batch_size) {
+            group.record_url(url, healthy);
+        }
+    }
+
+    pub fn prover_exists(&self, batch_size: usize, url: &str) -> bool {
+        self.map.get(&batch_size).map_or(false, |group| {
+            group.iter().any(|prover| prover.has_url(url))
+        })
+    }
 }
 
 impl<P> From<BTreeMap<usize, P>> for ProverMap<P> {
     fn from(map: BTreeMap<usize, P>) -> Self {
-        Self { map }
+        let mut prover_map = Self {
+            map: BTreeMap::new(),
+        };
+
+        for (batch_size, prover) in map {
+            prover_map.add(batch_size, prover);
+        }
+
+        prover_map
     }
 }
 
 /// A map of provers for batch insertion operations.
 pub type InsertionProverMap = SharedProverMap<batch_insertion::Prover>;
 
+/// A group of provers for batch insertion operations.
+pub type InsertionProverGroup = ProverGroup<batch_insertion::Prover>;
+
 /// The type of provers that can only be read from for insertion operations.
-pub type ReadOnlyInsertionProver<'a> = ReadOnlyProver<'a, batch_insertion::Prover>;
+pub type ReadOnlyInsertionProver<'a> = ReadOnlyProver<'a, InsertionProverGroup>;
 
 /// Builds an insertion prover map from the provided configuration.
 pub fn make_insertion_map(db_provers: prover::Provers) -> anyhow::Result<InsertionProverMap> {
-    let mut map = BTreeMap::new();
+    let mut insertion_map = ProverMap {
+        map: BTreeMap::new(),
+    };
 
     for prover in db_provers {
-        map.insert(
+        insertion_map.add(
             prover.batch_size,
             batch_insertion::Prover::from_prover_conf(&prover)?,
         );
     }
-    let insertion_map = ProverMap::from(map);
 
     Ok(RwLock::new(insertion_map))
 }
 
+/// Probes the health of every prover in `map` and records the results.
+///
+/// The probes take up to `HEALTH_CHECK_TIMEOUT` each, so they are sent to
+/// copies of the provers, and the map is only locked again to record the
+/// results. Holding the lock throughout would block adding and removing
+/// provers for as long.
+pub async fn check_health(map: &InsertionProverMap) {
+    let provers = map.read().await.provers();
+
+    let results = join_all(provers.iter().map(|(_, prover)| prover.is_healthy())).await;
+
+    let map = map.read().await;
+    for ((batch_size, prover), healthy) in provers.iter().zip(results) {
+        if !healthy {
+            warn!(
+                url = prover.url(),
+                batch_size = *batch_size,
+                "Prover failed its health check."
+            );
+        }
+
+        map.record_health(*batch_size, &prover.url(), healthy);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -118,4 +419,90 @@ mod tests {
         assert_eq!(prover_map.get(7), Some(&7));
         assert!(prover_map.get(8).is_none());
     }
+
+    #[test]
+    fn prover_group_round_robin() {
+        let mut prover_map: ProverMap<usize> = ProverMap::from(maplit::btreemap! {
+            3 => 30,
+        });
+        prover_map.add(3, 31);
+        prover_map.add(3, 32);
+
+        assert_eq!(prover_map.len(), 1);
+        assert_eq!(prover_map.get(3), Some(&30));
+        assert_eq!(prover_map.get(3), Some(&31));
+        assert_eq!(prover_map.get(3), Some(&32));
+        assert_eq!(prover_map.get(3), Some(&30));
+    }
+
+    #[test]
+    fn prover_group_skips_unhealthy() {
+        let mut prover_map: ProverMap<usize> = ProverMap::from(maplit::btreemap! {
+            3 => 30,
+        });
+        prover_map.add(3, 31);
+
+        let group = prover_map.get_group(3).unwrap();
+        group.report(0, false);
+
+        assert_eq!(prover_map.get(3), Some(&31));
+        assert_eq!(prover_map.get(3), Some(&31));
+
+        let candidates: Vec<_> = group.candidates().into_iter().map(|(i, _)| i).collect();
+        assert_eq!(candidates, vec![1, 0]);
+
+        group.report(0, true);
+        let candidates: Vec<_> = group.candidates().into_iter().map(|(i, _)| i).collect();
+        assert_eq!(candidates.len(), 2);
+    }
+
+    #[test]
+    fn prover_map_skips_unhealthy_groups() {
+        let prover_map: ProverMap<usize> = ProverMap::from(maplit::btreemap! {
+            3 => 3,
+            5 => 5,
+            7 => 7,
+        });
+
+        prover_map.get_group(3).unwrap().report(0, false);
+        assert_eq!(prover_map.get(2), Some(&5));
+
+        prover_map.get_group(5).unwrap().report(0, false);
+        assert_eq!(prover_map.get(2), Some(&7));
+
+        prover_map.get_group(7).unwrap().report(0, false);
+        assert_eq!(prover_map.get(2), Some(&3));
+    }
+
+    #[test]
+    fn only_server_errors_are_failed_over() {
+        let rejection = |status| {
+            anyhow::Error::from(ProverRejection {
+                url: "http://prover".to_string(),
+                status,
+                message: "rejected".to_string(),
+                body: String::new(),
+            })
+        };
+
+        assert!(is_rejection(&rejection(400)));
+        assert!(is_rejection(&rejection(200)));
+        assert!(!is_rejection(&rejection(500)));
+        assert!(!is_rejection(&rejection(503)));
+        assert!(!is_rejection(&anyhow!("connection refused")));
+    }
+
+    #[test]
+    fn prover_group_removal() {
+        let mut prover_map: ProverMap<usize> = ProverMap::from(maplit::btreemap! {
+            3 => 30,
+        });
+        prover_map.add(3, 31);
+
+        assert_eq!(prover_map.remove_where(3, |p| *p == 30), Some(30));
+        assert!(prover_map.batch_size_exists(3));
+        assert_eq!(prover_map.remove_where(3, |p| *p == 30), None);
+        assert_eq!(prover_map.remove_where(3, |p| *p == 31), Some(31));
+        assert!(!prover_map.batch_size_exists(3));
+    }
 }
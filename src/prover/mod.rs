@@ -8,8 +8,10 @@
 //! `batch_insertion::Prover`, `batch_insertion::Identity` and so on).
 
 pub mod batch_insertion;
+#[cfg(feature = "submission")]
 pub mod map;
 pub mod proof;
 
+#[cfg(feature = "submission")]
 pub use map::{InsertionProverMap, ProverMap, ReadOnlyProver};
 pub use proof::Proof;
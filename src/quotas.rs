@@ -0,0 +1,82 @@
+//! Daily and monthly insertion quotas per API key. Keys are identified by the
+//! fingerprint of the credentials in their `Authorization` header, as in the
+//! audit log, and requests without credentials share the quotas of the
+//! `anonymous` key.
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use clap::Parser;
+
+use crate::database::types::QuotaPeriod;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// The number of identities an API key may insert per day (UTC), unless
+    /// the key has a quota of its own. Unlimited if not set.
+    #[clap(long, env)]
+    pub default_daily_insert_quota: Option<u64>,
+
+    /// The number of identities an API key may insert per month (UTC), unless
+    /// the key has a quota of its own. Unlimited if not set.
+    #[clap(long, env)]
+    pub default_monthly_insert_quota: Option<u64>,
+}
+
+/// The first days of the day and the month `at` is in, which the usage of
+/// API keys is counted under.
+#[must_use]
+pub fn current_periods(at: DateTime<Utc>) -> (NaiveDate, NaiveDate) {
+    let day = at.date_naive();
+    let month = day.with_day(1).expect("every month has a first day");
+
+    (day, month)
+}
+
+/// When the `period` that `at` is in ends and its quota is replenished.
+#[must_use]
+pub fn period_end(period: QuotaPeriod, at: DateTime<Utc>) -> DateTime<Utc> {
+    let (day, month) = current_periods(at);
+
+    let end = match period {
+        QuotaPeriod::Day => day + Duration::days(1),
+        QuotaPeriod::Month => {
+            let (year, month) = if month.month() == 12 {
+                (month.year() + 1, 1)
+            } else {
+                (month.year(), month.month() + 1)
+            };
+            NaiveDate::from_ymd_opt(year, month, 1).expect("every month has a first day")
+        }
+    };
+
+    Utc.from_utc_datetime(&end.and_hms_opt(0, 0, 0).expect("midnight exists"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn periods_end_at_midnight_utc() {
+        let at: DateTime<Utc> = "2023-12-31T15:30:00Z".parse().unwrap();
+
+        assert_eq!(
+            current_periods(at),
+            (
+                NaiveDate::from_ymd_opt(2023, 12, 31).unwrap(),
+                NaiveDate::from_ymd_opt(2023, 12, 1).unwrap()
+            )
+        );
+        assert_eq!(
+            period_end(QuotaPeriod::Day, at),
+            "2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(
+            period_end(QuotaPeriod::Month, at),
+            "2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(
+            period_end(QuotaPeriod::Month, "2024-02-10T00:00:00Z".parse().unwrap()),
+            "2024-03-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+    }
+}
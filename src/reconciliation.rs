@@ -0,0 +1,238 @@
+//! Compares the latest root of the identity manager with the database on
+//! startup, before the tree is built from the database. A tree built from a
+//! database that disagrees with the chain produces proofs for roots that the
+//! contracts don't accept.
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Result as AnyhowResult};
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::database::types::BatchStatus;
+use crate::database::Database;
+use crate::identity_tree::{Hash, ProcessedStatus};
+
+#[derive(Debug, Error)]
+#[error("invalid reconciliation policy {0:?}, expected `repair` or `refuse`")]
+pub struct InvalidReconciliationPolicy(String);
+
+/// What to do when the database has gotten ahead of the chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconciliationPolicy {
+    /// Return the identities that aren't on chain to pending, so that they
+    /// are batched again.
+    Repair,
+    /// Refuse to start until an operator has looked into it.
+    Refuse,
+}
+
+impl FromStr for ReconciliationPolicy {
+    type Err = InvalidReconciliationPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repair" => Ok(Self::Repair),
+            "refuse" => Ok(Self::Refuse),
+            _ => Err(InvalidReconciliationPolicy(s.to_string())),
+        }
+    }
+}
+
+/// A way in which the database disagrees with the chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    /// The latest root on chain is not in the database, e.g. because another
+    /// sequencer wrote to the identity manager or identities were lost. This
+    /// can't be repaired, as the leaves behind the root are unknown.
+    UnknownChainRoot { root: Hash },
+    /// The database has identities processed or mined after the latest root
+    /// on chain, e.g. because it was restored from another deployment.
+    AheadOfChain {
+        first_leaf: usize,
+        last_leaf:  usize,
+    },
+}
+
+impl Divergence {
+    const fn is_repairable(&self) -> bool {
+        matches!(self, Self::AheadOfChain { .. })
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownChainRoot { root } => {
+                write!(
+                    f,
+                    "the latest root on chain {root:?} is not in the database"
+                )
+            }
+            Self::AheadOfChain {
+                first_leaf,
+                last_leaf,
+            } => write!(
+                f,
+                "leaves {first_leaf} to {last_leaf} are processed or mined in the database, but \
+                 not on chain"
+            ),
+        }
+    }
+}
+
+/// Where the latest root on chain is in the database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChainRoot {
+    /// The root of the empty tree.
+    Initial,
+    Known {
+        leaf_index: usize,
+    },
+    Unknown {
+        root: Hash,
+    },
+}
+
+/// `last_leaf` is the last leaf that is processed or mined in the database.
+fn assess(chain_root: ChainRoot, last_leaf: Option<usize>) -> Option<Divergence> {
+    let first_leaf = match chain_root {
+        ChainRoot::Unknown { root } => return Some(Divergence::UnknownChainRoot { root }),
+        ChainRoot::Initial => 0,
+        ChainRoot::Known { leaf_index } => leaf_index + 1,
+    };
+
+    let last_leaf = last_leaf?;
+
+    (last_leaf >= first_leaf).then_some(Divergence::AheadOfChain {
+        first_leaf,
+        last_leaf,
+    })
+}
+
+/// Compares `chain_root`, the latest root of the identity manager, with the
+/// database and repairs the database or refuses to start according to
+/// `policy`. Marks the identities up to `chain_root` as processed.
+///
+/// # Errors
+///
+/// Will return `Err` if the database diverged in a way that isn't repaired,
+/// with a description of the divergence.
+pub async fn reconcile(
+    database: &Database,
+    chain_root: Hash,
+    initial_root: Hash,
+    policy: ReconciliationPolicy,
+) -> AnyhowResult<()> {
+    let located = if chain_root == initial_root {
+        ChainRoot::Initial
+    } else {
+        match database.get_root_leaf_index(&chain_root).await? {
+            Some(leaf_index) => ChainRoot::Known { leaf_index },
+            None => ChainRoot::Unknown { root: chain_root },
+        }
+    };
+
+    let last_leaf = database
+        .get_max_leaf_index_by_status(ProcessedStatus::Mined)
+        .await?
+        .max(
+            database
+                .get_max_leaf_index_by_status(ProcessedStatus::Processed)
+                .await?,
+        );
+
+    if let Some(divergence) = assess(located, last_leaf) {
+        if !divergence.is_repairable() || policy == ReconciliationPolicy::Refuse {
+            bail!(
+                "The database diverged from the chain: {divergence}. Refusing to start, as the \
+                 tree would produce proofs for roots that aren't on chain."
+            );
+        }
+
+        error!(%divergence, "The database diverged from the chain, repairing it");
+
+        if let Divergence::AheadOfChain { first_leaf, .. } = divergence {
+            repair(database, first_leaf).await?;
+        }
+    }
+
+    // We don't store the initial root in the database, so there is nothing to
+    // mark if the chain is still at the initial root. Note that we don't have
+    // a way of queuing a root here for finalization, so it's going to stay as
+    // "processed" until the next root is mined.
+    if chain_root != initial_root {
+        database.mark_root_as_processed(&chain_root).await?;
+    }
+
+    Ok(())
+}
+
+/// Returns the identities from `first_leaf` on to pending and fails the
+/// batches that inserted them.
+async fn repair(database: &Database, first_leaf: usize) -> AnyhowResult<()> {
+    let reset = database.reset_identities_from(first_leaf).await?;
+
+    for status in [BatchStatus::Mined, BatchStatus::Finalized] {
+        for batch in database.get_batches_by_status(status).await? {
+            if batch.start_index >= first_leaf {
+                database
+                    .update_batch_status(&batch.post_root, BatchStatus::Failed)
+                    .await?;
+            }
+        }
+    }
+
+    info!(first_leaf, reset, "Batching identities not on chain again");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agrees_when_the_database_ends_at_the_chain_root() {
+        assert_eq!(assess(ChainRoot::Initial, None), None);
+        assert_eq!(assess(ChainRoot::Known { leaf_index: 9 }, Some(9)), None);
+        // The chain being ahead is repaired by marking its root as processed.
+        assert_eq!(assess(ChainRoot::Known { leaf_index: 9 }, Some(4)), None);
+    }
+
+    #[test]
+    fn detects_databases_ahead_of_the_chain() {
+        assert_eq!(
+            assess(ChainRoot::Initial, Some(3)),
+            Some(Divergence::AheadOfChain {
+                first_leaf: 0,
+                last_leaf:  3,
+            })
+        );
+        assert_eq!(
+            assess(ChainRoot::Known { leaf_index: 9 }, Some(12)),
+            Some(Divergence::AheadOfChain {
+                first_leaf: 10,
+                last_leaf:  12,
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_chain_roots_are_not_repairable() {
+        let root = Hash::from(42);
+        let divergence = assess(ChainRoot::Unknown { root }, None).unwrap();
+
+        assert_eq!(divergence, Divergence::UnknownChainRoot { root });
+        assert!(!divergence.is_repairable());
+    }
+
+    #[test]
+    fn parses_reconciliation_policy() {
+        assert_eq!(
+            "refuse".parse::<ReconciliationPolicy>().unwrap(),
+            ReconciliationPolicy::Refuse
+        );
+        assert!("ignore".parse::<ReconciliationPolicy>().is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! Reconstructs the tree as it was at a root or point in time from the
+//! identities in the database, for audits and incident investigations.
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, ensure, Context, Result as AnyhowResult};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use semaphore::Field;
+use serde::Serialize;
+use tracing::info;
+
+use crate::database::types::IdentityRecord;
+use crate::database::{self, Database};
+use crate::identity_tree::{dense_prefix_depth_for, CanonicalTreeBuilder, Hash, TreeUpdate};
+
+/// The number of identities read from the database at once.
+const PAGE_SIZE: usize = 10_000;
+
+/// The depth of the dense prefix of the reconstructed tree.
+const DENSE_PREFIX_DEPTH: usize = 20;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// The database to read the identities from. It is never migrated.
+    #[clap(flatten)]
+    pub database: database::Options,
+
+    /// Reconstruct the tree as of this root
+    #[clap(long, env)]
+    pub at_root: Option<Hash>,
+
+    /// Reconstruct the tree as of this time (RFC 3339), i.e. with the
+    /// identities accepted at or before it
+    #[clap(long, env)]
+    pub at_time: Option<DateTime<Utc>>,
+
+    /// The file the tree is written to as JSON. It is written to stdout if not
+    /// set.
+    #[clap(long, env)]
+    pub tree_output: Option<PathBuf>,
+
+    /// The depth of the tree the roots are recomputed with
+    #[clap(long, env, default_value = "10")]
+    pub tree_depth: usize,
+
+    /// Initial value of the Merkle tree leaves
+    #[clap(
+        long,
+        env,
+        default_value = "0000000000000000000000000000000000000000000000000000000000000000"
+    )]
+    pub initial_leaf_value: Field,
+}
+
+/// The tree as it was at a root.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TreeState {
+    root:          Hash,
+    /// When the last leaf was accepted and mined. Absent for the empty tree.
+    pending_as_of: Option<DateTime<Utc>>,
+    mined_at:      Option<DateTime<Utc>>,
+    /// The leaves in order. Deleted leaves are zero.
+    leaves:        Vec<Hash>,
+}
+
+/// Writes the tree as of `--at-root` or `--at-time`.
+///
+/// # Errors
+///
+/// Will return `Err` if the root is unknown, if the roots in the database
+/// don't follow from the leaves, or if the tree can't be written.
+pub async fn main(options: Options) -> AnyhowResult<()> {
+    ensure!(
+        options.at_root.is_some() != options.at_time.is_some(),
+        "Exactly one of --at-root and --at-time must be given"
+    );
+
+    let database = Database::new(database::Options {
+        database_migrate: false,
+        ..options.database
+    })
+    .await?;
+
+    let mut reconstruction = Reconstruction::new(options.tree_depth, options.initial_leaf_value);
+
+    // The number of leaves the tree had.
+    let leaf_count = match (options.at_root, options.at_time) {
+        (Some(root), _) if root == reconstruction.tree.root() => 0,
+        (Some(root), _) => {
+            database
+                .get_root_leaf_index(&root)
+                .await?
+                .ok_or_else(|| anyhow!("The root {root:?} is unknown"))?
+                + 1
+        }
+        (None, Some(at)) => database
+            .get_last_leaf_index_at(at)
+            .await?
+            .map_or(0, |leaf_index| leaf_index + 1),
+        (None, None) => unreachable!(),
+    };
+
+    while reconstruction.leaves.len() < leaf_count {
+        let from = reconstruction.leaves.len();
+        let page = database
+            .get_identity_records(from, PAGE_SIZE.min(leaf_count - from))
+            .await?;
+        ensure!(!page.is_empty(), "The leaves from {from} on are missing");
+
+        for identity in &page {
+            reconstruction.apply(identity)?;
+        }
+    }
+
+    let state = reconstruction.finish();
+    info!(root = ?state.root, leaves = state.leaves.len(), "Reconstructed the tree");
+
+    if let Some(path) = &options.tree_output {
+        let file =
+            fs::File::create(path).with_context(|| format!("error creating {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &state)?;
+        writer.flush()?;
+    } else {
+        let mut stdout = io::stdout().lock();
+        serde_json::to_writer(&mut stdout, &state)?;
+        writeln!(stdout)?;
+    }
+
+    Ok(())
+}
+
+/// Replays the identities in leaf order, checking that every root follows
+/// from the leaves before it.
+struct Reconstruction {
+    tree:   CanonicalTreeBuilder,
+    leaves: Vec<Hash>,
+    last:   Option<IdentityRecord>,
+}
+
+impl Reconstruction {
+    fn new(tree_depth: usize, initial_leaf_value: Field) -> Self {
+        let tree = CanonicalTreeBuilder::new(
+            tree_depth,
+            dense_prefix_depth_for(DENSE_PREFIX_DEPTH, tree_depth, 0),
+            PAGE_SIZE,
+            initial_leaf_value,
+            &[],
+        );
+
+        Self {
+            tree,
+            leaves: Vec::new(),
+            last: None,
+        }
+    }
+
+    fn apply(&mut self, identity: &IdentityRecord) -> AnyhowResult<()> {
+        ensure!(
+            identity.leaf_index == self.leaves.len(),
+            "Expected leaf {}, found leaf {}",
+            self.leaves.len(),
+            identity.leaf_index
+        );
+
+        self.tree.update(&TreeUpdate {
+            leaf_index: identity.leaf_index,
+            element:    identity.commitment,
+        });
+        ensure!(
+            self.tree.root() == identity.root,
+            "The root of leaf {} doesn't follow from the leaves before it",
+            identity.leaf_index
+        );
+
+        self.leaves.push(identity.commitment);
+        self.last = Some(identity.clone());
+
+        Ok(())
+    }
+
+    fn finish(self) -> TreeState {
+        TreeState {
+            root:          self.tree.root(),
+            pending_as_of: self.last.as_ref().map(|last| last.pending_as_of),
+            mined_at:      self.last.and_then(|last| last.mined_at),
+            leaves:        self.leaves,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+
+    use super::*;
+    use crate::identity_tree::ProcessedStatus;
+
+    fn identities(count: usize) -> Vec<IdentityRecord> {
+        let mut tree = LazyPoseidonTree::new(10, Hash::ZERO).derived();
+
+        (0..count)
+            .map(|leaf_index| {
+                let commitment = Hash::from(leaf_index as u64 + 1);
+                tree = tree.update(leaf_index, &commitment);
+
+                IdentityRecord {
+                    leaf_index,
+                    commitment,
+                    root: tree.root(),
+                    status: ProcessedStatus::Mined,
+                    pending_as_of: Utc::now(),
+                    mined_at: None,
+                    received_at: None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reconstructs_the_tree_at_a_root() {
+        let identities = identities(3);
+        let mut reconstruction = Reconstruction::new(10, Hash::ZERO);
+        for identity in &identities[..2] {
+            reconstruction.apply(identity).unwrap();
+        }
+
+        let state = reconstruction.finish();
+        assert_eq!(state.root, identities[1].root);
+        assert_eq!(state.leaves, vec![
+            identities[0].commitment,
+            identities[1].commitment
+        ]);
+        assert_eq!(state.pending_as_of, Some(identities[1].pending_as_of));
+    }
+
+    #[test]
+    fn rejects_roots_that_dont_follow_from_the_leaves() {
+        let mut identities = identities(2);
+        identities[1].root = identities[0].root;
+
+        let mut reconstruction = Reconstruction::new(10, Hash::ZERO);
+        reconstruction.apply(&identities[0]).unwrap();
+        assert!(reconstruction.apply(&identities[1]).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Deletes unprocessed identities that failed longer ago than the retention
+//! period, optionally archiving them to a file first, so that the table
+//! doesn't grow without bound.
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, IntCounter};
+use serde::Serialize;
+use tokio::time::sleep;
+use tracing::{error, info};
+
+use crate::database::types::UnprocessedCommitment;
+use crate::database::Database;
+use crate::identity_tree::{CommitmentError, Hash, UnprocessedStatus};
+
+/// The number of identities archived and deleted at once.
+const ROWS_PER_ROUND: usize = 1_000;
+
+static DELETED_IDENTITIES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "retention_deleted_identities",
+        "Number of failed unprocessed identities deleted after the retention period"
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// How long failed unprocessed identities are kept before they are
+    /// deleted (days). They are kept forever if not set.
+    #[clap(long, env)]
+    pub retention_days: Option<u32>,
+
+    /// The directory failed unprocessed identities are archived to before
+    /// they are deleted, as gzipped JSON lines. They are deleted without an
+    /// archive if not set.
+    #[clap(long, env)]
+    pub retention_archive_dir: Option<PathBuf>,
+
+    /// How often to delete expired identities (seconds)
+    #[clap(long, env, default_value = "3600")]
+    pub retention_interval_seconds: u64,
+}
+
+/// An unprocessed identity, as archived.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArchivedIdentity<'a> {
+    commitment:   Hash,
+    status:       UnprocessedStatus,
+    created_at:   DateTime<Utc>,
+    processed_at: Option<DateTime<Utc>>,
+    error:        Option<&'a CommitmentError>,
+    request_id:   Option<&'a str>,
+}
+
+impl<'a> From<&'a UnprocessedCommitment> for ArchivedIdentity<'a> {
+    fn from(identity: &'a UnprocessedCommitment) -> Self {
+        Self {
+            commitment:   identity.commitment,
+            status:       identity.status,
+            created_at:   identity.created_at,
+            processed_at: identity.processed_at,
+            error:        identity.error.as_ref(),
+            request_id:   identity.request_id.as_deref(),
+        }
+    }
+}
+
+pub struct Retention {
+    database:    Arc<Database>,
+    period:      chrono::Duration,
+    archive_dir: Option<PathBuf>,
+    interval:    Duration,
+}
+
+impl Retention {
+    /// Returns `None` if no retention period is configured.
+    pub fn new(database: Arc<Database>, options: &Options) -> Option<Self> {
+        let days = options.retention_days?;
+
+        Some(Self {
+            database,
+            period: chrono::Duration::days(days.into()),
+            archive_dir: options.retention_archive_dir.clone(),
+            interval: Duration::from_secs(options.retention_interval_seconds),
+        })
+    }
+
+    pub async fn run(self) {
+        info!(
+            retention_days = self.period.num_days(),
+            archive_dir = ?self.archive_dir,
+            "Deleting expired failed identities"
+        );
+
+        loop {
+            match self.delete_expired().await {
+                Ok(0) => {}
+                Ok(deleted) => info!(deleted, "Deleted expired failed identities"),
+                Err(error) => error!(?error, "Failed to delete expired failed identities"),
+            }
+
+            sleep(self.interval).await;
+        }
+    }
+
+    async fn delete_expired(&self) -> AnyhowResult<u64> {
+        let before = Utc::now() - self.period;
+        let mut deleted = 0;
+
+        loop {
+            let expired = self
+                .database
+                .get_expired_unprocessed_identities(before, ROWS_PER_ROUND)
+                .await?;
+            if expired.is_empty() {
+                return Ok(deleted);
+            }
+
+            if let Some(archive_dir) = &self.archive_dir {
+                let archive_dir = archive_dir.clone();
+                let expired = expired.clone();
+                tokio::task::spawn_blocking(move || archive(&archive_dir, &expired)).await??;
+            }
+
+            let commitments = expired
+                .iter()
+                .map(|identity| identity.commitment)
+                .collect::<Vec<_>>();
+            let round = self
+                .database
+                .delete_failed_unprocessed_identities(&commitments)
+                .await?;
+
+            DELETED_IDENTITIES.inc_by(round);
+            deleted += round;
+
+            if expired.len() < ROWS_PER_ROUND {
+                return Ok(deleted);
+            }
+        }
+    }
+}
+
+/// Writes `identities` to a new gzipped JSON lines file in `dir`. The file is
+/// synced before returning, so that nothing is deleted that isn't archived.
+fn archive(dir: &Path, identities: &[UnprocessedCommitment]) -> AnyhowResult<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let path = dir.join(format!(
+        "unprocessed_identities-{}.jsonl.gz",
+        Utc::now().format("%Y%m%dT%H%M%S%.6fZ")
+    ));
+
+    let mut encoder = GzEncoder::new(fs::File::create(&path)?, Compression::default());
+    for identity in identities {
+        serde_json::to_writer(&mut encoder, &ArchivedIdentity::from(identity))?;
+        encoder.write_all(b"\n")?;
+    }
+    encoder.finish()?.sync_all()?;
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    use super::*;
+    use crate::identity_tree::ErrorCode;
+
+    #[test]
+    fn archives_identities_as_gzipped_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let identities = (1..=2_u64)
+            .map(|i| UnprocessedCommitment {
+                commitment:   Hash::from(i),
+                status:       UnprocessedStatus::Failed,
+                created_at:   Utc::now(),
+                processed_at: None,
+                error:        Some(CommitmentError::new(
+                    ErrorCode::InvalidCommitment,
+                    serde_json::Value::Null,
+                )),
+                request_id:   None,
+            })
+            .collect::<Vec<_>>();
+
+        let path = archive(dir.path(), &identities).unwrap();
+
+        let mut lines = String::new();
+        GzDecoder::new(fs::File::open(path).unwrap())
+            .read_to_string(&mut lines)
+            .unwrap();
+        let lines = lines
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["status"], "failed");
+        assert_eq!(lines[1]["error"]["code"], "invalid_commitment");
+    }
+}
@@ -1,4 +1,7 @@
 pub mod api_metrics_layer;
+pub mod audit_layer;
 pub mod logging_layer;
+pub mod quota_layer;
 pub mod remove_auth_layer;
+pub mod request_id_layer;
 pub mod timeout_layer;
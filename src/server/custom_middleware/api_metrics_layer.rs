@@ -2,10 +2,9 @@ use axum::http::{Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::Response;
 use once_cell::sync::Lazy;
-use prometheus::{
-    opts, register_counter, register_histogram, register_int_counter_vec, Counter, Histogram,
-    IntCounterVec,
-};
+use prometheus::{opts, register_counter, register_int_counter_vec, Counter, IntCounterVec};
+
+use crate::utils::exemplars::ExemplarHistogram;
 
 static REQUESTS: Lazy<Counter> =
     Lazy::new(|| register_counter!(opts!("api_requests", "Number of requests received.")).unwrap());
@@ -19,8 +18,12 @@ static STATUS: Lazy<IntCounterVec> = Lazy::new(|| {
     .unwrap()
 });
 
-static LATENCY: Lazy<Histogram> = Lazy::new(|| {
-    register_histogram!("api_latency_seconds", "The API latency in seconds.").unwrap()
+static LATENCY: Lazy<ExemplarHistogram> = Lazy::new(|| {
+    ExemplarHistogram::new(
+        "api_latency_seconds",
+        "The API latency in seconds.",
+        prometheus::DEFAULT_BUCKETS.to_vec(),
+    )
 });
 
 pub async fn middleware<B>(request: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
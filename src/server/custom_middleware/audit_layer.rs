@@ -0,0 +1,68 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use hyper::{Body, Method};
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+use crate::app::App;
+use crate::database::types::AuditEntry;
+
+/// Calls that are made with `POST` but don't change anything.
+const READ_ONLY_PATHS: &[&str] = &["/inclusionProof", "/verifySemaphoreProof"];
+
+/// Identifies the credentials a request was made with, without revealing
+/// them. Set by the layer that strips the `Authorization` header.
+#[derive(Clone, Debug)]
+pub struct ApiKeyFingerprint(pub String);
+
+impl ApiKeyFingerprint {
+    #[must_use]
+    pub fn of(credentials: &[u8]) -> Self {
+        Self(hex::encode(&Sha256::digest(credentials)[..8]))
+    }
+}
+
+/// Records every mutating call in the audit log once it has been handled.
+pub async fn middleware(
+    State(app): State<Arc<App>>,
+    ConnectInfo(source): ConnectInfo<SocketAddr>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Result<Response, StatusCode> {
+    if request.method() != Method::POST || READ_ONLY_PATHS.contains(&request.uri().path()) {
+        return Ok(next.run(request).await);
+    }
+
+    let (parts, body) = request.into_parts();
+    // The body has already been read, and limited in size, by the logging
+    // layer.
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mut entry = AuditEntry {
+        method:       parts.method.to_string(),
+        path:         parts.uri.path().to_string(),
+        api_key:      parts
+            .extensions
+            .get::<ApiKeyFingerprint>()
+            .map(|fingerprint| fingerprint.0.clone()),
+        source_ip:    source.ip().to_string(),
+        payload_hash: hex::encode(Sha256::digest(&body)),
+        outcome:      0,
+    };
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    entry.outcome = response.status().as_u16();
+    if let Err(error) = app.record_audit_entry(&entry).await {
+        error!(?error, path = entry.path, "Failed to record an audit entry");
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::header::HeaderName;
+use axum::http::{HeaderMap, HeaderValue, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use hyper::{Body, Method};
+use tracing::error;
+
+use super::audit_layer::ApiKeyFingerprint;
+use crate::app::{App, QuotaAllowance};
+use crate::database::types::QuotaPeriod;
+use crate::server::error::Error;
+
+/// Calls that insert identities, and count against the quotas of the API key
+/// they are made with.
+const INSERTION_PATHS: &[&str] = &["/insertIdentity", "/confirmIdentity"];
+
+/// The key that insertions made without an `Authorization` header share their
+/// quotas under. Fingerprints are hex, so no API key can clash with it.
+const ANONYMOUS_API_KEY: &str = "anonymous";
+
+/// Counts insertions against the quotas of their API key, refusing them once a
+/// quota is used up. Insertions that fail are not counted.
+pub async fn middleware(
+    State(app): State<Arc<App>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    if request.method() != Method::POST || !INSERTION_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let api_key = quota_key(&request);
+
+    let allowances = match app.consume_insert_quota(&api_key).await {
+        Ok(allowances) => allowances,
+        Err(error) => {
+            let exceeded = match &error {
+                Error::QuotaExceeded { period, limit, .. } => Some(QuotaAllowance {
+                    period:    *period,
+                    limit:     *limit,
+                    remaining: 0,
+                }),
+                _ => None,
+            };
+
+            let mut response = error.into_response();
+            insert_quota_headers(response.headers_mut(), exceeded);
+            return response;
+        }
+    };
+
+    let mut response = next.run(request).await;
+
+    if response.status().is_success() {
+        insert_quota_headers(response.headers_mut(), allowances);
+    } else if let Err(error) = app.refund_insert_quota(&api_key).await {
+        error!(?error, api_key, "Failed to refund an insertion quota");
+    }
+
+    response
+}
+
+/// The API key whose quotas `request` counts against.
+fn quota_key(request: &Request<Body>) -> String {
+    request.extensions().get::<ApiKeyFingerprint>().map_or_else(
+        || ANONYMOUS_API_KEY.to_string(),
+        |fingerprint| fingerprint.0.clone(),
+    )
+}
+
+fn insert_quota_headers(
+    headers: &mut HeaderMap,
+    allowances: impl IntoIterator<Item = QuotaAllowance>,
+) {
+    for allowance in allowances {
+        let (limit, remaining) = match allowance.period {
+            QuotaPeriod::Day => ("x-quota-daily-limit", "x-quota-daily-remaining"),
+            QuotaPeriod::Month => ("x-quota-monthly-limit", "x-quota-monthly-remaining"),
+        };
+
+        headers.insert(
+            HeaderName::from_static(limit),
+            HeaderValue::from(allowance.limit),
+        );
+        headers.insert(
+            HeaderName::from_static(remaining),
+            HeaderValue::from(allowance.remaining),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_without_credentials_share_a_quota() {
+        let request = || {
+            Request::post("/insertIdentity")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert_eq!(quota_key(&request()), ANONYMOUS_API_KEY);
+
+        let fingerprint = ApiKeyFingerprint::of(b"Basic dXNlcjpwYXNz");
+        let mut authorized = request();
+        authorized.extensions_mut().insert(fingerprint.clone());
+
+        assert_eq!(quota_key(&authorized), fingerprint.0);
+        assert_ne!(quota_key(&authorized), ANONYMOUS_API_KEY);
+    }
+}
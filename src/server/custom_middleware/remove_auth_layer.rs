@@ -3,8 +3,14 @@ use axum::http::{Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::Response;
 
+use super::audit_layer::ApiKeyFingerprint;
+
 pub async fn middleware<B>(mut request: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
-    request.headers_mut().remove(AUTHORIZATION);
+    if let Some(credentials) = request.headers_mut().remove(AUTHORIZATION) {
+        request
+            .extensions_mut()
+            .insert(ApiKeyFingerprint::of(credentials.as_bytes()));
+    }
 
     let response = next.run(request).await;
     Ok(response)
@@ -0,0 +1,65 @@
+use axum::http::{HeaderValue, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use hyper::header::HeaderName;
+use tracing::{info_span, Instrument};
+
+pub const X_REQUEST_ID: &str = "x-request-id";
+
+/// Request ids passed by clients longer than this are replaced.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Correlates the logs of a request, including those of inserting the
+/// identities it queued, and is returned in the `X-Request-Id` header.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// Takes the request id passed by the client, or makes up one, and attaches it
+/// to all logs of the request.
+pub async fn middleware<B>(mut request: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
+    let request_id = request
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|request_id| is_valid(request_id))
+        .map_or_else(
+            || format!("{:032x}", rand::random::<u128>()),
+            ToString::to_string,
+        );
+
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+
+    let span = info_span!("request_id", request_id);
+    let mut response = next.run(request).instrument(span).await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(X_REQUEST_ID), value);
+    }
+
+    Ok(response)
+}
+
+fn is_valid(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LENGTH
+        && request_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_only_printable_request_ids() {
+        assert!(is_valid("0b7c1d9e-5a3f-4c2b-9f1e-8d6a4b2c0e1f"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("two words"));
+        assert!(!is_valid(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+    }
+}
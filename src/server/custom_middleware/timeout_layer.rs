@@ -1,17 +1,165 @@
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use axum::extract::State;
-use axum::http::{Request, StatusCode};
+use axum::http::Request;
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use thiserror::Error;
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::server::error::Error as ServerError;
+use crate::utils::request_deadline;
+
+static TIMEOUTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "api_request_timeouts",
+        "Number of requests that took longer than the timeout of their endpoint.",
+        &["route"]
+    )
+    .unwrap()
+});
+
+/// The label of requests without a timeout of their own.
+const DEFAULT_ROUTE: &str = "default";
+
+#[derive(Debug, Error)]
+#[error("invalid endpoint timeout {0:?}, expected `<route>=<seconds>`")]
+pub struct InvalidEndpointTimeout(String);
+
+/// The timeout of a single route, as `<route>=<seconds>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EndpointTimeout {
+    pub route:   String,
+    pub timeout: Duration,
+}
+
+impl FromStr for EndpointTimeout {
+    type Err = InvalidEndpointTimeout;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidEndpointTimeout(s.to_string());
+
+        let (route, seconds) = s.split_once('=').ok_or_else(invalid)?;
+        let route = route.trim().trim_end_matches('/');
+        if !route.starts_with('/') || route.len() < 2 {
+            return Err(invalid());
+        }
+        let seconds = seconds.trim().parse::<u64>().map_err(|_| invalid())?;
+
+        Ok(Self {
+            route:   route.to_string(),
+            timeout: Duration::from_secs(seconds),
+        })
+    }
+}
+
+/// The timeouts of the routes of the server.
+#[derive(Clone, Debug)]
+pub struct Timeouts {
+    default:   Duration,
+    endpoints: Vec<EndpointTimeout>,
+}
+
+impl Timeouts {
+    #[must_use]
+    pub fn new(default: Duration, endpoints: Vec<EndpointTimeout>) -> Self {
+        Self { default, endpoints }
+    }
+
+    /// The route `path` is timed by, if it has a timeout of its own, and its
+    /// timeout. Routes apply to every group as well.
+    fn for_path(&self, path: &str) -> (&str, Duration) {
+        let path = strip_group(path);
+
+        self.endpoints
+            .iter()
+            .find(|endpoint| {
+                path.strip_prefix(endpoint.route.as_str())
+                    .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map_or((DEFAULT_ROUTE, self.default), |endpoint| {
+                (&endpoint.route, endpoint.timeout)
+            })
+    }
+}
+
+/// `path` without the `/groups/<group id>` prefix of group routes.
+fn strip_group(path: &str) -> &str {
+    path.strip_prefix("/groups/")
+        .and_then(|rest| {
+            let (group_id, rest) = rest.split_once('/')?;
+            group_id.parse::<u64>().ok()?;
+            Some(rest)
+        })
+        .map_or(path, |rest| &path[path.len() - rest.len() - 1..])
+}
 
 pub async fn middleware<B>(
-    State(timeout_duration): State<Duration>,
+    State(timeouts): State<Arc<Timeouts>>,
     request: Request<B>,
     next: Next<B>,
-) -> Result<Response, StatusCode> {
-    match tokio::time::timeout(timeout_duration, next.run(request)).await {
-        Ok(response) => Ok(response),
-        Err(_elapsed) => Err(StatusCode::REQUEST_TIMEOUT),
+) -> Response {
+    let (route, timeout) = timeouts.for_path(request.uri().path());
+    let deadline = Instant::now() + timeout;
+
+    // The handler is dropped once the deadline passes, and with it any query or
+    // proof it is waiting for. Handlers check the deadline between steps that
+    // don't yield, see `request_deadline`.
+    let response = tokio::time::timeout_at(
+        deadline,
+        request_deadline::scope(deadline, next.run(request)),
+    )
+    .await;
+
+    match response {
+        Ok(response) => response,
+        Err(_elapsed) => {
+            warn!(route, ?timeout, "Request timed out");
+            TIMEOUTS.with_label_values(&[route]).inc();
+            ServerError::DeadlineExceeded.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_endpoint_timeouts() {
+        assert_eq!(
+            "/inclusionProof=5".parse::<EndpointTimeout>().unwrap(),
+            EndpointTimeout {
+                route:   "/inclusionProof".to_string(),
+                timeout: Duration::from_secs(5),
+            }
+        );
+        assert!("inclusionProof=5".parse::<EndpointTimeout>().is_err());
+        assert!("/inclusionProof".parse::<EndpointTimeout>().is_err());
+        assert!("/=5".parse::<EndpointTimeout>().is_err());
+    }
+
+    #[test]
+    fn routes_apply_to_groups_and_subpaths() {
+        let timeouts = Timeouts::new(Duration::from_secs(300), vec![
+            "/inclusionProof=5".parse().unwrap(),
+            "/proofBundle=10".parse().unwrap(),
+        ]);
+
+        let five = ("/inclusionProof", Duration::from_secs(5));
+        let ten = ("/proofBundle", Duration::from_secs(10));
+        let default = (DEFAULT_ROUTE, Duration::from_secs(300));
+
+        assert_eq!(timeouts.for_path("/inclusionProof"), five);
+        assert_eq!(timeouts.for_path("/groups/2/inclusionProof"), five);
+        assert_eq!(timeouts.for_path("/proofBundle/0x01"), ten);
+        assert_eq!(timeouts.for_path("/inclusionProofs"), default);
+        assert_eq!(timeouts.for_path("/groups/x/inclusionProof"), default);
+        assert_eq!(timeouts.for_path("/insertIdentity"), default);
     }
 }
@@ -1,9 +1,15 @@
-use anyhow::Error as EyreError;
 use axum::response::IntoResponse;
+use axum::Json;
+use hyper::header::{CONTENT_TYPE, RETRY_AFTER};
 use hyper::{Body, StatusCode};
+use serde::Serialize;
 use thiserror::Error;
+use tracing::error;
 
 use crate::database;
+use crate::database::types::QuotaPeriod;
+use crate::error::{Chain, ErrorCategory, SequencerError};
+use crate::identity_tree::Status;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -17,101 +23,293 @@ pub enum Error {
     InvalidGroupId,
     #[error("invalid root")]
     InvalidRoot,
+    #[error("root not found")]
+    RootNotFound,
+    #[error("batch not found")]
+    BatchNotFound,
+    #[error("only batches that have not been submitted yet can be cancelled")]
+    BatchNotCancellable,
+    #[error("status must be one of mined, processed or pending")]
+    InvalidRootStatus,
     #[error("invalid semaphore proof")]
     InvalidProof,
     #[error("provided identity index out of bounds")]
     IndexOutOfBounds,
     #[error("provided identity commitment not found")]
     IdentityCommitmentNotFound,
+    #[error("provided identity commitment is not in the tree with the requested status yet")]
+    NotInRequestedTree,
+    #[error("provided identity commitment is not in the tree as of the requested root")]
+    NotAtRequestedRoot,
+    #[error("proofs are only served as of the latest mined root and later roots")]
+    ProofRootTooOld,
     #[error("provided identity commitment is invalid")]
     InvalidCommitment,
     #[error("provided identity commitment is not in reduced form")]
     UnreducedCommitment,
-    #[error("provided identity commitment is already included")]
-    DuplicateCommitment,
+    #[error("provided identity commitment is already included, with status {status}")]
+    DuplicateCommitment { status: Status },
+    #[error("provided identity commitment has been deleted")]
+    DeletedCommitment,
+    #[error("provided identity commitment is banned")]
+    BannedCommitment,
+    #[error("provided identity commitment is not banned")]
+    CommitmentNotBanned,
+    #[error("provided identity commitment is reserved and must be confirmed")]
+    CommitmentReserved,
+    #[error("provided identity commitment is not reserved or its reservation expired")]
+    ReservationNotFound,
+    #[error("no signing key is configured")]
+    NoSigningKey,
     #[error("Root mismatch between tree and contract.")]
     RootMismatch,
     #[error("Root provided in semaphore proof is too old.")]
     RootTooOld,
     #[error("invalid JSON request: {0}")]
     InvalidSerialization(#[from] serde_json::Error),
-    #[error(transparent)]
-    Database(#[from] database::Error),
-    #[error(transparent)]
-    Hyper(#[from] hyper::Error),
-    #[error(transparent)]
-    Http(#[from] hyper::http::Error),
     #[error("not semaphore manager")]
     NotManager,
-    #[error(transparent)]
-    Elapsed(#[from] tokio::time::error::Elapsed),
     #[error("prover error")]
     ProverError,
     #[error("Failed to insert identity")]
     FailedToInsert,
-    #[error("The provided batch size already exists")]
-    BatchSizeAlreadyExists,
+    #[error("The provided prover is already registered for this batch size")]
+    ProverAlreadyExists,
     #[error("The requested batch size does not exist")]
     NoSuchBatchSize,
     #[error("The last batch size cannot be removed")]
     CannotRemoveLastBatchSize,
+    #[error("The batch timeout must be at least one second")]
+    InvalidBatchTimeout,
     #[error("Identity Manager had no provers on point of identity insertion.")]
     NoProversOnIdInsert,
+    #[error("The tree is full")]
+    TreeFull,
+    #[error("The insertion queue is full, retry in {retry_after_seconds} seconds")]
+    QueueFull { retry_after_seconds: u64 },
+    #[error(
+        "The {period} insertion quota of {limit} is used up, retry in {retry_after_seconds} \
+         seconds"
+    )]
+    QuotaExceeded {
+        period:              QuotaPeriod,
+        limit:               u64,
+        retry_after_seconds: u64,
+    },
+    #[error("This sequencer is an observer and doesn't accept changes")]
+    ObserverMode,
+    #[error("The request took longer than the timeout of its endpoint")]
+    DeadlineExceeded,
+    #[error("invalid config file: {0}")]
+    InvalidConfig(String),
     #[error(transparent)]
-    Other(#[from] EyreError),
+    Internal(#[from] SequencerError),
 }
 
-impl Error {
-    #[allow(clippy::enum_glob_use)]
-    #[must_use]
-    pub fn to_response(&self) -> hyper::Response<Body> {
-        use Error::*;
-
-        let status_code = match self {
-            InvalidMethod => StatusCode::METHOD_NOT_ALLOWED,
-            InvalidPath => StatusCode::NOT_FOUND,
-            InvalidContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
-            IndexOutOfBounds
-            | RootTooOld
-            | IdentityCommitmentNotFound
-            | InvalidCommitment
-            | DuplicateCommitment
-            | InvalidSerialization(_) => StatusCode::BAD_REQUEST,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        };
-        hyper::Response::builder()
-            .status(status_code)
-            .body(hyper::Body::from(self.to_string()))
-            .expect("Failed to convert error string into hyper::Body")
+impl From<database::Error> for Error {
+    fn from(error: database::Error) -> Self {
+        match error {
+            database::Error::CommitmentExists { status, .. } => {
+                Self::DuplicateCommitment { status }
+            }
+            database::Error::LaterLeavesSubmitted { .. } => Self::BatchNotCancellable,
+            error => Self::Internal(error.into()),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Internal(error.into())
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(error: hyper::Error) -> Self {
+        Self::Internal(error.into())
+    }
+}
+
+impl From<hyper::http::Error> for Error {
+    fn from(error: hyper::http::Error) -> Self {
+        Self::Internal(error.into())
+    }
+}
+
+impl From<tokio::time::error::Elapsed> for Error {
+    fn from(error: tokio::time::error::Elapsed) -> Self {
+        Self::Internal(error.into())
     }
 }
 
+/// The body of error responses.
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    category: ErrorCategory,
+    code:     &'static str,
+    message:  String,
+}
+
 impl Error {
+    #[must_use]
+    pub const fn category(&self) -> ErrorCategory {
+        match self {
+            Self::InvalidPath
+            | Self::RootNotFound
+            | Self::BatchNotFound
+            | Self::CommitmentNotBanned
+            | Self::ReservationNotFound
+            | Self::NoSigningKey
+            | Self::NoSuchBatchSize => ErrorCategory::NotFound,
+            Self::InvalidMethod
+            | Self::InvalidContentType
+            | Self::InvalidGroupId
+            | Self::InvalidRoot
+            | Self::InvalidRootStatus
+            | Self::InvalidProof
+            | Self::IndexOutOfBounds
+            | Self::IdentityCommitmentNotFound
+            | Self::NotInRequestedTree
+            | Self::NotAtRequestedRoot
+            | Self::ProofRootTooOld
+            | Self::InvalidCommitment
+            | Self::UnreducedCommitment
+            | Self::RootTooOld
+            | Self::InvalidBatchTimeout
+            | Self::InvalidConfig(_)
+            | Self::InvalidSerialization(_) => ErrorCategory::InvalidRequest,
+            Self::DuplicateCommitment { .. }
+            | Self::DeletedCommitment
+            | Self::CommitmentReserved
+            | Self::BatchNotCancellable
+            | Self::ProverAlreadyExists
+            | Self::CannotRemoveLastBatchSize => ErrorCategory::Conflict,
+            Self::ObserverMode | Self::BannedCommitment => ErrorCategory::Forbidden,
+            Self::TreeFull => ErrorCategory::CapacityExhausted,
+            Self::QuotaExceeded { .. } => ErrorCategory::QuotaExceeded,
+            Self::RootMismatch
+            | Self::NoProversOnIdInsert
+            | Self::QueueFull { .. }
+            | Self::DeadlineExceeded => ErrorCategory::Unavailable,
+            Self::NotManager | Self::ProverError | Self::FailedToInsert => ErrorCategory::Internal,
+            Self::Internal(error) => error.category(),
+        }
+    }
+
+    /// The stable code of the error, for clients to match on instead of the
+    /// message.
+    #[must_use]
+    pub const fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidMethod => "invalid_method",
+            Self::InvalidPath => "invalid_path",
+            Self::InvalidContentType => "invalid_content_type",
+            Self::InvalidGroupId => "invalid_group_id",
+            Self::InvalidRoot => "invalid_root",
+            Self::RootNotFound => "root_not_found",
+            Self::BatchNotFound => "batch_not_found",
+            Self::BatchNotCancellable => "batch_not_cancellable",
+            Self::InvalidRootStatus => "invalid_root_status",
+            Self::InvalidProof => "invalid_proof",
+            Self::IndexOutOfBounds => "index_out_of_bounds",
+            Self::IdentityCommitmentNotFound => "identity_commitment_not_found",
+            Self::NotInRequestedTree => "not_in_requested_tree",
+            Self::NotAtRequestedRoot => "not_at_requested_root",
+            Self::ProofRootTooOld => "proof_root_too_old",
+            Self::InvalidCommitment => "invalid_commitment",
+            Self::UnreducedCommitment => "unreduced_commitment",
+            Self::DuplicateCommitment { .. } => "duplicate_commitment",
+            Self::DeletedCommitment => "deleted_commitment",
+            Self::BannedCommitment => "banned_commitment",
+            Self::CommitmentNotBanned => "commitment_not_banned",
+            Self::CommitmentReserved => "commitment_reserved",
+            Self::ReservationNotFound => "reservation_not_found",
+            Self::NoSigningKey => "no_signing_key",
+            Self::RootMismatch => "root_mismatch",
+            Self::RootTooOld => "root_too_old",
+            Self::InvalidSerialization(_) => "invalid_serialization",
+            Self::NotManager => "not_manager",
+            Self::ProverError => "prover_error",
+            Self::FailedToInsert => "failed_to_insert",
+            Self::ProverAlreadyExists => "prover_already_exists",
+            Self::NoSuchBatchSize => "no_such_batch_size",
+            Self::CannotRemoveLastBatchSize => "cannot_remove_last_batch_size",
+            Self::InvalidBatchTimeout => "invalid_batch_timeout",
+            Self::NoProversOnIdInsert => "no_provers",
+            Self::TreeFull => "tree_full",
+            Self::QueueFull { .. } => "queue_full",
+            Self::QuotaExceeded { .. } => "quota_exceeded",
+            Self::ObserverMode => "observer_mode",
+            Self::DeadlineExceeded => "request_timeout",
+            Self::InvalidConfig(_) => "invalid_config",
+            Self::Internal(error) => error.code(),
+        }
+    }
+
     fn to_status_code(&self) -> StatusCode {
         match self {
             Self::InvalidMethod => StatusCode::METHOD_NOT_ALLOWED,
-            Self::InvalidPath => StatusCode::NOT_FOUND,
             Self::InvalidContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
-            Self::IndexOutOfBounds
-            | Self::IdentityCommitmentNotFound
-            | Self::InvalidCommitment
-            | Self::InvalidSerialization(_) => StatusCode::BAD_REQUEST,
-            Self::DuplicateCommitment => StatusCode::CONFLICT,
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::DeadlineExceeded => StatusCode::REQUEST_TIMEOUT,
+            _ => self.category().status_code(),
+        }
+    }
+
+    /// The value of the `Retry-After` header, for errors that go away.
+    const fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::QueueFull {
+                retry_after_seconds,
+            }
+            | Self::QuotaExceeded {
+                retry_after_seconds,
+                ..
+            } => Some(*retry_after_seconds),
+            _ => None,
+        }
+    }
+
+    fn to_body(&self) -> ErrorBody {
+        ErrorBody {
+            category: self.category(),
+            code:     self.code(),
+            message:  Chain(self).to_string(),
         }
     }
+
+    /// # Panics
+    ///
+    /// Never, the error body always serializes.
+    #[must_use]
+    pub fn to_response(&self) -> hyper::Response<Body> {
+        let body = serde_json::to_vec(&self.to_body()).expect("Failed to serialize error");
+
+        let mut response = hyper::Response::builder()
+            .status(self.to_status_code())
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(retry_after) = self.retry_after() {
+            response = response.header(RETRY_AFTER, retry_after);
+        }
+
+        response
+            .body(Body::from(body))
+            .expect("Failed to convert error into hyper::Body")
+    }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
-        let status_code = self.to_status_code();
+        if self.category() == ErrorCategory::Internal {
+            error!(error = %Chain(&self), code = self.code(), "Request failed");
+        }
 
-        let body = if let Self::Other(err) = self {
-            format!("{err:?}")
-        } else {
-            self.to_string()
-        };
+        let mut response = (self.to_status_code(), Json(self.to_body())).into_response();
+        if let Some(retry_after) = self.retry_after() {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, retry_after.into());
+        }
 
-        (status_code, body).into_response()
+        response
     }
 }
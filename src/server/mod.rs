@@ -1,17 +1,22 @@
 pub mod error;
+pub mod proof_format;
 
+use std::collections::BTreeMap;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{bail, ensure, Result as AnyhowResult};
-use axum::extract::{Query, State};
-use axum::routing::{get, post};
-use axum::{middleware, Json, Router};
+use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{any, get, post};
+use axum::{middleware, Extension, Json, Router};
 use clap::Parser;
 use cli_batteries::await_shutdown;
 use error::Error;
-use hyper::StatusCode;
+use hyper::header::{HeaderName, ACCEPT, CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH, VARY};
+use hyper::{HeaderMap, StatusCode};
+use prometheus::TextEncoder;
 use semaphore::protocol::Proof;
 use semaphore::Field;
 use serde::{Deserialize, Serialize};
@@ -19,12 +24,22 @@ use tracing::info;
 use url::{Host, Url};
 
 use crate::app::{
-    App, InclusionProofResponse, ListBatchSizesResponse, VerifySemaphoreProofResponse,
+    App, AuditLogResponse, BanCommitmentResponse, BatchResponse, BatchTimeoutResponse,
+    BatchingPauseResponse, CancelBatchResponse, CostsResponse, DeadLetterEventsResponse,
+    IdentityStatusResponse, InsertIdentityResponse, LatestRootResponse, ListBatchSizesResponse,
+    ProofBundleResponse, PublicKeyResponse, QuotasResponse, RedriveEventsResponse,
+    ReloadConfigResponse, ReserveIdentityResponse, RootResponse, ScheduledJobsResponse,
+    SloReportResponse, SyncResponse, TasksResponse, VerifySemaphoreProofResponse,
 };
-use crate::identity_tree::Hash;
+use crate::identity_tree::{Hash, ProcessedStatus, Status};
+use crate::server::custom_middleware::request_id_layer::RequestId;
+use crate::server::proof_format::ProofFormat;
+use crate::utils::exemplars;
 
 mod custom_middleware;
 
+pub use custom_middleware::timeout_layer::{EndpointTimeout, InvalidEndpointTimeout, Timeouts};
+
 #[derive(Clone, Debug, PartialEq, Eq, Parser)]
 #[group(skip)]
 pub struct Options {
@@ -36,6 +51,12 @@ pub struct Options {
     /// Request handling timeout (seconds)
     #[clap(long, env, default_value = "300")]
     pub serve_timeout: u64,
+
+    /// Timeouts of single routes that replace `--serve-timeout`, as
+    /// comma-separated `<route>=<seconds>`, e.g. `/inclusionProof=5`. They
+    /// apply to the route in every group too.
+    #[clap(long, env, value_delimiter = ',')]
+    pub endpoint_timeouts: Vec<EndpointTimeout>,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -45,6 +66,56 @@ pub struct InsertCommitmentRequest {
     identity_commitment: Hash,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct ReserveIdentityRequest {
+    identity_commitment: Hash,
+    /// How long the reservation lasts, `--reservation-ttl-seconds` by
+    /// default.
+    #[serde(default)]
+    ttl_seconds:         Option<u64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct SetQuotaRequest {
+    /// The fingerprint of the key, as in the audit log.
+    api_key:       String,
+    /// Limits that aren't given fall back to the default quotas.
+    #[serde(default)]
+    daily_limit:   Option<u64>,
+    #[serde(default)]
+    monthly_limit: Option<u64>,
+    /// Forget the insertions of the current day and month.
+    #[serde(default)]
+    reset_usage:   bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct PauseBatchingRequest {
+    /// Why batching is paused, for the record.
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct BanCommitmentRequest {
+    identity_commitment: Hash,
+    /// Why the commitment is banned, for the record.
+    #[serde(default)]
+    reason:              Option<String>,
+    /// Queue the leaf of the commitment for deletion if it is already in the
+    /// tree.
+    #[serde(default)]
+    queue_deletion:      bool,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(deny_unknown_fields)]
@@ -63,6 +134,26 @@ pub struct AddBatchSizeRequest {
 pub struct RemoveBatchSizeRequest {
     /// The batch size to remove from the prover map.
     batch_size: usize,
+    /// The URL of a single prover to remove. If omitted, all the provers for
+    /// the batch size are removed.
+    #[serde(default)]
+    url:        Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct SetBatchTimeoutRequest {
+    /// The maximum number of seconds to wait before submitting a batch that is
+    /// not full.
+    batch_timeout_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct IdentityStatusRequest {
+    pub identity_commitment: Hash,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -70,6 +161,21 @@ pub struct RemoveBatchSizeRequest {
 #[serde(deny_unknown_fields)]
 pub struct InclusionProofRequest {
     pub identity_commitment: Hash,
+    /// Prove against the tree with this status, see `/latestRoot`, instead
+    /// of the tree matching the status of the identity.
+    #[serde(default)]
+    pub status:              Option<Status>,
+    /// Prove as of this root instead. `status` is ignored if it is given.
+    #[serde(default)]
+    pub root:                Option<Hash>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct InclusionProofQuery {
+    #[serde(default)]
+    pub format: Option<ProofFormat>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,6 +197,90 @@ pub struct VerifySemaphoreProofQuery {
     pub max_root_age_seconds: Option<i64>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct LatestRootQuery {
+    #[serde(default = "default_latest_root_status")]
+    pub status: Status,
+}
+
+const fn default_latest_root_status() -> Status {
+    Status::Processed(ProcessedStatus::Mined)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncQuery {
+    #[serde(default)]
+    pub since_leaf: usize,
+    #[serde(default)]
+    pub since_root: Option<Hash>,
+    #[serde(default = "default_sync_limit")]
+    pub limit:      usize,
+}
+
+const fn default_sync_limit() -> usize {
+    1000
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct AuditLogQuery {
+    #[serde(default)]
+    pub before: Option<i64>,
+    #[serde(default = "default_audit_limit")]
+    pub limit:  usize,
+}
+
+const fn default_audit_limit() -> usize {
+    100
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct QuotasQuery {
+    #[serde(default)]
+    pub api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct SloQuery {
+    #[serde(default = "default_slo_window_seconds")]
+    pub window_seconds: u64,
+}
+
+const fn default_slo_window_seconds() -> u64 {
+    24 * 60 * 60
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+pub struct CostsQuery {
+    #[serde(default = "default_costs_window_seconds")]
+    pub window_seconds: u64,
+}
+
+const fn default_costs_window_seconds() -> u64 {
+    30 * 24 * 60 * 60
+}
+
+/// Latest roots change at most once per batch, so clients may reuse them for
+/// a short while.
+const LATEST_ROOT_CACHE_CONTROL: &str = "public, max-age=2";
+
+/// Mined proofs as of a given root never change.
+const PINNED_PROOF_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Proofs that aren't mined yet change with every batch, so clients have to
+/// revalidate them.
+const UNSETTLED_PROOF_CACHE_CONTROL: &str = "no-cache";
+
 pub trait ToResponseCode {
     fn to_response_code(&self) -> StatusCode;
 }
@@ -101,29 +291,314 @@ impl ToResponseCode for () {
     }
 }
 
+async fn identity_status(
+    State(app): State<Arc<App>>,
+    Json(identity_status_request): Json<IdentityStatusRequest>,
+) -> Result<(StatusCode, Json<IdentityStatusResponse>), Error> {
+    let result = app
+        .identity_status(&identity_status_request.identity_commitment)
+        .await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
 async fn inclusion_proof(
     State(app): State<Arc<App>>,
+    Query(query): Query<InclusionProofQuery>,
+    headers: HeaderMap,
     Json(inclusion_proof_request): Json<InclusionProofRequest>,
-) -> Result<(StatusCode, Json<InclusionProofResponse>), Error> {
+) -> Result<Response, Error> {
+    let format = ProofFormat::negotiate(query.format, &headers);
+
     let result = app
-        .inclusion_proof(&inclusion_proof_request.identity_commitment)
+        .inclusion_proof(
+            &inclusion_proof_request.identity_commitment,
+            inclusion_proof_request.status,
+            inclusion_proof_request.root,
+        )
         .await?;
 
     let result = result.hide_processed_status();
 
+    let Some(etag) = result.etag(&inclusion_proof_request.identity_commitment) else {
+        return Ok((result.to_response_code(), Json(result)).into_response());
+    };
+    // The ETag identifies the proof, so the format is part of it.
+    let etag = format!("{}{}\"", etag.trim_end_matches('"'), format.etag_suffix());
+    let cache_control = match (result.is_mined(), inclusion_proof_request.root) {
+        (true, Some(_)) => PINNED_PROOF_CACHE_CONTROL,
+        (true, None) => LATEST_ROOT_CACHE_CONTROL,
+        (false, _) => UNSETTLED_PROOF_CACHE_CONTROL,
+    };
+
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)], [(
+            CACHE_CONTROL,
+            cache_control,
+        )])
+            .into_response());
+    }
+
+    let response_headers = [
+        (ETAG, etag),
+        (CACHE_CONTROL, cache_control.to_owned()),
+        (VARY, ACCEPT.to_string()),
+    ];
+
+    match (format, result.merkle_proof()) {
+        (ProofFormat::Branches, _) | (_, None) => {
+            Ok((result.to_response_code(), response_headers, Json(result)).into_response())
+        }
+        (ProofFormat::Binary, Some((root, proof))) => {
+            let body = proof_format::encode_binary(root, proof);
+            Ok((
+                result.to_response_code(),
+                response_headers,
+                [(CONTENT_TYPE, proof_format::BINARY_CONTENT_TYPE)],
+                body,
+            )
+                .into_response())
+        }
+        (format, Some((_, proof))) => {
+            let proof = format.encode_json(proof).map_err(anyhow::Error::from)?;
+            let mut body = serde_json::to_value(&result).map_err(anyhow::Error::from)?;
+            body["proof"] = proof;
+            Ok((result.to_response_code(), response_headers, Json(body)).into_response())
+        }
+    }
+}
+
+/// Whether the `If-None-Match` headers of a request list `etag`. Tags are
+/// compared weakly, as a proof is the same whatever its encoding.
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| {
+            let tag = tag.trim();
+            tag.strip_prefix("W/").unwrap_or(tag)
+        })
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+async fn batch(
+    State(app): State<Arc<App>>,
+    Path(root): Path<Hash>,
+) -> Result<(StatusCode, Json<BatchResponse>), Error> {
+    let result = app.batch(&root).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn cancel_batch(
+    State(app): State<Arc<App>>,
+    Path(batch_id): Path<Hash>,
+) -> Result<(StatusCode, Json<CancelBatchResponse>), Error> {
+    let result = app.cancel_batch(&batch_id).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn latest_root(
+    State(app): State<Arc<App>>,
+    Query(query): Query<LatestRootQuery>,
+) -> Result<
+    (
+        StatusCode,
+        [(HeaderName, &'static str); 1],
+        Json<LatestRootResponse>,
+    ),
+    Error,
+> {
+    let result = app.latest_root(query.status)?;
+
+    Ok((
+        result.to_response_code(),
+        [(CACHE_CONTROL, LATEST_ROOT_CACHE_CONTROL)],
+        Json(result),
+    ))
+}
+
+async fn sync(
+    State(app): State<Arc<App>>,
+    Query(query): Query<SyncQuery>,
+) -> Result<(StatusCode, Json<SyncResponse>), Error> {
+    let result = app
+        .sync(query.since_leaf, query.since_root, query.limit)
+        .await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn root(
+    State(app): State<Arc<App>>,
+    Path(root): Path<Hash>,
+) -> Result<(StatusCode, Json<RootResponse>), Error> {
+    let result = app.root(&root).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn proof_bundle(
+    State(app): State<Arc<App>>,
+    Path(commitment): Path<Hash>,
+) -> Result<(StatusCode, Json<ProofBundleResponse>), Error> {
+    let result = app.proof_bundle(&commitment).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn public_key(
+    State(app): State<Arc<App>>,
+) -> Result<(StatusCode, Json<PublicKeyResponse>), Error> {
+    let result = app.public_key()?;
+
     Ok((result.to_response_code(), Json(result)))
 }
 
 async fn insert_identity(
     State(app): State<Arc<App>>,
+    request_id: Option<Extension<RequestId>>,
     Json(insert_identity_request): Json<InsertCommitmentRequest>,
-) -> Result<(), Error> {
-    app.insert_identity(insert_identity_request.identity_commitment)
+) -> Result<(StatusCode, Json<InsertIdentityResponse>), Error> {
+    let request_id = request_id.map(|Extension(RequestId(request_id))| request_id);
+
+    let result = app
+        .insert_identity(
+            insert_identity_request.identity_commitment,
+            request_id.as_deref(),
+        )
         .await?;
 
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn reserve_identity(
+    State(app): State<Arc<App>>,
+    Json(req): Json<ReserveIdentityRequest>,
+) -> Result<(StatusCode, Json<ReserveIdentityResponse>), Error> {
+    let result = app
+        .reserve_identity(req.identity_commitment, req.ttl_seconds)
+        .await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn confirm_identity(
+    State(app): State<Arc<App>>,
+    request_id: Option<Extension<RequestId>>,
+    Json(req): Json<InsertCommitmentRequest>,
+) -> Result<(StatusCode, Json<InsertIdentityResponse>), Error> {
+    let request_id = request_id.map(|Extension(RequestId(request_id))| request_id);
+
+    let result = app
+        .confirm_identity(req.identity_commitment, request_id.as_deref())
+        .await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn batching_pause(
+    State(app): State<Arc<App>>,
+) -> Result<(StatusCode, Json<BatchingPauseResponse>), Error> {
+    let result = app.batching_pause().await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+/// The request body is optional.
+async fn pause_batching(
+    State(app): State<Arc<App>>,
+    req: Option<Json<PauseBatchingRequest>>,
+) -> Result<(StatusCode, Json<BatchingPauseResponse>), Error> {
+    let reason = req.and_then(|Json(req)| req.reason);
+    let result = app.pause_batching(reason).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn resume_batching(
+    State(app): State<Arc<App>>,
+) -> Result<(StatusCode, Json<BatchingPauseResponse>), Error> {
+    let result = app.resume_batching().await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn ban_commitment(
+    State(app): State<Arc<App>>,
+    Json(req): Json<BanCommitmentRequest>,
+) -> Result<(StatusCode, Json<BanCommitmentResponse>), Error> {
+    let result = app
+        .ban_commitment(req.identity_commitment, req.reason, req.queue_deletion)
+        .await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn unban_commitment(
+    State(app): State<Arc<App>>,
+    Json(req): Json<InsertCommitmentRequest>,
+) -> Result<(), Error> {
+    app.unban_commitment(req.identity_commitment).await?;
+
     Ok(())
 }
 
+async fn audit_log(
+    State(app): State<Arc<App>>,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<(StatusCode, Json<AuditLogResponse>), Error> {
+    let result = app.audit_log(query.before, query.limit).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn quotas(
+    State(app): State<Arc<App>>,
+    Query(query): Query<QuotasQuery>,
+) -> Result<(StatusCode, Json<QuotasResponse>), Error> {
+    let result = app.quotas(query.api_key.as_deref()).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn set_quota(
+    State(app): State<Arc<App>>,
+    Json(req): Json<SetQuotaRequest>,
+) -> Result<(StatusCode, Json<QuotasResponse>), Error> {
+    let result = app
+        .set_quota(
+            &req.api_key,
+            req.daily_limit,
+            req.monthly_limit,
+            req.reset_usage,
+        )
+        .await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn slo_report(
+    State(app): State<Arc<App>>,
+    Query(query): Query<SloQuery>,
+) -> Result<(StatusCode, Json<SloReportResponse>), Error> {
+    let result = app.slo_report(query.window_seconds).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn costs(
+    State(app): State<Arc<App>>,
+    Query(query): Query<CostsQuery>,
+) -> Result<(StatusCode, Json<CostsResponse>), Error> {
+    let result = app.costs(query.window_seconds).await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
 async fn verify_semaphore_proof(
     State(app): State<Arc<App>>,
     Query(verify_semaphore_proof_query): Query<VerifySemaphoreProofQuery>,
@@ -150,14 +625,97 @@ async fn add_batch_size(
 
     Ok(())
 }
+
 async fn remove_batch_size(
     State(app): State<Arc<App>>,
     Json(req): Json<RemoveBatchSizeRequest>,
 ) -> Result<(), Error> {
-    app.remove_batch_size(req.batch_size).await?;
+    app.remove_batch_size(req.batch_size, req.url).await?;
+
+    Ok(())
+}
+
+async fn batch_timeout(
+    State(app): State<Arc<App>>,
+) -> Result<(StatusCode, Json<BatchTimeoutResponse>), Error> {
+    let result = app.batch_timeout();
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn scheduled_jobs(
+    State(app): State<Arc<App>>,
+) -> Result<(StatusCode, Json<ScheduledJobsResponse>), Error> {
+    let result = app.scheduled_jobs();
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn tasks(State(app): State<Arc<App>>) -> Result<(StatusCode, Json<TasksResponse>), Error> {
+    let result = app.tasks();
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn set_batch_timeout(
+    State(app): State<Arc<App>>,
+    Json(req): Json<SetBatchTimeoutRequest>,
+) -> Result<(), Error> {
+    app.set_batch_timeout(req.batch_timeout_seconds)?;
 
     Ok(())
 }
+
+async fn reload_config(
+    State(app): State<Arc<App>>,
+) -> Result<(StatusCode, Json<ReloadConfigResponse>), Error> {
+    let result = app.reload_config().await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn dead_letter_events(
+    State(app): State<Arc<App>>,
+) -> Result<(StatusCode, Json<DeadLetterEventsResponse>), Error> {
+    let result = app.dead_letter_events().await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn redrive_dead_letter_events(
+    State(app): State<Arc<App>>,
+) -> Result<(StatusCode, Json<RedriveEventsResponse>), Error> {
+    let result = app.redrive_dead_letter_events().await?;
+
+    Ok((result.to_response_code(), Json(result)))
+}
+
+async fn ready(State(app): State<Arc<App>>) -> Result<(), Error> {
+    app.readiness()?;
+
+    Ok(())
+}
+
+async fn exemplars() -> ([(HeaderName, &'static str); 1], String) {
+    (
+        [(CONTENT_TYPE, exemplars::OPENMETRICS_CONTENT_TYPE)],
+        exemplars::encode(),
+    )
+}
+
+async fn metrics(
+    State(app): State<Arc<App>>,
+) -> Result<([(HeaderName, &'static str); 1], String), Error> {
+    app.update_metrics().await?;
+
+    let encoder = TextEncoder::new();
+    let metrics = encoder
+        .encode_to_string(&prometheus::gather())
+        .map_err(anyhow::Error::from)?;
+
+    Ok(([(CONTENT_TYPE, prometheus::TEXT_FORMAT)], metrics))
+}
+
 async fn list_batch_sizes(
     State(app): State<Arc<App>>,
 ) -> Result<(StatusCode, Json<ListBatchSizesResponse>), Error> {
@@ -170,7 +728,11 @@ async fn list_batch_sizes(
 /// Will return `Err` if `options.server` URI is not http, incorrectly includes
 /// a path beyond `/`, or cannot be cast into an IP address. Also returns an
 /// `Err` if the server cannot bind to the given address.
-pub async fn main(app: Arc<App>, options: Options) -> AnyhowResult<()> {
+pub async fn main(
+    app: Arc<App>,
+    groups: BTreeMap<u64, Arc<App>>,
+    options: Options,
+) -> AnyhowResult<()> {
     ensure!(
         options.server.scheme() == "http",
         "Only http:// is supported in {}",
@@ -194,8 +756,11 @@ pub async fn main(app: Arc<App>, options: Options) -> AnyhowResult<()> {
     info!("Will listen on {}", addr);
     let listener = TcpListener::bind(addr)?;
 
-    let serve_timeout = Duration::from_secs(options.serve_timeout);
-    bind_from_listener(app, serve_timeout, listener).await?;
+    let timeouts = Timeouts::new(
+        Duration::from_secs(options.serve_timeout),
+        options.endpoint_timeouts,
+    );
+    bind_groups_from_listener(app, groups, timeouts, listener).await?;
 
     Ok(())
 }
@@ -209,33 +774,105 @@ pub async fn bind_from_listener(
     serve_timeout: Duration,
     listener: TcpListener,
 ) -> AnyhowResult<()> {
-    let router = Router::new()
-        .route("/verifySemaphoreProof", post(verify_semaphore_proof))
-        .route("/inclusionProof", post(inclusion_proof))
-        .route("/insertIdentity", post(insert_identity))
-        .route("/addBatchSize", post(add_batch_size))
-        .route("/removeBatchSize", post(remove_batch_size))
-        .route("/listBatchSizes", get(list_batch_sizes))
+    let timeouts = Timeouts::new(serve_timeout, Vec::new());
+    bind_groups_from_listener(app, BTreeMap::new(), timeouts, listener).await
+}
+
+/// Serves `app` at the root and the app of every group in `groups` under
+/// `/groups/<group id>/`.
+///
+/// # Errors
+///
+/// Will return `Err` if the provided `listener` address cannot be accessed or
+/// if the server fails to bind to the given address.
+pub async fn bind_groups_from_listener(
+    app: Arc<App>,
+    groups: BTreeMap<u64, Arc<App>>,
+    timeouts: Timeouts,
+    listener: TcpListener,
+) -> AnyhowResult<()> {
+    let mut router = group_router(app)
+        .route("/groups/:group_id/*path", any(unknown_group))
+        .route("/groups/:group_id", any(unknown_group));
+    for (group_id, app) in groups {
+        router = router.nest(&format!("/groups/{group_id}"), group_router(app));
+    }
+
+    let router = router
         .layer(middleware::from_fn(
             custom_middleware::api_metrics_layer::middleware,
         ))
         .layer(middleware::from_fn_with_state(
-            serve_timeout,
+            Arc::new(timeouts),
             custom_middleware::timeout_layer::middleware,
         ))
         .layer(middleware::from_fn(
             custom_middleware::logging_layer::middleware,
         ))
         .layer(middleware::from_fn(
-            custom_middleware::remove_auth_layer::middleware,
+            custom_middleware::request_id_layer::middleware,
         ))
-        .with_state(app.clone());
+        .layer(middleware::from_fn(
+            custom_middleware::remove_auth_layer::middleware,
+        ));
 
     let server = axum::Server::from_tcp(listener)?
-        .serve(router.into_make_service())
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(await_shutdown());
 
     server.await?;
 
     Ok(())
 }
+
+async fn unknown_group() -> Error {
+    Error::InvalidGroupId
+}
+
+/// The API of a single tree. Calls are audited in the database of the tree.
+fn group_router(app: Arc<App>) -> Router {
+    Router::new()
+        .route("/verifySemaphoreProof", post(verify_semaphore_proof))
+        .route("/inclusionProof", post(inclusion_proof))
+        .route("/identityStatus", post(identity_status))
+        .route("/proofBundle/:commitment", get(proof_bundle))
+        .route("/publicKey", get(public_key))
+        .route("/insertIdentity", post(insert_identity))
+        .route("/reserveIdentity", post(reserve_identity))
+        .route("/confirmIdentity", post(confirm_identity))
+        .route("/addBatchSize", post(add_batch_size))
+        .route("/removeBatchSize", post(remove_batch_size))
+        .route("/listBatchSizes", get(list_batch_sizes))
+        .route("/batchTimeout", get(batch_timeout))
+        .route("/setBatchTimeout", post(set_batch_timeout))
+        .route("/scheduledJobs", get(scheduled_jobs))
+        .route("/batches/:root", get(batch))
+        .route("/admin/cancelBatch/:batch_id", post(cancel_batch))
+        .route("/admin/pause", get(batching_pause).post(pause_batching))
+        .route("/admin/resume", post(resume_batching))
+        .route("/admin/banCommitment", post(ban_commitment))
+        .route("/admin/unbanCommitment", post(unban_commitment))
+        .route("/admin/audit", get(audit_log))
+        .route("/admin/slo", get(slo_report))
+        .route("/admin/tasks", get(tasks))
+        .route("/admin/quotas", get(quotas).post(set_quota))
+        .route("/admin/costs", get(costs))
+        .route("/admin/config/reload", post(reload_config))
+        .route("/roots/:root", get(root))
+        .route("/latestRoot", get(latest_root))
+        .route("/sync", get(sync))
+        .route("/deadLetterEvents", get(dead_letter_events))
+        .route("/redriveDeadLetterEvents", post(redrive_dead_letter_events))
+        .route("/ready", get(ready))
+        .route("/metrics", get(metrics))
+        .route("/metrics/exemplars", get(exemplars))
+        .layer(middleware::from_fn_with_state(
+            app.clone(),
+            custom_middleware::quota_layer::middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app.clone(),
+            custom_middleware::audit_layer::middleware,
+        ))
+        .with_state(app)
+}
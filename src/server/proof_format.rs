@@ -0,0 +1,186 @@
+//! Encodings of the Merkle proof in `/inclusionProof` responses, so that
+//! smart contracts and mobile clients can consume proofs without transforming
+//! them first.
+use hyper::header::ACCEPT;
+use hyper::HeaderMap;
+use semaphore::merkle_tree::Branch;
+use semaphore::poseidon_tree::Proof;
+use semaphore::Field;
+use serde::{Deserialize, Serialize};
+
+/// The media type of [`ProofFormat::Binary`].
+pub const BINARY_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// How the `proof` of `/inclusionProof` is encoded. Chosen with the `format`
+/// query parameter, or with an `Accept: application/octet-stream` header for
+/// [`ProofFormat::Binary`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProofFormat {
+    /// `[{"Left": sibling}, {"Right": sibling}, ...]` from the leaf up, where
+    /// `Left` means the node on the path is the left child.
+    #[default]
+    Branches,
+    /// `{"siblings": [...], "pathBits": n}`, the siblings from the leaf up,
+    /// and bit `i` of `pathBits` set if the node on the path at level `i` is
+    /// the right child, i.e. the leaf index.
+    Flat,
+    /// The path bits as 8 big-endian bytes followed by the siblings as 32
+    /// big-endian bytes each, as a `0x` prefixed hex string.
+    Packed,
+    /// The root followed by the packed proof as the raw response body.
+    Binary,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FlatProof {
+    siblings:  Vec<Field>,
+    path_bits: u64,
+}
+
+impl ProofFormat {
+    /// The format asked for by the query parameter, or by the `Accept`
+    /// header.
+    #[must_use]
+    pub fn negotiate(query: Option<Self>, headers: &HeaderMap) -> Self {
+        query.unwrap_or_else(|| {
+            let accepts_binary = headers
+                .get_all(ACCEPT)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .any(|media_type| {
+                    media_type.split(';').next().map(str::trim) == Some(BINARY_CONTENT_TYPE)
+                });
+
+            if accepts_binary {
+                Self::Binary
+            } else {
+                Self::Branches
+            }
+        })
+    }
+
+    /// Distinguishes the representation in the `ETag`.
+    #[must_use]
+    pub const fn etag_suffix(self) -> &'static str {
+        match self {
+            Self::Branches => "",
+            Self::Flat => "-flat",
+            Self::Packed => "-packed",
+            Self::Binary => "-binary",
+        }
+    }
+
+    /// The proof as a JSON value in this format. [`ProofFormat::Binary`] is
+    /// encoded like [`ProofFormat::Packed`] for responses that are JSON
+    /// anyway.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the proof can't be serialized.
+    pub fn encode_json(self, proof: &Proof) -> serde_json::Result<serde_json::Value> {
+        match self {
+            Self::Branches => serde_json::to_value(proof),
+            Self::Flat => serde_json::to_value(FlatProof {
+                siblings:  proof.0.iter().map(sibling).collect(),
+                path_bits: path_bits(proof),
+            }),
+            Self::Packed | Self::Binary => Ok(format!("0x{}", hex::encode(pack(proof))).into()),
+        }
+    }
+}
+
+/// The root followed by the packed proof, see [`ProofFormat::Binary`].
+#[must_use]
+pub fn encode_binary(root: &Field, proof: &Proof) -> Vec<u8> {
+    let mut bytes = root.to_be_bytes::<32>().to_vec();
+    bytes.extend(pack(proof));
+    bytes
+}
+
+fn pack(proof: &Proof) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + 32 * proof.0.len());
+    bytes.extend_from_slice(&path_bits(proof).to_be_bytes());
+    for branch in &proof.0 {
+        bytes.extend_from_slice(&sibling(branch).to_be_bytes::<32>());
+    }
+    bytes
+}
+
+const fn sibling(branch: &Branch<Field>) -> Field {
+    match branch {
+        Branch::Left(sibling) | Branch::Right(sibling) => *sibling,
+    }
+}
+
+fn path_bits(proof: &Proof) -> u64 {
+    proof
+        .0
+        .iter()
+        .enumerate()
+        .filter(|(_, branch)| matches!(branch, Branch::Right(_)))
+        .fold(0, |bits, (level, _)| bits | (1 << level))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header::HeaderValue;
+    use serde_json::json;
+
+    use super::*;
+
+    fn proof() -> Proof {
+        Proof(vec![
+            Branch::Right(Field::from(1_u64)),
+            Branch::Left(Field::from(2_u64)),
+            Branch::Right(Field::from(3_u64)),
+        ])
+    }
+
+    #[test]
+    fn flat_proofs_have_the_leaf_index_as_path_bits() {
+        assert_eq!(
+            ProofFormat::Flat.encode_json(&proof()).unwrap(),
+            json!({
+                "siblings": [Field::from(1_u64), Field::from(2_u64), Field::from(3_u64)],
+                "pathBits": 0b101,
+            })
+        );
+    }
+
+    #[test]
+    fn packed_proofs_are_path_bits_and_siblings() {
+        let packed = ProofFormat::Packed.encode_json(&proof()).unwrap();
+        let bytes = hex::decode(packed.as_str().unwrap().strip_prefix("0x").unwrap()).unwrap();
+
+        assert_eq!(bytes.len(), 8 + 3 * 32);
+        assert_eq!(bytes[..8], 5_u64.to_be_bytes());
+        assert_eq!(bytes[8 + 31], 1);
+        assert_eq!(bytes[8 + 32 + 31], 2);
+
+        let binary = encode_binary(&Field::from(7_u64), &proof());
+        assert_eq!(binary[31], 7);
+        assert_eq!(binary[32..], bytes);
+    }
+
+    #[test]
+    fn binary_is_negotiated_with_the_accept_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            ProofFormat::negotiate(None, &headers),
+            ProofFormat::Branches
+        );
+
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("application/json, application/octet-stream;q=0.5"),
+        );
+        assert_eq!(ProofFormat::negotiate(None, &headers), ProofFormat::Binary);
+        assert_eq!(
+            ProofFormat::negotiate(Some(ProofFormat::Flat), &headers),
+            ProofFormat::Flat
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Snapshots of the leaves of the mined tree, so that a restart only reads the
+//! leaves mined after the last snapshot from the database.
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Result as AnyhowResult};
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge, IntGauge};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+use crate::identity_tree::{Hash, TreeState, TreeVersionReadOps};
+
+/// Identifies snapshot files and their format.
+const MAGIC: &[u8; 8] = b"SEQSNAP1";
+
+/// The number of leaves read from the tree while holding its lock.
+const LEAVES_PER_LOCK: usize = 10_000;
+
+static SNAPSHOT_LEAVES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "tree_snapshot_leaves",
+        "Number of leaves in the last tree snapshot written"
+    )
+    .unwrap()
+});
+
+/// The leaves of the mined tree up to its next free leaf, and the root they
+/// result in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub root:   Hash,
+    pub leaves: Vec<Hash>,
+}
+
+impl Snapshot {
+    /// Reads the snapshot at `path`, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be read or isn't a snapshot.
+    pub fn read(path: &Path) -> AnyhowResult<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Self::decode(&fs::read(path)?).map(Some)
+    }
+
+    /// Replaces the snapshot at `path`. The snapshot is written next to it
+    /// first, so that a crash doesn't leave a partial snapshot behind.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the file can't be written.
+    pub fn write(&self, path: &Path) -> AnyhowResult<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+
+        let mut file = fs::File::create(&partial)?;
+        file.write_all(&self.encode())?;
+        file.sync_all()?;
+
+        fs::rename(&partial, path)?;
+
+        Ok(())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + 8 + 32 * (self.leaves.len() + 1));
+
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&(self.leaves.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&self.root.to_be_bytes::<32>());
+        for leaf in &self.leaves {
+            bytes.extend_from_slice(&leaf.to_be_bytes::<32>());
+        }
+
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> AnyhowResult<Self> {
+        let Some(bytes) = bytes.strip_prefix(MAGIC) else {
+            bail!("not a tree snapshot");
+        };
+        ensure!(bytes.len() >= 40, "truncated tree snapshot");
+
+        let (count, bytes) = bytes.split_at(8);
+        let count = u64::from_be_bytes(count.try_into()?);
+        ensure!(
+            count.checked_add(1).and_then(|count| count.checked_mul(32))
+                == Some(bytes.len() as u64),
+            "tree snapshot of {count} leaves has {} bytes",
+            bytes.len()
+        );
+
+        let mut hashes = bytes.chunks_exact(32).map(Hash::try_from_be_slice);
+        let root = hashes.next().flatten();
+        let leaves = hashes.collect::<Option<Vec<_>>>();
+
+        let (Some(root), Some(leaves)) = (root, leaves) else {
+            bail!("tree snapshot contains values out of range");
+        };
+
+        Ok(Self { root, leaves })
+    }
+}
+
+/// Writes a snapshot of the mined tree every interval, if it changed.
+pub struct Snapshotter {
+    tree_state: TreeState,
+    path:       PathBuf,
+    interval:   Duration,
+}
+
+impl Snapshotter {
+    pub fn new(tree_state: TreeState, path: PathBuf, interval: Duration) -> Self {
+        Self {
+            tree_state,
+            path,
+            interval,
+        }
+    }
+
+    pub async fn run(self) {
+        info!(path = %self.path.display(), "Writing tree snapshots");
+
+        let mut last_root = None;
+
+        loop {
+            sleep(self.interval).await;
+
+            let root = self.tree_state.get_mined_tree().get_root();
+            if last_root == Some(root) {
+                continue;
+            }
+
+            let tree_state = self.tree_state.clone();
+            let path = self.path.clone();
+            let result = tokio::task::spawn_blocking(move || -> AnyhowResult<Option<Snapshot>> {
+                let Some(snapshot) = take(&tree_state) else {
+                    // The tree changed while it was read, try again next time.
+                    return Ok(None);
+                };
+
+                snapshot.write(&path)?;
+
+                Ok(Some(snapshot))
+            })
+            .await;
+
+            match result {
+                Ok(Ok(Some(snapshot))) => {
+                    let leaf_count = snapshot.leaves.len();
+                    SNAPSHOT_LEAVES.set(leaf_count.try_into().unwrap_or(i64::MAX));
+                    info!(root = ?snapshot.root, leaf_count, "Wrote a tree snapshot");
+
+                    last_root = Some(snapshot.root);
+                }
+                Ok(Ok(None)) => {}
+                Ok(Err(error)) => error!(?error, "Failed to write a tree snapshot"),
+                Err(error) => error!(?error, "Tree snapshot task failed"),
+            }
+        }
+    }
+}
+
+/// Reads the leaves of the mined tree a chunk at a time, so that the tree isn't
+/// locked for long. Returns `None` if the tree changed meanwhile.
+fn take(tree_state: &TreeState) -> Option<Snapshot> {
+    let mined_tree = tree_state.get_mined_tree();
+
+    let root = mined_tree.get_root();
+    let next_leaf = mined_tree.next_leaf();
+
+    let mut leaves = Vec::with_capacity(next_leaf);
+    for start in (0..next_leaf).step_by(LEAVES_PER_LOCK) {
+        let end = (start + LEAVES_PER_LOCK).min(next_leaf);
+        leaves.extend(mined_tree.get_leaves(start..end));
+    }
+
+    (mined_tree.get_root() == root).then_some(Snapshot { root, leaves })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_survive_a_round_trip() {
+        let snapshot = Snapshot {
+            root:   Hash::from(42),
+            leaves: vec![Hash::from(1), Hash::ZERO, Hash::from(3)],
+        };
+
+        assert_eq!(Snapshot::decode(&snapshot.encode()).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn rejects_truncated_snapshots() {
+        let snapshot = Snapshot {
+            root:   Hash::from(42),
+            leaves: vec![Hash::from(1), Hash::from(2)],
+        };
+        let bytes = snapshot.encode();
+
+        assert!(Snapshot::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Snapshot::decode(&bytes[1..]).is_err());
+    }
+}
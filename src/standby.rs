@@ -0,0 +1,223 @@
+//! Keeps the database of a standby sequencer, e.g. in another region, a copy
+//! of the tree and the insertion queue of the primary, so that the standby can
+//! take over batching if the primary is lost. The standby tails a logical
+//! replication slot in the primary database and applies the changes to its
+//! own database, which its observer then builds the tree from.
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use clap::Parser;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter, IntCounter};
+use tokio::time::sleep;
+use tracing::{error, info};
+
+use crate::database::replication::{Change, IdentityChange};
+use crate::database::{self, Database};
+use crate::identity_tree::{Hash, ProcessedStatus};
+use crate::secret::SecretUrl;
+
+/// Maximum number of changes read from the replication slot at once.
+const MAX_CHANGES: usize = 10_000;
+
+static MIRRORED_CHANGES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "standby_mirrored_changes",
+        "Number of changes to the tree and the queue mirrored from the primary"
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct Options {
+    /// Run as the standby of the sequencer using this database: mirror its
+    /// tree and insertion queue into our own database through a logical
+    /// replication slot, while serving reads as an observer. Requires
+    /// `--mode observer` and `wal_level = logical` on the primary.
+    #[clap(long, env)]
+    pub standby_primary_database: Option<SecretUrl>,
+
+    /// The logical replication slot in the primary database that the standby
+    /// reads from. It is created if it doesn't exist, and holds on to the
+    /// changes the standby hasn't applied yet.
+    #[clap(long, env, default_value = "sequencer_standby")]
+    pub standby_replication_slot: String,
+
+    /// How often to poll the replication slot when there are no changes
+    /// (milliseconds)
+    #[clap(long, env, default_value = "500")]
+    pub standby_poll_interval_ms: u64,
+}
+
+/// A status change of the tree, waiting to be applied.
+struct Marking {
+    status:     ProcessedStatus,
+    leaf_index: usize,
+    root:       Hash,
+}
+
+pub struct Standby {
+    primary:       Database,
+    database:      Arc<Database>,
+    slot:          String,
+    poll_interval: Duration,
+}
+
+impl Standby {
+    /// Connects to the primary database and creates the replication slot.
+    /// Returns `None` if this instance isn't a standby.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the primary database can't be reached or its
+    /// schema is of another version.
+    pub async fn new(
+        database: Arc<Database>,
+        database_options: &database::Options,
+        options: &Options,
+    ) -> AnyhowResult<Option<Self>> {
+        let Some(url) = &options.standby_primary_database else {
+            return Ok(None);
+        };
+
+        // The schema of the primary is left to the primary.
+        let primary = Database::new(database::Options {
+            database: url.clone(),
+            database_read: None,
+            database_migrate: false,
+            database_password_file: None,
+            ..database_options.clone()
+        })
+        .await?;
+        primary
+            .create_replication_slot(&options.standby_replication_slot)
+            .await?;
+
+        Ok(Some(Self {
+            primary,
+            database,
+            slot: options.standby_replication_slot.clone(),
+            poll_interval: Duration::from_millis(options.standby_poll_interval_ms),
+        }))
+    }
+
+    pub async fn run(self) {
+        info!(slot = self.slot, "Mirroring the primary sequencer");
+
+        loop {
+            if let Err(error) = self.mirror().await {
+                error!(
+                    ?error,
+                    slot = self.slot,
+                    "Failed to mirror the primary sequencer"
+                );
+                sleep(self.poll_interval).await;
+            }
+        }
+    }
+
+    async fn mirror(&self) -> AnyhowResult<()> {
+        loop {
+            let (changes, lsn) = self
+                .primary
+                .peek_replicated_changes(&self.slot, MAX_CHANGES)
+                .await?;
+
+            let Some(lsn) = lsn else {
+                sleep(self.poll_interval).await;
+                continue;
+            };
+
+            let count = changes.len();
+            self.apply(changes).await?;
+            MIRRORED_CHANGES.inc_by(count as u64);
+
+            // Only consumed once applied, so that nothing is lost if applying
+            // fails. Changes may be applied again after a restart, which is a
+            // no-op.
+            self.primary
+                .advance_replication_slot(&self.slot, &lsn)
+                .await?;
+        }
+    }
+
+    /// Applies the changes in order. Consecutive status changes to the same
+    /// status are applied at once, as marking a root marks all roots before
+    /// it, and returning a leaf to pending returns all leaves after it.
+    async fn apply(&self, changes: Vec<Change>) -> AnyhowResult<()> {
+        let mut marking: Option<Marking> = None;
+
+        for change in changes {
+            match change {
+                Change::Identity(IdentityChange::StatusChanged {
+                    leaf_index,
+                    root,
+                    status,
+                }) => match &mut marking {
+                    Some(pending) if pending.status == status => {
+                        let supersedes = if status == ProcessedStatus::Pending {
+                            leaf_index < pending.leaf_index
+                        } else {
+                            leaf_index > pending.leaf_index
+                        };
+                        if supersedes {
+                            pending.leaf_index = leaf_index;
+                            pending.root = root;
+                        }
+                    }
+                    _ => {
+                        self.flush(marking.take()).await?;
+                        marking = Some(Marking {
+                            status,
+                            leaf_index,
+                            root,
+                        });
+                    }
+                },
+                Change::Identity(IdentityChange::Inserted {
+                    leaf_index,
+                    commitment,
+                    root,
+                }) => {
+                    self.flush(marking.take()).await?;
+                    self.database
+                        .insert_pending_identity(leaf_index, &commitment, &root)
+                        .await?;
+                    self.database
+                        .remove_unprocessed_identity(&commitment)
+                        .await?;
+                }
+                Change::Queued(queued) => {
+                    self.flush(marking.take()).await?;
+                    self.database
+                        .insert_mirrored_identity(&queued.commitment, queued.request_id.as_deref())
+                        .await?;
+                }
+            }
+        }
+
+        self.flush(marking).await
+    }
+
+    async fn flush(&self, marking: Option<Marking>) -> AnyhowResult<()> {
+        let Some(marking) = marking else {
+            return Ok(());
+        };
+
+        match marking.status {
+            ProcessedStatus::Pending => {
+                self.database
+                    .reset_identities_from(marking.leaf_index)
+                    .await?;
+            }
+            ProcessedStatus::Processed => {
+                self.database.mark_root_as_processed(&marking.root).await?;
+            }
+            ProcessedStatus::Mined => self.database.mark_root_as_mined(&marking.root).await?,
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,477 @@
+//! Exports the identities and batches of a database to a file and imports them
+//! into a fresh database, e.g. to migrate between database clusters. Imports
+//! are checked against the tree before anything is written.
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result as AnyhowResult};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use semaphore::Field;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::database::types::{Batch, BatchStatus, IdentityRecord};
+use crate::database::{self, Database};
+use crate::identity_tree::{dense_prefix_depth_for, CanonicalTreeBuilder, Hash, TreeUpdate};
+
+/// The version of the state file format. Files of other versions are refused.
+const FORMAT_VERSION: u32 = 1;
+
+/// The number of identities read from or written to the database at once.
+const PAGE_SIZE: usize = 10_000;
+
+/// The depth of the dense prefix of the tree used to check the roots.
+const DENSE_PREFIX_DEPTH: usize = 20;
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct ExportOptions {
+    /// The database to export. It is never migrated.
+    #[clap(flatten)]
+    pub database: database::Options,
+
+    /// The file the state is written to, as gzipped JSON lines
+    #[clap(long, env)]
+    pub state_file: PathBuf,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct ImportOptions {
+    /// The database to import into. It must not have any identities or
+    /// batches yet.
+    #[clap(flatten)]
+    pub database: database::Options,
+
+    /// The file the state is read from, as written by `state export`
+    #[clap(long, env)]
+    pub state_file: PathBuf,
+
+    /// The depth of the tree the roots in the file are checked against
+    #[clap(long, env, default_value = "10")]
+    pub tree_depth: usize,
+
+    /// Initial value of the Merkle tree leaves
+    #[clap(
+        long,
+        env,
+        default_value = "0000000000000000000000000000000000000000000000000000000000000000"
+    )]
+    pub initial_leaf_value: Field,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Parser)]
+#[group(skip)]
+pub struct VerifyOptions {
+    /// The database to check. It is never migrated.
+    #[clap(flatten)]
+    pub database: database::Options,
+
+    /// The depth of the tree the roots are checked against
+    #[clap(long, env, default_value = "10")]
+    pub tree_depth: usize,
+
+    /// Initial value of the Merkle tree leaves
+    #[clap(
+        long,
+        env,
+        default_value = "0000000000000000000000000000000000000000000000000000000000000000"
+    )]
+    pub initial_leaf_value: Field,
+}
+
+/// A line of a state file. Files start with a header, followed by the
+/// identities in leaf order and the batches, and end with a trailer that
+/// counts them, so that truncated files are detected.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Record {
+    #[serde(rename_all = "camelCase")]
+    Header {
+        format_version: u32,
+        exported_at:    DateTime<Utc>,
+    },
+    Identity(IdentityRecord),
+    Batch(Batch),
+    End {
+        identities: usize,
+        batches:    usize,
+    },
+}
+
+/// Writes the identities and batches of the database to the state file.
+///
+/// # Errors
+///
+/// Will return `Err` if the database can't be read or the file can't be
+/// written.
+pub async fn export(options: ExportOptions) -> AnyhowResult<()> {
+    let database = Database::new(database::Options {
+        database_migrate: false,
+        ..options.database
+    })
+    .await?;
+
+    let file = fs::File::create(&options.state_file)
+        .with_context(|| format!("error creating {}", options.state_file.display()))?;
+    let mut writer = GzEncoder::new(BufWriter::new(file), Compression::default());
+
+    write_record(&mut writer, &Record::Header {
+        format_version: FORMAT_VERSION,
+        exported_at:    Utc::now(),
+    })?;
+
+    let mut identities = 0;
+    loop {
+        let page = database.get_identity_records(identities, PAGE_SIZE).await?;
+        if page.is_empty() {
+            break;
+        }
+
+        identities += page.len();
+        for identity in page {
+            write_record(&mut writer, &Record::Identity(identity))?;
+        }
+    }
+
+    let batches = database.get_batches().await?;
+    for batch in &batches {
+        write_record(&mut writer, &Record::Batch(batch.clone()))?;
+    }
+
+    write_record(&mut writer, &Record::End {
+        identities,
+        batches: batches.len(),
+    })?;
+
+    writer
+        .finish()?
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()?;
+
+    info!(
+        path = %options.state_file.display(),
+        identities,
+        batches = batches.len(),
+        "Exported the tree state"
+    );
+
+    Ok(())
+}
+
+/// Checks the state file against the tree and imports it into an empty
+/// database.
+///
+/// # Errors
+///
+/// Will return `Err` if the file is malformed or inconsistent, if the database
+/// isn't empty, or if it can't be written.
+pub async fn import(options: ImportOptions) -> AnyhowResult<()> {
+    // Check the whole file before writing anything, so that a bad file
+    // doesn't leave a partial import behind.
+    let mut checker = Checker::new(options.tree_depth, options.initial_leaf_value);
+    for record in read_records(&options.state_file)? {
+        checker.check(&record?)?;
+    }
+    let (identities, batches) = checker.finish()?;
+
+    let database = Database::new(options.database).await?;
+    ensure!(
+        !database.has_tree_state().await?,
+        "The database already has identities or batches, refusing to import into it"
+    );
+
+    let mut page = Vec::with_capacity(PAGE_SIZE);
+    for record in read_records(&options.state_file)? {
+        match record? {
+            Record::Identity(identity) => {
+                page.push(identity);
+                if page.len() == PAGE_SIZE {
+                    database.insert_identity_records(&page).await?;
+                    page.clear();
+                }
+            }
+            Record::Batch(batch) => database.insert_batch_record(&batch).await?,
+            Record::Header { .. } | Record::End { .. } => {}
+        }
+    }
+    database.insert_identity_records(&page).await?;
+
+    info!(
+        path = %options.state_file.display(),
+        identities,
+        batches,
+        "Imported the tree state"
+    );
+
+    Ok(())
+}
+
+/// Checks the identities and batches of the database like the file of an
+/// import: the leaves have to be contiguous, every root has to follow from the
+/// leaves before it, and every batch has to end at one of those roots.
+///
+/// # Errors
+///
+/// Will return `Err` if the schema of the database isn't up to date, or its
+/// state is inconsistent.
+pub async fn verify(options: VerifyOptions) -> AnyhowResult<()> {
+    let database = Database::new(database::Options {
+        database_migrate: false,
+        ..options.database
+    })
+    .await?;
+
+    let mut checker = Checker::new(options.tree_depth, options.initial_leaf_value);
+    checker.check(&Record::Header {
+        format_version: FORMAT_VERSION,
+        exported_at:    Utc::now(),
+    })?;
+
+    let mut identities = 0;
+    loop {
+        let page = database.get_identity_records(identities, PAGE_SIZE).await?;
+        if page.is_empty() {
+            break;
+        }
+
+        identities += page.len();
+        for identity in page {
+            checker.check(&Record::Identity(identity))?;
+        }
+    }
+
+    let batches = database.get_batches().await?;
+    let batch_count = batches.len();
+    for batch in batches {
+        checker.check(&Record::Batch(batch))?;
+    }
+
+    checker.check(&Record::End {
+        identities,
+        batches: batch_count,
+    })?;
+    let (identities, batches) = checker.finish()?;
+
+    info!(identities, batches, "The database is consistent");
+
+    Ok(())
+}
+
+fn write_record(writer: &mut impl Write, record: &Record) -> AnyhowResult<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+
+    Ok(())
+}
+
+fn read_records(path: &Path) -> AnyhowResult<impl Iterator<Item = AnyhowResult<Record>>> {
+    let file = fs::File::open(path).with_context(|| format!("error opening {}", path.display()))?;
+
+    Ok(BufReader::new(GzDecoder::new(file))
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str(&line?)
+                .with_context(|| format!("malformed record on line {}", index + 1))
+        }))
+}
+
+/// Checks that the records of a state file are complete and that every root
+/// follows from the leaves before it.
+struct Checker {
+    tree:       CanonicalTreeBuilder,
+    /// The root after each leaf.
+    roots:      Vec<Hash>,
+    batches:    usize,
+    has_header: bool,
+    has_end:    bool,
+}
+
+impl Checker {
+    fn new(tree_depth: usize, initial_leaf_value: Field) -> Self {
+        let tree = CanonicalTreeBuilder::new(
+            tree_depth,
+            dense_prefix_depth_for(DENSE_PREFIX_DEPTH, tree_depth, 0),
+            PAGE_SIZE,
+            initial_leaf_value,
+            &[],
+        );
+
+        Self {
+            tree,
+            roots: Vec::new(),
+            batches: 0,
+            has_header: false,
+            has_end: false,
+        }
+    }
+
+    fn check(&mut self, record: &Record) -> AnyhowResult<()> {
+        ensure!(!self.has_end, "records after the end of the state file");
+
+        match record {
+            Record::Header { format_version, .. } => {
+                ensure!(!self.has_header, "duplicate header");
+                ensure!(
+                    *format_version == FORMAT_VERSION,
+                    "unsupported state file version {format_version}, expected {FORMAT_VERSION}"
+                );
+                self.has_header = true;
+            }
+            _ if !self.has_header => bail!("not a state file, the header is missing"),
+            Record::Identity(identity) => {
+                ensure!(
+                    identity.leaf_index == self.roots.len(),
+                    "expected leaf {}, found leaf {}",
+                    self.roots.len(),
+                    identity.leaf_index
+                );
+
+                self.tree.update(&TreeUpdate {
+                    leaf_index: identity.leaf_index,
+                    element:    identity.commitment,
+                });
+                ensure!(
+                    self.tree.root() == identity.root,
+                    "the root of leaf {} doesn't follow from the leaves before it",
+                    identity.leaf_index
+                );
+
+                self.roots.push(identity.root);
+            }
+            Record::Batch(batch) => {
+                // The identities of failed and cancelled batches have been
+                // removed from the tree.
+                let in_tree = !matches!(batch.status, BatchStatus::Failed | BatchStatus::Cancelled);
+                if in_tree {
+                    ensure!(
+                        batch.end_index > 0
+                            && self.roots.get(batch.end_index - 1) == Some(&batch.post_root),
+                        "batch {:?} doesn't end at a root of the tree",
+                        batch.post_root
+                    );
+                }
+
+                self.batches += 1;
+            }
+            Record::End {
+                identities,
+                batches,
+            } => {
+                ensure!(
+                    *identities == self.roots.len() && *batches == self.batches,
+                    "the state file has {} identities and {} batches, but {identities} and \
+                     {batches} were exported",
+                    self.roots.len(),
+                    self.batches
+                );
+                self.has_end = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of identities and batches checked.
+    fn finish(self) -> AnyhowResult<(usize, usize)> {
+        ensure!(self.has_end, "the state file is truncated");
+
+        Ok((self.roots.len(), self.batches))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+
+    use super::*;
+    use crate::identity_tree::ProcessedStatus;
+
+    fn identities(count: usize) -> Vec<IdentityRecord> {
+        let mut tree = LazyPoseidonTree::new(10, Hash::ZERO).derived();
+
+        (0..count)
+            .map(|leaf_index| {
+                let commitment = Hash::from(leaf_index as u64 + 1);
+                tree = tree.update(leaf_index, &commitment);
+
+                IdentityRecord {
+                    leaf_index,
+                    commitment,
+                    root: tree.root(),
+                    status: ProcessedStatus::Mined,
+                    pending_as_of: Utc::now(),
+                    mined_at: None,
+                    received_at: None,
+                }
+            })
+            .collect()
+    }
+
+    fn check(records: impl IntoIterator<Item = Record>) -> AnyhowResult<(usize, usize)> {
+        let mut checker = Checker::new(10, Hash::ZERO);
+        for record in records {
+            checker.check(&record)?;
+        }
+        checker.finish()
+    }
+
+    fn header() -> Record {
+        Record::Header {
+            format_version: FORMAT_VERSION,
+            exported_at:    Utc::now(),
+        }
+    }
+
+    #[test]
+    fn accepts_consistent_state() {
+        let records = std::iter::once(header())
+            .chain(identities(3).into_iter().map(Record::Identity))
+            .chain([Record::End {
+                identities: 3,
+                batches:    0,
+            }]);
+
+        assert_eq!(check(records).unwrap(), (3, 0));
+    }
+
+    #[test]
+    fn rejects_roots_that_dont_follow_from_the_leaves() {
+        let mut identities = identities(3);
+        identities[1].root = identities[2].root;
+
+        let records = std::iter::once(header())
+            .chain(identities.into_iter().map(Record::Identity))
+            .chain([Record::End {
+                identities: 3,
+                batches:    0,
+            }]);
+
+        assert!(check(records).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_state() {
+        let records =
+            std::iter::once(header()).chain(identities(3).into_iter().map(Record::Identity));
+
+        assert!(check(records).is_err());
+    }
+
+    #[test]
+    fn records_survive_a_round_trip() {
+        let record = Record::Identity(identities(1).remove(0));
+
+        let mut bytes = Vec::new();
+        write_record(&mut bytes, &record).unwrap();
+
+        assert_eq!(serde_json::from_slice::<Record>(&bytes).unwrap(), record);
+    }
+}
@@ -1,3 +1,8 @@
+// Most of the tasks, and the state they share, only run when the sequencer
+// submits batches itself.
+#![cfg_attr(not(feature = "submission"), allow(dead_code))]
+
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -6,26 +11,53 @@ use clap::Parser;
 use ethers::types::U256;
 use once_cell::sync::Lazy;
 use prometheus::{linear_buckets, register_gauge, register_histogram, Gauge, Histogram};
-use tokio::sync::{broadcast, Notify, RwLock};
+#[cfg(feature = "submission")]
+use tokio::sync::Notify;
+use tokio::sync::{broadcast, watch, Mutex, RwLock};
 use tokio::task::JoinHandle;
-use tracing::{info, instrument, warn};
-
-use self::tasks::finalize_identities::FinalizeRoots;
-use self::tasks::insert_identities::InsertIdentities;
-use self::tasks::mine_identities::MineIdentities;
-use self::tasks::process_identities::ProcessIdentities;
+use tracing::info;
+#[cfg(feature = "submission")]
+use tracing::{instrument, warn};
+
+use self::batching_policy::{BatchingPolicy, FlushPolicy};
+use self::scheduler::{JobStatus, Scheduler};
+use self::submission_windows::SubmissionWindows;
+use self::supervisor::{RestartPolicy, Supervisor, TaskStatus};
+#[cfg(feature = "submission")]
+use self::tasks::{
+    check_integrity::CheckIntegrity, deliver_events::DeliverEvents,
+    expire_reservations::ExpireReservations, finalize_identities::FinalizeRoots,
+    insert_identities::InsertIdentities, mine_identities::MineIdentities,
+    monitor_balance::MonitorBalance, monitor_provers::MonitorProvers, monitor_roots::MonitorRoots,
+    process_identities::ProcessIdentities, propagate_roots::PropagateRoots,
+};
+use crate::contracts::scanner::FinalizationStrategy;
 use crate::contracts::SharedIdentityManager;
+use crate::database::types::Batch;
 use crate::database::Database;
 use crate::ethereum::write::TransactionId;
-use crate::identity_tree::TreeState;
+use crate::events;
+use crate::identity_tree::{Hash, TreeState};
+#[cfg(feature = "submission")]
 use crate::utils::async_queue::AsyncQueue;
 
+pub mod batching_policy;
+pub mod scheduler;
+pub mod submission_windows;
+pub mod supervisor;
+#[cfg(feature = "submission")]
 pub mod tasks;
 
-const PROCESS_IDENTITIES_BACKOFF: Duration = Duration::from_secs(5);
-const FINALIZE_IDENTITIES_BACKOFF: Duration = Duration::from_secs(5);
-const MINE_IDENTITIES_BACKOFF: Duration = Duration::from_secs(5);
-const INSERT_IDENTITIES_BACKOFF: Duration = Duration::from_secs(5);
+const PROCESS_IDENTITIES_RESTART: RestartPolicy =
+    RestartPolicy::exponential(Duration::from_secs(5), Duration::from_secs(60));
+const FINALIZE_IDENTITIES_RESTART: RestartPolicy =
+    RestartPolicy::exponential(Duration::from_secs(5), Duration::from_secs(300));
+const MINE_IDENTITIES_RESTART: RestartPolicy =
+    RestartPolicy::exponential(Duration::from_secs(5), Duration::from_secs(60));
+const INSERT_IDENTITIES_RESTART: RestartPolicy =
+    RestartPolicy::exponential(Duration::from_secs(5), Duration::from_secs(60));
+const DELIVER_EVENTS_RESTART: RestartPolicy =
+    RestartPolicy::exponential(Duration::from_secs(5), Duration::from_secs(300));
 
 struct RunningInstance {
     handles:         Vec<JoinHandle<()>>,
@@ -44,6 +76,9 @@ static PENDING_IDENTITIES: Lazy<Gauge> = Lazy::new(|| {
     register_gauge!("pending_identities", "Identities not submitted on-chain").unwrap()
 });
 
+static BATCHING_PAUSED: Lazy<Gauge> =
+    Lazy::new(|| register_gauge!("batching_paused", "Whether batching is paused").unwrap());
+
 static UNPROCESSED_IDENTITIES: Lazy<Gauge> = Lazy::new(|| {
     register_gauge!(
         "unprocessed_identities",
@@ -85,10 +120,46 @@ impl RunningInstance {
 #[group(skip)]
 pub struct Options {
     /// The maximum number of seconds the sequencer will wait before sending a
-    /// batch of identities to the chain, even if the batch is not full.
-    #[clap(long, env, default_value = "180")]
+    /// batch of identities to the chain, even if the batch is not full. A
+    /// partially filled batch is padded before it is submitted.
+    ///
+    /// This can be changed at runtime through the `/setBatchTimeout` route.
+    #[clap(long, env, alias = "batch-insertion-timeout", default_value = "180")]
     pub batch_timeout_seconds: u64,
 
+    /// The number of pending identities from which batches are always
+    /// submitted in the largest batch size. Shorter queues are submitted as
+    /// soon as they fill a smaller batch size. With the default of 0 the
+    /// sequencer always waits for the largest batch size to fill up.
+    #[clap(long, env, default_value = "0")]
+    pub batch_deep_queue_threshold: usize,
+
+    /// The gas price (in gwei) above which batches are only submitted once the
+    /// batch timeout expires.
+    #[clap(long, env)]
+    pub batch_max_gas_price_gwei: Option<u64>,
+
+    /// How long identities should wait for their batch to be submitted. When
+    /// waiting for a batch to fill up would take longer at the current arrival
+    /// rate, a smaller batch size is submitted instead.
+    #[clap(long, env)]
+    pub batch_latency_budget_seconds: Option<u64>,
+
+    /// When batches are flushed: `fixed-interval` submits one batch every
+    /// batch timeout, `size-or-timeout` as soon as a batch fills up and
+    /// `cost-aware` also holds batches back while gas is more expensive than
+    /// `--batch-max-gas-price-gwei`. Defaults to `cost-aware` when a maximum
+    /// gas price is set, and `size-or-timeout` otherwise.
+    #[clap(long, env)]
+    pub batch_flush_policy: Option<FlushPolicy>,
+
+    /// The windows of the day (UTC) in which batches are submitted, e.g.
+    /// `01:00-06:00,22:00-23:30`. Outside of them identities are inserted
+    /// into the tree as pending, but not batched, even once the batch timeout
+    /// expires. Batches are submitted at any time if not set.
+    #[clap(long, env)]
+    pub submission_windows: Option<SubmissionWindows>,
+
     /// How many transactions can be sent "at once" to the blockchain via the
     /// write provider.
     #[clap(long, env, default_value = "1")]
@@ -101,6 +172,67 @@ pub struct Options {
     /// The number of seconds to wait between fetching logs
     #[clap(long, env, default_value = "30")]
     pub time_between_scans_seconds: u64,
+
+    /// When a root is final on a chain, and therefore marked as mined once it
+    /// is final on every chain: either a number of confirmations, or
+    /// `finalized` to follow the finalized block of the node.
+    #[clap(long, env, default_value = "0")]
+    pub finalization_strategy: FinalizationStrategy,
+
+    /// The number of seconds between prover health checks. Provers that fail
+    /// a check are skipped until they pass one again.
+    #[clap(long, env, default_value = "30")]
+    pub prover_health_check_interval_seconds: u64,
+
+    /// The number of seconds between comparing the root of the identity
+    /// manager with the processed root.
+    #[clap(long, env, default_value = "30")]
+    pub root_check_interval_seconds: u64,
+
+    /// How long the identity manager root may differ from the processed root
+    /// before batch submissions are stopped. The roots differ briefly whenever
+    /// a batch has been mined but not yet processed.
+    #[clap(long, env, default_value = "600")]
+    pub root_mismatch_grace_period_seconds: u64,
+
+    /// The number of seconds between propagating the latest final root to the
+    /// secondary chains with a state bridge.
+    #[clap(long, env, default_value = "60")]
+    pub root_propagation_interval_seconds: u64,
+
+    /// The number of seconds between checking the balance of the signers.
+    #[clap(long, env, default_value = "60")]
+    pub balance_check_interval_seconds: u64,
+
+    /// The balance (in gwei) below which a signer is reported as running low.
+    #[clap(long, env, default_value = "100000000")]
+    pub signer_balance_warning_gwei: u64,
+
+    /// The balance (in gwei) below which batch submissions are paused, as the
+    /// signer can't be relied on to pay for them. Identities are still
+    /// accepted.
+    #[clap(long, env, default_value = "10000000")]
+    pub signer_balance_minimum_gwei: u64,
+
+    /// The number of seconds between integrity checks, which recompute the
+    /// root of the tree from the leaves in the database and compare it with
+    /// the roots stored with the leaves, the latest tree and the chain.
+    #[clap(long, env, default_value = "3600")]
+    pub integrity_check_interval_seconds: u64,
+
+    /// Pause batching when an integrity check fails, until an operator
+    /// resumes it through `/admin/resume`.
+    #[clap(long, env)]
+    pub integrity_check_halt: bool,
+
+    /// The number of seconds between deleting expired reservations.
+    #[clap(long, env, default_value = "300")]
+    pub reservation_cleanup_interval_seconds: u64,
+
+    /// The largest random delay added to each run of a periodic job, such as
+    /// prover health checks, as a percentage of the job's interval.
+    #[clap(long, env, default_value = "10")]
+    pub scheduler_jitter_percent: u8,
 }
 
 /// A worker that commits identities to the blockchain.
@@ -114,16 +246,58 @@ pub struct TaskMonitor {
     /// when shutdown is called we want to be able to gracefully
     /// await the join handles - which requires ownership of the handle and by
     /// extension the instance.
-    instance:                    RwLock<Option<RunningInstance>>,
-    database:                    Arc<Database>,
-    identity_manager:            SharedIdentityManager,
-    tree_state:                  TreeState,
-    batch_insert_timeout_secs:   u64,
+    instance: RwLock<Option<RunningInstance>>,
+    database: Arc<Database>,
+    identity_manager: SharedIdentityManager,
+    tree_state: TreeState,
+    /// Held while the latest tree and the pending identities in the database
+    /// are changed together, i.e. while identities are inserted or a batch is
+    /// cancelled.
+    latest_tree_lock: Arc<Mutex<()>>,
+    batch_timeout: watch::Sender<Duration>,
+    /// Set while an operator has paused batching. Identities are still
+    /// accepted and queued, but no batches are built or submitted.
+    paused: Arc<watch::Sender<bool>>,
+    batching_policy: watch::Sender<BatchingPolicy>,
+    /// The flush policy given in the options, if any, which is kept when the
+    /// maximum gas price changes.
+    batch_flush_policy: Option<FlushPolicy>,
+    submission_windows: Option<SubmissionWindows>,
     pending_identities_capacity: usize,
 
     // Finalization params
-    scanning_window_size: u64,
-    time_between_scans:   Duration,
+    scanning_window_size:  u64,
+    time_between_scans:    Duration,
+    finalization_strategy: FinalizationStrategy,
+
+    prover_health_check_interval: Duration,
+
+    // Root monitoring params
+    root_check_interval:        Duration,
+    root_mismatch_grace_period: Duration,
+    root_mismatch:              Arc<AtomicBool>,
+
+    root_propagation_interval: Duration,
+
+    integrity_check_interval: Duration,
+    integrity_check_halt:     bool,
+
+    reservation_cleanup_interval: Duration,
+
+    // Balance monitoring params
+    balance_check_interval: Duration,
+    warning_balance:        U256,
+    minimum_balance:        U256,
+    insufficient_funds:     Arc<AtomicBool>,
+
+    /// Runs the periodic jobs. Kept across restarts of the committer so that
+    /// the status of the last runs stays available.
+    scheduler: Scheduler,
+
+    /// Runs and restarts the long-lived tasks, kept like the scheduler.
+    supervisor: Supervisor,
+
+    events: events::Options,
 }
 
 impl TaskMonitor {
@@ -132,12 +306,30 @@ impl TaskMonitor {
         contracts: SharedIdentityManager,
         tree_state: TreeState,
         options: &Options,
+        events: &events::Options,
     ) -> Self {
         let Options {
             batch_timeout_seconds,
+            batch_deep_queue_threshold,
+            batch_max_gas_price_gwei,
+            batch_latency_budget_seconds,
+            batch_flush_policy,
+            ref submission_windows,
             pending_identities_capacity,
             scanning_window_size,
             time_between_scans_seconds,
+            finalization_strategy,
+            prover_health_check_interval_seconds,
+            root_check_interval_seconds,
+            root_mismatch_grace_period_seconds,
+            root_propagation_interval_seconds,
+            balance_check_interval_seconds,
+            signer_balance_warning_gwei,
+            signer_balance_minimum_gwei,
+            integrity_check_interval_seconds,
+            integrity_check_halt,
+            reservation_cleanup_interval_seconds,
+            scheduler_jitter_percent,
         } = *options;
 
         Self {
@@ -145,13 +337,42 @@ impl TaskMonitor {
             database,
             identity_manager: contracts,
             tree_state,
-            batch_insert_timeout_secs: batch_timeout_seconds,
+            latest_tree_lock: Arc::new(Mutex::new(())),
+            batch_timeout: watch::channel(Duration::from_secs(batch_timeout_seconds)).0,
+            paused: Arc::new(watch::channel(false).0),
+            batching_policy: watch::channel(
+                BatchingPolicy::new(batch_deep_queue_threshold, batch_max_gas_price_gwei)
+                    .with_latency_budget(batch_latency_budget_seconds.map(Duration::from_secs))
+                    .with_flush_policy(batch_flush_policy),
+            )
+            .0,
+            batch_flush_policy,
+            submission_windows: submission_windows.clone(),
             pending_identities_capacity,
             scanning_window_size,
             time_between_scans: Duration::from_secs(time_between_scans_seconds),
+            finalization_strategy,
+            prover_health_check_interval: Duration::from_secs(prover_health_check_interval_seconds),
+            root_check_interval: Duration::from_secs(root_check_interval_seconds),
+            root_mismatch_grace_period: Duration::from_secs(root_mismatch_grace_period_seconds),
+            root_mismatch: Arc::new(AtomicBool::new(false)),
+            root_propagation_interval: Duration::from_secs(root_propagation_interval_seconds),
+            integrity_check_interval: Duration::from_secs(integrity_check_interval_seconds),
+            integrity_check_halt,
+            reservation_cleanup_interval: Duration::from_secs(reservation_cleanup_interval_seconds),
+            balance_check_interval: Duration::from_secs(balance_check_interval_seconds),
+            warning_balance: gwei(signer_balance_warning_gwei),
+            minimum_balance: gwei(signer_balance_minimum_gwei),
+            insufficient_funds: Arc::new(AtomicBool::new(false)),
+            scheduler: Scheduler::new(scheduler_jitter_percent),
+            supervisor: Supervisor::new(),
+            events: events.clone(),
         }
     }
 
+    /// Starts the batching tasks and jobs. Proof-only builds leave them out
+    /// and only run as observers, which never start them.
+    #[cfg(feature = "submission")]
     #[instrument(level = "debug", skip_all)]
     pub async fn start(&self) {
         let mut instance = self.instance.write().await;
@@ -180,12 +401,14 @@ impl TaskMonitor {
             self.tree_state.get_mined_tree(),
             self.scanning_window_size,
             self.time_between_scans,
+            self.finalization_strategy,
         );
 
-        let finalize_identities_handle = crate::utils::spawn_monitored_with_backoff(
+        let finalize_identities_handle = self.supervisor.supervise(
+            "finalize_identities",
+            FINALIZE_IDENTITIES_RESTART,
             move || finalize_identities.clone().run(),
-            shutdown_sender.clone(),
-            FINALIZE_IDENTITIES_BACKOFF,
+            &shutdown_sender,
         );
 
         handles.push(finalize_identities_handle);
@@ -196,12 +419,14 @@ impl TaskMonitor {
             self.identity_manager.clone(),
             self.tree_state.get_processed_tree(),
             pending_batch_submissions_queue.clone(),
+            self.events.is_enabled(),
         );
 
-        let mine_identities_handle = crate::utils::spawn_monitored_with_backoff(
+        let mine_identities_handle = self.supervisor.supervise(
+            "mine_identities",
+            MINE_IDENTITIES_RESTART,
             move || mine_identities.clone().run(),
-            shutdown_sender.clone(),
-            MINE_IDENTITIES_BACKOFF,
+            &shutdown_sender,
         );
 
         handles.push(mine_identities_handle);
@@ -211,15 +436,22 @@ impl TaskMonitor {
             self.database.clone(),
             self.identity_manager.clone(),
             self.tree_state.get_batching_tree(),
-            self.batch_insert_timeout_secs,
+            self.batch_timeout.subscribe(),
+            self.batching_policy.subscribe(),
+            self.submission_windows.clone(),
+            self.paused.subscribe(),
+            self.root_mismatch.clone(),
+            self.insufficient_funds.clone(),
+            self.latest_tree_lock.clone(),
             pending_batch_submissions_queue,
             wake_up_notify.clone(),
         );
 
-        let process_identities_handle = crate::utils::spawn_monitored_with_backoff(
+        let process_identities_handle = self.supervisor.supervise(
+            "process_identities",
+            PROCESS_IDENTITIES_RESTART,
             move || process_identities.clone().run(),
-            shutdown_sender.clone(),
-            PROCESS_IDENTITIES_BACKOFF,
+            &shutdown_sender,
         );
 
         handles.push(process_identities_handle);
@@ -228,23 +460,209 @@ impl TaskMonitor {
         let insert_identities = InsertIdentities::new(
             self.database.clone(),
             self.tree_state.get_latest_tree(),
+            self.latest_tree_lock.clone(),
             wake_up_notify,
         );
 
-        let insert_identities_handle = crate::utils::spawn_monitored_with_backoff(
+        let insert_identities_handle = self.supervisor.supervise(
+            "insert_identities",
+            INSERT_IDENTITIES_RESTART,
             move || insert_identities.clone().run(),
-            shutdown_sender.clone(),
-            INSERT_IDENTITIES_BACKOFF,
+            &shutdown_sender,
         );
 
         handles.push(insert_identities_handle);
 
+        // Monitor provers job
+        let monitor_provers = MonitorProvers::new(self.identity_manager.clone());
+
+        let monitor_provers_handle = self.scheduler.schedule(
+            "monitor_provers",
+            self.prover_health_check_interval,
+            move || monitor_provers.clone().run(),
+            &shutdown_sender,
+        );
+
+        handles.push(monitor_provers_handle);
+
+        // Monitor roots job. Simulated batches never reach the chain, so in a
+        // dry run the roots always differ.
+        if self.identity_manager.is_simulated() {
+            info!("Dry run, not comparing the processed root with the chain");
+        } else {
+            let monitor_roots = MonitorRoots::new(
+                self.identity_manager.clone(),
+                self.tree_state.get_processed_tree(),
+                self.root_mismatch.clone(),
+                self.root_mismatch_grace_period,
+            );
+
+            let monitor_roots_handle = self.scheduler.schedule(
+                "monitor_roots",
+                self.root_check_interval,
+                move || monitor_roots.clone().run(),
+                &shutdown_sender,
+            );
+
+            handles.push(monitor_roots_handle);
+        }
+
+        // Check integrity job
+        let check_integrity = CheckIntegrity::new(
+            self.database.clone(),
+            self.identity_manager.clone(),
+            self.tree_state.get_latest_tree(),
+            self.latest_tree_lock.clone(),
+            self.paused.clone(),
+            self.integrity_check_halt,
+        );
+
+        let check_integrity_handle = self.scheduler.schedule(
+            "check_integrity",
+            self.integrity_check_interval,
+            move || check_integrity.clone().run(),
+            &shutdown_sender,
+        );
+
+        handles.push(check_integrity_handle);
+
+        // Expire reservations job
+        let expire_reservations = ExpireReservations::new(self.database.clone());
+
+        let expire_reservations_handle = self.scheduler.schedule(
+            "expire_reservations",
+            self.reservation_cleanup_interval,
+            move || expire_reservations.clone().run(),
+            &shutdown_sender,
+        );
+
+        handles.push(expire_reservations_handle);
+
+        // Monitor balance job
+        let monitor_balance = MonitorBalance::new(
+            self.identity_manager.clone(),
+            self.insufficient_funds.clone(),
+            self.warning_balance,
+            self.minimum_balance,
+        );
+
+        let monitor_balance_handle = self.scheduler.schedule(
+            "monitor_balance",
+            self.balance_check_interval,
+            move || monitor_balance.clone().run(),
+            &shutdown_sender,
+        );
+
+        handles.push(monitor_balance_handle);
+
+        // Deliver events task
+        if self.events.is_enabled() {
+            let deliver_events = DeliverEvents::new(self.database.clone(), self.events.clone());
+
+            let deliver_events_handle = self.supervisor.supervise(
+                "deliver_events",
+                DELIVER_EVENTS_RESTART,
+                move || deliver_events.clone().run(),
+                &shutdown_sender,
+            );
+
+            handles.push(deliver_events_handle);
+        }
+
+        // Propagate roots job
+        if !self.identity_manager.state_bridge_chain_ids().is_empty() {
+            let propagate_roots =
+                PropagateRoots::new(self.database.clone(), self.identity_manager.clone());
+
+            let propagate_roots_handle = self.scheduler.schedule(
+                "propagate_roots",
+                self.root_propagation_interval,
+                move || propagate_roots.clone().run(),
+                &shutdown_sender,
+            );
+
+            handles.push(propagate_roots_handle);
+        }
+
         *instance = Some(RunningInstance {
             handles,
             shutdown_sender,
         });
     }
 
+    /// Whether the identity manager root diverged from the processed root, in
+    /// which case no batches are submitted.
+    pub fn is_root_mismatched(&self) -> bool {
+        self.root_mismatch.load(Ordering::SeqCst)
+    }
+
+    /// The status of the periodic jobs.
+    pub fn scheduled_jobs(&self) -> Vec<JobStatus> {
+        self.scheduler.status()
+    }
+
+    /// The status of the long-lived tasks.
+    pub fn tasks(&self) -> Vec<TaskStatus> {
+        self.supervisor.status()
+    }
+
+    /// The maximum time to wait before submitting a batch that is not full.
+    pub fn batch_timeout(&self) -> Duration {
+        *self.batch_timeout.borrow()
+    }
+
+    /// Changes the batch timeout. A running batching task picks up the new
+    /// timeout immediately.
+    pub fn set_batch_timeout(&self, timeout: Duration) {
+        self.batch_timeout.send_replace(timeout);
+    }
+
+    /// Pauses or resumes building and submitting batches. A batch that is
+    /// already being built when batching is paused is still submitted.
+    pub fn set_paused(&self, paused: bool) {
+        set_batching_paused(&self.paused, paused);
+    }
+
+    /// Changes the gas price above which batches are held back, or stops
+    /// holding them back if `None`. A running batching task uses the new price
+    /// from its next batch on.
+    pub fn set_max_gas_price(&self, max_gas_price_gwei: Option<u64>) {
+        self.batching_policy.send_modify(|policy| {
+            *policy = policy.with_max_gas_price(max_gas_price_gwei, self.batch_flush_policy);
+        });
+    }
+
+    /// Cancels `batch` if it hasn't been submitted yet, moving the identities
+    /// from its first leaf on back to the queue and removing them from the
+    /// latest tree. Returns the requeued commitments, or `None` if the batch
+    /// can no longer be cancelled.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the database can't be updated.
+    pub async fn cancel_batch(&self, batch: &Batch) -> AnyhowResult<Option<Vec<Hash>>> {
+        let _latest_tree_guard = self.latest_tree_lock.lock().await;
+
+        let Some(requeued) = self
+            .database
+            .cancel_batch(&batch.post_root, batch.start_index)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        self.tree_state.truncate_latest(batch.start_index);
+
+        info!(
+            post_root = ?batch.post_root,
+            start_index = batch.start_index,
+            requeued = requeued.len(),
+            "Batch cancelled"
+        );
+
+        Ok(Some(requeued))
+    }
+
     async fn log_pending_identities_count(database: &Database) -> AnyhowResult<()> {
         let identities = database.count_pending_identities().await?;
         PENDING_IDENTITIES.set(f64::from(identities));
@@ -282,3 +700,13 @@ impl TaskMonitor {
         Ok(())
     }
 }
+
+fn gwei(amount: u64) -> U256 {
+    U256::from(amount) * U256::exp10(9)
+}
+
+/// Pauses or resumes batching through the sender the batching task watches.
+pub(crate) fn set_batching_paused(sender: &watch::Sender<bool>, paused: bool) {
+    BATCHING_PAUSED.set(if paused { 1.0 } else { 0.0 });
+    sender.send_replace(paused);
+}
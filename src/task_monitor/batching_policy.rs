@@ -0,0 +1,357 @@
+//! Decides how many identities to submit in a batch, and when.
+//!
+//! By default the sequencer waits for the largest batch size to fill up, only
+//! submitting smaller batches when the batch timeout expires. This saves gas at
+//! high volume, but adds latency at low volume. The policy can instead submit
+//! smaller full batches while the queue is shallow, and can hold batches back
+//! while gas is expensive. With a latency budget, it also falls back to a
+//! smaller batch size when the identities that are already waiting would
+//! otherwise wait longer than the budget for the batch to fill up.
+//!
+//! Which of these rules apply is chosen by the [`FlushPolicy`].
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use ethers::types::U256;
+use thiserror::Error;
+
+/// The number of wei in a gwei.
+const GWEI: u64 = 1_000_000_000;
+
+/// When batches are flushed, between waking up on new identities and the
+/// batch timeout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// One batch every batch timeout, however many identities are pending.
+    /// This caps throughput at one batch per timeout.
+    FixedInterval,
+    /// As soon as a batch fills up, or a smaller batch once the latency budget
+    /// or the batch timeout runs out.
+    SizeOrTimeout,
+    /// Like `SizeOrTimeout`, but batches are held back until the batch timeout
+    /// while the live gas price is above the maximum gas price.
+    CostAware,
+}
+
+impl FlushPolicy {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::FixedInterval => "fixed-interval",
+            Self::SizeOrTimeout => "size-or-timeout",
+            Self::CostAware => "cost-aware",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("invalid flush policy {0:?}, expected `fixed-interval`, `size-or-timeout` or `cost-aware`")]
+pub struct InvalidFlushPolicy(String);
+
+impl FromStr for FlushPolicy {
+    type Err = InvalidFlushPolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed-interval" => Ok(Self::FixedInterval),
+            "size-or-timeout" => Ok(Self::SizeOrTimeout),
+            "cost-aware" => Ok(Self::CostAware),
+            _ => Err(InvalidFlushPolicy(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchingPolicy {
+    flush_policy:         FlushPolicy,
+    /// Queues with at least this many pending identities are submitted in the
+    /// largest batch size.
+    deep_queue_threshold: usize,
+    /// Batches are held back while the gas price is above this, in wei.
+    max_gas_price:        Option<U256>,
+    /// How long an identity should wait before its batch is submitted.
+    latency_budget:       Option<Duration>,
+}
+
+impl BatchingPolicy {
+    /// Creates a policy that is cost-aware if there is a maximum gas price,
+    /// and size-or-timeout otherwise.
+    #[must_use]
+    pub fn new(deep_queue_threshold: usize, max_gas_price_gwei: Option<u64>) -> Self {
+        let max_gas_price = max_gas_price_gwei.map(|gwei| U256::from(gwei) * GWEI);
+        let flush_policy = if max_gas_price.is_some() {
+            FlushPolicy::CostAware
+        } else {
+            FlushPolicy::SizeOrTimeout
+        };
+
+        Self {
+            flush_policy,
+            deep_queue_threshold,
+            max_gas_price,
+            latency_budget: None,
+        }
+    }
+
+    #[must_use]
+    pub const fn with_latency_budget(mut self, latency_budget: Option<Duration>) -> Self {
+        self.latency_budget = latency_budget;
+        self
+    }
+
+    /// Overrides the flush policy implied by [`Self::new`].
+    #[must_use]
+    pub const fn with_flush_policy(mut self, flush_policy: Option<FlushPolicy>) -> Self {
+        if let Some(flush_policy) = flush_policy {
+            self.flush_policy = flush_policy;
+        }
+        self
+    }
+
+    /// Changes the maximum gas price, keeping the rest of the policy. The flush
+    /// policy is implied by the new maximum gas price unless `flush_policy`
+    /// overrides it, as in [`Self::new`].
+    #[must_use]
+    pub fn with_max_gas_price(
+        self,
+        max_gas_price_gwei: Option<u64>,
+        flush_policy: Option<FlushPolicy>,
+    ) -> Self {
+        Self::new(self.deep_queue_threshold, max_gas_price_gwei)
+            .with_latency_budget(self.latency_budget)
+            .with_flush_policy(flush_policy)
+    }
+
+    #[must_use]
+    pub const fn flush_policy(&self) -> FlushPolicy {
+        self.flush_policy
+    }
+
+    /// The latency budget, if the flush policy uses one.
+    #[must_use]
+    pub const fn latency_budget(&self) -> Option<Duration> {
+        match self.flush_policy {
+            FlushPolicy::FixedInterval => None,
+            FlushPolicy::SizeOrTimeout | FlushPolicy::CostAware => self.latency_budget,
+        }
+    }
+
+    /// The number of pending identities that need to be known to make a
+    /// decision.
+    #[must_use]
+    pub fn lookahead(&self, max_batch_size: usize) -> usize {
+        max_batch_size.max(self.deep_queue_threshold)
+    }
+
+    /// Whether the policy depends on the current gas price.
+    #[must_use]
+    pub const fn uses_gas_price(&self) -> bool {
+        matches!(self.flush_policy, FlushPolicy::CostAware)
+    }
+
+    /// Returns the number of identities to submit right away, or `None` if it's
+    /// better to keep waiting for more identities or cheaper gas.
+    ///
+    /// `batch_sizes` are the available prover batch sizes in ascending order.
+    #[must_use]
+    pub fn batch_size(
+        &self,
+        pending: usize,
+        batch_sizes: &[usize],
+        gas_price: Option<U256>,
+    ) -> Option<usize> {
+        let max_batch_size = *batch_sizes.last()?;
+
+        if self.flush_policy == FlushPolicy::FixedInterval || self.is_gas_too_expensive(gas_price) {
+            return None;
+        }
+
+        if pending >= self.deep_queue_threshold {
+            return (pending >= max_batch_size).then_some(max_batch_size);
+        }
+
+        // The queue is shallow, so submit the largest batch it fills completely.
+        batch_sizes
+            .iter()
+            .rev()
+            .find(|batch_size| **batch_size <= pending)
+            .copied()
+    }
+
+    /// Returns a smaller batch size to submit right away if waiting for the
+    /// batch size [`Self::batch_size`] is holding out for would exceed the
+    /// latency budget, given how long the oldest pending identity has waited
+    /// and the current arrival rate in identities per second.
+    ///
+    /// Batches held back by the gas price are never submitted early.
+    #[must_use]
+    pub fn latency_fallback(
+        &self,
+        pending: usize,
+        batch_sizes: &[usize],
+        gas_price: Option<U256>,
+        oldest_wait: Duration,
+        arrival_rate: f64,
+    ) -> Option<usize> {
+        let latency_budget = self.latency_budget()?;
+
+        if self.is_gas_too_expensive(gas_price) {
+            return None;
+        }
+
+        let max_batch_size = *batch_sizes.last()?;
+        let awaited_batch_size = if pending >= self.deep_queue_threshold {
+            max_batch_size
+        } else {
+            batch_sizes
+                .iter()
+                .find(|batch_size| **batch_size > pending)
+                .copied()
+                .unwrap_or(max_batch_size)
+        };
+
+        #[allow(clippy::cast_precision_loss)]
+        let missing = awaited_batch_size.saturating_sub(pending) as f64;
+        let fill_time = if arrival_rate > 0.0 {
+            Duration::try_from_secs_f64(missing / arrival_rate).unwrap_or(Duration::MAX)
+        } else {
+            Duration::MAX
+        };
+
+        if oldest_wait.saturating_add(fill_time) <= latency_budget {
+            return None;
+        }
+
+        batch_sizes
+            .iter()
+            .rev()
+            .find(|batch_size| **batch_size <= pending)
+            .copied()
+    }
+
+    fn is_gas_too_expensive(&self, gas_price: Option<U256>) -> bool {
+        self.uses_gas_price()
+            && matches!(
+                (self.max_gas_price, gas_price),
+                (Some(max_gas_price), Some(gas_price)) if gas_price > max_gas_price
+            )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_largest_batch_by_default() {
+        let policy = BatchingPolicy::new(0, None);
+
+        assert_eq!(policy.batch_size(2, &[2, 3], None), None);
+        assert_eq!(policy.batch_size(3, &[2, 3], None), Some(3));
+        assert_eq!(policy.batch_size(5, &[2, 3], None), Some(3));
+    }
+
+    #[test]
+    fn submits_smaller_batches_while_queue_is_shallow() {
+        let policy = BatchingPolicy::new(10, None);
+
+        assert_eq!(policy.batch_size(1, &[2, 5, 10], None), None);
+        assert_eq!(policy.batch_size(2, &[2, 5, 10], None), Some(2));
+        assert_eq!(policy.batch_size(7, &[2, 5, 10], None), Some(5));
+        assert_eq!(policy.batch_size(12, &[2, 5, 10], None), Some(10));
+    }
+
+    #[test]
+    fn waits_while_gas_is_expensive() {
+        let policy = BatchingPolicy::new(0, Some(50));
+        let gwei = U256::from(GWEI);
+
+        assert_eq!(policy.batch_size(3, &[3], Some(gwei * 51)), None);
+        assert_eq!(policy.batch_size(3, &[3], Some(gwei * 50)), Some(3));
+    }
+
+    #[test]
+    fn max_gas_price_can_be_changed() {
+        let policy = BatchingPolicy::new(0, None).with_max_gas_price(Some(50), None);
+        let gwei = U256::from(GWEI);
+
+        assert_eq!(policy.flush_policy(), FlushPolicy::CostAware);
+        assert_eq!(policy.batch_size(3, &[3], Some(gwei * 51)), None);
+        assert_eq!(
+            policy.with_max_gas_price(None, None).flush_policy(),
+            FlushPolicy::SizeOrTimeout
+        );
+    }
+
+    #[test]
+    fn falls_back_to_smaller_batch_when_over_latency_budget() {
+        let policy =
+            BatchingPolicy::new(0, None).with_latency_budget(Some(Duration::from_secs(60)));
+        let waited = Duration::from_secs(30);
+
+        // Two identities per second fill the remaining 40 slots in 20 seconds.
+        assert_eq!(
+            policy.latency_fallback(60, &[10, 50, 100], None, waited, 2.0),
+            None
+        );
+        // One identity per second takes 40 seconds, which is over budget.
+        assert_eq!(
+            policy.latency_fallback(60, &[10, 50, 100], None, waited, 1.0),
+            Some(50)
+        );
+        // Nothing arrives, and there aren't enough identities for any batch size.
+        assert_eq!(
+            policy.latency_fallback(5, &[10, 50, 100], None, waited, 0.0),
+            None
+        );
+    }
+
+    #[test]
+    fn fixed_interval_only_flushes_on_timeout() {
+        let policy = BatchingPolicy::new(0, None)
+            .with_latency_budget(Some(Duration::from_secs(60)))
+            .with_flush_policy(Some(FlushPolicy::FixedInterval));
+
+        assert_eq!(policy.batch_size(100, &[10, 100], None), None);
+        assert_eq!(
+            policy.latency_fallback(60, &[10, 100], None, Duration::from_secs(3600), 0.0),
+            None
+        );
+    }
+
+    #[test]
+    fn only_cost_aware_policy_uses_gas_price() {
+        let policy = BatchingPolicy::new(0, Some(50));
+        let gwei = U256::from(GWEI);
+
+        assert_eq!(policy.flush_policy(), FlushPolicy::CostAware);
+        assert!(policy.uses_gas_price());
+
+        let policy = policy.with_flush_policy(Some(FlushPolicy::SizeOrTimeout));
+        assert!(!policy.uses_gas_price());
+        assert_eq!(policy.batch_size(3, &[3], Some(gwei * 51)), Some(3));
+    }
+
+    #[test]
+    fn parses_flush_policies() {
+        for policy in [
+            FlushPolicy::FixedInterval,
+            FlushPolicy::SizeOrTimeout,
+            FlushPolicy::CostAware,
+        ] {
+            assert_eq!(policy.name().parse::<FlushPolicy>().unwrap(), policy);
+        }
+        assert!("poisson".parse::<FlushPolicy>().is_err());
+    }
+
+    #[test]
+    fn latency_budget_is_disabled_by_default() {
+        let policy = BatchingPolicy::new(0, None);
+
+        assert_eq!(
+            policy.latency_fallback(60, &[10, 100], None, Duration::from_secs(3600), 0.0),
+            None
+        );
+    }
+}
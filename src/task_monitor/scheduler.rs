@@ -0,0 +1,260 @@
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use rand::Rng;
+use serde::Serialize;
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Instant};
+use tracing::{error, warn};
+
+/// The last known state of a scheduled job.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    pub name:             &'static str,
+    pub interval_seconds: u64,
+    pub running:          bool,
+    pub runs:             u64,
+    pub failures:         u64,
+    /// Runs that were skipped because the previous run was still going.
+    pub skipped_runs:     u64,
+    pub last_started_at:  Option<DateTime<Utc>>,
+    pub last_finished_at: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<u64>,
+    pub last_error:       Option<String>,
+}
+
+/// Runs periodic jobs such as health checks and reconciliation. Every run is
+/// delayed by a random fraction of the interval so that replicas don't hit
+/// shared dependencies in lockstep, and a job never overlaps with itself.
+#[derive(Debug)]
+pub struct Scheduler {
+    jitter: f64,
+    jobs:   Mutex<Vec<Arc<Mutex<JobStatus>>>>,
+}
+
+impl Scheduler {
+    /// `jitter_percent` is the largest delay added to a run, as a percentage
+    /// of the job's interval.
+    pub fn new(jitter_percent: u8) -> Self {
+        Self {
+            jitter: f64::from(jitter_percent.min(100)) / 100.0,
+            jobs:   Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Runs `job` every `interval` until a shutdown signal is received. A
+    /// failing or panicking run is recorded, and the job runs again at the
+    /// next interval. Scheduling a job with the same name again replaces its
+    /// status.
+    pub fn schedule<S, F>(
+        &self,
+        name: &'static str,
+        interval: Duration,
+        job: S,
+        shutdown_sender: &broadcast::Sender<()>,
+    ) -> JoinHandle<()>
+    where
+        S: Fn() -> F + Send + Sync + 'static,
+        F: Future<Output = AnyhowResult<()>> + Send + 'static,
+    {
+        let status = Arc::new(Mutex::new(JobStatus {
+            name,
+            interval_seconds: interval.as_secs(),
+            ..JobStatus::default()
+        }));
+
+        {
+            let mut jobs = self.jobs.lock().expect("no lock poisoning");
+            jobs.retain(|job| job.lock().expect("no lock poisoning").name != name);
+            jobs.push(status.clone());
+        }
+
+        let jitter = self.jitter;
+        let mut shutdown_receiver = shutdown_sender.subscribe();
+
+        tokio::spawn(async move {
+            let mut next_run = Instant::now() + jitter_delay(interval, jitter);
+
+            loop {
+                select! {
+                    () = sleep_until(next_run) => {}
+                    _ = shutdown_receiver.recv() => return,
+                }
+
+                let started = Instant::now();
+                status.lock().expect("no lock poisoning").start();
+
+                let result = select! {
+                    result = AssertUnwindSafe(job()).catch_unwind() => result,
+                    _ = shutdown_receiver.recv() => return,
+                };
+
+                let elapsed = started.elapsed();
+                let error = match result {
+                    Ok(Ok(())) => None,
+                    Ok(Err(error)) => {
+                        error!(job = name, ?error, "Scheduled job failed");
+                        Some(format!("{error:?}"))
+                    }
+                    Err(_) => {
+                        error!(job = name, "Scheduled job panicked");
+                        Some("panicked".to_string())
+                    }
+                };
+
+                // Runs that were due while this one was still going are
+                // skipped rather than run back to back.
+                let skipped = skipped_runs(elapsed, interval);
+                if skipped > 0 {
+                    warn!(
+                        job = name,
+                        ?elapsed,
+                        skipped,
+                        "Scheduled job overran its interval"
+                    );
+                }
+
+                status
+                    .lock()
+                    .expect("no lock poisoning")
+                    .finish(elapsed, error, skipped);
+
+                next_run = started
+                    + interval * u32::try_from(skipped + 1).unwrap_or(u32::MAX)
+                    + jitter_delay(interval, jitter);
+            }
+        })
+    }
+
+    /// The status of every scheduled job, in the order they were scheduled.
+    pub fn status(&self) -> Vec<JobStatus> {
+        self.jobs
+            .lock()
+            .expect("no lock poisoning")
+            .iter()
+            .map(|job| job.lock().expect("no lock poisoning").clone())
+            .collect()
+    }
+}
+
+impl JobStatus {
+    fn start(&mut self) {
+        self.running = true;
+        self.last_started_at = Some(Utc::now());
+    }
+
+    fn finish(&mut self, elapsed: Duration, error: Option<String>, skipped: u64) {
+        self.running = false;
+        self.runs += 1;
+        self.skipped_runs += skipped;
+        self.last_finished_at = Some(Utc::now());
+        self.last_duration_ms = Some(u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX));
+
+        if error.is_some() {
+            self.failures += 1;
+        }
+        self.last_error = error;
+    }
+}
+
+fn jitter_delay(interval: Duration, jitter: f64) -> Duration {
+    if jitter <= 0.0 {
+        return Duration::ZERO;
+    }
+
+    interval.mul_f64(rand::thread_rng().gen_range(0.0..jitter))
+}
+
+fn skipped_runs(elapsed: Duration, interval: Duration) -> u64 {
+    if interval.is_zero() {
+        return 0;
+    }
+
+    u64::try_from(elapsed.as_nanos() / interval.as_nanos()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn runs_jobs_and_records_failures() {
+        let scheduler = Scheduler::new(0);
+        let (shutdown_sender, _) = broadcast::channel(1);
+        let calls = Arc::new(AtomicU64::new(0));
+
+        let handle = {
+            let calls = calls.clone();
+            scheduler.schedule(
+                "flaky",
+                Duration::from_secs(10),
+                move || {
+                    let call = calls.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        anyhow::ensure!(call % 2 == 0, "odd call");
+                        Ok(())
+                    }
+                },
+                &shutdown_sender,
+            )
+        };
+
+        tokio::time::sleep(Duration::from_secs(35)).await;
+
+        let status = scheduler.status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].name, "flaky");
+        assert_eq!(status[0].runs, 4);
+        assert_eq!(status[0].failures, 2);
+        assert!(status[0].last_error.is_some());
+
+        shutdown_sender.send(()).unwrap();
+        handle.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_runs_instead_of_overlapping() {
+        let scheduler = Scheduler::new(0);
+        let (shutdown_sender, _) = broadcast::channel(1);
+
+        let handle = scheduler.schedule(
+            "slow",
+            Duration::from_secs(10),
+            || async {
+                tokio::time::sleep(Duration::from_secs(25)).await;
+                Ok(())
+            },
+            &shutdown_sender,
+        );
+
+        tokio::time::sleep(Duration::from_secs(26)).await;
+
+        let status = scheduler.status();
+        assert_eq!(status[0].runs, 1);
+        assert_eq!(status[0].skipped_runs, 2);
+        assert!(!status[0].running);
+
+        shutdown_sender.send(()).unwrap();
+        handle.await.unwrap();
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let interval = Duration::from_secs(100);
+
+        assert_eq!(jitter_delay(interval, 0.0), Duration::ZERO);
+        for _ in 0..100 {
+            assert!(jitter_delay(interval, 0.1) < Duration::from_secs(10));
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Restricts when batches are submitted to windows of the day, e.g. the hours
+//! in which gas is cheap. Identities keep being inserted into the tree as
+//! pending outside of the windows, and are batched once a window opens.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("invalid submission windows {0:?}, expected e.g. `01:00-06:00,22:00-23:30`")]
+pub struct InvalidSubmissionWindows(String);
+
+/// A window of the day in UTC, from `start` up to `end`. Windows that end
+/// before they start wrap around midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Window {
+    start: NaiveTime,
+    end:   NaiveTime,
+}
+
+impl Window {
+    fn contains(self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl FromStr for Window {
+    type Err = InvalidSubmissionWindows;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidSubmissionWindows(s.to_string());
+
+        let (start, end) = s.trim().split_once('-').ok_or_else(invalid)?;
+        let start = NaiveTime::parse_from_str(start.trim(), "%H:%M").map_err(|_| invalid())?;
+        let end = NaiveTime::parse_from_str(end.trim(), "%H:%M").map_err(|_| invalid())?;
+        if start == end {
+            return Err(invalid());
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+/// The windows of the day in which batches are submitted, configured with
+/// `--submission-windows`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubmissionWindows(Vec<Window>);
+
+impl FromStr for SubmissionWindows {
+    type Err = InvalidSubmissionWindows;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let windows = s
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Window>, _>>()?;
+
+        Ok(Self(windows))
+    }
+}
+
+impl SubmissionWindows {
+    /// Whether batches are submitted at `at`.
+    #[must_use]
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        self.0.iter().any(|window| window.contains(at.time()))
+    }
+
+    /// The earliest time from `at` on at which batches are submitted.
+    #[must_use]
+    pub fn next_open(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        if self.is_open(at) {
+            return at;
+        }
+
+        self.0
+            .iter()
+            .map(|window| {
+                let start = Utc.from_utc_datetime(&at.date_naive().and_time(window.start));
+                if start > at {
+                    start
+                } else {
+                    start + Duration::days(1)
+                }
+            })
+            .min()
+            .unwrap_or(at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        format!("2024-01-01T{time}:00Z").parse().unwrap()
+    }
+
+    #[test]
+    fn windows_are_parsed() {
+        assert!("01:00-06:00, 22:00-23:30"
+            .parse::<SubmissionWindows>()
+            .is_ok());
+        assert!("01:00".parse::<SubmissionWindows>().is_err());
+        assert!("01:00-01:00".parse::<SubmissionWindows>().is_err());
+        assert!("25:00-01:00".parse::<SubmissionWindows>().is_err());
+    }
+
+    #[test]
+    fn windows_wrap_around_midnight() {
+        let windows: SubmissionWindows = "22:00-02:00".parse().unwrap();
+
+        assert!(windows.is_open(at("23:00")));
+        assert!(windows.is_open(at("01:59")));
+        assert!(!windows.is_open(at("02:00")));
+        assert!(!windows.is_open(at("21:59")));
+    }
+
+    #[test]
+    fn next_open_is_the_start_of_the_next_window() {
+        let windows: SubmissionWindows = "01:00-06:00,12:00-13:00".parse().unwrap();
+
+        assert_eq!(windows.next_open(at("02:00")), at("02:00"));
+        assert_eq!(windows.next_open(at("07:00")), at("12:00"));
+        assert_eq!(
+            windows.next_open(at("14:00")),
+            at("01:00") + Duration::days(1)
+        );
+    }
+}
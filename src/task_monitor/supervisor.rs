@@ -0,0 +1,391 @@
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result as AnyhowResult;
+use chrono::{DateTime, TimeZone, Utc};
+use futures::future::poll_fn;
+use futures::FutureExt;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde::Serialize;
+use tokio::select;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Instant};
+use tracing::{error, info, warn};
+
+static TASK_RESTARTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "supervised_task_restarts",
+        "Restarts of background tasks, by task and whether it failed or panicked",
+        &["task", "reason"]
+    )
+    .unwrap()
+});
+
+/// How a supervised task is restarted after it fails or panics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// The backoff before the first restart.
+    pub initial_backoff: Duration,
+    /// The backoff doubles with every consecutive restart, up to this.
+    pub max_backoff:     Duration,
+}
+
+impl RestartPolicy {
+    /// Restarts after `initial_backoff`, doubling the backoff after every
+    /// consecutive failure up to `max_backoff`. A run that lasts longer than
+    /// `max_backoff` counts as recovered and resets the backoff.
+    pub const fn exponential(initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            initial_backoff,
+            max_backoff,
+        }
+    }
+
+    fn backoff(&self, consecutive_failures: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(consecutive_failures.saturating_sub(1));
+
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// What a supervised task is doing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TaskState {
+    #[default]
+    Running,
+    /// Waiting out the backoff after a failure or panic.
+    Restarting,
+    /// Returned without an error, so it isn't restarted.
+    Finished,
+    /// Stopped by a shutdown.
+    Stopped,
+}
+
+/// The last known state of a supervised task.
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskStatus {
+    pub name:              &'static str,
+    pub state:             TaskState,
+    pub restarts:          u64,
+    pub panics:            u64,
+    pub started_at:        Option<DateTime<Utc>>,
+    /// The last time the task made progress, i.e. was woken up to do work.
+    pub last_heartbeat_at: Option<DateTime<Utc>>,
+    pub last_failed_at:    Option<DateTime<Utc>>,
+    pub last_error:        Option<String>,
+}
+
+struct SupervisedTask {
+    status:    Mutex<TaskStatus>,
+    /// Milliseconds since the epoch, updated far too often to take the lock.
+    heartbeat: AtomicI64,
+}
+
+impl SupervisedTask {
+    fn update(&self, update: impl FnOnce(&mut TaskStatus)) {
+        update(&mut self.status.lock().expect("no lock poisoning"));
+    }
+
+    fn status(&self) -> TaskStatus {
+        let mut status = self.status.lock().expect("no lock poisoning").clone();
+        status.last_heartbeat_at = match self.heartbeat.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Utc.timestamp_millis_opt(millis).single(),
+        };
+        status
+    }
+}
+
+/// Runs the long-lived background tasks, such as batching and finalization,
+/// restarting them with a backoff when they fail or panic. Every failure is
+/// logged and counted, and the status of every task, including when it last
+/// made progress, is kept for `/admin/tasks`, so that a task that died or got
+/// stuck doesn't go unnoticed.
+#[derive(Default)]
+pub struct Supervisor {
+    tasks: Mutex<Vec<Arc<SupervisedTask>>>,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs the future returned by `task` until a shutdown signal is received,
+    /// starting it again according to `policy` whenever it fails or panics.
+    /// Supervising a task with the same name again replaces its status.
+    pub fn supervise<S, F>(
+        &self,
+        name: &'static str,
+        policy: RestartPolicy,
+        task: S,
+        shutdown_sender: &broadcast::Sender<()>,
+    ) -> JoinHandle<()>
+    where
+        S: Fn() -> F + Send + Sync + 'static,
+        F: Future<Output = AnyhowResult<()>> + Send + 'static,
+    {
+        let supervised = Arc::new(SupervisedTask {
+            status:    Mutex::new(TaskStatus {
+                name,
+                ..TaskStatus::default()
+            }),
+            heartbeat: AtomicI64::new(0),
+        });
+
+        {
+            let mut tasks = self.tasks.lock().expect("no lock poisoning");
+            tasks.retain(|task| task.status.lock().expect("no lock poisoning").name != name);
+            tasks.push(supervised.clone());
+        }
+
+        let mut shutdown_receiver = shutdown_sender.subscribe();
+
+        tokio::spawn(async move {
+            let mut consecutive_failures = 0;
+
+            loop {
+                let started = Instant::now();
+                supervised.update(|status| {
+                    status.state = TaskState::Running;
+                    status.started_at = Some(Utc::now());
+                });
+
+                let mut future = Box::pin(task());
+                let heartbeat = &supervised.heartbeat;
+                let future = poll_fn(|cx| {
+                    heartbeat.store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+                    future.as_mut().poll(cx)
+                });
+
+                let result = select! {
+                    result = AssertUnwindSafe(future).catch_unwind() => result,
+                    _ = shutdown_receiver.recv() => {
+                        info!(task = name, "Woke up by shutdown signal, exiting.");
+                        supervised.update(|status| status.state = TaskState::Stopped);
+                        return;
+                    }
+                };
+
+                let (reason, error) = match result {
+                    Ok(Ok(())) => {
+                        warn!(task = name, "Task finished, not restarting it");
+                        supervised.update(|status| status.state = TaskState::Finished);
+                        return;
+                    }
+                    Ok(Err(error)) => {
+                        error!(task = name, ?error, "Task failed");
+                        ("failed", format!("{error:?}"))
+                    }
+                    Err(panic) => {
+                        let message = panic_message(panic.as_ref());
+                        error!(task = name, message, "Task panicked");
+                        ("panicked", format!("panicked: {message}"))
+                    }
+                };
+
+                if cli_batteries::is_shutting_down() {
+                    std::process::abort();
+                }
+
+                if started.elapsed() > policy.max_backoff {
+                    consecutive_failures = 0;
+                }
+                consecutive_failures += 1;
+                let backoff = policy.backoff(consecutive_failures);
+
+                TASK_RESTARTS.with_label_values(&[name, reason]).inc();
+                supervised.update(|status| {
+                    status.state = TaskState::Restarting;
+                    status.restarts += 1;
+                    if reason == "panicked" {
+                        status.panics += 1;
+                    }
+                    status.last_failed_at = Some(Utc::now());
+                    status.last_error = Some(error);
+                });
+
+                select! {
+                    () = sleep(backoff) => {}
+                    _ = shutdown_receiver.recv() => {
+                        supervised.update(|status| status.state = TaskState::Stopped);
+                        return;
+                    }
+                }
+            }
+        })
+    }
+
+    /// The status of every supervised task, in the order they were started.
+    pub fn status(&self) -> Vec<TaskStatus> {
+        self.tasks
+            .lock()
+            .expect("no lock poisoning")
+            .iter()
+            .map(|task| task.status())
+            .collect()
+    }
+}
+
+/// The message a task panicked with, if it panicked with a string.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicU64};
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn restarts_failed_and_panicked_tasks() {
+        let supervisor = Supervisor::new();
+        let (shutdown_sender, _) = broadcast::channel(1);
+        let calls = Arc::new(AtomicU64::new(0));
+
+        let handle = {
+            let calls = calls.clone();
+            supervisor.supervise(
+                "flaky",
+                RestartPolicy::exponential(Duration::from_secs(1), Duration::from_secs(60)),
+                move || {
+                    let call = calls.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        match call {
+                            0 => anyhow::bail!("first call"),
+                            1 => panic!("second call"),
+                            _ => std::future::pending().await,
+                        }
+                    }
+                },
+                &shutdown_sender,
+            )
+        };
+
+        // Backoffs of one and two seconds.
+        tokio::time::sleep(Duration::from_secs(4)).await;
+
+        let status = supervisor.status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].name, "flaky");
+        assert_eq!(status[0].state, TaskState::Running);
+        assert_eq!(status[0].restarts, 2);
+        assert_eq!(status[0].panics, 1);
+        assert_eq!(
+            status[0].last_error.as_deref(),
+            Some("panicked: second call")
+        );
+        assert!(status[0].last_heartbeat_at.is_some());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        shutdown_sender.send(()).unwrap();
+        handle.await.unwrap();
+        assert_eq!(supervisor.status()[0].state, TaskState::Stopped);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn finished_tasks_are_not_restarted() {
+        let supervisor = Supervisor::new();
+        let (shutdown_sender, _) = broadcast::channel(1);
+        let ran = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let ran = ran.clone();
+            supervisor.supervise(
+                "once",
+                RestartPolicy::exponential(Duration::from_secs(1), Duration::from_secs(1)),
+                move || {
+                    assert!(!ran.swap(true, Ordering::SeqCst), "restarted");
+                    async { Ok(()) }
+                },
+                &shutdown_sender,
+            )
+        };
+
+        handle.await.unwrap();
+
+        let status = supervisor.status();
+        assert_eq!(status[0].state, TaskState::Finished);
+        assert_eq!(status[0].restarts, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn panicking_tasks_are_restarted_until_they_finish() {
+        let supervisor = Supervisor::new();
+        let (shutdown_sender, _) = broadcast::channel(1);
+        let can_finish = Arc::new(AtomicBool::new(false));
+        let triggered_panic = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let can_finish = can_finish.clone();
+            let triggered_panic = triggered_panic.clone();
+            supervisor.supervise(
+                "panicking",
+                RestartPolicy::exponential(Duration::from_millis(200), Duration::from_millis(200)),
+                move || {
+                    let can_finish = can_finish.load(Ordering::SeqCst);
+                    let triggered_panic = triggered_panic.clone();
+                    async move {
+                        if can_finish {
+                            return Ok(());
+                        }
+
+                        triggered_panic.store(true, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                        panic!("Panicking!");
+                    }
+                },
+                &shutdown_sender,
+            )
+        };
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        assert!(triggered_panic.load(Ordering::SeqCst));
+        assert!(!handle.is_finished(), "Task should not be finished");
+        let status = supervisor.status();
+        assert!(status[0].restarts >= 2);
+        assert_eq!(status[0].panics, status[0].restarts);
+        assert_eq!(
+            status[0].last_error.as_deref(),
+            Some("panicked: Panicking!")
+        );
+
+        can_finish.store(true, Ordering::SeqCst);
+        triggered_panic.store(false, Ordering::SeqCst);
+
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("Task should finish once it stops panicking")
+            .unwrap();
+
+        assert!(!triggered_panic.load(Ordering::SeqCst));
+        assert_eq!(supervisor.status()[0].state, TaskState::Finished);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let policy = RestartPolicy::exponential(Duration::from_secs(5), Duration::from_secs(60));
+
+        assert_eq!(policy.backoff(1), Duration::from_secs(5));
+        assert_eq!(policy.backoff(2), Duration::from_secs(10));
+        assert_eq!(policy.backoff(4), Duration::from_secs(40));
+        assert_eq!(policy.backoff(5), Duration::from_secs(60));
+        assert_eq!(policy.backoff(100), Duration::from_secs(60));
+    }
+}
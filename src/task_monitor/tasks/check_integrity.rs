@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use anyhow::Result as AnyhowResult;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge, IntGauge};
+use tokio::sync::{watch, Mutex};
+use tracing::{error, info};
+
+use crate::contracts::SharedIdentityManager;
+use crate::database::Database;
+use crate::identity_tree::{Hash, Latest, RootAccumulator, TreeVersion, TreeVersionReadOps};
+use crate::task_monitor::set_batching_paused;
+
+/// The number of leaves read from the database at once.
+const PAGE_SIZE: usize = 10_000;
+
+static INTEGRITY_FAILURE: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "tree_integrity_failure",
+        "Whether the last integrity check found the tree to be inconsistent."
+    )
+    .unwrap()
+});
+
+/// Recomputes the root of the tree from the leaves in the database, one page
+/// at a time, and checks that it agrees with the root stored with every leaf,
+/// with the root of the latest tree in memory, and that the latest root on
+/// chain is one of the roots of the leaves. Scheduled every
+/// `--integrity-check-interval-seconds`.
+pub struct CheckIntegrity {
+    database:         Arc<Database>,
+    identity_manager: SharedIdentityManager,
+    latest_tree:      TreeVersion<Latest>,
+    latest_tree_lock: Arc<Mutex<()>>,
+    paused:           Arc<watch::Sender<bool>>,
+    /// Whether batching is paused when the check fails.
+    halt:             bool,
+}
+
+impl CheckIntegrity {
+    pub fn new(
+        database: Arc<Database>,
+        identity_manager: SharedIdentityManager,
+        latest_tree: TreeVersion<Latest>,
+        latest_tree_lock: Arc<Mutex<()>>,
+        paused: Arc<watch::Sender<bool>>,
+        halt: bool,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            database,
+            identity_manager,
+            latest_tree,
+            latest_tree_lock,
+            paused,
+            halt,
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        // Cancelling a batch removes leaves while they are read, so failures
+        // are only reported if they persist in a second check.
+        if self.check().await?.is_none() {
+            INTEGRITY_FAILURE.set(0);
+            return Ok(());
+        }
+
+        let Some(failure) = self.check().await? else {
+            INTEGRITY_FAILURE.set(0);
+            return Ok(());
+        };
+
+        INTEGRITY_FAILURE.set(1);
+        error!(%failure, "CRITICAL: The tree failed its integrity check.");
+
+        if self.halt && !*self.paused.borrow() {
+            let reason = format!("integrity check failed: {failure}");
+            self.database.pause_batching(Some(&reason)).await?;
+            set_batching_paused(&self.paused, true);
+
+            error!("Paused batching until the tree is repaired.");
+        }
+
+        Ok(())
+    }
+
+    /// Returns a description of the first inconsistency found, if any.
+    async fn check(&self) -> AnyhowResult<Option<String>> {
+        // The latest tree and the leaves in the database only change together
+        // while the lock is held.
+        let (latest_root, leaf_count) = {
+            let _guard = self.latest_tree_lock.lock().await;
+            (self.latest_tree.get_root(), self.latest_tree.next_leaf())
+        };
+
+        // Only leaves that are in the latest tree can have been published, so
+        // the root on chain is one of their roots. Simulated batches are never
+        // published.
+        let chain_root: Option<Hash> = if self.identity_manager.is_simulated() {
+            None
+        } else {
+            Some(self.identity_manager.latest_root().await?.into())
+        };
+
+        let mut accumulator = RootAccumulator::new(
+            self.identity_manager.tree_depth(),
+            self.identity_manager.initial_leaf_value(),
+        );
+        let mut chain_root_found = chain_root == Some(accumulator.root());
+
+        while accumulator.len() < leaf_count {
+            let from = accumulator.len();
+            let page = self
+                .database
+                .get_identities(from, Some(leaf_count), PAGE_SIZE)
+                .await?;
+
+            if page.is_empty() {
+                return Ok(Some(format!("the leaves from {from} on are missing")));
+            }
+
+            for identity in page {
+                if identity.leaf_index != accumulator.len() {
+                    return Ok(Some(format!(
+                        "leaf {} is missing from the database",
+                        accumulator.len()
+                    )));
+                }
+
+                let root = accumulator.push(identity.commitment);
+                if root != identity.root {
+                    return Ok(Some(format!(
+                        "the root {:?} of leaf {} doesn't follow from the leaves, which result in \
+                         {root:?}",
+                        identity.root, identity.leaf_index
+                    )));
+                }
+
+                chain_root_found |= chain_root == Some(root);
+            }
+        }
+
+        if accumulator.root() != latest_root {
+            return Ok(Some(format!(
+                "the latest tree has the root {latest_root:?}, but its {leaf_count} leaves result \
+                 in {:?}",
+                accumulator.root()
+            )));
+        }
+
+        if let (Some(chain_root), false) = (chain_root, chain_root_found) {
+            return Ok(Some(format!(
+                "the root {chain_root:?} on chain isn't the root of any of the leaves"
+            )));
+        }
+
+        info!(
+            leaves = leaf_count,
+            root = ?latest_root,
+            "The tree passed its integrity check"
+        );
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result as AnyhowResult};
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use tokio::time;
+use tracing::{info, warn};
+
+use crate::database::types::StoredEvent;
+use crate::database::Database;
+use crate::events::{self, SIGNATURE_HEADER};
+
+/// How long to wait before looking for new events when there are none due.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for the webhook to respond.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct DeliverEvents {
+    database: Arc<Database>,
+    options:  events::Options,
+    client:   reqwest::Client,
+}
+
+impl DeliverEvents {
+    pub fn new(database: Arc<Database>, options: events::Options) -> Arc<Self> {
+        Arc::new(Self {
+            database,
+            options,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> anyhow::Result<()> {
+        deliver_events_loop(&self.database, &self.options, &self.client).await
+    }
+}
+
+async fn deliver_events_loop(
+    database: &Database,
+    options: &events::Options,
+    client: &reqwest::Client,
+) -> AnyhowResult<()> {
+    let Some(webhook_url) = &options.webhook_url else {
+        return Ok(());
+    };
+
+    loop {
+        let events = database.get_due_events(options.webhook_batch_size).await?;
+        if events.is_empty() {
+            time::sleep(POLL_INTERVAL).await;
+            continue;
+        }
+
+        let ids: Vec<i64> = events.iter().map(|event| event.id).collect();
+
+        match deliver(client, webhook_url, options, &events).await {
+            Ok(()) => {
+                info!(count = ids.len(), "Delivered events.");
+                database.remove_delivered_events(&ids).await?;
+            }
+            Err(error) => {
+                warn!(?error, count = ids.len(), "Failed to deliver events.");
+
+                #[allow(clippy::cast_precision_loss)]
+                database
+                    .mark_events_failed(
+                        &ids,
+                        &error.to_string(),
+                        options.webhook_max_attempts,
+                        options.webhook_initial_backoff_seconds as f64,
+                    )
+                    .await?;
+            }
+        }
+    }
+}
+
+async fn deliver(
+    client: &reqwest::Client,
+    webhook_url: &url::Url,
+    options: &events::Options,
+    events: &[StoredEvent],
+) -> AnyhowResult<()> {
+    let events = events
+        .iter()
+        .map(|event| {
+            Ok(json!({
+                "id": event.id,
+                "event": serde_json::from_str::<Value>(&event.payload)?,
+            }))
+        })
+        .collect::<Result<Vec<_>, serde_json::Error>>()?;
+    let body = serde_json::to_vec(&events)?;
+
+    let mut request = client
+        .post(webhook_url.clone())
+        .timeout(DELIVERY_TIMEOUT)
+        .header(CONTENT_TYPE, "application/json");
+
+    if let Some(secret) = &options.webhook_secret {
+        request = request.header(SIGNATURE_HEADER, events::sign(secret, &body));
+    }
+
+    let response = request.body(body).send().await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "webhook responded with status {}",
+            response.status()
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use anyhow::Result as AnyhowResult;
+use tracing::info;
+
+use crate::database::Database;
+
+/// Deletes the reservations of commitments that weren't confirmed in time.
+/// Expired reservations are already ignored, this only keeps the table from
+/// growing. Scheduled every `--reservation-cleanup-interval-seconds`.
+pub struct ExpireReservations {
+    database: Arc<Database>,
+}
+
+impl ExpireReservations {
+    pub fn new(database: Arc<Database>) -> Arc<Self> {
+        Arc::new(Self { database })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        let expired = self.database.delete_expired_reservations().await?;
+        if expired > 0 {
+            info!(expired, "Deleted expired reservations");
+        }
+
+        Ok(())
+    }
+}
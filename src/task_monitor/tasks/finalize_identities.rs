@@ -6,12 +6,14 @@ use anyhow::Result as AnyhowResult;
 use ethers::abi::RawLog;
 use ethers::contract::EthEvent;
 use ethers::providers::Middleware;
-use ethers::types::{Address, Log, Topic, ValueOrArray, U256};
-use tracing::{info, instrument};
+use ethers::types::{Address, Log, Topic, ValueOrArray, H256, U256};
+use tracing::{info, instrument, warn};
 
-use crate::contracts::abi::{BridgedWorldId, RootAddedFilter, TreeChangedFilter};
-use crate::contracts::scanner::BlockScanner;
+use crate::contracts::abi::{BridgedWorldId, RootAddedFilter};
+use crate::contracts::interface::IdentityContract;
+use crate::contracts::scanner::{BlockScanner, FinalizationStrategy};
 use crate::contracts::{IdentityManager, SharedIdentityManager};
+use crate::database::types::BatchStatus;
 use crate::database::Database;
 use crate::identity_tree::{Canonical, Intermediate, TreeVersion, TreeWithNextVersion};
 
@@ -21,8 +23,9 @@ pub struct FinalizeRoots {
     processed_tree:   TreeVersion<Intermediate>,
     finalized_tree:   TreeVersion<Canonical>,
 
-    scanning_window_size: u64,
-    time_between_scans:   Duration,
+    scanning_window_size:  u64,
+    time_between_scans:    Duration,
+    finalization_strategy: FinalizationStrategy,
 }
 
 impl FinalizeRoots {
@@ -33,6 +36,7 @@ impl FinalizeRoots {
         finalized_tree: TreeVersion<Canonical>,
         scanning_window_size: u64,
         time_between_scans: Duration,
+        finalization_strategy: FinalizationStrategy,
     ) -> Arc<Self> {
         Arc::new(Self {
             database,
@@ -41,6 +45,7 @@ impl FinalizeRoots {
             finalized_tree,
             scanning_window_size,
             time_between_scans,
+            finalization_strategy,
         })
     }
 
@@ -52,6 +57,7 @@ impl FinalizeRoots {
             &self.finalized_tree,
             self.scanning_window_size,
             self.time_between_scans,
+            self.finalization_strategy,
         )
         .await
     }
@@ -64,56 +70,197 @@ async fn finalize_roots_loop(
     finalized_tree: &TreeVersion<Canonical>,
     scanning_window_size: u64,
     time_between_scans: Duration,
+    finalization_strategy: FinalizationStrategy,
 ) -> AnyhowResult<()> {
-    let mainnet_abi = identity_manager.abi();
+    let mainnet_contract = identity_manager.contract();
     let secondary_abis = identity_manager.secondary_abis();
 
-    let mut mainnet_scanner =
-        BlockScanner::new_latest(mainnet_abi.client().clone(), scanning_window_size).await?;
-    let mut secondary_scanners =
-        init_secondary_scanners(secondary_abis, scanning_window_size).await?;
-
-    let mainnet_address = mainnet_abi.address();
+    // The scanners only return logs from final blocks, so roots are marked as
+    // mined only once they are final on every chain. Roots are marked as
+    // processed as soon as their transaction is mined, by the mine identities
+    // task.
+    //
+    // Scanning resumes where it left off before a restart, so that roots
+    // published in the meantime are reconciled with the database.
+    let mainnet_address = mainnet_contract.address();
+    let mut mainnet_scanner = open_scanner(
+        database,
+        mainnet_contract.client(),
+        mainnet_address,
+        scanning_window_size,
+        finalization_strategy,
+    )
+    .await?;
+    let mut secondary_scanners = init_secondary_scanners(
+        database,
+        secondary_abis,
+        scanning_window_size,
+        finalization_strategy,
+    )
+    .await?;
 
     loop {
-        let all_roots = fetch_logs(
+        let (mainnet_roots, secondary_roots) = fetch_logs(
             &mut mainnet_scanner,
             &mut secondary_scanners,
-            mainnet_address,
+            mainnet_contract,
         )
         .await?;
 
+        // Mainnet roots are final at this point, so they're ready to be
+        // propagated to the secondary chains.
+        let state_bridge_chain_ids = identity_manager.state_bridge_chain_ids();
+
+        for mainnet_root in &mainnet_roots {
+            database
+                .insert_root_transaction(
+                    &mainnet_root.root.into(),
+                    mainnet_root.transaction_hash,
+                    mainnet_root.block_number,
+                    mainnet_root.block_hash,
+                )
+                .await?;
+
+            record_batch_cost(database, identity_manager, mainnet_root).await?;
+
+            if !state_bridge_chain_ids.is_empty() {
+                database
+                    .insert_root_propagations(&mainnet_root.root.into(), &state_bridge_chain_ids)
+                    .await?;
+            }
+        }
+
+        let all_roots = mainnet_roots
+            .into_iter()
+            .map(|mainnet_root| mainnet_root.root)
+            .chain(secondary_roots)
+            .collect();
+
+        let mut final_blocks = HashMap::new();
+        final_blocks.insert(mainnet_address, mainnet_scanner.final_block().await?);
+        for (address, scanner) in &secondary_scanners {
+            final_blocks.insert(*address, scanner.final_block().await?);
+        }
+
         finalize_roots(
             database,
             identity_manager,
             processed_tree,
             finalized_tree,
             all_roots,
+            &final_blocks,
         )
         .await?;
 
-        tokio::time::sleep(time_between_scans).await;
+        // Only persisted once the roots are handled, so that they are scanned
+        // again if anything above fails.
+        database
+            .set_scan_cursor(mainnet_address, mainnet_scanner.next_block())
+            .await?;
+        for (address, scanner) in &secondary_scanners {
+            database
+                .set_scan_cursor(*address, scanner.next_block())
+                .await?;
+        }
+
+        // Catch up without waiting when resuming from far behind.
+        let behind = mainnet_scanner.next_block() < final_blocks[&mainnet_address]
+            || secondary_scanners
+                .iter()
+                .any(|(address, scanner)| scanner.next_block() < final_blocks[address]);
+
+        if !behind {
+            tokio::time::sleep(time_between_scans).await;
+        }
+    }
+}
+
+/// Resumes scanning the contract at `address` where the last scan left off,
+/// or starts at the latest final block if it was never scanned.
+async fn open_scanner<T>(
+    database: &Database,
+    read_provider: T,
+    address: Address,
+    scanning_window_size: u64,
+    finalization_strategy: FinalizationStrategy,
+) -> anyhow::Result<BlockScanner<T>>
+where
+    T: Middleware,
+    <T as Middleware>::Error: 'static,
+{
+    if let Some(next_block) = database.get_scan_cursor(address).await? {
+        info!(?address, next_block, "Resuming event scan");
+
+        return Ok(BlockScanner::new_at(
+            read_provider,
+            next_block,
+            scanning_window_size,
+            finalization_strategy,
+        ));
+    }
+
+    BlockScanner::new_latest(read_provider, scanning_window_size, finalization_strategy).await
+}
+
+/// Records the gas used by the transaction that published `mainnet_root`, if
+/// it is the result of a batch whose cost isn't recorded yet.
+async fn record_batch_cost(
+    database: &Database,
+    identity_manager: &IdentityManager,
+    mainnet_root: &MainnetRoot,
+) -> anyhow::Result<()> {
+    let root = mainnet_root.root.into();
+    if !database.is_batch_cost_missing(&root).await? {
+        return Ok(());
     }
+
+    let Some((gas_used, gas_price)) = identity_manager
+        .transaction_gas(mainnet_root.transaction_hash)
+        .await?
+    else {
+        warn!(
+            transaction_hash = ?mainnet_root.transaction_hash,
+            "No receipt for the transaction of a batch, not recording its cost"
+        );
+        return Ok(());
+    };
+
+    let (Ok(gas_used), Ok(gas_price)) = (u64::try_from(gas_used), u64::try_from(gas_price)) else {
+        warn!(
+            ?gas_used,
+            ?gas_price,
+            "Batch cost out of range, not recording it"
+        );
+        return Ok(());
+    };
+
+    database.set_batch_cost(&root, gas_used, gas_price).await?;
+
+    Ok(())
+}
+
+/// A root published to the identity manager on mainnet.
+struct MainnetRoot {
+    root:             U256,
+    transaction_hash: H256,
+    block_number:     u64,
+    block_hash:       H256,
 }
 
+/// Returns the roots published on mainnet and on the secondary chains.
 #[instrument(level = "info", skip_all)]
 async fn fetch_logs<A, B>(
     mainnet_scanner: &mut BlockScanner<A>,
     secondary_scanners: &mut HashMap<Address, BlockScanner<B>>,
-    mainnet_address: Address,
-) -> anyhow::Result<Vec<U256>>
+    mainnet_contract: &IdentityContract,
+) -> anyhow::Result<(Vec<MainnetRoot>, Vec<U256>)>
 where
     A: Middleware,
     <A as Middleware>::Error: 'static,
     B: Middleware,
     <B as Middleware>::Error: 'static,
 {
-    let mainnet_topics = [
-        Some(Topic::from(TreeChangedFilter::signature())),
-        None,
-        None,
-        None,
-    ];
+    let mainnet_topics = mainnet_contract.root_topics();
 
     let bridged_topics = [
         Some(Topic::from(RootAddedFilter::signature())),
@@ -122,10 +269,10 @@ where
         None,
     ];
 
-    let mainnet_address = Some(ValueOrArray::Value(mainnet_address));
+    let mainnet_address = Some(ValueOrArray::Value(mainnet_contract.address()));
 
     let mainnet_logs = mainnet_scanner
-        .next(mainnet_address, mainnet_topics.clone())
+        .next(mainnet_address, mainnet_topics)
         .await?;
     let mut secondary_logs = vec![];
 
@@ -137,10 +284,10 @@ where
         secondary_logs.extend(logs);
     }
 
-    let mut roots = extract_root_from_mainnet_logs(&mainnet_logs);
-    roots.extend(extract_roots_from_secondary_logs(&secondary_logs));
+    let mainnet_roots = extract_root_from_mainnet_logs(mainnet_contract, &mainnet_logs);
+    let secondary_roots = extract_roots_from_secondary_logs(&secondary_logs);
 
-    Ok(roots)
+    Ok((mainnet_roots, secondary_roots))
 }
 
 #[instrument(level = "info", skip_all)]
@@ -150,11 +297,19 @@ async fn finalize_roots(
     processed_tree: &TreeVersion<Intermediate>,
     finalized_tree: &TreeVersion<Canonical>,
     all_roots: Vec<U256>,
+    final_blocks: &HashMap<Address, u64>,
 ) -> Result<(), anyhow::Error> {
     for root in all_roots {
+        if database.get_root_state(&root.into()).await?.is_none() {
+            warn!(?root, "Root on chain is not in the database, skipping it");
+            continue;
+        }
+
         info!(?root, "Finalizing root");
 
-        let is_root_finalized = identity_manager.is_root_mined_multi_chain(root).await?;
+        let is_root_finalized = identity_manager
+            .is_root_mined_multi_chain(root, final_blocks)
+            .await?;
 
         if is_root_finalized {
             // What can sometimes happen is that this finalize roots function is faster
@@ -170,6 +325,9 @@ async fn finalize_roots(
 
             finalized_tree.apply_updates_up_to(root.into());
             database.mark_root_as_mined(&root.into()).await?;
+            database
+                .update_batch_status(&root.into(), BatchStatus::Finalized)
+                .await?;
 
             info!(?root, "Root finalized");
         }
@@ -179,8 +337,10 @@ async fn finalize_roots(
 }
 
 async fn init_secondary_scanners<T>(
+    database: &Database,
     providers: &[BridgedWorldId<T>],
     scanning_window_size: u64,
+    finalization_strategy: FinalizationStrategy,
 ) -> anyhow::Result<HashMap<Address, BlockScanner<Arc<T>>>>
 where
     T: Middleware,
@@ -189,24 +349,42 @@ where
     let mut secondary_scanners = HashMap::new();
 
     for bridged_abi in providers {
-        let scanner =
-            BlockScanner::new_latest(bridged_abi.client().clone(), scanning_window_size).await?;
-
         let address = bridged_abi.address();
 
+        let scanner = open_scanner(
+            database,
+            bridged_abi.client().clone(),
+            address,
+            scanning_window_size,
+            finalization_strategy,
+        )
+        .await?;
+
         secondary_scanners.insert(address, scanner);
     }
 
     Ok(secondary_scanners)
 }
 
-fn extract_root_from_mainnet_logs(logs: &[Log]) -> Vec<U256> {
+fn extract_root_from_mainnet_logs(contract: &IdentityContract, logs: &[Log]) -> Vec<MainnetRoot> {
     let mut roots = vec![];
 
     for log in logs {
         let raw_log = RawLog::from((log.topics.clone(), log.data.to_vec()));
-        if let Ok(event) = TreeChangedFilter::decode_log(&raw_log) {
-            roots.push(event.post_root);
+        if let Some(root) = contract.decode_root(&raw_log) {
+            // Logs returned by a filter are always part of a mined block.
+            let (Some(transaction_hash), Some(block_number), Some(block_hash)) =
+                (log.transaction_hash, log.block_number, log.block_hash)
+            else {
+                continue;
+            };
+
+            roots.push(MainnetRoot {
+                root,
+                transaction_hash,
+                block_number: block_number.as_u64(),
+                block_hash,
+            });
         }
     }
 
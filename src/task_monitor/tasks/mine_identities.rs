@@ -1,19 +1,42 @@
 use std::sync::Arc;
 
 use anyhow::Result as AnyhowResult;
+use once_cell::sync::Lazy;
+use prometheus::{exponential_buckets, register_histogram, Histogram};
 use tracing::{info, instrument};
 
 use crate::contracts::{IdentityManager, SharedIdentityManager};
+use crate::database::types::BatchStatus;
 use crate::database::Database;
+use crate::events::Event;
 use crate::identity_tree::{Intermediate, TreeVersion, TreeWithNextVersion};
 use crate::task_monitor::{PendingBatchSubmission, TaskMonitor};
 use crate::utils::async_queue::{AsyncPopGuard, AsyncQueue};
 
+static CONFIRMATION_TIME: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "transaction_confirmation_seconds",
+        "The time a submitted batch waited to be mined in seconds",
+        exponential_buckets(1.0, 2.0, 12).unwrap()
+    )
+    .unwrap()
+});
+
+static INCLUSION_LATENCY: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "inclusion_latency_seconds",
+        "The time from receiving an identity to mining it in seconds",
+        exponential_buckets(15.0, 2.0, 12).unwrap()
+    )
+    .unwrap()
+});
+
 pub struct MineIdentities {
     database: Arc<Database>,
     identity_manager: SharedIdentityManager,
     mined_tree: TreeVersion<Intermediate>,
     pending_batch_submissions_queue: AsyncQueue<PendingBatchSubmission>,
+    publish_events: bool,
 }
 
 impl MineIdentities {
@@ -22,12 +45,14 @@ impl MineIdentities {
         identity_manager: SharedIdentityManager,
         mined_tree: TreeVersion<Intermediate>,
         pending_batch_submissions_queue: AsyncQueue<PendingBatchSubmission>,
+        publish_events: bool,
     ) -> Arc<Self> {
         Arc::new(Self {
             database,
             identity_manager,
             mined_tree,
             pending_batch_submissions_queue,
+            publish_events,
         })
     }
 
@@ -37,6 +62,7 @@ impl MineIdentities {
             &self.identity_manager,
             &self.mined_tree,
             &self.pending_batch_submissions_queue,
+            self.publish_events,
         )
         .await
     }
@@ -47,11 +73,19 @@ async fn mine_identities_loop(
     identity_manager: &IdentityManager,
     mined_tree: &TreeVersion<Intermediate>,
     pending_batch_submissions_queue: &AsyncQueue<PendingBatchSubmission>,
+    publish_events: bool,
 ) -> AnyhowResult<()> {
     loop {
         let pending_identity = pending_batch_submissions_queue.pop().await;
 
-        mine_identities(&pending_identity, database, identity_manager, mined_tree).await?;
+        mine_identities(
+            &pending_identity,
+            database,
+            identity_manager,
+            mined_tree,
+            publish_events,
+        )
+        .await?;
 
         pending_identity.commit().await;
     }
@@ -63,6 +97,7 @@ async fn mine_identities(
     database: &Database,
     identity_manager: &IdentityManager,
     mined_tree: &TreeVersion<Intermediate>,
+    publish_events: bool,
 ) -> AnyhowResult<()> {
     let PendingBatchSubmission {
         transaction_id,
@@ -79,23 +114,43 @@ async fn mine_identities(
         "Mining batch"
     );
 
-    if !identity_manager
+    let timer = CONFIRMATION_TIME.start_timer();
+    let mined = identity_manager
         .mine_identities(transaction_id.clone())
-        .await?
-    {
-        panic!(
-            "Transaction {} failed on chain - sequencer will crash and restart",
-            transaction_id
-        );
-    }
+        .await?;
+    timer.observe_duration();
+
+    assert!(
+        mined,
+        "Transaction {transaction_id} failed on chain - sequencer will crash and restart"
+    );
 
     // With this done, all that remains is to mark them as submitted to the
     // blockchain in the source-of-truth database, and also update the mined tree to
     // agree with the database and chain.
     database.mark_root_as_processed(&post_root.into()).await?;
+    database.remove_prover_results(&post_root.into()).await?;
+    database
+        .update_batch_status(&post_root.into(), BatchStatus::Mined)
+        .await?;
 
     info!(start_index, ?pre_root, ?post_root, "Batch mined");
 
+    for latency in database.get_inclusion_latencies(&post_root.into()).await? {
+        INCLUSION_LATENCY.observe(latency);
+    }
+
+    if publish_events {
+        let event = Event::BatchMined {
+            pre_root: pre_root.into(),
+            post_root: post_root.into(),
+            start_index,
+        };
+        database
+            .insert_event(&serde_json::to_string(&event)?)
+            .await?;
+    }
+
     let updates_count = mined_tree.apply_updates_up_to(post_root.into());
 
     info!(
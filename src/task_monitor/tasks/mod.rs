@@ -1,4 +1,11 @@
+pub mod check_integrity;
+pub mod deliver_events;
+pub mod expire_reservations;
 pub mod finalize_identities;
 pub mod insert_identities;
 pub mod mine_identities;
+pub mod monitor_balance;
+pub mod monitor_provers;
+pub mod monitor_roots;
 pub mod process_identities;
+pub mod propagate_roots;
@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result as AnyhowResult;
+use ethers::types::U256;
+use ethers::utils::format_units;
+use once_cell::sync::Lazy;
+use prometheus::{register_gauge_vec, register_int_gauge, GaugeVec, IntGauge};
+use tracing::{error, info, warn};
+
+use crate::contracts::SharedIdentityManager;
+
+static SIGNER_BALANCE: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!("signer_balance", "Balance of each signer in ether.", &[
+        "address"
+    ])
+    .unwrap()
+});
+
+static INSUFFICIENT_FUNDS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "insufficient_funds",
+        "Whether batch submissions are paused because the owner is out of funds."
+    )
+    .unwrap()
+});
+
+/// Polls the balance of every signer. Warns when a signer runs low, and
+/// pauses batch submissions while the signer that submits batches can't pay
+/// for them, instead of failing with RPC errors. Identities are still
+/// accepted in the meantime. Scheduled every
+/// `--balance-check-interval-seconds`.
+pub struct MonitorBalance {
+    identity_manager:   SharedIdentityManager,
+    insufficient_funds: Arc<AtomicBool>,
+    warning_balance:    U256,
+    minimum_balance:    U256,
+}
+
+impl MonitorBalance {
+    pub fn new(
+        identity_manager: SharedIdentityManager,
+        insufficient_funds: Arc<AtomicBool>,
+        warning_balance: U256,
+        minimum_balance: U256,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            identity_manager,
+            insufficient_funds,
+            warning_balance,
+            minimum_balance,
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        let owner = self.identity_manager.owner();
+
+        for (address, balance) in self.identity_manager.signer_balances().await? {
+            let ether = format_units(balance, "ether")?;
+            SIGNER_BALANCE
+                .with_label_values(&[&format!("{address:?}")])
+                .set(ether.parse().unwrap_or_default());
+
+            if address != owner {
+                if balance < self.warning_balance {
+                    warn!(?address, %ether, "Signer balance is low.");
+                }
+                continue;
+            }
+
+            if balance < self.minimum_balance {
+                if !self.insufficient_funds.swap(true, Ordering::SeqCst) {
+                    error!(
+                        ?address,
+                        %ether,
+                        "CRITICAL: Signer is out of funds. Batch submissions are paused until it is funded."
+                    );
+                }
+            } else {
+                if self.insufficient_funds.swap(false, Ordering::SeqCst) {
+                    info!(?address, %ether, "Signer was funded, resuming batch submissions.");
+                }
+
+                if balance < self.warning_balance {
+                    warn!(?address, %ether, "Signer balance is low.");
+                }
+            }
+        }
+
+        INSUFFICIENT_FUNDS.set(i64::from(self.insufficient_funds.load(Ordering::SeqCst)));
+
+        Ok(())
+    }
+}
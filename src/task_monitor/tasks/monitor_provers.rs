@@ -0,0 +1,23 @@
+use std::sync::Arc;
+
+use anyhow::Result as AnyhowResult;
+
+use crate::contracts::SharedIdentityManager;
+
+/// Checks the health of every prover. Scheduled every
+/// `--prover-health-check-interval-seconds`.
+pub struct MonitorProvers {
+    identity_manager: SharedIdentityManager,
+}
+
+impl MonitorProvers {
+    pub fn new(identity_manager: SharedIdentityManager) -> Arc<Self> {
+        Arc::new(Self { identity_manager })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        self.identity_manager.check_prover_health().await;
+
+        Ok(())
+    }
+}
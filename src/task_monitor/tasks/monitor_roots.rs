@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result as AnyhowResult;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge, IntGauge};
+use tracing::{error, info, warn};
+
+use crate::contracts::SharedIdentityManager;
+use crate::identity_tree::{Hash, Intermediate, TreeVersion, TreeVersionReadOps};
+
+static ROOT_MISMATCH: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "root_mismatch",
+        "Whether the root of the identity manager diverged from the processed tree."
+    )
+    .unwrap()
+});
+
+/// Compares the latest root of the identity manager with the root of the
+/// processed tree. If they diverge for longer than the grace period, something
+/// other than this sequencer has written to the tree, so submissions are
+/// stopped until the roots agree again. Scheduled every
+/// `--root-check-interval-seconds`.
+pub struct MonitorRoots {
+    identity_manager: SharedIdentityManager,
+    processed_tree:   TreeVersion<Intermediate>,
+    root_mismatch:    Arc<AtomicBool>,
+    grace_period:     Duration,
+    /// When the roots were first seen to differ. They briefly do whenever a
+    /// batch has been mined but not yet processed by the sequencer.
+    diverged_since:   Mutex<Option<Instant>>,
+}
+
+impl MonitorRoots {
+    pub fn new(
+        identity_manager: SharedIdentityManager,
+        processed_tree: TreeVersion<Intermediate>,
+        root_mismatch: Arc<AtomicBool>,
+        grace_period: Duration,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            identity_manager,
+            processed_tree,
+            root_mismatch,
+            grace_period,
+            diverged_since: Mutex::new(None),
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        let chain_root: Hash = self.identity_manager.latest_root().await?.into();
+        let local_root = self.processed_tree.get_root();
+        let root_mismatch = &self.root_mismatch;
+
+        if chain_root == local_root {
+            if root_mismatch.swap(false, Ordering::SeqCst) {
+                info!(?chain_root, "Roots reconciled, resuming batch submissions.");
+            }
+
+            ROOT_MISMATCH.set(0);
+            *self.diverged_since.lock().expect("no lock poisoning") = None;
+            return Ok(());
+        }
+
+        let since = *self
+            .diverged_since
+            .lock()
+            .expect("no lock poisoning")
+            .get_or_insert_with(Instant::now);
+        if since.elapsed() < self.grace_period {
+            warn!(
+                ?chain_root,
+                ?local_root,
+                "Identity manager root differs from processed root."
+            );
+            return Ok(());
+        }
+
+        if !root_mismatch.swap(true, Ordering::SeqCst) {
+            error!(
+                ?chain_root,
+                ?local_root,
+                diverged_for = ?since.elapsed(),
+                "CRITICAL: Identity manager root diverged from processed root. Batch submissions are stopped until the roots are reconciled."
+            );
+        }
+
+        ROOT_MISMATCH.set(1);
+
+        Ok(())
+    }
+}
@@ -1,24 +1,34 @@
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
 use anyhow::Result as AnyhowResult;
+use chrono::Utc;
 use ethers::types::U256;
 use once_cell::sync::Lazy;
-use prometheus::{register_histogram, Histogram};
+use prometheus::{
+    exponential_buckets, register_histogram, register_int_counter, register_int_counter_vec,
+    Histogram, IntCounter, IntCounterVec,
+};
 use semaphore::poseidon_tree::Branch;
-use tokio::sync::Notify;
+use tokio::sync::{watch, Mutex, Notify};
 use tokio::{select, time};
 use tracing::{debug, error, info, instrument, warn};
 
 use crate::contracts::{IdentityManager, SharedIdentityManager};
+use crate::database::types::BatchStatus;
 use crate::database::Database;
 use crate::identity_tree::{
     AppliedTreeUpdate, Intermediate, TreeVersion, TreeVersionReadOps, TreeWithNextVersion,
 };
-use crate::prover::batch_insertion::Identity;
+use crate::prover::batch_insertion::{self, Identity, ProverRejection};
 use crate::prover::map::ReadOnlyInsertionProver;
+use crate::prover::Proof;
+use crate::task_monitor::batching_policy::BatchingPolicy;
+use crate::task_monitor::submission_windows::SubmissionWindows;
 use crate::task_monitor::{PendingBatchSubmission, TaskMonitor};
 use crate::utils::async_queue::AsyncQueue;
+use crate::utils::exemplars::ExemplarHistogram;
 
 /// The number of seconds either side of the timer tick to treat as enough to
 /// trigger a forced batch insertion.
@@ -32,11 +42,51 @@ static PENDING_IDENTITIES_CHANNEL_CAPACITY: Lazy<Histogram> = Lazy::new(|| {
     .unwrap()
 });
 
+static PADDED_LEAVES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "padded_leaves",
+        "Zero leaves added to fill batches up to the batch size of the prover"
+    )
+    .unwrap()
+});
+
+static LATENCY_BUDGET_FALLBACKS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "latency_budget_fallbacks",
+        "Batches submitted in a smaller batch size to stay within the latency budget",
+        &["batch_size"]
+    )
+    .unwrap()
+});
+
+static BATCH_FLUSHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "batch_flushes",
+        "Batches flushed to the prover, by flush policy and what triggered the flush",
+        &["policy", "trigger"]
+    )
+    .unwrap()
+});
+
+static BATCH_SUBMISSION_LATENCY: Lazy<ExemplarHistogram> = Lazy::new(|| {
+    ExemplarHistogram::new(
+        "batch_submission_latency_seconds",
+        "The time to submit a proven batch to the identity manager in seconds.",
+        exponential_buckets(0.1, 1.5, 25).unwrap(),
+    )
+});
+
 pub struct ProcessIdentities {
     database: Arc<Database>,
     identity_manager: SharedIdentityManager,
     batching_tree: TreeVersion<Intermediate>,
-    batch_insert_timeout_secs: u64,
+    batch_timeout: watch::Receiver<Duration>,
+    batching_policy: watch::Receiver<BatchingPolicy>,
+    submission_windows: Option<SubmissionWindows>,
+    paused: watch::Receiver<bool>,
+    root_mismatch: Arc<AtomicBool>,
+    insufficient_funds: Arc<AtomicBool>,
+    latest_tree_lock: Arc<Mutex<()>>,
     pending_batch_submissions_queue: AsyncQueue<PendingBatchSubmission>,
     wake_up_notify: Arc<Notify>,
 }
@@ -46,7 +96,13 @@ impl ProcessIdentities {
         database: Arc<Database>,
         identity_manager: SharedIdentityManager,
         batching_tree: TreeVersion<Intermediate>,
-        batch_insert_timeout_secs: u64,
+        batch_timeout: watch::Receiver<Duration>,
+        batching_policy: watch::Receiver<BatchingPolicy>,
+        submission_windows: Option<SubmissionWindows>,
+        paused: watch::Receiver<bool>,
+        root_mismatch: Arc<AtomicBool>,
+        insufficient_funds: Arc<AtomicBool>,
+        latest_tree_lock: Arc<Mutex<()>>,
         pending_batch_submissions_queue: AsyncQueue<PendingBatchSubmission>,
         wake_up_notify: Arc<Notify>,
     ) -> Arc<Self> {
@@ -54,7 +110,13 @@ impl ProcessIdentities {
             database,
             identity_manager,
             batching_tree,
-            batch_insert_timeout_secs,
+            batch_timeout,
+            batching_policy,
+            submission_windows,
+            paused,
+            root_mismatch,
+            insufficient_funds,
+            latest_tree_lock,
             pending_batch_submissions_queue,
             wake_up_notify,
         })
@@ -67,7 +129,13 @@ impl ProcessIdentities {
             &self.batching_tree,
             &self.wake_up_notify,
             &self.pending_batch_submissions_queue,
-            self.batch_insert_timeout_secs,
+            &self.batch_timeout,
+            &self.batching_policy,
+            self.submission_windows.as_ref(),
+            &self.paused,
+            &self.root_mismatch,
+            &self.insufficient_funds,
+            &self.latest_tree_lock,
         )
         .await
     }
@@ -79,16 +147,40 @@ async fn process_identities(
     batching_tree: &TreeVersion<Intermediate>,
     wake_up_notify: &Notify,
     pending_batch_submissions_queue: &AsyncQueue<PendingBatchSubmission>,
-    timeout_secs: u64,
+    batch_timeout: &watch::Receiver<Duration>,
+    batching_policy: &watch::Receiver<BatchingPolicy>,
+    submission_windows: Option<&SubmissionWindows>,
+    paused: &watch::Receiver<bool>,
+    root_mismatch: &AtomicBool,
+    insufficient_funds: &AtomicBool,
+    latest_tree_lock: &Mutex<()>,
 ) -> AnyhowResult<()> {
     info!("Awaiting for a clean slate");
     identity_manager.await_clean_slate().await?;
 
+    // Proven batches are submitted right away, so they wait for batching to be
+    // resumed too.
+    let mut paused = paused.clone();
+    while *paused.borrow_and_update() {
+        info!("Batching is paused, waiting for it to be resumed.");
+        paused.changed().await?;
+    }
+
+    resume_proven_batches(
+        database,
+        identity_manager,
+        batching_tree,
+        pending_batch_submissions_queue,
+        latest_tree_lock,
+    )
+    .await?;
+
     info!("Starting identity processor.");
-    let batch_size = identity_manager.max_batch_size().await;
 
     // We start a timer and force it to perform one initial tick to avoid an
     // immediate trigger.
+    let mut batch_timeout = batch_timeout.clone();
+    let mut timeout_secs = batch_timeout.borrow_and_update().as_secs();
     let mut timer = time::interval(Duration::from_secs(timeout_secs));
     timer.tick().await;
 
@@ -103,6 +195,11 @@ async fn process_identities(
     let mut last_batch_time: SystemTime = SystemTime::now();
 
     loop {
+        // The policy can change at runtime, so a copy is taken on every
+        // iteration.
+        let policy = *batching_policy.borrow();
+        let batching_policy = &policy;
+
         // We ping-pong between two cases for being woken. This ensures that there is a
         // maximum time that users can wait for their identity commitment to be
         // processed, but also that we are not inefficient with on-chain gas by being
@@ -111,10 +208,35 @@ async fn process_identities(
             _ = timer.tick() => {
                 debug!("Identity batch insertion woken due to timeout.");
 
+                if *paused.borrow() {
+                    warn!("Not submitting batches while batching is paused.");
+                    continue;
+                }
+
+                if root_mismatch.load(Ordering::SeqCst) {
+                    warn!("Not submitting batches until the identity manager root is reconciled.");
+                    continue;
+                }
+
+                if insufficient_funds.load(Ordering::SeqCst) {
+                    warn!("Not submitting batches until the signer is funded.");
+                    continue;
+                }
+
+                if !is_submission_window_open(submission_windows) {
+                    debug!("Not submitting batches outside of the submission windows.");
+                    continue;
+                }
+
                 // If the timer has fired we want to insert whatever
                 // identities we have, even if it's not many. This ensures
                 // a minimum quality of service for API users.
-                let updates = batching_tree.peek_next_updates(batch_size);
+                //
+                // Batch sizes can change at runtime, so we look them up on every
+                // tick. The smallest prover that fits the updates is used, with
+                // the remaining slots padded.
+                let max_batch_size = identity_manager.max_batch_size().await;
+                let updates = batching_tree.peek_next_updates(max_batch_size);
                 if updates.is_empty() {
                     continue;
                 }
@@ -126,12 +248,22 @@ async fn process_identities(
                     updates.len(),
                     prover.batch_size()
                 );
+                log_flush(
+                    batching_policy,
+                    "timeout",
+                    updates.len(),
+                    max_batch_size,
+                    updates.len(),
+                    None,
+                    timeout_secs,
+                );
 
                 commit_identities(
                     database,
                     identity_manager,
                     batching_tree,
                     pending_batch_submissions_queue,
+                    latest_tree_lock,
                     &updates,
                     prover
                 ).await?;
@@ -144,6 +276,17 @@ async fn process_identities(
             _ = wake_up_notify.notified() => {
                 tracing::trace!("Identity batch insertion woken due to request.");
 
+                // The timer keeps ticking, so batches are submitted again soon
+                // after the roots are reconciled, the signer is funded or a
+                // submission window opens.
+                if *paused.borrow()
+                    || root_mismatch.load(Ordering::SeqCst)
+                    || insufficient_funds.load(Ordering::SeqCst)
+                    || !is_submission_window_open(submission_windows)
+                {
+                    continue;
+                }
+
                 // Capture the time difference since the last batch, and compute
                 // whether we want to insert anyway. We do this if the difference
                 // is less than some debounce threshold.
@@ -161,23 +304,75 @@ async fn process_identities(
                 let should_process_anyway =
                     timeout_secs.abs_diff(diff_secs) <= DEBOUNCE_THRESHOLD_SECS;
 
-                // We have _at most_ one complete batch here.
-                let updates = batching_tree.peek_next_updates(batch_size);
-
-                // If there are not enough identities to insert at this
-                // stage we can wait. The timer will ensure that the API
-                // clients do not wait too long for their submission to be
-                // completed.
-                if updates.len() < batch_size && !should_process_anyway {
-                    // We do not reset the timer here as we may want to
-                    // insert anyway soon.
-                    tracing::trace!(
-                        "Pending identities ({}) is less than batch size ({}). Waiting.",
-                        updates.len(),
-                        batch_size
-                    );
+                let batch_sizes = identity_manager.batch_sizes().await;
+                let Some(max_batch_size) = batch_sizes.last().copied() else {
+                    warn!("No provers available, not submitting batches.");
                     continue;
-                }
+                };
+
+                let mut updates =
+                    batching_tree.peek_next_updates(batching_policy.lookahead(max_batch_size));
+                let pending = updates.len();
+
+                let gas_price = if batching_policy.uses_gas_price() {
+                    Some(identity_manager.gas_price().await?)
+                } else {
+                    None
+                };
+
+                // If there are not enough identities to insert at this stage,
+                // or gas is too expensive, we can wait. The timer will ensure
+                // that the API clients do not wait too long for their
+                // submission to be completed.
+                let decision = batching_policy.batch_size(pending, &batch_sizes, gas_price);
+                let (size, trigger) = match decision {
+                    Some(size) => (size, "batch_size"),
+                    None if should_process_anyway => (max_batch_size, "timeout"),
+                    None => {
+                        let fallback_size = latency_fallback(
+                            database,
+                            batching_policy,
+                            &updates,
+                            &batch_sizes,
+                            gas_price,
+                        ).await?;
+
+                        if let Some(size) = fallback_size {
+                            info!(
+                                pending,
+                                max_batch_size,
+                                size,
+                                "Submitting a smaller batch to stay within the latency budget."
+                            );
+                            LATENCY_BUDGET_FALLBACKS
+                                .with_label_values(&[&size.to_string()])
+                                .inc();
+                            (size, "latency_budget")
+                        } else {
+                            // We do not reset the timer here as we may want to
+                            // insert anyway soon.
+                            tracing::trace!(
+                                pending,
+                                max_batch_size,
+                                ?gas_price,
+                                "Batching policy decided to wait."
+                            );
+                            continue;
+                        }
+                    }
+                };
+
+                // We have _at most_ one complete batch here.
+                updates.truncate(size);
+                log_flush(
+                    batching_policy,
+                    trigger,
+                    pending,
+                    max_batch_size,
+                    updates.len(),
+                    gas_price,
+                    timeout_secs,
+                );
 
                 let prover = identity_manager.get_suitable_prover(updates.len()).await?;
 
@@ -186,6 +381,7 @@ async fn process_identities(
                     identity_manager,
                     batching_tree,
                     pending_batch_submissions_queue,
+                    latest_tree_lock,
                     &updates,
                     prover
                 ).await?;
@@ -199,16 +395,152 @@ async fn process_identities(
                 // We want to check if there's a full batch available immediately
                 wake_up_notify.notify_one();
             }
+            Ok(()) = paused.changed() => {
+                if !*paused.borrow_and_update() {
+                    info!("Batching resumed.");
+                    wake_up_notify.notify_one();
+                }
+            }
+            Ok(()) = batch_timeout.changed() => {
+                timeout_secs = batch_timeout.borrow_and_update().as_secs();
+                info!(timeout_secs, "Batch timeout changed.");
+
+                // The new timeout applies from now on, so it never takes
+                // longer than the new timeout for a batch to be submitted.
+                timer = time::interval(Duration::from_secs(timeout_secs));
+                timer.tick().await;
+            }
         }
     }
 }
 
+fn is_submission_window_open(submission_windows: Option<&SubmissionWindows>) -> bool {
+    submission_windows.map_or(true, |windows| windows.is_open(Utc::now()))
+}
+
+/// Logs the inputs of the decision to flush a batch of `size` identities, so
+/// that the flush policy can be tuned for throughput or cost.
+fn log_flush(
+    batching_policy: &BatchingPolicy,
+    trigger: &str,
+    pending: usize,
+    max_batch_size: usize,
+    size: usize,
+    gas_price: Option<U256>,
+    timeout_secs: u64,
+) {
+    let policy = batching_policy.flush_policy().name();
+
+    info!(
+        policy,
+        trigger,
+        pending,
+        max_batch_size,
+        size,
+        ?gas_price,
+        timeout_secs,
+        latency_budget = ?batching_policy.latency_budget(),
+        "Flushing batch."
+    );
+    BATCH_FLUSHES.with_label_values(&[policy, trigger]).inc();
+}
+
+/// Asks the batching policy for a smaller batch size, based on how long the
+/// first of `updates` has waited and the recent arrival rate. Returns `None`
+/// without querying the database if there is no latency budget.
+async fn latency_fallback(
+    database: &Database,
+    batching_policy: &BatchingPolicy,
+    updates: &[AppliedTreeUpdate],
+    batch_sizes: &[usize],
+    gas_price: Option<U256>,
+) -> AnyhowResult<Option<usize>> {
+    let (Some(latency_budget), Some(first)) = (batching_policy.latency_budget(), updates.first())
+    else {
+        return Ok(None);
+    };
+
+    let (oldest_wait, arrivals) = database
+        .get_arrival_stats(first.update.leaf_index, latency_budget)
+        .await?;
+
+    #[allow(clippy::cast_precision_loss)]
+    let arrival_rate = arrivals as f64 / latency_budget.as_secs_f64().max(1.0);
+
+    Ok(batching_policy.latency_fallback(
+        updates.len(),
+        batch_sizes,
+        gas_price,
+        oldest_wait.unwrap_or_default(),
+        arrival_rate,
+    ))
+}
+
+/// Submits the batches that were proven but not submitted before a restart,
+/// reusing their stored proofs. Batches that no longer line up with the
+/// batching tree are marked as failed and their identities are batched again.
+#[instrument(level = "info", skip_all)]
+async fn resume_proven_batches(
+    database: &Database,
+    identity_manager: &IdentityManager,
+    batching_tree: &TreeVersion<Intermediate>,
+    pending_batch_submissions_queue: &AsyncQueue<PendingBatchSubmission>,
+    latest_tree_lock: &Mutex<()>,
+) -> AnyhowResult<()> {
+    for batch in database.get_batches_by_status(BatchStatus::Proven).await? {
+        let identity_count = batch.end_index - batch.start_index;
+        let updates = batching_tree.peek_next_updates(identity_count);
+
+        let resumable = batching_tree.get_root() == batch.pre_root
+            && updates.len() == identity_count
+            && updates.first().map(|update| update.update.leaf_index) == Some(batch.start_index)
+            && updates.last().map(|update| update.result.root()) == Some(batch.post_root);
+
+        if !resumable {
+            warn!(
+                start_index = batch.start_index,
+                post_root = ?batch.post_root,
+                "Proven batch no longer matches the batching tree, batching its identities again."
+            );
+
+            database
+                .update_batch_status(&batch.post_root, BatchStatus::Failed)
+                .await?;
+            continue;
+        }
+
+        info!(
+            start_index = batch.start_index,
+            post_root = ?batch.post_root,
+            "Resuming proven batch"
+        );
+
+        let prover = identity_manager
+            .get_suitable_prover(batch.batch_size)
+            .await?;
+
+        commit_identities(
+            database,
+            identity_manager,
+            batching_tree,
+            pending_batch_submissions_queue,
+            latest_tree_lock,
+            &updates,
+            prover,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
 #[instrument(level = "info", skip_all)]
 async fn commit_identities(
     database: &Database,
     identity_manager: &IdentityManager,
     batching_tree: &TreeVersion<Intermediate>,
     pending_batch_submissions_queue: &AsyncQueue<PendingBatchSubmission>,
+    latest_tree_lock: &Mutex<()>,
     updates: &[AppliedTreeUpdate],
     insertion_prover: ReadOnlyInsertionProver<'_>,
 ) -> AnyhowResult<()> {
@@ -289,6 +621,12 @@ async fn commit_identities(
         let padding = batch_size - commitment_count;
         commitments.append(&mut vec![U256::zero(); padding]);
 
+        debug!(
+            commitment_count,
+            batch_size, padding, "Padding batch with zero leaves."
+        );
+        PADDED_LEAVES.inc_by(padding as u64);
+
         for i in start_index..(start_index + padding) {
             let proof = latest_tree_from_updates.proof(i);
             merkle_proofs.push(proof);
@@ -327,19 +665,59 @@ async fn commit_identities(
 
     identity_manager.validate_merkle_proofs(&identity_commitments)?;
 
-    // We prepare the proof before reserving a slot in the pending identities
-    let proof = IdentityManager::prepare_proof(
-        insertion_prover,
-        start_index,
+    // A batch with the same inputs may already have been proven before a failed
+    // attempt at submitting it, in which case there's no need to prove it again.
+    let input_hash = batch_insertion::compute_input_hash(
+        start_index.try_into()?,
         pre_root,
         post_root,
-        &identity_commitments,
-    )
-    .await
-    .map_err(|e| {
-        error!(?e, "Failed to prepare proof.");
-        e
-    })?;
+        &commitments,
+    );
+
+    database
+        .insert_batch(
+            &pre_root.into(),
+            &post_root.into(),
+            start_index,
+            start_index + commitment_count,
+            batch_size,
+        )
+        .await?;
+
+    // We prepare the proof before reserving a slot in the pending identities
+    let proof = if let Some(proof) = database.get_prover_result(input_hash).await? {
+        info!(
+            start_index,
+            ?pre_root,
+            ?post_root,
+            "Reusing previously generated proof"
+        );
+
+        database
+            .mark_batch_as_proven(&post_root.into(), None, &proof)
+            .await?;
+
+        proof
+    } else {
+        let (proof, prover_url) = prove_batch(
+            database,
+            insertion_prover,
+            start_index,
+            pre_root,
+            post_root,
+            &identity_commitments,
+        )
+        .await?;
+
+        database
+            .insert_prover_result(input_hash, &post_root.into(), &proof)
+            .await?;
+        database
+            .mark_batch_as_proven(&post_root.into(), Some(&prover_url), &proof)
+            .await?;
+
+        proof
+    };
 
     #[allow(clippy::cast_precision_loss)]
     PENDING_IDENTITIES_CHANNEL_CAPACITY.observe(pending_batch_submissions_queue.len().await as f64);
@@ -349,11 +727,29 @@ async fn commit_identities(
     // identities to mine.
     let permit = pending_batch_submissions_queue.reserve().await;
 
+    // The batch may have been cancelled while it was being proven or waiting
+    // for a slot.
+    if !database.start_batch_submission(&post_root.into()).await? {
+        warn!(
+            start_index,
+            ?pre_root,
+            ?post_root,
+            "Batch was cancelled, not submitting it"
+        );
+
+        // Wait for the cancellation to remove the identities from the latest tree,
+        // so that they aren't batched again.
+        drop(latest_tree_lock.lock().await);
+
+        return Ok(());
+    }
+
     info!(start_index, ?pre_root, ?post_root, "Submitting batch");
 
     // With all the data prepared we can submit the identities to the on-chain
     // identity manager and wait for that transaction to be mined.
-    let transaction_id = identity_manager
+    let submission_timer = BATCH_SUBMISSION_LATENCY.start_timer();
+    let transaction_id = match identity_manager
         .register_identities(
             start_index,
             pre_root,
@@ -362,10 +758,21 @@ async fn commit_identities(
             proof,
         )
         .await
-        .map_err(|e| {
+    {
+        Ok(transaction_id) => transaction_id,
+        Err(e) => {
             error!(?e, "Failed to insert identity to contract.");
-            e
-        })?;
+            database
+                .update_batch_status(&post_root.into(), BatchStatus::Failed)
+                .await?;
+            return Err(e);
+        }
+    };
+    submission_timer.observe_duration();
+
+    database
+        .mark_batch_as_submitted(&post_root.into(), transaction_id.as_ref())
+        .await?;
 
     info!(
         start_index,
@@ -394,3 +801,48 @@ async fn commit_identities(
 
     Ok(())
 }
+
+async fn prove_batch(
+    database: &Database,
+    insertion_prover: ReadOnlyInsertionProver<'_>,
+    start_index: usize,
+    pre_root: U256,
+    post_root: U256,
+    identity_commitments: &[Identity],
+) -> AnyhowResult<(Proof, String)> {
+    match IdentityManager::prepare_proof(
+        insertion_prover,
+        start_index,
+        pre_root,
+        post_root,
+        identity_commitments,
+    )
+    .await
+    {
+        Ok(proof) => Ok(proof),
+        Err(e) => {
+            error!(?e, "Failed to prepare proof.");
+
+            database
+                .update_batch_status(&post_root.into(), BatchStatus::Failed)
+                .await?;
+
+            // Keep the full response of the prover around, as the error itself only
+            // carries a summary of what went wrong.
+            if let Some(rejection) = e.downcast_ref::<ProverRejection>() {
+                database
+                    .insert_failed_batch(
+                        &pre_root.into(),
+                        &post_root.into(),
+                        start_index,
+                        &rejection.url,
+                        rejection.status,
+                        &rejection.body,
+                    )
+                    .await?;
+            }
+
+            Err(e)
+        }
+    }
+}
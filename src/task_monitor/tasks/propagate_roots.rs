@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use anyhow::Result as AnyhowResult;
+use tracing::{info, warn};
+
+use crate::contracts::{IdentityManager, SharedIdentityManager};
+use crate::database::types::PropagationStatus;
+use crate::database::Database;
+
+/// Propagates the latest final root to the secondary chains with a state
+/// bridge. Scheduled every `--root-propagation-interval-seconds`.
+pub struct PropagateRoots {
+    database:         Arc<Database>,
+    identity_manager: SharedIdentityManager,
+}
+
+impl PropagateRoots {
+    pub fn new(database: Arc<Database>, identity_manager: SharedIdentityManager) -> Arc<Self> {
+        Arc::new(Self {
+            database,
+            identity_manager,
+        })
+    }
+
+    pub async fn run(self: Arc<Self>) -> AnyhowResult<()> {
+        for chain_id in self.identity_manager.state_bridge_chain_ids() {
+            confirm_propagations(&self.database, &self.identity_manager, chain_id).await?;
+            propagate_latest_root(&self.database, &self.identity_manager, chain_id).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Marks the submitted roots that arrived on the secondary chain as
+/// propagated. Bridges deliver roots in order, so submitted roots that are
+/// older than a propagated one never will be.
+async fn confirm_propagations(
+    database: &Database,
+    identity_manager: &IdentityManager,
+    chain_id: u64,
+) -> AnyhowResult<()> {
+    let submitted = database
+        .get_root_propagations_by_status(chain_id, PropagationStatus::Submitted)
+        .await?;
+
+    for propagation in submitted.iter().rev() {
+        if !identity_manager
+            .is_root_on_secondary_chain(chain_id, propagation.root.into())
+            .await?
+        {
+            continue;
+        }
+
+        info!(chain_id, root = ?propagation.root, "Root propagated");
+
+        database
+            .update_root_propagation(propagation.id, PropagationStatus::Propagated, None)
+            .await?;
+        database
+            .supersede_root_propagations(chain_id, propagation.id, PropagationStatus::Submitted)
+            .await?;
+
+        break;
+    }
+
+    Ok(())
+}
+
+/// Bridges the latest pending root. The state bridge always propagates the
+/// latest root of the identity manager, so older pending roots are superseded.
+async fn propagate_latest_root(
+    database: &Database,
+    identity_manager: &IdentityManager,
+    chain_id: u64,
+) -> AnyhowResult<()> {
+    let pending = database
+        .get_root_propagations_by_status(chain_id, PropagationStatus::Pending)
+        .await?;
+
+    let Some(latest) = pending.last() else {
+        return Ok(());
+    };
+
+    info!(chain_id, root = ?latest.root, "Propagating root");
+
+    let transaction_id = identity_manager.propagate_root(chain_id).await?;
+
+    if !identity_manager
+        .mine_identities(transaction_id.clone())
+        .await?
+    {
+        warn!(chain_id, root = ?latest.root, ?transaction_id, "Root propagation failed on chain, retrying.");
+        return Ok(());
+    }
+
+    database
+        .update_root_propagation(
+            latest.id,
+            PropagationStatus::Submitted,
+            Some(transaction_id.as_ref()),
+        )
+        .await?;
+    database
+        .supersede_root_propagations(chain_id, latest.id, PropagationStatus::Pending)
+        .await?;
+
+    Ok(())
+}
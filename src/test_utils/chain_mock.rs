@@ -50,16 +50,17 @@ pub async fn spawn_mock_chain(
 
     // Loading the semaphore verifier contract is special as it requires replacing
     // the address of the Pairing library.
-    let pairing_library_factory = load_and_build_contract("./sol/Pairing.json", client.clone())?;
+    let pairing_library_factory =
+        load_and_build_contract(contract_path("Pairing"), client.clone())?;
     let pairing_library = pairing_library_factory
         .deploy(())?
         .confirmations(0usize)
         .send()
         .await?;
 
-    let verifier_path = "./sol/SemaphoreVerifier.json";
+    let verifier_path = contract_path("SemaphoreVerifier");
     let verifier_file =
-        File::open(verifier_path).unwrap_or_else(|_| panic!("Failed to open `{verifier_path}`"));
+        File::open(&verifier_path).unwrap_or_else(|_| panic!("Failed to open `{verifier_path}`"));
 
     let verifier_contract_json: CompiledContract =
         serde_json::from_reader(BufReader::new(verifier_file))
@@ -75,9 +76,10 @@ pub async fn spawn_mock_chain(
         .resolve()
         .unwrap();
 
-    if verifier_bytecode_object.is_unlinked() {
-        panic!("Could not link the Pairing library into the Verifier.");
-    }
+    assert!(
+        !verifier_bytecode_object.is_unlinked(),
+        "Could not link the Pairing library into the Verifier."
+    );
 
     let bytecode_bytes = verifier_bytecode_object.as_bytes().unwrap_or_else(|| {
         panic!("Could not parse the bytecode for the contract at {verifier_path}")
@@ -97,7 +99,7 @@ pub async fn spawn_mock_chain(
 
     // The rest of the contracts can be deployed to the mock chain normally.
     let mock_state_bridge_factory =
-        load_and_build_contract("./sol/SimpleStateBridge.json", client.clone())?;
+        load_and_build_contract(contract_path("SimpleStateBridge"), client.clone())?;
 
     let mock_state_bridge = mock_state_bridge_factory
         .deploy(())?
@@ -106,7 +108,7 @@ pub async fn spawn_mock_chain(
         .await?;
 
     let mock_verifier_factory =
-        load_and_build_contract("./sol/SequencerVerifier.json", client.clone())?;
+        load_and_build_contract(contract_path("SequencerVerifier"), client.clone())?;
 
     let mock_verifier = mock_verifier_factory
         .deploy(())?
@@ -115,7 +117,7 @@ pub async fn spawn_mock_chain(
         .await?;
 
     let unimplemented_verifier_factory =
-        load_and_build_contract("./sol/UnimplementedTreeVerifier.json", client.clone())?;
+        load_and_build_contract(contract_path("UnimplementedTreeVerifier"), client.clone())?;
 
     let unimplemented_verifier = unimplemented_verifier_factory
         .deploy(())?
@@ -124,7 +126,7 @@ pub async fn spawn_mock_chain(
         .await?;
 
     let verifier_lookup_table_factory =
-        load_and_build_contract("./sol/VerifierLookupTable.json", client.clone())?;
+        load_and_build_contract(contract_path("VerifierLookupTable"), client.clone())?;
 
     let first_batch_size = batch_sizes[0];
 
@@ -141,8 +143,10 @@ pub async fn spawn_mock_chain(
         .send()
         .await?;
 
-    let identity_manager_impl_factory =
-        load_and_build_contract("./sol/WorldIDIdentityManagerImplV1.json", client.clone())?;
+    let identity_manager_impl_factory = load_and_build_contract(
+        contract_path("WorldIDIdentityManagerImplV1"),
+        client.clone(),
+    )?;
 
     let identity_manager_impl = identity_manager_impl_factory
         .deploy(())?
@@ -162,7 +166,7 @@ pub async fn spawn_mock_chain(
     }
 
     let identity_manager_factory =
-        load_and_build_contract("./sol/WorldIDIdentityManager.json", client.clone())?;
+        load_and_build_contract(contract_path("WorldIDIdentityManager"), client.clone())?;
     let state_bridge_address = mock_state_bridge.address();
     let enable_state_bridge = true;
     let identity_manager_impl_address = identity_manager_impl.address();
@@ -202,6 +206,12 @@ type SpecialisedClient =
 type SharableClient = Arc<SpecialisedClient>;
 type SpecialisedFactory = ContractFactory<SpecialisedClient>;
 
+/// Returns the path of the compiled contract `name`, which ships with the
+/// crate so that the mock chain can be spawned from other crates too.
+fn contract_path(name: &str) -> String {
+    format!("{}/sol/{name}.json", env!("CARGO_MANIFEST_DIR"))
+}
+
 fn load_and_build_contract(
     path: impl Into<String>,
     client: SharableClient,
@@ -0,0 +1,126 @@
+//! Test doubles for running the whole pipeline without real proving: a mock
+//! prover service, an Anvil chain with the identity manager contracts deployed
+//! against a mock verifier, a mock `OpenZeppelin` relayer and a Postgres
+//! container. Enabled by the `test_utils` feature.
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc)]
+
+pub mod abi;
+mod chain_mock;
+mod prover_mock;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use ethers::core::abi::Abi;
+use ethers::core::k256::ecdsa::SigningKey;
+use ethers::prelude::artifacts::Bytecode;
+use ethers::types::U256;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+pub use postgres_docker_utils::DockerContainerGuard;
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{info, instrument};
+use url::Host;
+
+pub use self::chain_mock::{spawn_mock_chain, MockChain, SpecialisedContract};
+pub use self::prover_mock::ProverService;
+use crate::app::App;
+use crate::{server, Options};
+
+#[derive(Deserialize, Serialize, Debug)]
+struct CompiledContract {
+    abi:      Abi,
+    bytecode: Bytecode,
+}
+
+/// Starts the sequencer on the address of `options.server.server`, where port
+/// 0 picks a free port, and returns the task running it and the address it
+/// listens on.
+#[instrument(skip_all)]
+pub async fn spawn_app(options: Options) -> anyhow::Result<(JoinHandle<()>, SocketAddr)> {
+    let app = App::new(options.app).await.expect("Failed to create App");
+
+    let ip: IpAddr = match options.server.server.host() {
+        Some(Host::Ipv4(ip)) => ip.into(),
+        Some(Host::Ipv6(ip)) => ip.into(),
+        Some(_) => return Err(anyhow!("Cannot bind {}", options.server.server)),
+        None => Ipv4Addr::LOCALHOST.into(),
+    };
+    let port = options.server.server.port().unwrap_or(9998);
+    let addr = SocketAddr::new(ip, port);
+    let listener = TcpListener::bind(addr).expect("Failed to bind random port");
+    let local_addr = listener.local_addr()?;
+
+    let app = tokio::spawn({
+        async move {
+            info!("App thread starting");
+            server::bind_from_listener(Arc::new(app), Duration::from_secs(30), listener)
+                .await
+                .expect("Failed to bind address");
+            info!("App thread stopping");
+        }
+    });
+
+    Ok((app, local_addr))
+}
+
+/// Starts everything the sequencer depends on: the chain, the database, a
+/// prover for each of the `batch_sizes` and the relayer. The provers are keyed
+/// by their batch size.
+pub async fn spawn_deps(
+    initial_root: U256,
+    batch_sizes: &[usize],
+    tree_depth: u8,
+) -> anyhow::Result<(
+    MockChain,
+    DockerContainerGuard,
+    HashMap<usize, ProverService>,
+    micro_oz::ServerHandle,
+)> {
+    let chain = spawn_mock_chain(initial_root, batch_sizes, tree_depth);
+    let db_container = spawn_db();
+
+    let prover_futures = FuturesUnordered::new();
+    for batch_size in batch_sizes {
+        prover_futures.push(spawn_mock_prover(*batch_size));
+    }
+
+    let (chain, db_container, provers) =
+        tokio::join!(chain, db_container, prover_futures.collect::<Vec<_>>());
+
+    let chain = chain?;
+    let micro_oz = spawn_relayer(&chain).await?;
+
+    let provers = provers.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    let prover_map = provers
+        .into_iter()
+        .map(|prover| (prover.batch_size(), prover))
+        .collect();
+
+    Ok((chain, db_container?, prover_map, micro_oz))
+}
+
+/// Starts a relayer that submits the transactions of the sequencer to
+/// `chain`, e.g. to replace one that was shut down.
+pub async fn spawn_relayer(chain: &MockChain) -> anyhow::Result<micro_oz::ServerHandle> {
+    let signing_key = SigningKey::from_bytes(chain.private_key.as_bytes())?;
+
+    micro_oz::spawn(chain.anvil.endpoint(), signing_key).await
+}
+
+async fn spawn_db() -> anyhow::Result<DockerContainerGuard> {
+    let db_container = postgres_docker_utils::setup().await.unwrap();
+
+    Ok(db_container)
+}
+
+pub async fn spawn_mock_prover(batch_size: usize) -> anyhow::Result<ProverService> {
+    let mock_prover_service = ProverService::new(batch_size).await?;
+
+    Ok(mock_prover_service)
+}
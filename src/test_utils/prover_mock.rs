@@ -5,7 +5,7 @@ use std::sync::Arc;
 
 use anyhow::Context;
 use axum::extract::State;
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::{Json, Router};
 use axum_server::Handle;
 use ethers::types::U256;
@@ -105,9 +105,9 @@ impl ProverService {
     /// Returns a new instance of the mock prover service, serving at the
     /// provided `url`.
     ///
-    /// It provides only a single endpoint for now, `/prove` in order to match
-    /// the full service (`semaphore-mtb`). This can be extended in the future
-    /// if needed.
+    /// It provides the `/prove` endpoint in order to match the full service
+    /// (`semaphore-mtb`), and a `/health` endpoint that reflects the
+    /// availability of the mock.
     pub async fn new(batch_size: usize) -> anyhow::Result<Self> {
         async fn prove(
             State(state): State<Arc<Mutex<Prover>>>,
@@ -118,10 +118,23 @@ impl ProverService {
             state.prove(input).map(Json)
         }
 
+        async fn health(State(state): State<Arc<Mutex<Prover>>>) -> StatusCode {
+            let state = state.lock().await;
+
+            if state.is_available {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }
+
         let inner = Arc::new(Mutex::new(Prover { is_available: true }));
         let state = inner.clone();
 
-        let app = Router::new().route("/prove", post(prove)).with_state(state);
+        let app = Router::new()
+            .route("/prove", post(prove))
+            .route("/health", get(health))
+            .with_state(state);
 
         // We use a random port here so that we can run multiple tests in many
         // threads/tasks
@@ -151,6 +164,7 @@ impl ProverService {
         Ok(service)
     }
 
+    #[must_use]
     pub fn url(&self) -> String {
         format!("http://{}", self.address)
     }
@@ -165,6 +179,7 @@ impl ProverService {
         self.server.shutdown();
     }
 
+    #[must_use]
     pub fn batch_size(&self) -> usize {
         self.batch_size
     }
@@ -173,6 +188,7 @@ impl ProverService {
     /// as is in the CLI args
     ///
     /// e.g. `[{"url": "http://localhost:3001","batch_size": 3,"timeout_s": 30}]`
+    #[must_use]
     pub fn arg_string(&self) -> String {
         format!("[{}]", self.arg_string_single())
     }
@@ -181,6 +197,7 @@ impl ProverService {
     /// wrapped in an array
     ///
     /// e.g. `{"url": "http://localhost:3001","batch_size": 3,"timeout_s": 30}`
+    #[must_use]
     pub fn arg_string_single(&self) -> String {
         format!(
             r#"{{"url": "{}","batch_size": {},"timeout_s": 30}}"#,
@@ -261,7 +278,7 @@ impl Prover {
                 }
             })
             .collect();
-        TreeProof { 0: proof_vec }
+        TreeProof(proof_vec)
     }
 
     /// Computes whether the node at a given index is a left child or right
@@ -0,0 +1,239 @@
+//! Latency histograms that carry OpenMetrics exemplars.
+//!
+//! The `prometheus` crate has no support for exemplars, so the histograms in
+//! this module remember the trace id of the latest observation in each bucket
+//! next to the regular histogram. Both are rendered in the OpenMetrics text
+//! format by [`encode`]. Trace ids are only known when OTLP (or Datadog)
+//! tracing is enabled, otherwise the histograms behave like plain ones.
+
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use axum::http::HeaderMap;
+use once_cell::sync::Lazy;
+use prometheus::core::Metric;
+use prometheus::{register_histogram, Histogram};
+
+/// The content type of the OpenMetrics text format.
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+static HISTOGRAMS: Lazy<Mutex<Vec<Arc<Inner>>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+#[derive(Clone, Debug)]
+struct Exemplar {
+    trace_id:  String,
+    value:     f64,
+    timestamp: f64,
+}
+
+struct Inner {
+    name:      &'static str,
+    help:      &'static str,
+    buckets:   Vec<f64>,
+    histogram: Histogram,
+    /// The latest exemplar of every bucket, with the last one being `+Inf`.
+    exemplars: Mutex<Vec<Option<Exemplar>>>,
+}
+
+/// A histogram that is registered with the default Prometheus registry and
+/// additionally records exemplars.
+#[derive(Clone)]
+pub struct ExemplarHistogram(Arc<Inner>);
+
+impl ExemplarHistogram {
+    /// Creates and registers a new histogram.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a metric with the same name is already registered.
+    #[must_use]
+    pub fn new(name: &'static str, help: &'static str, buckets: Vec<f64>) -> Self {
+        let histogram = register_histogram!(name, help, buckets.clone()).unwrap();
+        let exemplars = Mutex::new(vec![None; buckets.len() + 1]);
+
+        let inner = Arc::new(Inner {
+            name,
+            help,
+            buckets,
+            histogram,
+            exemplars,
+        });
+
+        HISTOGRAMS
+            .lock()
+            .expect("no lock poisoning")
+            .push(inner.clone());
+
+        Self(inner)
+    }
+
+    /// Starts a timer that observes its duration when dropped.
+    #[must_use]
+    pub fn start_timer(&self) -> ExemplarTimer<'_> {
+        ExemplarTimer {
+            histogram: self,
+            start:     Instant::now(),
+            observed:  false,
+        }
+    }
+
+    /// Observes `value`, attaching the trace of the current span if there is
+    /// one.
+    pub fn observe(&self, value: f64) {
+        self.0.histogram.observe(value);
+
+        let Some(trace_id) = current_trace_id() else {
+            return;
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+
+        let bucket = self
+            .0
+            .buckets
+            .iter()
+            .position(|upper_bound| value <= *upper_bound)
+            .unwrap_or(self.0.buckets.len());
+
+        self.0.exemplars.lock().expect("no lock poisoning")[bucket] = Some(Exemplar {
+            trace_id,
+            value,
+            timestamp,
+        });
+    }
+}
+
+/// A timer that observes the elapsed time into an [`ExemplarHistogram`] once
+/// it's dropped or explicitly stopped.
+pub struct ExemplarTimer<'a> {
+    histogram: &'a ExemplarHistogram,
+    start:     Instant,
+    observed:  bool,
+}
+
+impl ExemplarTimer<'_> {
+    #[cfg(feature = "submission")]
+    pub fn observe_duration(mut self) {
+        self.observe();
+    }
+
+    fn observe(&mut self) {
+        self.observed = true;
+        self.histogram.observe(self.start.elapsed().as_secs_f64());
+    }
+}
+
+impl Drop for ExemplarTimer<'_> {
+    fn drop(&mut self) {
+        if !self.observed {
+            self.observe();
+        }
+    }
+}
+
+/// Extracts the trace id of the current span by letting the configured
+/// propagator inject it into a set of headers.
+fn current_trace_id() -> Option<String> {
+    let mut headers = HeaderMap::new();
+    cli_batteries::trace_to_headers(&mut headers);
+
+    // W3C trace context: `{version}-{trace_id}-{parent_id}-{flags}`
+    let trace_id = if let Some(traceparent) = headers.get("traceparent") {
+        traceparent.to_str().ok()?.split('-').nth(1)?.to_owned()
+    } else {
+        headers.get("x-datadog-trace-id")?.to_str().ok()?.to_owned()
+    };
+
+    // An all zeroes trace id means that there is no active trace.
+    if trace_id.chars().all(|c| c == '0') {
+        return None;
+    }
+
+    Some(trace_id)
+}
+
+/// Renders all the exemplar histograms in the OpenMetrics text format.
+#[must_use]
+pub fn encode() -> String {
+    let mut output = String::new();
+
+    for histogram in HISTOGRAMS.lock().expect("no lock poisoning").iter() {
+        encode_histogram(&mut output, histogram).expect("writing to a string can't fail");
+    }
+
+    output.push_str("# EOF\n");
+
+    output
+}
+
+fn encode_histogram(output: &mut String, inner: &Inner) -> fmt::Result {
+    let metric = inner.histogram.metric();
+    let histogram = metric.get_histogram();
+    let exemplars = inner.exemplars.lock().expect("no lock poisoning");
+
+    let name = inner.name;
+
+    writeln!(output, "# TYPE {name} histogram")?;
+    writeln!(output, "# HELP {name} {}", inner.help)?;
+
+    let buckets = histogram
+        .get_bucket()
+        .iter()
+        .map(|bucket| {
+            (
+                bucket.get_upper_bound().to_string(),
+                bucket.get_cumulative_count(),
+            )
+        })
+        .chain(std::iter::once((
+            "+Inf".to_string(),
+            histogram.get_sample_count(),
+        )));
+
+    for ((upper_bound, count), exemplar) in buckets.zip(exemplars.iter()) {
+        write!(output, "{name}_bucket{{le=\"{upper_bound}\"}} {count}")?;
+
+        if let Some(exemplar) = exemplar {
+            write!(
+                output,
+                " # {{trace_id=\"{}\"}} {} {:.3}",
+                exemplar.trace_id, exemplar.value, exemplar.timestamp
+            )?;
+        }
+
+        writeln!(output)?;
+    }
+
+    writeln!(output, "{name}_sum {}", histogram.get_sample_sum())?;
+    writeln!(output, "{name}_count {}", histogram.get_sample_count())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_histogram_in_openmetrics_format() {
+        let histogram =
+            ExemplarHistogram::new("exemplar_test_latency_seconds", "Test histogram.", vec![
+                0.5, 1.0,
+            ]);
+
+        histogram.observe(0.25);
+        histogram.observe(2.0);
+
+        let encoded = encode();
+
+        assert!(encoded.contains("# TYPE exemplar_test_latency_seconds histogram"));
+        assert!(encoded.contains("exemplar_test_latency_seconds_bucket{le=\"0.5\"} 1"));
+        assert!(encoded.contains("exemplar_test_latency_seconds_bucket{le=\"+Inf\"} 2"));
+        assert!(encoded.contains("exemplar_test_latency_seconds_count 2"));
+        assert!(encoded.ends_with("# EOF\n"));
+    }
+}
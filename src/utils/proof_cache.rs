@@ -0,0 +1,117 @@
+//! Caches inclusion proofs of frequently requested identities. A proof never
+//! changes for a given root, so proofs are keyed by root and leaf, and only
+//! dropped once their root is no longer the root of any tree version.
+
+use std::sync::Mutex;
+
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use semaphore::poseidon_tree::Proof;
+
+use crate::identity_tree::Hash;
+
+static LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "proof_cache_lookups",
+        "Number of inclusion proofs looked up in the cache, by result",
+        &["result"]
+    )
+    .unwrap()
+});
+
+struct Inner {
+    proofs: LruCache<(Hash, usize), Proof>,
+    /// The roots of the tree versions the cached proofs were computed for.
+    roots:  Vec<Hash>,
+}
+
+pub struct ProofCache {
+    inner: Option<Mutex<Inner>>,
+}
+
+impl ProofCache {
+    /// Creates a cache of up to `capacity` proofs, or a disabled cache if
+    /// `capacity` is zero.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        let inner = (capacity > 0).then(|| {
+            Mutex::new(Inner {
+                proofs: LruCache::new(capacity),
+                roots:  Vec::new(),
+            })
+        });
+
+        Self { inner }
+    }
+
+    /// Returns the proof of `leaf_index` as of `root`, if it is cached. `roots`
+    /// are the current roots of the tree versions. Proofs for other roots are
+    /// dropped whenever they change.
+    pub fn get(&self, roots: &[Hash], root: &Hash, leaf_index: usize) -> Option<Proof> {
+        let mut inner = self.inner.as_ref()?.lock().expect("no lock poisoning");
+
+        if inner.roots != roots {
+            let stale = inner
+                .proofs
+                .iter()
+                .filter(|((root, _), _)| !roots.contains(root))
+                .map(|(key, _)| *key)
+                .collect::<Vec<_>>();
+            for key in stale {
+                inner.proofs.pop(&key);
+            }
+
+            inner.roots = roots.to_vec();
+        }
+
+        let proof = inner.proofs.get(&(*root, leaf_index)).cloned();
+
+        LOOKUPS
+            .with_label_values(&[if proof.is_some() { "hit" } else { "miss" }])
+            .inc();
+
+        proof
+    }
+
+    pub fn insert(&self, root: Hash, leaf_index: usize, proof: Proof) {
+        if let Some(inner) = &self.inner {
+            inner
+                .lock()
+                .expect("no lock poisoning")
+                .proofs
+                .put((root, leaf_index), proof);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+
+    use super::*;
+
+    #[test]
+    fn drops_proofs_once_their_root_is_gone() {
+        let proof = LazyPoseidonTree::new(3, Hash::ZERO).proof(0);
+        let (old_root, new_root) = (Hash::from(1), Hash::from(2));
+        let cache = ProofCache::new(10);
+
+        cache.insert(old_root, 0, proof.clone());
+        assert_eq!(cache.get(&[old_root], &old_root, 0), Some(proof.clone()));
+        assert_eq!(cache.get(&[old_root], &old_root, 1), None);
+
+        cache.insert(new_root, 0, proof.clone());
+        assert_eq!(cache.get(&[new_root], &old_root, 0), None);
+        assert_eq!(cache.get(&[new_root], &new_root, 0), Some(proof));
+    }
+
+    #[test]
+    fn caches_nothing_without_capacity() {
+        let proof = LazyPoseidonTree::new(3, Hash::ZERO).proof(0);
+        let cache = ProofCache::new(0);
+
+        cache.insert(Hash::ZERO, 0, proof);
+        assert_eq!(cache.get(&[Hash::ZERO], &Hash::ZERO, 0), None);
+    }
+}
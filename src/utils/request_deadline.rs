@@ -0,0 +1,60 @@
+//! The deadline of the request being handled, set by the timeout layer of the
+//! server, so that handlers can stop between steps instead of starting work
+//! whose response nobody waits for anymore.
+
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::server::error::Error as ServerError;
+
+tokio::task_local! {
+    static DEADLINE: Instant;
+}
+
+/// Runs `future` with `deadline` as the deadline of the current request.
+pub async fn scope<F: Future>(deadline: Instant, future: F) -> F::Output {
+    DEADLINE.scope(deadline, future).await
+}
+
+/// The time left until the deadline of the current request, or `None` outside
+/// of requests.
+#[must_use]
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .try_with(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// # Errors
+///
+/// Will return `Err` if the deadline of the current request has passed.
+pub fn check() -> Result<(), ServerError> {
+    match remaining() {
+        Some(remaining) if remaining.is_zero() => Err(ServerError::DeadlineExceeded),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn checks_the_deadline_of_the_request() {
+        assert!(check().is_ok());
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+        scope(deadline, async {
+            assert!(remaining().unwrap() > Duration::from_secs(59));
+            assert!(check().is_ok());
+        })
+        .await;
+
+        scope(Instant::now(), async {
+            assert!(matches!(check(), Err(ServerError::DeadlineExceeded)));
+        })
+        .await;
+    }
+}
@@ -0,0 +1,155 @@
+//! A dedicated pool of threads for CPU heavy work, such as computing merkle
+//! proofs, so that bursts of such work don't starve the tasks running on the
+//! async executor.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use anyhow::Context;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use tokio::sync::oneshot;
+use tracing::warn;
+
+pub struct WorkerPool {
+    pool: ThreadPool,
+}
+
+impl WorkerPool {
+    /// Creates a pool of `num_threads` threads, or one thread per CPU if
+    /// `num_threads` is zero. If `pin_to_cpus` is set, every thread is pinned
+    /// to a single CPU.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the threads can't be spawned.
+    pub fn new(num_threads: usize, pin_to_cpus: bool) -> anyhow::Result<Self> {
+        let cpus = std::thread::available_parallelism().map_or(1, usize::from);
+
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|index| format!("worker-pool-{index}"))
+            .start_handler(move |index| {
+                if pin_to_cpus {
+                    pin_current_thread(index % cpus);
+                }
+            })
+            .build()
+            .context("error spawning worker pool")?;
+
+        Ok(Self { pool })
+    }
+
+    /// Runs `work` on the pool and waits for its result. If the caller is
+    /// cancelled, e.g. because its request timed out or its client went away,
+    /// before a thread picks up `work`, it is skipped.
+    ///
+    /// # Panics
+    ///
+    /// If `work` panics, the panic is resumed on the caller. A panic escaping a
+    /// thread of the pool would abort the process.
+    pub async fn run<T, F>(&self, work: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+
+        self.pool.spawn(move || {
+            // The receiver is gone if the caller was cancelled, in which case
+            // nobody is interested in the result.
+            if sender.is_closed() {
+                return;
+            }
+            _ = sender.send(panic::catch_unwind(AssertUnwindSafe(work)));
+        });
+
+        // The sender is only dropped without a result once the caller is gone.
+        match receiver.await.expect("worker pool dropped the work") {
+            Ok(result) => result,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) {
+    // SAFETY: `cpu_set_t` is plain data that is valid when zeroed, and it's only
+    // read for the duration of the call.
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+
+    if result != 0 {
+        warn!(
+            cpu,
+            error = %std::io::Error::last_os_error(),
+            "Failed to pin worker thread to CPU"
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(cpu: usize) {
+    warn!(
+        cpu,
+        "Pinning worker threads to CPUs is only supported on Linux"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn runs_work_off_the_executor() {
+        let pool = WorkerPool::new(2, false).unwrap();
+
+        let thread_name = pool
+            .run(|| std::thread::current().name().map(ToOwned::to_owned))
+            .await;
+
+        assert!(thread_name.unwrap().starts_with("worker-pool-"));
+    }
+
+    #[tokio::test]
+    async fn skips_work_of_cancelled_callers() {
+        let pool = WorkerPool::new(1, false).unwrap();
+        let (release, blocked) = mpsc::channel::<()>();
+        let ran = Arc::new(AtomicBool::new(false));
+
+        // Polling hands the work to the pool, where the first call keeps the
+        // only thread busy until the second one is cancelled.
+        let mut blocking = Box::pin(pool.run(move || blocked.recv().ok()));
+        let mut cancelled = Box::pin(pool.run({
+            let ran = ran.clone();
+            move || ran.store(true, Ordering::SeqCst)
+        }));
+        assert!(futures::poll!(&mut blocking).is_pending());
+        assert!(futures::poll!(&mut cancelled).is_pending());
+        drop(cancelled);
+
+        release.send(()).unwrap();
+        blocking.await;
+        pool.run(|| ()).await;
+
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn resumes_panics_on_the_caller() {
+        let pool = Arc::new(WorkerPool::new(1, false).unwrap());
+
+        let caller = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(|| -> u32 { panic!("bad proof request") }).await }
+        });
+        assert!(caller.await.unwrap_err().is_panic());
+
+        // The panic didn't take the thread, or the process, down with it.
+        assert_eq!(pool.run(|| 1).await, 1);
+    }
+}
@@ -0,0 +1,195 @@
+//! Offline verification of inclusion proofs and of the roots of the tree, for
+//! services that consume proofs or roots of the sequencer and check them
+//! without network access. Everything here is a pure function of its
+//! arguments, and hashes like the tree of the sequencer.
+use semaphore::merkle_tree::{Branch, Hasher};
+use semaphore::poseidon_tree::Proof;
+use thiserror::Error;
+
+use crate::identity_tree::{Hash, RootAccumulator, TreeHasher};
+
+/// An identity appended to the tree, and the root of the tree after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Insertion {
+    pub leaf_index: usize,
+    pub commitment: Hash,
+    pub root:       Hash,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ContinuityError {
+    #[error("expected leaf {expected}, found leaf {found}")]
+    NonContiguousLeaf { expected: usize, found: usize },
+    #[error("leaf {leaf_index} doesn't fit into a tree of depth {tree_depth}")]
+    TreeFull {
+        leaf_index: usize,
+        tree_depth: usize,
+    },
+    #[error("the root of leaf {leaf_index} is {expected:?}, but {actual:?} was given")]
+    RootMismatch {
+        leaf_index: usize,
+        expected:   Hash,
+        actual:     Hash,
+    },
+}
+
+/// The root of the tree that `proof` proves `leaf` to be in.
+#[must_use]
+pub fn compute_root(leaf: &Hash, proof: &Proof) -> Hash {
+    proof.0.iter().fold(*leaf, |node, branch| match branch {
+        Branch::Left(sibling) => TreeHasher::hash_node(&node, sibling),
+        Branch::Right(sibling) => TreeHasher::hash_node(sibling, &node),
+    })
+}
+
+/// Whether `proof` proves that `leaf` is in the tree with `root`, e.g. a
+/// proof of `/inclusionProof` for the commitment it was requested for.
+#[must_use]
+pub fn verify_inclusion_proof(root: &Hash, leaf: &Hash, proof: &Proof) -> bool {
+    compute_root(leaf, proof) == *root
+}
+
+/// Checks that `insertions`, in leaf order from the first leaf of an empty
+/// tree of `tree_depth` with `initial_leaf_value`, are contiguous and that
+/// every root follows from the leaves before it. Returns the root after the
+/// last insertion.
+///
+/// # Errors
+///
+/// Will return `Err` for the first insertion that doesn't continue the tree.
+pub fn verify_root_continuity(
+    tree_depth: usize,
+    initial_leaf_value: Hash,
+    insertions: impl IntoIterator<Item = Insertion>,
+) -> Result<Hash, ContinuityError> {
+    let capacity = u32::try_from(tree_depth)
+        .ok()
+        .and_then(|depth| 1_usize.checked_shl(depth))
+        .unwrap_or(usize::MAX);
+    let mut accumulator = RootAccumulator::new(tree_depth, initial_leaf_value);
+
+    for insertion in insertions {
+        if insertion.leaf_index != accumulator.len() {
+            return Err(ContinuityError::NonContiguousLeaf {
+                expected: accumulator.len(),
+                found:    insertion.leaf_index,
+            });
+        }
+        if insertion.leaf_index >= capacity {
+            return Err(ContinuityError::TreeFull {
+                leaf_index: insertion.leaf_index,
+                tree_depth,
+            });
+        }
+
+        let root = accumulator.push(insertion.commitment);
+        if root != insertion.root {
+            return Err(ContinuityError::RootMismatch {
+                leaf_index: insertion.leaf_index,
+                expected:   root,
+                actual:     insertion.root,
+            });
+        }
+    }
+
+    Ok(accumulator.root())
+}
+
+#[cfg(test)]
+mod tests {
+    use semaphore::poseidon_tree::LazyPoseidonTree;
+
+    use super::*;
+
+    fn insertions(tree_depth: usize, count: usize) -> (LazyPoseidonTree, Vec<Insertion>) {
+        let mut tree = LazyPoseidonTree::new(tree_depth, Hash::ZERO).derived();
+
+        let insertions = (0..count)
+            .map(|leaf_index| {
+                let commitment = Hash::from(leaf_index as u64 + 1);
+                tree = tree.update(leaf_index, &commitment);
+
+                Insertion {
+                    leaf_index,
+                    commitment,
+                    root: tree.root(),
+                }
+            })
+            .collect();
+
+        (tree, insertions)
+    }
+
+    #[test]
+    fn proofs_of_the_tree_verify() {
+        let (tree, insertions) = insertions(4, 5);
+
+        let proof = tree.proof(3);
+        assert!(verify_inclusion_proof(
+            &tree.root(),
+            &insertions[3].commitment,
+            &proof
+        ));
+        assert!(!verify_inclusion_proof(
+            &tree.root(),
+            &insertions[2].commitment,
+            &proof
+        ));
+        assert!(!verify_inclusion_proof(
+            &insertions[3].root,
+            &insertions[3].commitment,
+            &proof
+        ));
+    }
+
+    #[test]
+    fn roots_have_to_follow_from_the_leaves() {
+        let (tree, mut insertions) = insertions(4, 5);
+
+        assert_eq!(
+            verify_root_continuity(4, Hash::ZERO, insertions.clone()),
+            Ok(tree.root())
+        );
+        assert_eq!(
+            verify_root_continuity(4, Hash::ZERO, []),
+            Ok(LazyPoseidonTree::new(4, Hash::ZERO).root())
+        );
+        assert_eq!(
+            verify_root_continuity(4, Hash::ZERO, insertions[1..].to_vec()),
+            Err(ContinuityError::NonContiguousLeaf {
+                expected: 0,
+                found:    1,
+            })
+        );
+
+        let expected = insertions[2].root;
+        let actual = insertions[1].root;
+        insertions[2].root = actual;
+        assert_eq!(
+            verify_root_continuity(4, Hash::ZERO, insertions),
+            Err(ContinuityError::RootMismatch {
+                leaf_index: 2,
+                expected,
+                actual,
+            })
+        );
+    }
+
+    #[test]
+    fn full_trees_are_rejected() {
+        let (_, mut insertions) = insertions(2, 4);
+        insertions.push(Insertion {
+            leaf_index: 4,
+            commitment: Hash::from(5_u64),
+            root:       Hash::ZERO,
+        });
+
+        assert_eq!(
+            verify_root_continuity(2, Hash::ZERO, insertions),
+            Err(ContinuityError::TreeFull {
+                leaf_index: 4,
+                tree_depth: 2,
+            })
+        );
+    }
+}
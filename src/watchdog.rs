@@ -0,0 +1,373 @@
+//! An independent process that compares the database of a sequencer with the
+//! identity manager on chain, without serving the API or submitting anything.
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result as AnyhowResult;
+use clap::Parser;
+use cli_batteries::await_shutdown;
+use once_cell::sync::Lazy;
+use prometheus::{register_int_gauge_vec, IntGaugeVec};
+use serde::Serialize;
+use tokio::select;
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+use url::Url;
+
+use crate::contracts::interface::IdentityContract;
+use crate::database::Database;
+use crate::ethereum::ReadProvider;
+use crate::identity_tree::{Hash, ProcessedStatus, RootAccumulator};
+use crate::{contracts, database, ethereum};
+
+static DIVERGENCES: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "watchdog_divergence",
+        "Whether the database and the chain disagree, by check",
+        &["check"]
+    )
+    .unwrap()
+});
+
+#[derive(Clone, Debug, PartialEq, Parser)]
+#[group(skip)]
+pub struct Options {
+    #[clap(flatten)]
+    pub ethereum: ethereum::Options,
+
+    #[clap(flatten)]
+    pub contracts: contracts::Options,
+
+    /// The database of the sequencer being watched. It is never migrated.
+    #[clap(flatten)]
+    pub database: database::Options,
+
+    /// How often to compare the database with the chain (seconds)
+    #[clap(long, env, default_value = "60")]
+    pub watch_interval_seconds: u64,
+
+    /// How long the status of a root in the database may lag behind the
+    /// chain before an alert is raised. Must cover the time it takes the
+    /// sequencer to finalize a batch (seconds)
+    #[clap(long, env, default_value = "1800")]
+    pub watch_grace_period_seconds: u64,
+
+    /// URL that alerts are posted to as JSON, in addition to being logged
+    #[clap(long, env)]
+    pub watch_alert_webhook_url: Option<Url>,
+}
+
+/// A way in which the database and the chain can disagree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum Check {
+    /// The latest root on chain is not in the database, e.g. because another
+    /// writer modified the tree or identities were lost.
+    UnknownRoot,
+    /// The database marks a root as mined that the chain doesn't know.
+    MinedRootNotOnChain,
+    /// The latest root on chain is not marked as mined in the database. This
+    /// is expected for a while after every batch.
+    StaleRootStatus,
+}
+
+impl Check {
+    const ALL: [Self; 3] = [
+        Self::UnknownRoot,
+        Self::MinedRootNotOnChain,
+        Self::StaleRootStatus,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::UnknownRoot => "unknown_root",
+            Self::MinedRootNotOnChain => "mined_root_not_on_chain",
+            Self::StaleRootStatus => "stale_root_status",
+        }
+    }
+
+    const fn is_transient(self) -> bool {
+        matches!(self, Self::StaleRootStatus)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Alert {
+    check:    Check,
+    /// `false` once the check passes again.
+    diverged: bool,
+    message:  String,
+}
+
+/// Tracks since when each check has been failing. Transient divergences are
+/// only alerted on once they outlast the grace period.
+#[derive(Debug, Default)]
+struct Divergences {
+    since:   HashMap<Check, Instant>,
+    alerted: HashSet<Check>,
+}
+
+impl Divergences {
+    fn update(
+        &mut self,
+        now: Instant,
+        grace_period: Duration,
+        mut current: HashMap<Check, String>,
+    ) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+
+        for check in Check::ALL {
+            if let Some(message) = current.remove(&check) {
+                let since = *self.since.entry(check).or_insert(now);
+                let due = !check.is_transient() || now.duration_since(since) >= grace_period;
+
+                if due && self.alerted.insert(check) {
+                    alerts.push(Alert {
+                        check,
+                        diverged: true,
+                        message,
+                    });
+                }
+            } else {
+                self.since.remove(&check);
+
+                if self.alerted.remove(&check) {
+                    alerts.push(Alert {
+                        check,
+                        diverged: false,
+                        message: "the database agrees with the chain again".to_string(),
+                    });
+                }
+            }
+        }
+
+        alerts
+    }
+}
+
+struct Watchdog {
+    database:        Database,
+    contract:        IdentityContract,
+    initial_root:    Hash,
+    grace_period:    Duration,
+    alert_webhook:   Option<Url>,
+    client:          reqwest::Client,
+    divergences:     Divergences,
+    last_chain_root: Option<Hash>,
+}
+
+impl Watchdog {
+    /// Returns the checks that currently fail, with a description.
+    async fn check(&mut self) -> AnyhowResult<HashMap<Check, String>> {
+        let mut failing = HashMap::new();
+
+        let chain_root: Hash = self.contract.latest_root().await?.into();
+        if self.last_chain_root != Some(chain_root) {
+            info!(?chain_root, "Identity manager root changed");
+            self.last_chain_root = Some(chain_root);
+        }
+
+        if chain_root != self.initial_root {
+            match self.database.get_root_state(&chain_root).await? {
+                None => {
+                    failing.insert(
+                        Check::UnknownRoot,
+                        format!("latest root {chain_root:?} on chain is not in the database"),
+                    );
+                }
+                Some(state) if state.status != ProcessedStatus::Mined => {
+                    failing.insert(
+                        Check::StaleRootStatus,
+                        format!(
+                            "latest root {chain_root:?} on chain is {} in the database",
+                            <&str>::from(state.status)
+                        ),
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(leaf_index) = self
+            .database
+            .get_max_leaf_index_by_status(ProcessedStatus::Mined)
+            .await?
+        {
+            let identities = self
+                .database
+                .get_identities(leaf_index, Some(leaf_index + 1), 1)
+                .await?;
+
+            if let Some(identity) = identities.first() {
+                if !self
+                    .contract
+                    .is_root_known(identity.root.into(), None)
+                    .await?
+                {
+                    failing.insert(
+                        Check::MinedRootNotOnChain,
+                        format!(
+                            "root {:?} of leaf {leaf_index} is mined in the database but not \
+                             known on chain",
+                            identity.root
+                        ),
+                    );
+                }
+            }
+        }
+
+        Ok(failing)
+    }
+
+    async fn report(&mut self, failing: HashMap<Check, String>) {
+        for check in Check::ALL {
+            DIVERGENCES
+                .with_label_values(&[check.name()])
+                .set(i64::from(failing.contains_key(&check)));
+        }
+
+        let alerts = self
+            .divergences
+            .update(Instant::now(), self.grace_period, failing);
+
+        for alert in alerts {
+            if alert.diverged {
+                error!(check = alert.check.name(), %alert.message, "Database and chain diverged");
+            } else {
+                info!(check = alert.check.name(), "Database and chain agree again");
+            }
+
+            if let Some(url) = &self.alert_webhook {
+                let result = self
+                    .client
+                    .post(url.clone())
+                    .json(&alert)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status);
+
+                if let Err(error) = result {
+                    warn!(?error, "Failed to deliver alert");
+                }
+            }
+        }
+    }
+}
+
+/// Compares the database with the chain every interval until shut down.
+///
+/// # Errors
+///
+/// Will return `Err` if the database or the Ethereum provider can't be
+/// connected to.
+pub async fn main(options: Options) -> AnyhowResult<()> {
+    let database = Database::new(database::Options {
+        database_migrate: false,
+        ..options.database
+    })
+    .await?;
+
+    let provider = ReadProvider::new(
+        &options.ethereum.provider_urls(),
+        options.ethereum.failover_config(),
+    )
+    .await?;
+    let contract = IdentityContract::new(&options.contracts, Arc::new(provider));
+
+    let initial_root = RootAccumulator::new(
+        options.contracts.tree_depth,
+        options.contracts.initial_leaf_value,
+    )
+    .root();
+
+    let mut watchdog = Watchdog {
+        database,
+        contract,
+        initial_root,
+        grace_period: Duration::from_secs(options.watch_grace_period_seconds),
+        alert_webhook: options.watch_alert_webhook_url,
+        client: reqwest::Client::new(),
+        divergences: Divergences::default(),
+        last_chain_root: None,
+    };
+    let interval = Duration::from_secs(options.watch_interval_seconds);
+
+    info!(
+        address = ?options.contracts.identity_manager_address,
+        "Watching the identity manager"
+    );
+
+    loop {
+        match watchdog.check().await {
+            Ok(failing) => watchdog.report(failing).await,
+            Err(error) => error!(?error, "Failed to compare the database with the chain"),
+        }
+
+        select! {
+            () = sleep(interval) => {}
+            () = await_shutdown() => break,
+        }
+    }
+
+    info!("Stopping the watchdog");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failing(checks: &[Check]) -> HashMap<Check, String> {
+        checks
+            .iter()
+            .map(|check| (*check, check.name().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn transient_divergences_are_alerted_after_the_grace_period() {
+        let grace_period = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut divergences = Divergences::default();
+
+        let alerts = divergences.update(start, grace_period, failing(&[Check::StaleRootStatus]));
+        assert!(alerts.is_empty());
+
+        let alerts = divergences.update(
+            start + grace_period,
+            grace_period,
+            failing(&[Check::StaleRootStatus]),
+        );
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].diverged);
+
+        // Alerted once, until the check passes again.
+        let alerts = divergences.update(
+            start + grace_period * 2,
+            grace_period,
+            failing(&[Check::StaleRootStatus]),
+        );
+        assert!(alerts.is_empty());
+
+        let alerts = divergences.update(start + grace_period * 3, grace_period, failing(&[]));
+        assert_eq!(alerts.len(), 1);
+        assert!(!alerts[0].diverged);
+    }
+
+    #[test]
+    fn other_divergences_are_alerted_immediately() {
+        let mut divergences = Divergences::default();
+
+        let alerts = divergences.update(
+            Instant::now(),
+            Duration::from_secs(60),
+            failing(&[Check::UnknownRoot, Check::MinedRootNotOnChain]),
+        );
+
+        assert_eq!(alerts.len(), 2);
+        assert!(alerts.iter().all(|alert| alert.diverged));
+    }
+}
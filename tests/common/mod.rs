@@ -2,10 +2,6 @@
 // test crates - so some code may not be used in some cases
 #![allow(dead_code)]
 
-pub mod abi;
-mod chain_mock;
-mod prover_mock;
-
 pub mod prelude {
     pub use std::time::Duration;
 
@@ -27,7 +23,6 @@ pub mod prelude {
     pub use hyper::client::HttpConnector;
     pub use hyper::{Body, Client, Request};
     pub use once_cell::sync::Lazy;
-    pub use postgres_docker_utils::DockerContainerGuard;
     pub use semaphore::identity::Identity;
     pub use semaphore::merkle_tree::{self, Branch};
     pub use semaphore::poseidon_tree::{PoseidonHash, PoseidonTree};
@@ -37,6 +32,10 @@ pub mod prelude {
     pub use serde_json::json;
     pub use signup_sequencer::app::App;
     pub use signup_sequencer::identity_tree::Hash;
+    pub use signup_sequencer::test_utils::{
+        abi as ContractAbi, spawn_app, spawn_deps, spawn_mock_prover, spawn_relayer,
+        DockerContainerGuard, ProverService,
+    };
     pub use signup_sequencer::{server, Options};
     pub use tokio::spawn;
     pub use tokio::task::JoinHandle;
@@ -45,23 +44,16 @@ pub mod prelude {
     pub use tracing_subscriber::fmt::time::Uptime;
     pub use url::{Host, Url};
 
-    pub use super::prover_mock::ProverService;
     pub use super::{
-        abi as ContractAbi, generate_reference_proof_json, generate_test_identities,
-        init_tracing_subscriber, spawn_app, spawn_deps, spawn_mock_prover, test_inclusion_proof,
-        test_insert_identity, test_verify_proof, test_verify_proof_on_chain,
+        generate_reference_proof_json, generate_test_identities, init_tracing_subscriber,
+        test_batch_status, test_inclusion_proof, test_insert_identity, test_set_batching_paused,
+        test_verify_proof, test_verify_proof_on_chain, wait_for_batch_status,
     };
 }
 
-use std::collections::HashMap;
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
-use std::sync::Arc;
-
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
 use hyper::StatusCode;
+use signup_sequencer::test_utils::SpecialisedContract;
 
-use self::chain_mock::{spawn_mock_chain, MockChain, SpecialisedContract};
 use self::prelude::*;
 
 #[allow(clippy::too_many_arguments)]
@@ -327,7 +319,9 @@ pub async fn test_remove_batch_size(
     let body_str =
         String::from_utf8(body_bytes.into_iter().collect()).expect("Failed to decode response.");
 
-    if expect_failure && body_str != "The last batch size cannot be removed" {
+    let body: serde_json::Value = serde_json::from_str(&body_str).unwrap_or_default();
+
+    if expect_failure && body["code"] != "cannot_remove_last_batch_size" {
         anyhow::bail!("Expected failure, but got success");
     } else {
         Ok(())
@@ -361,12 +355,103 @@ pub async fn test_insert_identity(
         panic!("Failed to insert identity");
     }
 
-    assert!(bytes.is_empty());
+    let result: serde_json::Value =
+        serde_json::from_slice(&bytes).expect("Failed to parse insert identity response");
+    assert!(result["estimatedInclusionTime"].is_string());
     ref_tree.set(leaf_index, test_leaves[leaf_index]);
 
     (ref_tree.proof(leaf_index).unwrap(), ref_tree.root())
 }
 
+/// Pauses batching with `/admin/pause`, or resumes it with `/admin/resume`.
+#[instrument(skip_all)]
+pub async fn test_set_batching_paused(uri: &str, client: &Client<HttpConnector>, paused: bool) {
+    let req = if paused {
+        Request::builder()
+            .method("POST")
+            .uri(uri.to_owned() + "/admin/pause")
+            .header("Content-Type", "application/json")
+            .body(Body::from(json!({ "reason": "test" }).to_string()))
+    } else {
+        Request::builder()
+            .method("POST")
+            .uri(uri.to_owned() + "/admin/resume")
+            .body(Body::empty())
+    }
+    .expect("Failed to create batching pause hyper::Body");
+
+    let mut response = client
+        .request(req)
+        .await
+        .expect("Failed to execute request.");
+    assert!(response.status().is_success());
+
+    let bytes = hyper::body::to_bytes(response.body_mut())
+        .await
+        .expect("Failed to convert response body to bytes");
+    let result: serde_json::Value =
+        serde_json::from_slice(&bytes).expect("Failed to parse batching pause response");
+    assert_eq!(result["paused"], paused);
+}
+
+/// Returns the status of the batch resulting in `root`, or `None` if there is
+/// no such batch.
+#[instrument(skip_all)]
+pub async fn test_batch_status(
+    uri: &str,
+    client: &Client<HttpConnector>,
+    root: &Field,
+) -> Option<String> {
+    let root = json!(root);
+    let req = Request::builder()
+        .method("GET")
+        .uri(format!(
+            "{uri}/batches/{}",
+            root.as_str().expect("Roots are serialized as strings")
+        ))
+        .body(Body::empty())
+        .expect("Failed to create batch hyper::Body");
+
+    let mut response = client
+        .request(req)
+        .await
+        .expect("Failed to execute request.");
+    let bytes = hyper::body::to_bytes(response.body_mut())
+        .await
+        .expect("Failed to convert response body to bytes");
+    if !response.status().is_success() {
+        return None;
+    }
+
+    let result: serde_json::Value =
+        serde_json::from_slice(&bytes).expect("Failed to parse batch response");
+    result["batch"]["status"].as_str().map(ToOwned::to_owned)
+}
+
+/// Polls the batch resulting in `root` every second until it has one of
+/// `statuses`, for up to `attempts` seconds. Returns its last status.
+#[instrument(skip_all)]
+pub async fn wait_for_batch_status(
+    uri: &str,
+    client: &Client<HttpConnector>,
+    root: &Field,
+    statuses: &[&str],
+    attempts: usize,
+) -> Option<String> {
+    let mut status = None;
+    for _ in 0..attempts {
+        status = test_batch_status(uri, client, root).await;
+        if status
+            .as_deref()
+            .map_or(false, |status| statuses.contains(&status))
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+    status
+}
+
 fn construct_inclusion_proof_body(identity_commitment: &Hash) -> Body {
     Body::from(
         json!({
@@ -404,88 +489,6 @@ fn construct_verify_proof_body(
     )
 }
 
-#[instrument(skip_all)]
-pub async fn spawn_app(options: Options) -> anyhow::Result<(JoinHandle<()>, SocketAddr)> {
-    let app = App::new(options.app).await.expect("Failed to create App");
-
-    let ip: IpAddr = match options.server.server.host() {
-        Some(Host::Ipv4(ip)) => ip.into(),
-        Some(Host::Ipv6(ip)) => ip.into(),
-        Some(_) => return Err(anyhow::anyhow!("Cannot bind {}", options.server.server)),
-        None => Ipv4Addr::LOCALHOST.into(),
-    };
-    let port = options.server.server.port().unwrap_or(9998);
-    let addr = SocketAddr::new(ip, port);
-    let listener = TcpListener::bind(addr).expect("Failed to bind random port");
-    let local_addr = listener.local_addr()?;
-
-    let app = spawn({
-        async move {
-            info!("App thread starting");
-            server::bind_from_listener(Arc::new(app), Duration::from_secs(30), listener)
-                .await
-                .expect("Failed to bind address");
-            info!("App thread stopping");
-        }
-    });
-
-    Ok((app, local_addr))
-}
-
-#[derive(Deserialize, Serialize, Debug)]
-struct CompiledContract {
-    abi:      Abi,
-    bytecode: Bytecode,
-}
-
-pub async fn spawn_deps(
-    initial_root: U256,
-    batch_sizes: &[usize],
-    tree_depth: u8,
-) -> anyhow::Result<(
-    MockChain,
-    DockerContainerGuard,
-    HashMap<usize, ProverService>,
-    micro_oz::ServerHandle,
-)> {
-    let chain = spawn_mock_chain(initial_root, batch_sizes, tree_depth);
-    let db_container = spawn_db();
-
-    let prover_futures = FuturesUnordered::new();
-    for batch_size in batch_sizes {
-        prover_futures.push(spawn_mock_prover(*batch_size));
-    }
-
-    let (chain, db_container, provers) =
-        tokio::join!(chain, db_container, prover_futures.collect::<Vec<_>>());
-
-    let chain = chain?;
-
-    let signing_key = SigningKey::from_bytes(chain.private_key.as_bytes())?;
-    let micro_oz = micro_oz::spawn(chain.anvil.endpoint(), signing_key).await?;
-
-    let provers = provers.into_iter().collect::<Result<Vec<_>, _>>()?;
-
-    let prover_map = provers
-        .into_iter()
-        .map(|prover| (prover.batch_size(), prover))
-        .collect();
-
-    Ok((chain, db_container?, prover_map, micro_oz))
-}
-
-async fn spawn_db() -> anyhow::Result<DockerContainerGuard> {
-    let db_container = postgres_docker_utils::setup().await.unwrap();
-
-    Ok(db_container)
-}
-
-pub async fn spawn_mock_prover(batch_size: usize) -> anyhow::Result<ProverService> {
-    let mock_prover_service = prover_mock::ProverService::new(batch_size).await?;
-
-    Ok(mock_prover_service)
-}
-
 /// Initializes the tracing subscriber.
 ///
 /// Set the `QUIET_MODE` environment variable to reduce the complexity of the